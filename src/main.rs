@@ -2,7 +2,38 @@
 mod log;
 mod server;
 
+use std::path::Path;
+use server::world::BlockPos;
+
+const USAGE: &str = "usage: r-rebstome [paste <schematic> <x> <y> <z>]";
+
 fn main() {
     let config = server::config::Config::read();
-    server::Server::new(config).update();
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    match args.as_slice() {
+        [] => server::Server::new(config).update(),
+        [command, file, coords @ ..] if command == "paste" && coords.len() == 3 => {
+            let Ok(coords) = coords.iter().map(|c| c.parse()).collect::<Result<Vec<i32>, _>>() else {
+                error!("{}", USAGE);
+                std::process::exit(2);
+            };
+
+            let pos = BlockPos::new(coords[0], coords[1], coords[2]);
+            let plots = config.plots.enabled.then(|| server::plot::Layout::new(&config.plots));
+            match server::schematic::paste_offline(&config.world.path, plots, Path::new(file), pos) {
+                Ok(count) => {
+                    info!("pasted {} into {}, {} blocks changed", file, config.world.path, count);
+                },
+                Err(e) => {
+                    error!("{}", e);
+                    std::process::exit(1);
+                },
+            }
+        },
+        _ => {
+            error!("{}", USAGE);
+            std::process::exit(2);
+        },
+    }
 }
@@ -0,0 +1,440 @@
+//! Who may join: a whitelist and bans of players and IP addresses, kept in
+//! the files and formats of vanilla. The files are read again whenever they
+//! change, so they can be edited while the server runs.
+
+use std::fs;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+use super::Server;
+use super::chat::Component;
+use super::config::AccessConfig;
+use super::player;
+use super::plugin::{Event, Kind, Outcome, Plugin, Priority, Registry};
+
+/// Who made the bans that came from the server itself rather than a player.
+pub const SERVER_SOURCE: &str = "Server";
+pub const DEFAULT_BAN_REASON: &str = "Banned by an operator.";
+
+/// Game ticks between looking for changed list files.
+const REFRESH_INTERVAL: u64 = 20;
+
+/// An entry of one of the list files.
+pub trait Entry: Sized {
+    /// The player name or IP address it's about.
+    fn key(&self) -> &str;
+    fn from_json(value: &json::JsonValue) -> Option<Self>;
+    fn to_json(&self) -> json::JsonValue;
+}
+
+#[derive(Debug, Clone)]
+pub struct Whitelisted {
+    pub uuid: String,
+    pub name: String,
+}
+
+impl Whitelisted {
+    pub fn new(name: &str) -> Self {
+        Self { uuid: player::format_uuid(player::offline_uuid(name)), name: name.to_string() }
+    }
+}
+
+impl Entry for Whitelisted {
+    fn key(&self) -> &str {
+        &self.name
+    }
+
+    fn from_json(value: &json::JsonValue) -> Option<Self> {
+        let name = value["name"].as_str()?;
+        Some(match value["uuid"].as_str() {
+            Some(uuid) => Self { uuid: uuid.to_string(), name: name.to_string() },
+            None => Self::new(name),
+        })
+    }
+
+    fn to_json(&self) -> json::JsonValue {
+        json::object! { uuid: self.uuid.as_str(), name: self.name.as_str() }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Ban {
+    /// Name of the player or the IP address banned.
+    pub target: String,
+    /// Only bans of players have one.
+    pub uuid: Option<String>,
+    /// Seconds since the Unix epoch.
+    pub created: u64,
+    pub source: String,
+    /// When the ban ends, or none if it's forever.
+    pub expires: Option<u64>,
+    pub reason: String,
+}
+
+impl Ban {
+    /// A ban of a player, or of an IP address without `uuid`, for `duration`
+    /// seconds from now or forever.
+    pub fn new(target: &str, uuid: Option<String>, source: &str, duration: Option<u64>, reason: &str) -> Self {
+        let created = now();
+        Self {
+            target: target.to_string(),
+            uuid,
+            created,
+            source: source.to_string(),
+            expires: duration.map(|d| created + d),
+            reason: reason.to_string(),
+        }
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.expires.is_some_and(|e| e <= now())
+    }
+}
+
+impl Entry for Ban {
+    fn key(&self) -> &str {
+        &self.target
+    }
+
+    fn from_json(value: &json::JsonValue) -> Option<Self> {
+        let (target, uuid) = match value["ip"].as_str() {
+            Some(ip) => (ip, None),
+            None => (value["name"].as_str()?, value["uuid"].as_str().map(str::to_string)),
+        };
+
+        Some(Self {
+            target: target.to_string(),
+            uuid,
+            created: value["created"].as_str().and_then(parse_date).unwrap_or_else(now),
+            source: value["source"].as_str().unwrap_or(SERVER_SOURCE).to_string(),
+            expires: value["expires"].as_str().and_then(parse_date),
+            reason: value["reason"].as_str().unwrap_or(DEFAULT_BAN_REASON).to_string(),
+        })
+    }
+
+    fn to_json(&self) -> json::JsonValue {
+        let mut value = match &self.uuid {
+            Some(uuid) => json::object! { uuid: uuid.as_str(), name: self.target.as_str() },
+            None => json::object! { ip: self.target.as_str() },
+        };
+
+        value["created"] = format_date(self.created).into();
+        value["source"] = self.source.as_str().into();
+        value["expires"] = self.expires.map_or_else(|| "forever".to_string(), format_date).into();
+        value["reason"] = self.reason.as_str().into();
+        value
+    }
+}
+
+/// The entries of a list file, which is read again when it changes.
+#[derive(Debug)]
+pub struct List<T> {
+    pub entries: Vec<T>,
+    path: String,
+    /// When the file was last read or written.
+    modified: Option<SystemTime>,
+}
+
+impl<T: Entry> List<T> {
+    pub fn load(path: String) -> Result<Self, String> {
+        let mut list = Self { entries: Vec::new(), path, modified: None };
+        list.refresh()?;
+        Ok(list)
+    }
+
+    fn modified(&self) -> Option<SystemTime> {
+        fs::metadata(&self.path).and_then(|m| m.modified()).ok()
+    }
+
+    /// Reads the file again if it changed since. Returns whether it did.
+    pub fn refresh(&mut self) -> Result<bool, String> {
+        let modified = self.modified();
+        if modified == self.modified {
+            return Ok(false);
+        }
+
+        self.modified = modified;
+        if !Path::new(&self.path).exists() {
+            self.entries.clear();
+            return Ok(true);
+        }
+
+        let text = fs::read_to_string(&self.path).map_err(|e| format!("can't read {}: {}", self.path, e))?;
+        let root = json::parse(&text).map_err(|e| format!("{} isn't valid JSON: {}", self.path, e))?;
+        self.entries = root.members()
+            .map(|e| T::from_json(e).ok_or_else(|| format!("{} has a broken entry: {}", self.path, e.dump())))
+            .collect::<Result<_, _>>()?;
+        Ok(true)
+    }
+
+    pub fn save(&mut self) -> Result<(), String> {
+        let mut root = json::JsonValue::new_array();
+        for entry in &self.entries {
+            root.push(entry.to_json()).unwrap();
+        }
+
+        fs::write(&self.path, root.pretty(2)).map_err(|e| format!("can't write {}: {}", self.path, e))?;
+        self.modified = self.modified();
+        Ok(())
+    }
+
+    /// Names and addresses are matched ignoring case.
+    pub fn get(&self, key: &str) -> Option<&T> {
+        self.entries.iter().find(|e| e.key().eq_ignore_ascii_case(key))
+    }
+
+    /// Adds an entry, replacing the one about the same player or address.
+    pub fn add(&mut self, entry: T) {
+        self.remove(entry.key().to_string().as_str());
+        self.entries.push(entry);
+    }
+
+    /// Returns whether there was an entry to remove.
+    pub fn remove(&mut self, key: &str) -> bool {
+        let count = self.entries.len();
+        self.entries.retain(|e| !e.key().eq_ignore_ascii_case(key));
+        self.entries.len() != count
+    }
+}
+
+impl List<Ban> {
+    /// The ban of a player or address, unless it's over.
+    pub fn active(&self, key: &str) -> Option<&Ban> {
+        self.get(key).filter(|b| !b.is_over())
+    }
+}
+
+pub struct Access {
+    /// Whether only whitelisted players and operators may join.
+    pub whitelist_on: bool,
+    pub whitelist: List<Whitelisted>,
+    pub banned_players: List<Ban>,
+    pub banned_ips: List<Ban>,
+}
+
+impl Access {
+    pub fn load(config: &AccessConfig) -> Result<Self, String> {
+        Ok(Self {
+            whitelist_on: config.whitelist,
+            whitelist: List::load(config.whitelist_file.clone())?,
+            banned_players: List::load(config.banned_players_file.clone())?,
+            banned_ips: List::load(config.banned_ips_file.clone())?,
+        })
+    }
+
+    /// Reads the files that changed again. A broken file keeps the entries
+    /// it had. Returns whether any changed.
+    pub fn refresh(&mut self) -> bool {
+        let results = [self.whitelist.refresh(), self.banned_players.refresh(), self.banned_ips.refresh()];
+        let mut changed = false;
+        for result in results {
+            match result {
+                Ok(c) => changed |= c,
+                Err(e) => {
+                    error!("{}", e);
+                },
+            }
+        }
+
+        if changed {
+            info!("reloaded the whitelist and bans");
+        }
+        changed
+    }
+
+    /// Reads every file again, changed or not.
+    pub fn reload(&mut self) -> Result<(), String> {
+        self.whitelist.modified = None;
+        self.banned_players.modified = None;
+        self.banned_ips.modified = None;
+        self.whitelist.refresh()?;
+        self.banned_players.refresh()?;
+        self.banned_ips.refresh()?;
+        Ok(())
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
+
+/// Reads how long something lasts, like `30m`, `12h` or `7d`, in seconds.
+pub fn parse_duration(text: &str) -> Option<u64> {
+    let unit = match text.chars().last()? {
+        's' => 1,
+        'm' => 60,
+        'h' => 60 * 60,
+        'd' => 24 * 60 * 60,
+        'w' => 7 * 24 * 60 * 60,
+        _ => return None,
+    };
+
+    let count: u64 = text[..text.len() - 1].parse().ok()?;
+    count.checked_mul(unit).filter(|d| *d > 0)
+}
+
+/// Writes a time the way the vanilla list files do, like
+/// `2022-03-01 12:30:00 +0000`.
+pub fn format_date(time: u64) -> String {
+    let (days, seconds) = (time / 86400, time % 86400);
+
+    // the proleptic Gregorian calendar, in cycles of 400 years starting in March
+    let days = days as i64 + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+    let year = year_of_era + era * 400 + (month <= 2) as i64;
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02} +0000",
+        year, month, day, seconds / 3600, seconds / 60 % 60, seconds % 60,
+    )
+}
+
+/// Reads a time written like [`format_date`] does, in any time zone.
+pub fn parse_date(text: &str) -> Option<u64> {
+    let mut parts = text.split(' ');
+    let date: Vec<i64> = parts.next()?.split('-').map(|p| p.parse().ok()).collect::<Option<_>>()?;
+    let time: Vec<i64> = parts.next()?.split(':').map(|p| p.parse().ok()).collect::<Option<_>>()?;
+    let zone = parts.next().unwrap_or("+0000");
+    let (&[year, month, day], &[hour, minute, second]) = (&date[..], &time[..]) else { return None };
+    if !(1..=12).contains(&month) || zone.len() != 5 || !zone.is_ascii() {
+        return None;
+    }
+
+    let offset: i64 = zone[1..3].parse::<i64>().ok()? * 3600 + zone[3..].parse::<i64>().ok()? * 60;
+    let offset = if zone.starts_with('-') { -offset } else { offset };
+
+    let year = year - (month <= 2) as i64;
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let shifted_month = if month > 2 { month - 3 } else { month + 9 };
+    let day_of_year = (153 * shifted_month + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146097 + day_of_era - 719468;
+
+    u64::try_from(days * 86400 + hour * 3600 + minute * 60 + second - offset).ok()
+}
+
+/// What a banned player is told.
+fn ban_message(ban: &Ban, ip: bool) -> Component {
+    let mut message = match ip {
+        true => format!("Your IP address is banned from this server.\nReason: {}", ban.reason),
+        false => format!("You are banned from this server.\nReason: {}", ban.reason),
+    };
+
+    if let Some(expires) = ban.expires {
+        message += &format!("\nYour ban will be removed on {}", format_date(expires));
+    }
+    Component::text(message)
+}
+
+/// Refuses players who may not join, and kicks the ones who may not stay
+/// when the lists change.
+pub struct AccessPlugin;
+
+impl Plugin for AccessPlugin {
+    fn name(&self) -> &str {
+        "access"
+    }
+
+    fn enable(&self, registry: &mut Registry) {
+        registry.listen(Kind::Join, Priority::Early, |server, event| {
+            let Event::Join { client, name } = event else { return Outcome::Continue };
+            if server.access.refresh() {
+                server.enforce_access();
+            }
+
+            let ip = server.clients[*client].ip();
+            if let Some(reason) = server.refusal(name, &ip) {
+                info!("refused {} from {}: {}", name, ip, reason.plain());
+                return Outcome::Cancel(Some(reason));
+            }
+
+            let bypasses = server.ops.get(name).is_some_and(|o| o.bypasses_player_limit);
+            if server.online() >= server.config.connection.max_players && !bypasses {
+                info!("refused {}, the server is full", name);
+                return Outcome::Cancel(Some(Component::text("The server is full!")));
+            }
+
+            Outcome::Continue
+        });
+    }
+}
+
+impl Server {
+    /// Why a player may not join from `ip`, if they may not.
+    pub fn refusal(&self, name: &str, ip: &str) -> Option<Component> {
+        if let Some(ban) = self.access.banned_players.active(name) {
+            return Some(ban_message(ban, false));
+        }
+
+        if let Some(ban) = self.access.banned_ips.active(ip) {
+            return Some(ban_message(ban, true));
+        }
+
+        if self.access.whitelist_on && self.access.whitelist.get(name).is_none() && self.ops.get(name).is_none() {
+            return Some(Component::text("You are not white-listed on this server!"));
+        }
+
+        None
+    }
+
+    /// Looks for changed list files now and then, kicking whoever they
+    /// don't let stay.
+    pub fn refresh_access(&mut self) {
+        if self.world.time.is_multiple_of(REFRESH_INTERVAL) && self.access.refresh() {
+            self.enforce_access();
+        }
+    }
+
+    /// Kicks the players that may not be on the server anymore.
+    pub fn enforce_access(&mut self) {
+        for id in self.clients.ids() {
+            let client = &self.clients[id];
+            let Some(player) = client.player.as_ref().filter(|_| !client.closed) else { continue };
+            if let Some(reason) = self.refusal(&player.name, &client.ip()) {
+                self.disconnect(id, &reason);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_date_writes_vanilla_dates() {
+        assert_eq!(format_date(0), "1970-01-01 00:00:00 +0000");
+        assert_eq!(format_date(951782400), "2000-02-29 00:00:00 +0000");
+        assert_eq!(format_date(1646137800), "2022-03-01 12:30:00 +0000");
+    }
+
+    #[test]
+    fn parse_date_reads_what_format_date_writes() {
+        for time in [0, 951782400, 1646137800, 4102444799] {
+            assert_eq!(parse_date(&format_date(time)), Some(time));
+        }
+    }
+
+    #[test]
+    fn parse_date_applies_time_zones() {
+        assert_eq!(parse_date("2022-03-01 14:30:00 +0200"), Some(1646137800));
+        assert_eq!(parse_date("2022-03-01 07:00:00 -0530"), Some(1646137800));
+        assert_eq!(parse_date("2022-03-01 12:30:00"), Some(1646137800));
+    }
+
+    #[test]
+    fn parse_date_refuses_broken_dates() {
+        assert_eq!(parse_date("forever"), None);
+        assert_eq!(parse_date("2022-13-01 00:00:00 +0000"), None);
+        assert_eq!(parse_date("2022-03-01 00:00 +0000"), None);
+        assert_eq!(parse_date("2022-03-01 00:00:00 +00"), None);
+        assert_eq!(parse_date("2022-03-01 00:00:00 +0é0"), None);
+        assert_eq!(parse_date("1969-12-31 23:59:59 +0000"), None);
+    }
+}
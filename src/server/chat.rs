@@ -0,0 +1,90 @@
+//! Chat components, the formatted text shown in chat and the action bar.
+
+#[derive(Debug, Clone, Default)]
+pub struct Component {
+    pub text: String,
+    /// Translation key, filled in with `with`, used instead of `text`.
+    pub translate: Option<String>,
+    pub with: Vec<Component>,
+    pub color: Option<&'static str>,
+    pub bold: bool,
+    /// Shown when hovering over the text.
+    pub hover: Option<Box<Component>>,
+    pub extra: Vec<Component>,
+}
+
+impl Component {
+    pub fn text(text: impl Into<String>) -> Self {
+        Self { text: text.into(), ..Self::default() }
+    }
+
+    pub fn translate(key: impl Into<String>, with: Vec<Component>) -> Self {
+        Self { translate: Some(key.into()), with, ..Self::default() }
+    }
+
+    pub fn color(mut self, color: &'static str) -> Self {
+        self.color = Some(color);
+        self
+    }
+
+    pub fn bold(mut self) -> Self {
+        self.bold = true;
+        self
+    }
+
+    pub fn hover(mut self, hover: Component) -> Self {
+        self.hover = Some(Box::new(hover));
+        self
+    }
+
+    /// Appends a component that inherits the style of this one.
+    pub fn push(&mut self, extra: Component) {
+        self.extra.push(extra);
+    }
+
+    pub fn to_json(&self) -> json::JsonValue {
+        let mut json = json::object! {};
+        match &self.translate {
+            Some(key) => {
+                json["translate"] = key.as_str().into();
+                json["with"] = self.with.iter().map(|c| c.to_json()).collect::<Vec<_>>().into();
+            },
+            None => json["text"] = self.text.as_str().into(),
+        }
+
+        if let Some(color) = self.color {
+            json["color"] = color.into();
+        }
+
+        if self.bold {
+            json["bold"] = true.into();
+        }
+
+        if let Some(hover) = &self.hover {
+            json["hoverEvent"] = json::object! { action: "show_text", contents: hover.to_json() };
+        }
+
+        if !self.extra.is_empty() {
+            json["extra"] = self.extra.iter().map(|c| c.to_json()).collect::<Vec<_>>().into();
+        }
+
+        json
+    }
+
+    /// The text without any formatting, for logs and files.
+    pub fn plain(&self) -> String {
+        let mut text = match &self.translate {
+            Some(key) => {
+                let with: Vec<_> = self.with.iter().map(|c| c.plain()).collect();
+                format!("{} {}", key, with.join(" "))
+            },
+            None => self.text.clone(),
+        };
+
+        for extra in &self.extra {
+            text += &extra.plain();
+        }
+
+        text
+    }
+}
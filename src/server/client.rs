@@ -1,39 +1,298 @@
-use std::net::TcpStream;
-use std::sync::mpsc;
-use super::packet::{self, PacketReader};
-
-pub struct Client {
-    pub tcp: TcpStream,
-    pub state: State,
-    pub packets: mpsc::Sender<Box<dyn packet::ServerPacket>>
-}
-
-impl Client {
-    pub fn listen(&mut self) {
-        loop {
-            let packet = self.tcp.read_packet(&mut self.state);
-
-            if let Ok(packet) = packet {
-                info!("client sent packet {:?} with state {:?}", packet, self.state);
-                self.packets.send(packet).unwrap();
-            } else {
-                info!("client is dead");
-                return;
-            }
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::net::IpAddr;
+use std::ops::{Index, IndexMut};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use super::{Server, ServerClient};
+use super::player::Player;
+use super::plugin::Event;
+use super::redstone;
+
+/// Names a connection. Ids aren't reused, so one kept after its client left
+/// names nobody rather than whoever connected next.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ClientId(pub u64);
+
+impl fmt::Display for ClientId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "#{}", self.0)
+    }
+}
+
+/// Runs commands from RCON. It has no client and may do anything.
+pub const CONSOLE: ClientId = ClientId(u64::MAX);
+
+/// The open connections, in the order they connected.
+#[derive(Default)]
+pub struct Clients {
+    clients: BTreeMap<ClientId, ServerClient>,
+}
+
+impl Clients {
+    pub fn insert(&mut self, id: ClientId, client: ServerClient) {
+        self.clients.insert(id, client);
+    }
+
+    pub fn remove(&mut self, id: ClientId) -> Option<ServerClient> {
+        self.clients.remove(&id)
+    }
+
+    pub fn get(&self, id: ClientId) -> Option<&ServerClient> {
+        self.clients.get(&id)
+    }
+
+    pub fn get_mut(&mut self, id: ClientId) -> Option<&mut ServerClient> {
+        self.clients.get_mut(&id)
+    }
+
+    /// The player of a client, none for the console and clients that
+    /// aren't playing.
+    pub fn player(&self, id: ClientId) -> Option<&Player> {
+        self.clients.get(&id).and_then(|c| c.player.as_ref())
+    }
+
+    pub fn player_mut(&mut self, id: ClientId) -> Option<&mut Player> {
+        self.clients.get_mut(&id).and_then(|c| c.player.as_mut())
+    }
+
+    /// The ids of the clients, to go through them while changing the server.
+    pub fn ids(&self) -> Vec<ClientId> {
+        self.clients.keys().copied().collect()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (ClientId, &ServerClient)> {
+        self.clients.iter().map(|(id, c)| (*id, c))
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (ClientId, &mut ServerClient)> {
+        self.clients.iter_mut().map(|(id, c)| (*id, c))
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &ServerClient> {
+        self.clients.values()
+    }
+
+    pub fn values_mut(&mut self) -> impl Iterator<Item = &mut ServerClient> {
+        self.clients.values_mut()
+    }
+}
+
+impl Index<ClientId> for Clients {
+    type Output = ServerClient;
+
+    fn index(&self, id: ClientId) -> &ServerClient {
+        self.clients.get(&id).unwrap_or_else(|| panic!("no client {}", id))
+    }
+}
+
+impl IndexMut<ClientId> for Clients {
+    fn index_mut(&mut self, id: ClientId) -> &mut ServerClient {
+        self.clients.get_mut(&id).unwrap_or_else(|| panic!("no client {}", id))
+    }
+}
+
+/// What happens to a connection over its life.
+#[derive(Debug, Clone, Copy)]
+pub enum Lifecycle {
+    Connected,
+    StateChanged(State),
+    LoggedIn,
+    /// The client is still there for the hooks, and gone after them.
+    Disconnected,
+}
+
+/// What subsystems do when something happens to a connection, in order.
+const HOOKS: &[fn(&mut Server, ClientId, Lifecycle)] = &[
+    log_lifecycle,
+    send_op_level,
+    fire_quit,
+    remove_player,
+];
+
+fn log_lifecycle(server: &mut Server, id: ClientId, event: Lifecycle) {
+    let client = &server.clients[id];
+    match (event, &client.player) {
+        (Lifecycle::Connected, _) => { info!("client {} connected from {}", id, client.ip()); },
+        (Lifecycle::StateChanged(state), _) => { info!("client {} is in state {:?}", id, state); },
+        (Lifecycle::LoggedIn, Some(player)) => { info!("{} logged in as client {}", player.name, id); },
+        (Lifecycle::Disconnected, Some(player)) => { info!("{} left", player.name); },
+        (Lifecycle::Disconnected, None) => { info!("client {} disconnected", id); },
+        _ => {},
+    }
+}
+
+fn send_op_level(server: &mut Server, id: ClientId, event: Lifecycle) {
+    if let Lifecycle::LoggedIn = event {
+        server.send_op_level(id);
+    }
+}
+
+fn fire_quit(server: &mut Server, id: ClientId, event: Lifecycle) {
+    if let (Lifecycle::Disconnected, Some(player)) = (event, &server.clients[id].player) {
+        let quit = Event::Quit { client: id, name: player.name.clone() };
+        server.fire(&quit);
+    }
+}
+
+/// Takes a player that left out of the world, so pressure plates and
+/// tripwires stop feeling them.
+fn remove_player(server: &mut Server, id: ClientId, event: Lifecycle) {
+    if let (Lifecycle::Disconnected, Some(player)) = (event, &server.clients[id].player) {
+        redstone::remove_entity(&mut server.world, player.entity_id);
+    }
+}
+
+impl Server {
+    /// Lets the subsystems react to something that happened to a client.
+    pub fn lifecycle(&mut self, id: ClientId, event: Lifecycle) {
+        if self.clients.get(id).is_none() {
+            return;
+        }
+
+        for hook in HOOKS {
+            hook(self, id, event);
         }
     }
+}
+
+/// When addresses last logged in, to make each wait between logins.
+#[derive(Default)]
+pub struct Throttle {
+    last: HashMap<IpAddr, Instant>,
+}
 
-    /* pub fn handle_packet(&mut self, packet: packet::GenericPacket) {
-        match (self.state, packet.typ) {
-            _ => todo!("{:?} {packet:?}", self.state),
+impl Throttle {
+    /// Whether an address may log in now, counting the login if so. Refused
+    /// logins don't count, so retrying doesn't make the wait longer.
+    pub fn allow(&mut self, ip: IpAddr, wait: Duration) -> bool {
+        let now = Instant::now();
+        self.last.retain(|_, t| now.duration_since(*t) < wait);
+        if ip.is_loopback() {
+            return true;
         }
-    } */
+        if self.last.contains_key(&ip) {
+            return false;
+        }
+
+        self.last.insert(ip, now);
+        true
+    }
+}
+
+/// Connections that haven't logged in yet, in all and by address.
+#[derive(Default)]
+pub struct PendingCount {
+    total: usize,
+    by_ip: HashMap<IpAddr, usize>,
+}
+
+/// Counts a connection as not logged in yet for as long as it's kept.
+pub struct Pending {
+    count: Arc<Mutex<PendingCount>>,
+    ip: IpAddr,
+    /// When the connection must have logged in by.
+    pub deadline: Instant,
 }
 
-#[derive(Debug)]
+impl Pending {
+    /// Counts a connection from `ip`, unless `max` connections are pending
+    /// already, or `max_per_ip` from that address. Local addresses, which
+    /// proxies connect from, only count towards `max`.
+    pub fn new(count: &Arc<Mutex<PendingCount>>, ip: IpAddr, max: usize, max_per_ip: usize, deadline: Instant) -> Result<Self, &'static str> {
+        let mut counts = count.lock().unwrap();
+        if counts.total >= max {
+            return Err("too many are logging in");
+        }
+
+        let from_ip = counts.by_ip.entry(ip).or_default();
+        if *from_ip >= max_per_ip && !ip.is_loopback() {
+            return Err("too many are logging in from there");
+        }
+
+        *from_ip += 1;
+        counts.total += 1;
+        Ok(Self { count: Arc::clone(count), ip, deadline })
+    }
+}
+
+impl Drop for Pending {
+    fn drop(&mut self) {
+        let mut counts = self.count.lock().unwrap();
+        counts.total -= 1;
+        if let Some(from_ip) = counts.by_ip.get_mut(&self.ip) {
+            *from_ip -= 1;
+            if *from_ip == 0 {
+                counts.by_ip.remove(&self.ip);
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum State {
     Handshake,
     Status,
     Login,
     Play,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WAIT: Duration = Duration::from_secs(60);
+
+    #[test]
+    fn throttle_makes_addresses_wait() {
+        let mut throttle = Throttle::default();
+        let ip: IpAddr = "203.0.113.7".parse().unwrap();
+        assert!(throttle.allow(ip, WAIT));
+        assert!(!throttle.allow(ip, WAIT));
+        assert!(throttle.allow("203.0.113.8".parse().unwrap(), WAIT));
+    }
+
+    #[test]
+    fn refused_logins_dont_extend_the_wait() {
+        let mut throttle = Throttle::default();
+        let ip: IpAddr = "203.0.113.7".parse().unwrap();
+        throttle.allow(ip, WAIT);
+        let first = throttle.last[&ip];
+        throttle.allow(ip, WAIT);
+        assert_eq!(throttle.last[&ip], first);
+    }
+
+    #[test]
+    fn throttle_lets_local_addresses_and_no_wait_through() {
+        let mut throttle = Throttle::default();
+        let local: IpAddr = "127.0.0.1".parse().unwrap();
+        assert!(throttle.allow(local, WAIT) && throttle.allow(local, WAIT));
+        let ip: IpAddr = "203.0.113.7".parse().unwrap();
+        assert!(throttle.allow(ip, Duration::ZERO) && throttle.allow(ip, Duration::ZERO));
+    }
+
+    fn pending(count: &Arc<Mutex<PendingCount>>, ip: &str) -> Result<Pending, &'static str> {
+        Pending::new(count, ip.parse().unwrap(), 3, 2, Instant::now())
+    }
+
+    #[test]
+    fn pending_connections_are_limited_per_address() {
+        let count = Arc::default();
+        let first = pending(&count, "203.0.113.7").unwrap();
+        let _second = pending(&count, "203.0.113.7").unwrap();
+        assert!(pending(&count, "203.0.113.7").is_err());
+        let _other = pending(&count, "203.0.113.8").unwrap();
+
+        drop(first);
+        assert!(pending(&count, "203.0.113.7").is_ok());
+    }
+
+    #[test]
+    fn pending_connections_are_limited_in_all() {
+        let count = Arc::default();
+        let _local: Vec<_> = (0..3).map(|_| pending(&count, "127.0.0.1").unwrap()).collect();
+        assert!(pending(&count, "127.0.0.1").is_err());
+        assert!(pending(&count, "203.0.113.7").is_err());
+        assert_eq!(count.lock().unwrap().by_ip.len(), 1);
+    }
+}
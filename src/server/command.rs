@@ -0,0 +1,1106 @@
+//! Commands players run from chat.
+
+use std::rc::Rc;
+use super::Server;
+use super::client::{ClientId, Clients, CONSOLE};
+use super::access::{self, Ban, Whitelisted};
+use super::chat::Component;
+use super::debug::Output;
+use super::edit::{self, Clipboard, Session, MAX_VOLUME};
+use super::permission;
+use super::redstone::compiled::{self, Region};
+use super::redstone::debug::{Profile, Trace};
+use super::plot::{self, Claim, PlotId};
+use super::plugin::{CommandFn, Event};
+use super::schematic::{self, Format, Schematic};
+use super::world::BlockPos;
+use super::world::block::{Block, Direction};
+
+/// A message for the player who ran a command, or what went wrong.
+pub type CommandResult = Result<Component, String>;
+
+/// Longest a trace or profile runs, five minutes.
+const MAX_DEBUG_TICKS: u64 = 6000;
+/// Op level that may take over the traces and profiles of others.
+const DEBUG_OTHERS_LEVEL: u8 = 2;
+
+pub struct Command {
+    pub name: &'static str,
+    pub usage: &'static str,
+    pub description: &'static str,
+    /// Permission node that lets players run it.
+    pub permission: &'static str,
+    /// Op level that may run it without the node, 0 for everyone.
+    pub level: u8,
+    pub run: fn(&mut Server, ClientId, &[&str]) -> CommandResult,
+}
+
+pub const COMMANDS: &[Command] = &[
+    Command {
+        name: "help",
+        usage: "/help",
+        description: "Lists the commands",
+        permission: "command.help",
+        level: 0,
+        run: help,
+    },
+    Command {
+        name: "redstone",
+        usage: "/redstone compile <x1> <y1> <z1> <x2> <y2> <z2> | accurate [all] | list",
+        description: "Switches regions between compiled and block-accurate redstone",
+        permission: "command.redstone",
+        level: 2,
+        run: redstone,
+    },
+    Command {
+        name: "debug",
+        usage: "/debug power | trace <x1> <y1> <z1> <x2> <y2> <z2> <ticks> [file] | profile <ticks> [file]",
+        description: "Shows power levels, traces the updates in a region or profiles the busiest blocks",
+        permission: "command.debug",
+        level: 2,
+        run: debug,
+    },
+    Command {
+        name: "/wand",
+        usage: "//wand",
+        description: "Makes the held item a wand, breaking a block with it selects the first corner and using one the second",
+        permission: "worldedit.wand",
+        level: 2,
+        run: wand,
+    },
+    Command {
+        name: "/pos1",
+        usage: "//pos1 [<x> <y> <z>]",
+        description: "Selects the first corner, where you stand by default",
+        permission: "worldedit.pos1",
+        level: 2,
+        run: pos1,
+    },
+    Command {
+        name: "/pos2",
+        usage: "//pos2 [<x> <y> <z>]",
+        description: "Selects the second corner, where you stand by default",
+        permission: "worldedit.pos2",
+        level: 2,
+        run: pos2,
+    },
+    Command {
+        name: "/set",
+        usage: "//set <block>",
+        description: "Fills the selection with a block",
+        permission: "worldedit.set",
+        level: 2,
+        run: set,
+    },
+    Command {
+        name: "/replace",
+        usage: "//replace <from> <to>",
+        description: "Replaces one kind of block in the selection with another",
+        permission: "worldedit.replace",
+        level: 2,
+        run: replace,
+    },
+    Command {
+        name: "/copy",
+        usage: "//copy",
+        description: "Copies the selection, relative to where you stand",
+        permission: "worldedit.copy",
+        level: 2,
+        run: copy,
+    },
+    Command {
+        name: "/paste",
+        usage: "//paste [-a]",
+        description: "Pastes the clipboard relative to where you stand, without its air with -a",
+        permission: "worldedit.paste",
+        level: 2,
+        run: paste,
+    },
+    Command {
+        name: "/move",
+        usage: "//move [<count>] [<direction>]",
+        description: "Moves the selection and its blocks, the way you look by default",
+        permission: "worldedit.move",
+        level: 2,
+        run: move_selection,
+    },
+    Command {
+        name: "/stack",
+        usage: "//stack [<count>] [<direction>]",
+        description: "Repeats the selection next to itself, the way you look by default",
+        permission: "worldedit.stack",
+        level: 2,
+        run: stack,
+    },
+    Command {
+        name: "/rotate",
+        usage: "//rotate <degrees>",
+        description: "Turns the clipboard clockwise around you by a multiple of 90 degrees",
+        permission: "worldedit.rotate",
+        level: 2,
+        run: rotate,
+    },
+    Command {
+        name: "/flip",
+        usage: "//flip [<direction>]",
+        description: "Mirrors the clipboard through you, the way you look by default",
+        permission: "worldedit.flip",
+        level: 2,
+        run: flip,
+    },
+    Command {
+        name: "/undo",
+        usage: "//undo [<count>]",
+        description: "Undoes your last edits",
+        permission: "worldedit.undo",
+        level: 2,
+        run: undo,
+    },
+    Command {
+        name: "/redo",
+        usage: "//redo [<count>]",
+        description: "Redoes the edits you undid last",
+        permission: "worldedit.redo",
+        level: 2,
+        run: redo,
+    },
+    Command {
+        name: "/schem",
+        usage: "//schem load <name> | save <name>[.litematic] [v2] | list",
+        description: "Loads the clipboard from a .schem, .schematic or .litematic file in schematics/ or saves it to one",
+        permission: "worldedit.schem",
+        level: 2,
+        run: schem,
+    },
+    Command {
+        name: "plot",
+        usage: "/plot claim | clear | reset | trust <player> | deny <player> | remove <player> | info",
+        description: "Claims the plot you stand in, clears or resets it, or lets others build in or enter it",
+        permission: "command.plot",
+        level: 0,
+        run: plot,
+    },
+    Command {
+        name: "save",
+        usage: "/save",
+        description: "Saves the world",
+        permission: "command.save",
+        level: 4,
+        run: save,
+    },
+    Command {
+        name: "op",
+        usage: "/op <player> [<level>]",
+        description: "Makes a player an operator, of level 4 by default",
+        permission: "command.op",
+        level: 3,
+        run: op,
+    },
+    Command {
+        name: "deop",
+        usage: "/deop <player>",
+        description: "Takes a player's operator status away",
+        permission: "command.deop",
+        level: 3,
+        run: deop,
+    },
+    Command {
+        name: "whitelist",
+        usage: "/whitelist on | off | add <player> | remove <player> | list | reload",
+        description: "Lets only whitelisted players and operators join, or changes who is whitelisted",
+        permission: "command.whitelist",
+        level: 3,
+        run: whitelist,
+    },
+    Command {
+        name: "ban",
+        usage: "/ban <player> [<duration>] [<reason>]",
+        description: "Bans a player for a duration like 30m, 12h or 7d, or forever",
+        permission: "command.ban",
+        level: 3,
+        run: ban,
+    },
+    Command {
+        name: "ban-ip",
+        usage: "/ban-ip <address> | <player> [<duration>] [<reason>]",
+        description: "Bans an IP address, or the one a player is on",
+        permission: "command.ban-ip",
+        level: 3,
+        run: ban_ip,
+    },
+    Command {
+        name: "pardon",
+        usage: "/pardon <player> | <address>",
+        description: "Lifts the ban of a player or IP address",
+        permission: "command.pardon",
+        level: 3,
+        run: pardon,
+    },
+    Command {
+        name: "say",
+        usage: "/say <message>",
+        description: "Announces a message to everyone",
+        permission: "command.say",
+        level: 2,
+        run: say,
+    },
+    Command {
+        name: "kick",
+        usage: "/kick <player> [<reason>]",
+        description: "Disconnects a player",
+        permission: "command.kick",
+        level: 3,
+        run: kick,
+    },
+];
+
+/// Runs a line a player typed, without the leading slash.
+pub fn run(server: &mut Server, client_id: ClientId, line: &str) {
+    if let Some(message) = dispatch(server, client_id, line) {
+        server.send_message(client_id, &message);
+    }
+}
+
+/// Runs a line for the console, returning everything it was told as text.
+/// Like the lines of players, it goes through the command listeners.
+pub fn run_console(server: &mut Server, line: &str) -> String {
+    let line = line.strip_prefix('/').unwrap_or(line);
+    let previous = server.console.replace(Vec::new());
+    server.allowed(&Event::Command { client: CONSOLE, line: line.to_string() });
+    let output = std::mem::replace(&mut server.console, previous).unwrap_or_default();
+    output.join("\n")
+}
+
+/// Runs a command, returning the message for whoever ran it. Blank lines
+/// run nothing.
+fn dispatch(server: &mut Server, client_id: ClientId, line: &str) -> Option<Component> {
+    let mut words = line.split_whitespace();
+    let name = words.next()?;
+    let args: Vec<_> = words.collect();
+
+    let command: Option<(String, u8, CommandFn)> = match COMMANDS.iter().find(|c| c.name == name) {
+        Some(c) => Some((c.permission.to_string(), c.level, Rc::new(c.run))),
+        None => server.bus.command(name).map(|c| (c.permission.clone(), c.level, Rc::clone(&c.run))),
+    };
+
+    let message = match command {
+        Some((permission, level, _)) if !server.has_permission(client_id, &permission, level) => {
+            Component::text(format!("You don't have permission to run /{}", name)).color("red")
+        },
+        Some((_, _, run)) => match run(server, client_id, &args) {
+            Ok(message) => message,
+            Err(e) => Component::text(e).color("red"),
+        },
+        None => Component::text(format!("Unknown command /{}, see /help", name)).color("red"),
+    };
+
+    Some(message)
+}
+
+fn usage(name: &str) -> String {
+    let command = COMMANDS.iter().find(|c| c.name == name).expect("known command");
+    format!("Usage: {}", command.usage)
+}
+
+/// The block the player running a command stands in.
+fn player_pos(server: &Server, client_id: ClientId) -> BlockPos {
+    match server.clients.player(client_id) {
+        Some(p) => BlockPos::new(p.x.floor() as i32, p.y.floor() as i32, p.z.floor() as i32),
+        None => BlockPos::new(0, 0, 0),
+    }
+}
+
+/// Reads a coordinate, which is relative to `base` if it starts with `~`.
+fn parse_coordinate(arg: &str, base: i32) -> Result<i32, String> {
+    let (offset, relative) = match arg.strip_prefix('~') {
+        Some("") => return Ok(base),
+        Some(offset) => (offset, true),
+        None => (arg, false),
+    };
+
+    let value: i32 = offset.parse().map_err(|_| format!("{} is not a coordinate", arg))?;
+    match relative {
+        true => base.checked_add(value).ok_or_else(|| format!("{} is too far away", arg)),
+        false => Ok(value),
+    }
+}
+
+/// Reads a position from three coordinates, relative to the player.
+pub fn parse_pos(server: &Server, client_id: ClientId, args: &[&str]) -> Result<BlockPos, String> {
+    let base = player_pos(server, client_id);
+    Ok(BlockPos::new(
+        parse_coordinate(args[0], base.x)?,
+        parse_coordinate(args[1], base.y)?,
+        parse_coordinate(args[2], base.z)?,
+    ))
+}
+
+fn help(server: &mut Server, client_id: ClientId, _args: &[&str]) -> CommandResult {
+    let builtin = COMMANDS.iter().map(|c| (c.usage, c.description, c.permission, c.level));
+    let plugins = server.bus.commands().map(|c| (c.usage.as_str(), c.description.as_str(), c.permission.as_str(), c.level));
+    let lines: Vec<_> = builtin.chain(plugins)
+        .filter(|(_, _, permission, level)| server.has_permission(client_id, permission, *level))
+        .map(|(usage, description, _, _)| format!("{}: {}", usage, description)).collect();
+    Ok(Component::text(lines.join("\n")))
+}
+
+fn redstone(server: &mut Server, client_id: ClientId, args: &[&str]) -> CommandResult {
+    match args {
+        ["compile", corners @ ..] if corners.len() == 6 => {
+            let a = parse_pos(server, client_id, &corners[..3])?;
+            let b = parse_pos(server, client_id, &corners[3..])?;
+            let region = Region::new(a, b);
+            if let Some(layout) = server.world.plots {
+                let plot = layout.plot_at(region.min);
+                if plot.is_none() || plot != layout.plot_at(region.max) {
+                    return Err("Regions can only be compiled within one plot".to_string());
+                }
+            }
+            if let Some(denial) = server.build_denial(client_id, region.min) {
+                return Err(denial);
+            }
+
+            let stats = compiled::compile(&mut server.world, region)?;
+            info!("compiled {} into {:?}", region, stats);
+            Ok(Component::text(format!(
+                "Compiled {}: {} nodes, {} of them constant, {} dust and {} links",
+                region, stats.nodes, stats.constant, stats.wires, stats.links,
+            )))
+        },
+        ["accurate"] => {
+            let pos = player_pos(server, client_id);
+            let i = compiled::find(&server.world, pos).ok_or("You aren't in a compiled region")?;
+            if let Some(denial) = server.build_denial(client_id, server.world.circuits[i].region.min) {
+                return Err(denial);
+            }
+
+            let region = compiled::decompile(&mut server.world, i);
+            Ok(Component::text(format!("{} is back to block-accurate redstone", region)))
+        },
+        ["accurate", "all"] => {
+            if !server.has_permission(client_id, plot::BYPASS, 3) {
+                return Err("Only operators can decompile every region".to_string());
+            }
+
+            let count = server.world.circuits.len();
+            while !server.world.circuits.is_empty() {
+                compiled::decompile(&mut server.world, 0);
+            }
+            Ok(Component::text(format!("{} regions are back to block-accurate redstone", count)))
+        },
+        ["list"] if server.world.circuits.is_empty() => Ok(Component::text("No region is compiled")),
+        ["list"] => {
+            let lines: Vec<_> = server.world.circuits.iter().map(|c| format!("Compiled: {}", c.region)).collect();
+            Ok(Component::text(lines.join("\n")))
+        },
+        _ => Err(usage("redstone")),
+    }
+}
+
+/// Reads a number of ticks to run a trace or profile for.
+fn parse_ticks(arg: &str) -> Result<u64, String> {
+    match arg.parse() {
+        Ok(ticks) if ticks > 0 && ticks <= MAX_DEBUG_TICKS => Ok(ticks),
+        _ => Err(format!("{} is not a number of ticks from 1 to {}", arg, MAX_DEBUG_TICKS)),
+    }
+}
+
+/// Whether a player may start a trace or profile while the one `running`
+/// holds it. Only whoever started it and ops may replace it.
+fn may_replace(server: &Server, client_id: ClientId, running: Option<ClientId>) -> bool {
+    running.is_none_or(|owner| owner == client_id) || server.op_level(client_id) >= DEBUG_OTHERS_LEVEL
+}
+
+fn debug(server: &mut Server, client_id: ClientId, args: &[&str]) -> CommandResult {
+    match args {
+        ["power"] => {
+            let Some(player) = server.clients.player_mut(client_id) else { return Err("You aren't playing".to_string()) };
+            player.overlay = match player.overlay {
+                Some(_) => None,
+                None => Some(String::new()),
+            };
+
+            let state = if player.overlay.is_some() { "on" } else { "off" };
+            Ok(Component::text(format!("Turned the power overlay {}", state)))
+        },
+        ["trace", rest @ ..] if rest.len() == 7 || rest.len() == 8 => {
+            let a = parse_pos(server, client_id, &rest[..3])?;
+            let b = parse_pos(server, client_id, &rest[3..6])?;
+            let ticks = parse_ticks(rest[6])?;
+            let output = Output::parse(client_id, rest.get(7))?;
+            if !may_replace(server, client_id, server.trace_output.as_ref().map(|(owner, _)| *owner)) {
+                return Err("Someone else's trace is already running".to_string());
+            }
+
+            let region = Region::new(a, b);
+            if region.volume() > MAX_VOLUME {
+                return Err(format!("{} has {} blocks, at most {} can be traced", region, region.volume(), MAX_VOLUME));
+            }
+
+            let start = server.world.time;
+            let end = start.checked_add(ticks).ok_or("The trace would never end")?;
+            server.world.trace = Some(Trace { region, start, end, events: Vec::new(), dropped: 0 });
+            server.trace_output = Some((client_id, output));
+            Ok(Component::text(format!("Tracing {} for {} ticks", region, ticks)))
+        },
+        ["profile", rest @ ..] if rest.len() == 1 || rest.len() == 2 => {
+            let ticks = parse_ticks(rest[0])?;
+            let output = Output::parse(client_id, rest.get(1))?;
+            if !may_replace(server, client_id, server.profile_output.as_ref().map(|(owner, _)| *owner)) {
+                return Err("Someone else's profile is already running".to_string());
+            }
+
+            let start = server.world.time;
+            let end = start.checked_add(ticks).ok_or("The profile would never end")?;
+            server.world.profile = Some(Profile { start, end, counts: Default::default() });
+            server.profile_output = Some((client_id, output));
+            Ok(Component::text(format!("Profiling for {} ticks", ticks)))
+        },
+        _ => Err(usage("debug")),
+    }
+}
+
+/// The editing session of the player running a command.
+fn session(clients: &mut Clients, client_id: ClientId) -> Result<&mut Session, String> {
+    match clients.player_mut(client_id) {
+        Some(player) => Ok(&mut player.edit),
+        None => Err("You aren't playing".to_string()),
+    }
+}
+
+/// The selected region, if it isn't too large to edit.
+fn selection(session: &Session) -> Result<Region, String> {
+    let region = session.selection().ok_or("Select two corners first, see //wand")?;
+    if region.volume() > MAX_VOLUME {
+        return Err(format!("The selection has {} blocks, more than the {} an edit can change", region.volume(), MAX_VOLUME));
+    }
+
+    Ok(region)
+}
+
+fn changed(count: usize) -> CommandResult {
+    Ok(Component::text(format!("{} blocks changed", count)))
+}
+
+/// Reads a direction, or takes the one the player looks in.
+fn parse_direction(server: &Server, client_id: ClientId, arg: Option<&&str>) -> Result<Direction, String> {
+    let look = server.clients.player(client_id).map_or(Direction::North, |p| p.look());
+    Ok(match arg.copied() {
+        None | Some("me") => look,
+        Some("north") => Direction::North,
+        Some("south") => Direction::South,
+        Some("east") => Direction::East,
+        Some("west") => Direction::West,
+        Some("up") => Direction::Up,
+        Some("down") => Direction::Down,
+        Some(arg) => return Err(format!("{} is not a direction", arg)),
+    })
+}
+
+/// Reads the optional count and direction of //move and //stack.
+fn parse_count_direction(server: &Server, client_id: ClientId, name: &str, args: &[&str]) -> Result<(i32, Direction), String> {
+    let (count, rest) = match args.first().map(|a| a.parse::<i32>()) {
+        Some(Ok(count)) if count > 0 => (count, &args[1..]),
+        Some(Ok(_)) => return Err(format!("{} is not a positive count", args[0])),
+        _ => (1, args),
+    };
+
+    match rest {
+        [] | [_] => Ok((count, parse_direction(server, client_id, rest.first())?)),
+        _ => Err(usage(name)),
+    }
+}
+
+/// Selects a corner, telling the player how large the selection is now.
+pub fn select(server: &mut Server, client_id: ClientId, first: bool, pos: BlockPos) -> CommandResult {
+    let session = session(&mut server.clients, client_id)?;
+    let corner = if first { &mut session.pos1 } else { &mut session.pos2 };
+    *corner = Some(pos);
+
+    let mut message = format!("{} corner set to ({}, {}, {})", if first { "First" } else { "Second" }, pos.x, pos.y, pos.z);
+    if let Some(region) = session.selection() {
+        message += &format!(", {} blocks selected", region.volume());
+    }
+
+    Ok(Component::text(message).color("light_purple"))
+}
+
+fn wand(server: &mut Server, client_id: ClientId, _args: &[&str]) -> CommandResult {
+    let Some(player) = server.clients.player_mut(client_id) else { return Err("You aren't playing".to_string()) };
+    let id = player.inventory.held().ok_or("Hold the item to use as a wand")?.id;
+    player.edit.wand = Some(id);
+    Ok(Component::text("Break a block with the held item to select the first corner, use one to select the second"))
+}
+
+fn corner(server: &mut Server, client_id: ClientId, args: &[&str], first: bool) -> CommandResult {
+    let pos = match args {
+        [] => player_pos(server, client_id),
+        [_, _, _] => parse_pos(server, client_id, args)?,
+        _ => return Err(usage(if first { "/pos1" } else { "/pos2" })),
+    };
+
+    select(server, client_id, first, pos)
+}
+
+fn pos1(server: &mut Server, client_id: ClientId, args: &[&str]) -> CommandResult {
+    corner(server, client_id, args, true)
+}
+
+fn pos2(server: &mut Server, client_id: ClientId, args: &[&str]) -> CommandResult {
+    corner(server, client_id, args, false)
+}
+
+fn set(server: &mut Server, client_id: ClientId, args: &[&str]) -> CommandResult {
+    let [block] = args else { return Err(usage("/set")) };
+    let block = edit::parse_block(block)?;
+    let check = server.build_check(client_id);
+    let session = session(&mut server.clients, client_id)?;
+    let region = selection(session)?;
+
+    let blocks = edit::positions(region).filter(|p| check.allows(*p)).map(|p| (p, (block, None)));
+    let changes = edit::apply(&mut server.world, blocks);
+    let count = changes.len();
+    session.remember(changes);
+    changed(count)
+}
+
+fn replace(server: &mut Server, client_id: ClientId, args: &[&str]) -> CommandResult {
+    let [from, to] = args else { return Err(usage("/replace")) };
+    let (from, to) = (edit::parse_block(from)?, edit::parse_block(to)?);
+    let check = server.build_check(client_id);
+    let session = session(&mut server.clients, client_id)?;
+    let region = selection(session)?;
+
+    let world = &mut server.world;
+    let blocks: Vec<_> = edit::positions(region)
+        .filter(|p| check.allows(*p) && edit::same_kind(world.get_block(*p), from))
+        .map(|p| (p, (to, None)))
+        .collect();
+    let changes = edit::apply(world, blocks);
+    let count = changes.len();
+    session.remember(changes);
+    changed(count)
+}
+
+fn copy(server: &mut Server, client_id: ClientId, _args: &[&str]) -> CommandResult {
+    let origin = player_pos(server, client_id);
+    let session = session(&mut server.clients, client_id)?;
+    let region = selection(session)?;
+
+    let blocks: Vec<_> = edit::positions(region).map(|p| {
+        (BlockPos::new(p.x - origin.x, p.y - origin.y, p.z - origin.z), edit::snapshot(&server.world, p))
+    }).collect();
+    let count = blocks.len();
+    session.clipboard = Some(Clipboard { blocks });
+    Ok(Component::text(format!("{} blocks copied", count)))
+}
+
+fn paste(server: &mut Server, client_id: ClientId, args: &[&str]) -> CommandResult {
+    let skip_air = match args {
+        [] => false,
+        ["-a"] => true,
+        _ => return Err(usage("/paste")),
+    };
+
+    let origin = player_pos(server, client_id);
+    let check = server.build_check(client_id);
+    let session = session(&mut server.clients, client_id)?;
+    let clipboard = session.clipboard.as_ref().ok_or("The clipboard is empty, see //copy")?;
+
+    let blocks = clipboard.blocks.iter()
+        .filter(|(_, (block, _))| !skip_air || *block != Block::Air)
+        .map(|(p, snapshot)| (BlockPos::new(origin.x + p.x, origin.y + p.y, origin.z + p.z), snapshot.clone()))
+        .filter(|(p, _)| check.allows(*p));
+    let changes = edit::apply(&mut server.world, blocks);
+    let count = changes.len();
+    session.remember(changes);
+    changed(count)
+}
+
+fn move_selection(server: &mut Server, client_id: ClientId, args: &[&str]) -> CommandResult {
+    let (count, dir) = parse_count_direction(server, client_id, "/move", args)?;
+    let check = server.build_check(client_id);
+    let session = session(&mut server.clients, client_id)?;
+    let region = selection(session)?;
+    let (Some(min), Some(max)) = (region.min.checked_offset_by(dir, count), region.max.checked_offset_by(dir, count)) else {
+        return Err("That would move the blocks past the end of the world".to_string());
+    };
+    let moved = Region::new(min, max);
+    if !edit::positions(region).chain(edit::positions(moved)).all(|p| check.allows(p)) {
+        return Err("You can only move blocks within plots you may build in".to_string());
+    }
+
+    // the moved blocks go after the air left behind, so they win where the two overlap
+    let world = &mut server.world;
+    let air = edit::positions(region).map(|p| (p, (Block::Air, None)));
+    let moved: Vec<_> = edit::positions(region).map(|p| (p.offset_by(dir, count), edit::snapshot(world, p))).collect();
+    let changes = edit::apply(world, air.chain(moved));
+
+    session.pos1 = session.pos1.map(|p| p.offset_by(dir, count));
+    session.pos2 = session.pos2.map(|p| p.offset_by(dir, count));
+    let count = changes.len();
+    session.remember(changes);
+    changed(count)
+}
+
+fn stack(server: &mut Server, client_id: ClientId, args: &[&str]) -> CommandResult {
+    let (count, dir) = parse_count_direction(server, client_id, "/stack", args)?;
+    let check = server.build_check(client_id);
+    let session = session(&mut server.clients, client_id)?;
+    let region = selection(session)?;
+    if region.volume().saturating_mul(count as u64) > MAX_VOLUME {
+        return Err(format!("Stacking changes more than the {} blocks an edit can change", MAX_VOLUME));
+    }
+
+    let size = match dir {
+        Direction::West | Direction::East => region.max.x - region.min.x + 1,
+        Direction::Down | Direction::Up => region.max.y - region.min.y + 1,
+        Direction::North | Direction::South => region.max.z - region.min.z + 1,
+    };
+
+    if region.min.checked_offset_by(dir, size * count).zip(region.max.checked_offset_by(dir, size * count)).is_none() {
+        return Err("That would stack the blocks past the end of the world".to_string());
+    }
+
+    let world = &mut server.world;
+    let blocks: Vec<_> = edit::positions(region).flat_map(|p| {
+        let snapshot = edit::snapshot(world, p);
+        (1..=count).map(move |i| (p.offset_by(dir, size * i), snapshot.clone()))
+    }).filter(|(p, _)| check.allows(*p)).collect();
+    let changes = edit::apply(world, blocks);
+    let count = changes.len();
+    session.remember(changes);
+    changed(count)
+}
+
+fn rotate(server: &mut Server, client_id: ClientId, args: &[&str]) -> CommandResult {
+    let [degrees] = args else { return Err(usage("/rotate")) };
+    let degrees: i32 = match degrees.parse() {
+        Ok(d) if d % 90 == 0 => d,
+        _ => return Err(format!("{} is not a multiple of 90 degrees", degrees)),
+    };
+
+    let session = session(&mut server.clients, client_id)?;
+    let clipboard = session.clipboard.as_mut().ok_or("The clipboard is empty, see //copy")?;
+    clipboard.rotate((degrees / 90).rem_euclid(4) as u8);
+    Ok(Component::text(format!("Clipboard turned {} degrees", degrees)))
+}
+
+fn flip(server: &mut Server, client_id: ClientId, args: &[&str]) -> CommandResult {
+    if args.len() > 1 {
+        return Err(usage("/flip"));
+    }
+
+    let dir = parse_direction(server, client_id, args.first())?;
+    let session = session(&mut server.clients, client_id)?;
+    let clipboard = session.clipboard.as_mut().ok_or("The clipboard is empty, see //copy")?;
+    clipboard.flip(dir.axis());
+    Ok(Component::text(format!("Clipboard flipped {:?}", dir).to_lowercase()))
+}
+
+/// Reads the optional count of //undo and //redo.
+fn parse_times(name: &str, args: &[&str]) -> Result<usize, String> {
+    match args {
+        [] => Ok(1),
+        [count] => count.parse().ok().filter(|c| *c > 0).ok_or_else(|| format!("{} is not a positive count", count)),
+        _ => Err(usage(name)),
+    }
+}
+
+fn undo(server: &mut Server, client_id: ClientId, args: &[&str]) -> CommandResult {
+    let times = parse_times("/undo", args)?;
+    let check = server.build_check(client_id);
+    let session = session(&mut server.clients, client_id)?;
+
+    let undone: Vec<_> = (0..times).map_while(|_| session.undo(&mut server.world, |p| check.allows(p))).collect();
+    if undone.is_empty() {
+        return Err("Nothing to undo".to_string());
+    }
+
+    Ok(Component::text(format!("Undid {} edits, {} blocks changed", undone.len(), undone.iter().sum::<usize>())))
+}
+
+fn redo(server: &mut Server, client_id: ClientId, args: &[&str]) -> CommandResult {
+    let times = parse_times("/redo", args)?;
+    let check = server.build_check(client_id);
+    let session = session(&mut server.clients, client_id)?;
+
+    let redone: Vec<_> = (0..times).map_while(|_| session.redo(&mut server.world, |p| check.allows(p))).collect();
+    if redone.is_empty() {
+        return Err("Nothing to redo".to_string());
+    }
+
+    Ok(Component::text(format!("Redid {} edits, {} blocks changed", redone.len(), redone.iter().sum::<usize>())))
+}
+
+fn schem(server: &mut Server, client_id: ClientId, args: &[&str]) -> CommandResult {
+    match args {
+        ["load", name] => {
+            let path = schematic::path(name)?;
+            let loaded = schematic::load(&path)?;
+            let (w, h, l) = loaded.size;
+            let mut message = format!("Loaded {}, {}x{}x{} blocks, into the clipboard", path.display(), w, h, l);
+            if !loaded.metadata.author.is_empty() {
+                message += &format!(", made by {}", loaded.metadata.author);
+            }
+            if !loaded.metadata.description.is_empty() {
+                message += &format!(": {}", loaded.metadata.description);
+            }
+
+            session(&mut server.clients, client_id)?.clipboard = Some(loaded.to_clipboard());
+            Ok(Component::text(message))
+        },
+        ["save", name, rest @ ..] => {
+            let path = schematic::path(name)?;
+            let litematic = path.extension().is_some_and(|e| e == "litematic");
+            let format = match rest {
+                [] if litematic => Format::Litematic,
+                [] => Format::SpongeV3,
+                ["v2"] if !litematic => Format::SpongeV2,
+                _ => return Err(usage("/schem")),
+            };
+            let path = if litematic { path } else { path.with_extension("schem") };
+
+            let author = server.clients.player(client_id).map(|p| p.name.clone()).unwrap_or_default();
+            let session = session(&mut server.clients, client_id)?;
+            let clipboard = session.clipboard.as_ref().ok_or("The clipboard is empty, see //copy")?;
+            let mut saved = Schematic::from_clipboard(clipboard);
+            saved.metadata.name = path.file_stem().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+            saved.metadata.author = author;
+            schematic::save(&saved, &path, format)?;
+            Ok(Component::text(format!("Saved the clipboard to {}", path.display())))
+        },
+        ["list"] => {
+            let names = schematic::list();
+            if names.is_empty() {
+                return Ok(Component::text(format!("There are no schematics in {}/", schematic::DIRECTORY)));
+            }
+            Ok(Component::text(names.join(", ")))
+        },
+        _ => Err(usage("/schem")),
+    }
+}
+
+fn save(server: &mut Server, _client_id: ClientId, _args: &[&str]) -> CommandResult {
+    server.save_world()?;
+    Ok(Component::text(format!("Saved the world to {}", server.config.world.path)))
+}
+
+/// The plot the player stands in.
+fn current_plot(server: &Server, client_id: ClientId) -> Result<(plot::Layout, PlotId), String> {
+    let layout = server.world.plots.ok_or("This isn't a plot world")?;
+    let id = layout.plot_at(player_pos(server, client_id)).ok_or("You aren't standing in a plot")?;
+    Ok((layout, id))
+}
+
+/// The plot the player stands in, if it's theirs.
+fn owned_plot(server: &Server, client_id: ClientId) -> Result<(plot::Layout, PlotId), String> {
+    let (layout, id) = current_plot(server, client_id)?;
+    let name = server.clients.player(client_id).map_or("", |p| p.name.as_str());
+    match server.plots.claims.get(&id) {
+        Some(claim) if claim.is_owner(name) => Ok((layout, id)),
+        Some(claim) => Err(format!("Plot {} belongs to {}", id, claim.owner)),
+        None => Err(format!("Plot {} isn't claimed, see /plot claim", id)),
+    }
+}
+
+fn save_claims(server: &Server) -> Result<(), String> {
+    server.plots.save().map_err(|e| {
+        error!("{}", e);
+        "The claims couldn't be saved".to_string()
+    })
+}
+
+fn plot(server: &mut Server, client_id: ClientId, args: &[&str]) -> CommandResult {
+    let name = server.clients.player(client_id).map(|p| p.name.clone()).ok_or("You aren't playing")?;
+    match args {
+        ["claim"] => {
+            let (_, id) = current_plot(server, client_id)?;
+            if let Some(claim) = server.plots.claims.get(&id) {
+                return Err(format!("Plot {} already belongs to {}", id, claim.owner));
+            }
+
+            server.plots.claims.insert(id, Claim { owner: name, trusted: Vec::new(), denied: Vec::new() });
+            save_claims(server)?;
+            Ok(Component::text(format!("Plot {} is yours now", id)))
+        },
+        ["clear"] => {
+            let (layout, id) = owned_plot(server, client_id)?;
+            plot::regenerate(&mut server.world, &layout, id, true);
+            Ok(Component::text(format!("Plot {} is cleared", id)))
+        },
+        ["reset"] => {
+            let (layout, id) = owned_plot(server, client_id)?;
+            plot::regenerate(&mut server.world, &layout, id, false);
+            server.plots.claims.remove(&id);
+            save_claims(server)?;
+            Ok(Component::text(format!("Plot {} is reset and unclaimed", id)))
+        },
+        [action @ ("trust" | "deny" | "remove"), other] => {
+            let (layout, id) = owned_plot(server, client_id)?;
+            if other.eq_ignore_ascii_case(&name) {
+                return Err("That's you".to_string());
+            }
+
+            let claim = server.plots.claims.get_mut(&id).unwrap();
+            claim.trusted.retain(|n| !n.eq_ignore_ascii_case(other));
+            claim.denied.retain(|n| !n.eq_ignore_ascii_case(other));
+            match *action {
+                "trust" => claim.trusted.push(other.to_string()),
+                "deny" => claim.denied.push(other.to_string()),
+                _ => {},
+            }
+            save_claims(server)?;
+
+            // denied players standing in the plot are sent away
+            let inside = server.clients.iter().find(|(_, c)| c.player.as_ref().is_some_and(|p| {
+                p.name.eq_ignore_ascii_case(other) && layout.plot_at(BlockPos::new(p.x.floor() as i32, 0, p.z.floor() as i32)) == Some(id)
+            }));
+            if let (Some((inside, _)), "deny") = (inside, *action) {
+                server.teleport(inside, layout.spawn());
+            }
+
+            Ok(Component::text(match *action {
+                "trust" => format!("{} may build on plot {} now", other, id),
+                "deny" => format!("{} may not enter plot {} anymore", other, id),
+                _ => format!("{} is neither trusted nor denied on plot {} anymore", other, id),
+            }))
+        },
+        ["info"] => {
+            let (_, id) = current_plot(server, client_id)?;
+            let Some(claim) = server.plots.claims.get(&id) else {
+                return Ok(Component::text(format!("Plot {} isn't claimed", id)));
+            };
+
+            let list = |names: &[String]| if names.is_empty() { "nobody".to_string() } else { names.join(", ") };
+            Ok(Component::text(format!(
+                "Plot {} belongs to {}\nTrusted: {}\nDenied: {}",
+                id, claim.owner, list(&claim.trusted), list(&claim.denied),
+            )))
+        },
+        _ => Err(usage("plot")),
+    }
+}
+
+/// Saves the operators and tells the player's client their new op level, if
+/// they're online.
+fn set_op_level(server: &mut Server, name: &str) -> Result<(), String> {
+    server.ops.save().map_err(|e| {
+        error!("{}", e);
+        "The operators couldn't be saved".to_string()
+    })?;
+
+    if let Some(i) = server.find_player(name) {
+        server.send_op_level(i);
+    }
+    Ok(())
+}
+
+fn op(server: &mut Server, client_id: ClientId, args: &[&str]) -> CommandResult {
+    let (name, level) = match args {
+        [name] => (*name, permission::MAX_LEVEL),
+        [name, level] => match level.parse() {
+            Ok(level) if level <= permission::MAX_LEVEL => (*name, level),
+            _ => return Err(format!("{} is not an op level from 0 to {}", level, permission::MAX_LEVEL)),
+        },
+        _ => return Err(usage("op")),
+    };
+
+    // nobody hands out more than they have, or demotes those above them
+    let own = server.op_level(client_id);
+    if level > own {
+        return Err(format!("You can't make operators above your own level of {}", own));
+    }
+    if server.ops.level(name) > own {
+        return Err(format!("{} is an operator above your own level of {}", name, own));
+    }
+
+    server.ops.set(name, level);
+    set_op_level(server, name)?;
+    info!("made {} an operator of level {}", name, level);
+    Ok(Component::text(format!("Made {} an operator of level {}", name, level)))
+}
+
+fn deop(server: &mut Server, client_id: ClientId, args: &[&str]) -> CommandResult {
+    let [name] = args else { return Err(usage("deop")) };
+    let own = server.op_level(client_id);
+    if server.ops.level(name) > own {
+        return Err(format!("{} is an operator above your own level of {}", name, own));
+    }
+    if !server.ops.remove(name) {
+        return Err(format!("{} isn't an operator", name));
+    }
+
+    set_op_level(server, name)?;
+    info!("{} isn't an operator anymore", name);
+    Ok(Component::text(format!("{} isn't an operator anymore", name)))
+}
+
+fn save_access<T: access::Entry>(list: &mut access::List<T>) -> Result<(), String> {
+    list.save().map_err(|e| {
+        error!("{}", e);
+        "The list couldn't be saved".to_string()
+    })
+}
+
+fn whitelist(server: &mut Server, _client_id: ClientId, args: &[&str]) -> CommandResult {
+    match args {
+        [state @ ("on" | "off")] => {
+            server.access.whitelist_on = *state == "on";
+            server.enforce_access();
+            Ok(Component::text(format!(
+                "Turned the whitelist {} until the server restarts, access.whitelist in the config keeps it",
+                state,
+            )))
+        },
+        ["add", name] => {
+            server.access.whitelist.add(Whitelisted::new(name));
+            save_access(&mut server.access.whitelist)?;
+            Ok(Component::text(format!("Added {} to the whitelist", name)))
+        },
+        ["remove", name] => {
+            if !server.access.whitelist.remove(name) {
+                return Err(format!("{} isn't whitelisted", name));
+            }
+
+            save_access(&mut server.access.whitelist)?;
+            server.enforce_access();
+            Ok(Component::text(format!("Removed {} from the whitelist", name)))
+        },
+        ["list"] if server.access.whitelist.entries.is_empty() => Ok(Component::text("Nobody is whitelisted")),
+        ["list"] => {
+            let names: Vec<_> = server.access.whitelist.entries.iter().map(|w| w.name.as_str()).collect();
+            Ok(Component::text(format!("Whitelisted: {}", names.join(", "))))
+        },
+        ["reload"] => {
+            server.access.reload()?;
+            server.enforce_access();
+            Ok(Component::text("Reloaded the whitelist and bans"))
+        },
+        _ => Err(usage("whitelist")),
+    }
+}
+
+/// Reads the duration and reason a ban may have after its target.
+fn parse_ban(args: &[&str]) -> (Option<u64>, String) {
+    let (duration, reason) = match args.first().and_then(|d| access::parse_duration(d)) {
+        Some(duration) => (Some(duration), &args[1..]),
+        None => (None, args),
+    };
+
+    match reason {
+        [] => (duration, access::DEFAULT_BAN_REASON.to_string()),
+        _ => (duration, reason.join(" ")),
+    }
+}
+
+fn describe_ban(ban: &Ban) -> String {
+    match ban.expires {
+        Some(expires) => format!("until {}: {}", access::format_date(expires), ban.reason),
+        None => format!("forever: {}", ban.reason),
+    }
+}
+
+fn source(server: &Server, client_id: ClientId) -> String {
+    server.clients.player(client_id).map_or_else(|| access::SERVER_SOURCE.to_string(), |p| p.name.clone())
+}
+
+fn ban(server: &mut Server, client_id: ClientId, args: &[&str]) -> CommandResult {
+    let [name, rest @ ..] = args else { return Err(usage("ban")) };
+    let (duration, reason) = parse_ban(rest);
+    let uuid = super::player::format_uuid(super::player::offline_uuid(name));
+    let ban = Ban::new(name, Some(uuid), &source(server, client_id), duration, &reason);
+    let description = describe_ban(&ban);
+
+    server.access.banned_players.add(ban);
+    save_access(&mut server.access.banned_players)?;
+    server.enforce_access();
+    info!("banned {} {}", name, description);
+    Ok(Component::text(format!("Banned {} {}", name, description)))
+}
+
+fn ban_ip(server: &mut Server, client_id: ClientId, args: &[&str]) -> CommandResult {
+    let [target, rest @ ..] = args else { return Err(usage("ban-ip")) };
+    let ip = match target.parse::<std::net::IpAddr>() {
+        Ok(ip) => ip.to_string(),
+        Err(_) => match server.find_player(target) {
+            Some(i) => server.clients[i].ip(),
+            None => return Err(format!("{} is neither an IP address nor a player online", target)),
+        },
+    };
+
+    let (duration, reason) = parse_ban(rest);
+    let ban = Ban::new(&ip, None, &source(server, client_id), duration, &reason);
+    let description = describe_ban(&ban);
+
+    server.access.banned_ips.add(ban);
+    save_access(&mut server.access.banned_ips)?;
+    server.enforce_access();
+    info!("banned {} {}", ip, description);
+    Ok(Component::text(format!("Banned {} {}", ip, description)))
+}
+
+fn pardon(server: &mut Server, _client_id: ClientId, args: &[&str]) -> CommandResult {
+    let [target] = args else { return Err(usage("pardon")) };
+    if target.parse::<std::net::IpAddr>().is_ok() {
+        if !server.access.banned_ips.remove(target) {
+            return Err(format!("{} isn't banned", target));
+        }
+        save_access(&mut server.access.banned_ips)?;
+    } else {
+        if !server.access.banned_players.remove(target) {
+            return Err(format!("{} isn't banned", target));
+        }
+        save_access(&mut server.access.banned_players)?;
+    }
+
+    info!("pardoned {}", target);
+    Ok(Component::text(format!("Lifted the ban of {}", target)))
+}
+
+fn kick(server: &mut Server, _client_id: ClientId, args: &[&str]) -> CommandResult {
+    let [name, reason @ ..] = args else { return Err(usage("kick")) };
+    let i = server.find_player(name).ok_or_else(|| format!("{} isn't online", name))?;
+    let reason = match reason {
+        [] => "Kicked by an operator.".to_string(),
+        _ => reason.join(" "),
+    };
+
+    server.disconnect(i, &Component::text(reason.as_str()));
+    Ok(Component::text(format!("Kicked {}: {}", name, reason)))
+}
+
+fn say(server: &mut Server, client_id: ClientId, args: &[&str]) -> CommandResult {
+    if args.is_empty() {
+        return Err(usage("say"));
+    }
+
+    let (from, message) = (source(server, client_id), args.join(" "));
+    info!("[{}] {}", from, message);
+    server.broadcast(&Component::translate("chat.type.announcement", vec![Component::text(from), Component::text(message)]), 0);
+    Ok(Component::text("Announced it"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ticks_are_limited() {
+        assert_eq!(parse_ticks("1"), Ok(1));
+        assert_eq!(parse_ticks(&MAX_DEBUG_TICKS.to_string()), Ok(MAX_DEBUG_TICKS));
+        assert!(parse_ticks("0").is_err());
+        assert!(parse_ticks(&(MAX_DEBUG_TICKS + 1).to_string()).is_err());
+        assert!(parse_ticks("18446744073709551615").is_err());
+    }
+
+    #[test]
+    fn relative_coordinates_dont_overflow() {
+        assert_eq!(parse_coordinate("~", 5), Ok(5));
+        assert_eq!(parse_coordinate("~-7", 5), Ok(-2));
+        assert_eq!(parse_coordinate("12", 5), Ok(12));
+        assert!(parse_coordinate("~2147483647", 5).is_err());
+        assert!(parse_coordinate("~-2147483648", -5).is_err());
+        assert!(parse_coordinate("~x", 5).is_err());
+    }
+}
@@ -1,9 +1,323 @@
 use serde::*;
+use std::collections::BTreeMap;
 
 #[derive(Deserialize, Clone, Debug)]
 pub struct Config {
-    pub motd: String,
+    pub motd: Motd,
     pub address: String,
+    #[serde(default)]
+    pub world: WorldConfig,
+    #[serde(default)]
+    pub plots: PlotConfig,
+    #[serde(default)]
+    pub permissions: PermissionConfig,
+    #[serde(default)]
+    pub access: AccessConfig,
+    #[serde(default)]
+    pub connection: ConnectionConfig,
+    #[serde(default)]
+    pub plugins: PluginConfig,
+    #[serde(default)]
+    pub rcon: RconConfig,
+    #[serde(default)]
+    pub query: QueryConfig,
+    #[serde(default)]
+    pub status: StatusConfig,
+}
+
+/// Text, or a chat component written as a table like vanilla's JSON.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(untagged)]
+pub enum Motd {
+    Text(String),
+    Component(toml::Table),
+}
+
+impl Motd {
+    pub fn to_json(&self) -> json::JsonValue {
+        match self {
+            Self::Text(text) => json::object! { text: text.as_str() },
+            Self::Component(table) => toml_to_json(&toml::Value::Table(table.clone())),
+        }
+    }
+}
+
+fn toml_to_json(value: &toml::Value) -> json::JsonValue {
+    match value {
+        toml::Value::String(s) => s.as_str().into(),
+        toml::Value::Integer(i) => (*i).into(),
+        toml::Value::Float(f) => (*f).into(),
+        toml::Value::Boolean(b) => (*b).into(),
+        toml::Value::Datetime(d) => d.to_string().into(),
+        toml::Value::Array(values) => values.iter().map(toml_to_json).collect::<Vec<_>>().into(),
+        toml::Value::Table(table) => {
+            let mut object = json::object! {};
+            for (key, value) in table {
+                object[key.as_str()] = toml_to_json(value);
+            }
+            object
+        },
+    }
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct WorldConfig {
+    /// File the world is loaded from and saved to.
+    pub path: String,
+    /// Seconds between saves, or 0 to only save with /save.
+    pub autosave: u64,
+}
+
+impl Default for WorldConfig {
+    fn default() -> Self {
+        Self {
+            path: "world.nbt".to_string(),
+            autosave: 300,
+        }
+    }
+}
+
+/// The plot world, see [`super::plot`].
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct PlotConfig {
+    pub enabled: bool,
+    pub size: i32,
+    pub road: i32,
+    pub height: i32,
+    /// Threads busy plots tick on, or 0 for one per core.
+    pub threads: usize,
+}
+
+impl Default for PlotConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            size: 48,
+            road: 6,
+            height: 4,
+            threads: 0,
+        }
+    }
+}
+
+/// Who may do what, see [`super::permission`].
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct PermissionConfig {
+    /// Operator list, in the format of vanilla's `ops.json`.
+    pub ops: String,
+    /// Groups by name. Everyone is in the one named `default`.
+    pub groups: BTreeMap<String, GroupConfig>,
+}
+
+impl Default for PermissionConfig {
+    fn default() -> Self {
+        let default = GroupConfig {
+            players: Vec::new(),
+            nodes: ["build", "worldedit.*"].map(String::from).to_vec(),
+        };
+
+        Self {
+            ops: "ops.json".to_string(),
+            groups: BTreeMap::from([("default".to_string(), default)]),
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct GroupConfig {
+    pub players: Vec<String>,
+    /// Permission nodes, where `*` at the end stands for anything.
+    pub nodes: Vec<String>,
+}
+
+/// Who may join, see [`super::access`].
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct AccessConfig {
+    /// Whether only whitelisted players and operators may join.
+    pub whitelist: bool,
+    pub whitelist_file: String,
+    pub banned_players_file: String,
+    pub banned_ips_file: String,
+}
+
+impl Default for AccessConfig {
+    fn default() -> Self {
+        Self {
+            whitelist: false,
+            whitelist_file: "whitelist.json".to_string(),
+            banned_players_file: "banned-players.json".to_string(),
+            banned_ips_file: "banned-ips.json".to_string(),
+        }
+    }
+}
+
+/// Limits on players and connections, so floods can't use up the server.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct ConnectionConfig {
+    pub max_players: usize,
+    /// Milliseconds an address has to wait between logins, or 0 to let it
+    /// log in as often as it likes. Local addresses never wait.
+    pub throttle: u64,
+    /// Seconds a connection has to log in within.
+    pub login_timeout: u64,
+    /// Connections that may be logging in or asking for the status at once.
+    pub max_pending: usize,
+    /// Of those, how many may come from one address. Local addresses may
+    /// have all of them.
+    pub max_pending_per_ip: usize,
+    /// Bytes that may wait to be sent to a client before it's disconnected
+    /// for falling behind.
+    pub send_buffer: usize,
+}
+
+impl Default for ConnectionConfig {
+    fn default() -> Self {
+        Self {
+            max_players: 20,
+            throttle: 4000,
+            login_timeout: 30,
+            max_pending: 32,
+            max_pending_per_ip: 4,
+            send_buffer: 8 << 20,
+        }
+    }
+}
+
+/// Plugins to enable, see [`super::plugin`].
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct PluginConfig {
+    /// Names of the built in plugins, which are enabled in this order.
+    pub enabled: Vec<String>,
+    pub wasm: WasmConfig,
+    pub scripts: ScriptConfig,
+}
+
+impl Default for PluginConfig {
+    fn default() -> Self {
+        Self {
+            enabled: vec!["join-messages".to_string()],
+            wasm: WasmConfig::default(),
+            scripts: ScriptConfig::default(),
+        }
+    }
+}
+
+/// WebAssembly plugins, see [`super::plugin::wasm`].
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct WasmConfig {
+    /// Directory every `.wasm` file in is loaded as a plugin.
+    pub dir: String,
+    /// Fuel a plugin may use each time it's called, roughly one per
+    /// instruction.
+    pub fuel: u64,
+    /// Bytes of memory a plugin may have.
+    pub memory: usize,
+    /// Limits of single plugins, by file name without the extension.
+    pub limits: BTreeMap<String, WasmLimits>,
+}
+
+impl Default for WasmConfig {
+    fn default() -> Self {
+        Self {
+            dir: "plugins".to_string(),
+            fuel: 10_000_000,
+            memory: 16 << 20,
+            limits: BTreeMap::new(),
+        }
+    }
+}
+
+/// Scripts, see [`super::plugin::script`].
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct ScriptConfig {
+    /// Directory every `.rhai` file in is run as a script.
+    pub dir: String,
+    /// Operations a script may run each time it's called, so one stuck in
+    /// a loop doesn't stop the server.
+    pub max_operations: u64,
+}
+
+impl Default for ScriptConfig {
+    fn default() -> Self {
+        Self {
+            dir: "scripts".to_string(),
+            max_operations: 1_000_000,
+        }
+    }
+}
+
+/// Overrides [`WasmConfig`]'s limits for one plugin.
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct WasmLimits {
+    pub fuel: Option<u64>,
+    pub memory: Option<usize>,
+}
+
+/// The remote console, see [`super::rcon`].
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct RconConfig {
+    pub enabled: bool,
+    pub address: String,
+    /// What clients have to log in with. RCON stays off without one.
+    pub password: String,
+}
+
+impl Default for RconConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            address: "127.0.0.1:25575".to_string(),
+            password: String::new(),
+        }
+    }
+}
+
+/// Answering status queries over UDP, see [`super::query`].
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct QueryConfig {
+    pub enabled: bool,
+    pub address: String,
+}
+
+impl Default for QueryConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            address: "127.0.0.1:25565".to_string(),
+        }
+    }
+}
+
+/// What the server list shows, see [`super::packet::status`].
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct StatusConfig {
+    /// A 64x64 PNG shown next to the server.
+    pub icon: String,
+    /// Lines one of which is picked for every request and shown under the
+    /// MOTD.
+    pub lines: Vec<Motd>,
+}
+
+impl Default for StatusConfig {
+    fn default() -> Self {
+        Self {
+            icon: "server-icon.png".to_string(),
+            lines: Vec::new(),
+        }
+    }
 }
 
 impl Config {
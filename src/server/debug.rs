@@ -0,0 +1,200 @@
+//! The power overlay and the results of redstone traces and profiles.
+
+use std::fs;
+use std::path::PathBuf;
+use super::Server;
+use super::client::ClientId;
+use super::chat::Component;
+use super::packet;
+use super::redstone::debug::{self, Event, Profile, Trace};
+use super::world::block::Block;
+use super::world::{BlockPos, World};
+
+/// How far players see blocks for the overlay.
+const REACH: f64 = 6.0;
+/// Height of the eyes of a standing player.
+const EYE_HEIGHT: f64 = 1.62;
+/// Ticks after which an unchanged overlay is sent again, before it fades.
+const OVERLAY_REFRESH: u64 = 40;
+/// Most lines of a result shown in chat.
+const MAX_CHAT_LINES: usize = 50;
+/// Directory results written to files go in.
+const OUTPUT_DIR: &str = "debug";
+
+/// Where the result of a trace or profile goes.
+#[derive(Debug, Clone)]
+pub enum Output {
+    Chat(ClientId),
+    File(String),
+}
+
+impl Output {
+    /// Reads an optional file name argument, falling back to the chat of
+    /// the player.
+    pub fn parse(client_id: ClientId, name: Option<&&str>) -> Result<Self, String> {
+        let Some(name) = name else { return Ok(Self::Chat(client_id)) };
+        let valid = name.chars().all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c));
+        if !valid || name.starts_with('.') {
+            return Err(format!("{} is not a valid file name", name));
+        }
+
+        Ok(Self::File(name.to_string()))
+    }
+}
+
+/// The first block that isn't air along the look of a player.
+fn looked_at(world: &World, (x, y, z): (f64, f64, f64), yaw: f32, pitch: f32) -> Option<BlockPos> {
+    let (yaw, pitch) = ((yaw as f64).to_radians(), (pitch as f64).to_radians());
+    let dir = (-yaw.sin() * pitch.cos(), -pitch.sin(), yaw.cos() * pitch.cos());
+    let eye = (x, y + EYE_HEIGHT, z);
+
+    let steps = (REACH * 20.0) as i32;
+    (0..=steps).map(|i| i as f64 / 20.0).find_map(|d| {
+        let pos = BlockPos::new(
+            (eye.0 + dir.0 * d).floor() as i32,
+            (eye.1 + dir.1 * d).floor() as i32,
+            (eye.2 + dir.2 * d).floor() as i32,
+        );
+        (world.get_block(pos) != Block::Air).then_some(pos)
+    })
+}
+
+fn format_pos(pos: BlockPos) -> String {
+    format!("({}, {}, {})", pos.x, pos.y, pos.z)
+}
+
+fn trace_lines(trace: &Trace) -> Vec<Component> {
+    trace.events.iter().map(|(time, event, pos, block)| {
+        let color = match event {
+            Event::NeighborUpdate => "gray",
+            Event::ShapeUpdate => "dark_gray",
+            Event::BlockChange => "green",
+            Event::ScheduledTick => "yellow",
+            Event::BlockAction => "light_purple",
+        };
+
+        let mut line = Component::text(format!("+{} ", time - trace.start)).color("white");
+        line.push(Component::text(format!("{} ", event)).color(color));
+        line.push(Component::text(format!("{} {}", debug::block_name(*block), format_pos(*pos))).color("gray"));
+        line
+    }).collect()
+}
+
+fn profile_lines(profile: &Profile, count: usize) -> Vec<Component> {
+    let ticks = (profile.end - profile.start).max(1);
+    profile.busiest(count).into_iter().map(|(pos, counts)| {
+        let mut breakdown = Component::text(format!("{} at {}", debug::block_name(counts.block), format_pos(pos)));
+        for (event, n) in Event::ALL.iter().zip(counts.events) {
+            breakdown.push(Component::text(format!("\n{}: {}", event, n)));
+        }
+
+        let mut line = Component::text(format!("{} ", counts.total)).color("gold").hover(breakdown);
+        line.push(Component::text(format!("{} {} ", debug::block_name(counts.block), format_pos(pos))).color("gray"));
+        line.push(Component::text(format!("{:.2}/tick, peak {}", counts.total as f64 / ticks as f64, counts.peak))
+            .color("dark_gray"));
+        line
+    }).collect()
+}
+
+impl Server {
+    /// Sends a message to a player, who might have left since asking for it.
+    fn deliver_message(&mut self, client_id: ClientId, message: &Component) {
+        let Some(client) = self.clients.get_mut(client_id) else { return };
+        let message = message.to_json().dump();
+        if let Err(e) = client.send_packet(&packet::play::ChatPacket { message, position: 1 }) {
+            error!("failed to send debug output: {}", e);
+        }
+    }
+
+    /// Shows a header and its lines in chat, or writes them to a file.
+    fn deliver(&mut self, output: Output, header: Component, lines: Vec<Component>) {
+        match output {
+            Output::Chat(client_id) => {
+                let more = lines.len().saturating_sub(MAX_CHAT_LINES);
+                self.deliver_message(client_id, &header.bold());
+                for line in lines.iter().take(MAX_CHAT_LINES) {
+                    self.deliver_message(client_id, line);
+                }
+
+                if more > 0 {
+                    let message = format!("{} more lines, write to a file to see all of them", more);
+                    self.deliver_message(client_id, &Component::text(message).color("gray"));
+                }
+            },
+            Output::File(name) => {
+                let path: PathBuf = [OUTPUT_DIR, &name].iter().collect();
+                let mut text = header.plain() + "\n";
+                for line in &lines {
+                    text += &line.plain();
+                    text.push('\n');
+                }
+
+                if let Err(e) = fs::create_dir_all(OUTPUT_DIR).and_then(|_| fs::write(&path, text)) {
+                    error!("failed to write {}: {}", path.display(), e);
+                    return;
+                }
+
+                info!("wrote {}", path.display());
+            },
+        }
+    }
+
+    /// Hands over traces and profiles that ran for as long as they should.
+    pub fn finish_debugging(&mut self) {
+        let time = self.world.time;
+
+        if self.world.trace.as_ref().is_some_and(|t| time >= t.end) {
+            let trace = self.world.trace.take().unwrap();
+            let mut header = format!("Traced {} for {} ticks: {} events", trace.region, trace.end - trace.start, trace.events.len());
+            if trace.dropped > 0 {
+                header += &format!(", {} more were dropped", trace.dropped);
+            }
+
+            if let Some((_, output)) = self.trace_output.take() {
+                self.deliver(output, Component::text(header), trace_lines(&trace));
+            }
+        }
+
+        if self.world.profile.as_ref().is_some_and(|p| time >= p.end) {
+            let profile = self.world.profile.take().unwrap();
+            let total: u32 = profile.counts.values().map(|c| c.total).sum();
+            let header = format!(
+                "Profiled {} ticks: {} events at {} positions",
+                profile.end - profile.start, total, profile.counts.len(),
+            );
+
+            if let Some((_, output)) = self.profile_output.take() {
+                let count = match output {
+                    Output::Chat(_) => 10,
+                    Output::File(_) => profile.counts.len(),
+                };
+                self.deliver(output, Component::text(header), profile_lines(&profile, count));
+            }
+        }
+    }
+
+    /// Shows players with the overlay on what the block they look at does.
+    pub fn update_overlays(&mut self) {
+        for id in self.clients.ids() {
+            let Some(player) = &self.clients[id].player else { continue };
+            let Some(last) = &player.overlay else { continue };
+
+            let text = match looked_at(&self.world, (player.x, player.y, player.z), player.yaw, player.pitch) {
+                Some(pos) => debug::inspect(&self.world, pos),
+                None => Component::text(""),
+            };
+            let message = text.to_json().dump();
+            if *last == message && !self.world.time.is_multiple_of(OVERLAY_REFRESH) {
+                continue;
+            }
+
+            if let Err(e) = self.clients[id].send_packet(&packet::play::ChatPacket { message: message.clone(), position: 2 }) {
+                error!("failed to send overlay: {}", e);
+            }
+
+            if let Some(player) = &mut self.clients[id].player {
+                player.overlay = Some(message);
+            }
+        }
+    }
+}
@@ -0,0 +1,210 @@
+//! Selections, the clipboard and the undo history of the region editing
+//! commands.
+
+use std::collections::BTreeMap;
+use super::item::{ItemStack, ITEMS};
+use super::redstone::compiled::{self, Region};
+use super::world::block::{Axis, Block};
+use super::world::{BlockEntity, BlockPos, World};
+
+/// Most blocks one edit may change.
+pub const MAX_VOLUME: u64 = 1 << 21;
+/// Most edits each player can undo.
+const MAX_HISTORY: usize = 20;
+
+/// A block together with its block entity, like the items of a container.
+pub type Snapshot = (Block, Option<BlockEntity>);
+
+#[derive(Debug, Clone)]
+pub struct Change {
+    pub pos: BlockPos,
+    pub before: Snapshot,
+    pub after: Snapshot,
+}
+
+/// Copied blocks, relative to where the player copying them stood.
+#[derive(Debug, Clone, Default)]
+pub struct Clipboard {
+    pub blocks: Vec<(BlockPos, Snapshot)>,
+}
+
+impl Clipboard {
+    /// Turns the blocks clockwise around the player, seen from above.
+    pub fn rotate(&mut self, turns: u8) {
+        for (pos, (block, _)) in self.blocks.iter_mut() {
+            for _ in 0..turns % 4 {
+                *pos = BlockPos::new(-pos.z, pos.y, pos.x);
+            }
+            *block = block.rotate(turns);
+        }
+    }
+
+    /// Mirrors the blocks along `axis`, through the player.
+    pub fn flip(&mut self, axis: Axis) {
+        for (pos, (block, _)) in self.blocks.iter_mut() {
+            match axis {
+                Axis::X => pos.x = -pos.x,
+                Axis::Y => pos.y = -pos.y,
+                Axis::Z => pos.z = -pos.z,
+            }
+            *block = block.flip(axis);
+        }
+    }
+}
+
+/// What a player is editing and has edited.
+#[derive(Debug, Default)]
+pub struct Session {
+    /// Item that selects the corners when used on blocks.
+    pub wand: Option<i32>,
+    pub pos1: Option<BlockPos>,
+    pub pos2: Option<BlockPos>,
+    pub clipboard: Option<Clipboard>,
+    undo: Vec<Vec<Change>>,
+    redo: Vec<Vec<Change>>,
+}
+
+impl Session {
+    pub fn selection(&self) -> Option<Region> {
+        Some(Region::new(self.pos1?, self.pos2?))
+    }
+
+    pub fn is_wand(&self, held: Option<&ItemStack>) -> bool {
+        self.wand.is_some_and(|w| held.is_some_and(|s| s.id == w))
+    }
+
+    /// Keeps an edit to undo later.
+    pub fn remember(&mut self, changes: Vec<Change>) {
+        if changes.is_empty() {
+            return;
+        }
+
+        self.undo.push(changes);
+        if self.undo.len() > MAX_HISTORY {
+            self.undo.remove(0);
+        }
+        self.redo.clear();
+    }
+
+    /// Reverts the last edit where `allows` lets it, returning how many
+    /// blocks it changed.
+    pub fn undo(&mut self, world: &mut World, allows: impl Fn(BlockPos) -> bool) -> Option<usize> {
+        let changes = self.undo.pop()?;
+        let blocks: Vec<_> = changes.iter().rev().filter(|c| allows(c.pos)).map(|c| (c.pos, c.before.clone())).collect();
+        let count = blocks.len();
+        apply(world, blocks);
+        self.redo.push(changes);
+        Some(count)
+    }
+
+    /// Makes the last undone edit again where `allows` lets it, returning
+    /// how many blocks it changed.
+    pub fn redo(&mut self, world: &mut World, allows: impl Fn(BlockPos) -> bool) -> Option<usize> {
+        let changes = self.redo.pop()?;
+        let blocks: Vec<_> = changes.iter().filter(|c| allows(c.pos)).map(|c| (c.pos, c.after.clone())).collect();
+        let count = blocks.len();
+        apply(world, blocks);
+        self.undo.push(changes);
+        Some(count)
+    }
+}
+
+/// Reads a block by the name of the item placing it, or by state id.
+pub fn parse_block(name: &str) -> Result<Block, String> {
+    if let Ok(id) = name.parse() {
+        return Ok(Block::from_id(id));
+    }
+
+    let name = name.strip_prefix("minecraft:").unwrap_or(name);
+    if name == "air" {
+        return Ok(Block::Air);
+    }
+
+    ITEMS.iter()
+        .find(|i| i.name.strip_prefix("minecraft:") == Some(name))
+        .and_then(|i| i.block)
+        .ok_or_else(|| format!("{} is not a block", name))
+}
+
+/// Whether two blocks are the same kind of block, whatever their state.
+pub fn same_kind(a: Block, b: Block) -> bool {
+    match (a, b) {
+        (Block::Solid(a), Block::Solid(b))
+            | (Block::Transparent(a), Block::Transparent(b))
+            | (Block::Other(a), Block::Other(b)) => a == b,
+        _ => std::mem::discriminant(&a) == std::mem::discriminant(&b),
+    }
+}
+
+pub fn snapshot(world: &World, pos: BlockPos) -> Snapshot {
+    (world.get_block(pos), world.block_entities.get(&pos).cloned())
+}
+
+/// Every position in a region, layer by layer from the bottom.
+pub fn positions(region: Region) -> impl Iterator<Item = BlockPos> {
+    (region.min.y..=region.max.y).flat_map(move |y| {
+        (region.min.z..=region.max.z).flat_map(move |z| {
+            (region.min.x..=region.max.x).map(move |x| BlockPos::new(x, y, z))
+        })
+    })
+}
+
+/// Sets blocks without updating their neighbours, the way structures are
+/// pasted, and returns what changed.
+pub fn apply(world: &mut World, blocks: impl IntoIterator<Item = (BlockPos, Snapshot)>) -> Vec<Change> {
+    // later blocks at the same position win
+    let blocks: BTreeMap<_, _> = blocks.into_iter().collect();
+    let mut keys = blocks.keys();
+    let Some(first) = keys.next() else { return Vec::new() };
+    let region = keys.fold(Region::new(*first, *first), |r, p| Region::new(
+        BlockPos::new(r.min.x.min(p.x), r.min.y.min(p.y), r.min.z.min(p.z)),
+        BlockPos::new(r.max.x.max(p.x), r.max.y.max(p.y), r.max.z.max(p.z)),
+    ));
+
+    while let Some(i) = world.circuits.iter().position(|c| c.region.intersects(&region)) {
+        let region = compiled::decompile(world, i);
+        info!("{} is back to block-accurate redstone for an edit", region);
+    }
+
+    let mut changes = Vec::new();
+    for (pos, (block, entity)) in blocks {
+        let before = snapshot(world, pos);
+        let changed = world.set_block(pos, block);
+
+        if let Some(entity) = &entity {
+            match world.block_entities.get_mut(&pos) {
+                Some(e) => *e = entity.clone(),
+                None => world.add_block_entity(pos, entity.clone()),
+            }
+        }
+
+        if changed || entity.is_some() {
+            changes.push(Change { pos, before, after: (block, entity) });
+        }
+    }
+
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn undo_and_redo_skip_what_they_may_not_change() {
+        let (kept, free) = (BlockPos::new(0, 10, 0), BlockPos::new(1, 10, 0));
+        let mut world = World::new();
+        let mut session = Session::default();
+        let changes = apply(&mut world, [kept, free].map(|p| (p, (Block::Solid(1), None))));
+        session.remember(changes);
+
+        assert_eq!(session.undo(&mut world, |p| p == free), Some(1));
+        assert_eq!(world.get_block(kept), Block::Solid(1));
+        assert_eq!(world.get_block(free), Block::Air);
+
+        world.set_block(kept, Block::Air);
+        assert_eq!(session.redo(&mut world, |p| p == free), Some(1));
+        assert_eq!(world.get_block(kept), Block::Air);
+        assert_eq!(world.get_block(free), Block::Solid(1));
+    }
+}
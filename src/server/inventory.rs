@@ -0,0 +1,48 @@
+//! The player inventory, numbered like the slots of its window.
+
+use super::item::ItemStack;
+
+pub const SLOTS: usize = 46;
+pub const HOTBAR: usize = 36;
+pub const OFFHAND: usize = 45;
+
+pub struct Inventory {
+    pub slots: Vec<Option<ItemStack>>,
+    /// Selected hotbar slot, from 0 to 8.
+    pub selected: usize,
+    /// Bumped whenever the server changes the inventory, so the client can
+    /// tell stale clicks apart.
+    pub state_id: i32,
+}
+
+impl Inventory {
+    pub fn new() -> Self {
+        Self {
+            slots: vec![None; SLOTS],
+            selected: 0,
+            state_id: 0,
+        }
+    }
+
+    pub fn held_slot(&self) -> usize {
+        HOTBAR + self.selected
+    }
+
+    pub fn held(&self) -> Option<&ItemStack> {
+        self.slots[self.held_slot()].as_ref()
+    }
+
+    /// Moves the item in `slot` into the hotbar and selects it, the way
+    /// middle clicking a block does. Returns the hotbar slot it went to.
+    pub fn pick(&mut self, slot: usize) -> usize {
+        let target = (0..9)
+            .map(|i| HOTBAR + (self.selected + i) % 9)
+            .find(|s| self.slots[*s].is_none())
+            .unwrap_or(self.held_slot());
+
+        self.slots.swap(slot, target);
+        self.selected = target - HOTBAR;
+        self.state_id += 1;
+        target
+    }
+}
@@ -0,0 +1,241 @@
+//! Items and the part of the 1.18.2 item registry the server can place.
+
+use quartz_nbt::NbtCompound;
+use super::world::block::*;
+
+const WIRE: RedstoneWire = RedstoneWire {
+    north: WireSide::None,
+    south: WireSide::None,
+    east: WireSide::None,
+    west: WireSide::None,
+    power: 0,
+};
+
+#[derive(Debug)]
+pub struct Item {
+    pub id: i32,
+    pub name: &'static str,
+    /// Block the item places, oriented later by [`Block::for_placement`].
+    pub block: Option<Block>,
+}
+
+/// Items other than these are still kept in inventories, they just don't
+/// place anything.
+pub const ITEMS: &[Item] = &[
+    Item { id: 1, name: "minecraft:stone", block: Some(Block::Solid(1)) },
+    Item { id: 2, name: "minecraft:granite", block: Some(Block::Solid(2)) },
+    Item { id: 3, name: "minecraft:polished_granite", block: Some(Block::Solid(3)) },
+    Item { id: 4, name: "minecraft:diorite", block: Some(Block::Solid(4)) },
+    Item { id: 5, name: "minecraft:polished_diorite", block: Some(Block::Solid(5)) },
+    Item { id: 6, name: "minecraft:andesite", block: Some(Block::Solid(6)) },
+    Item { id: 7, name: "minecraft:polished_andesite", block: Some(Block::Solid(7)) },
+    Item { id: 14, name: "minecraft:grass_block", block: Some(Block::Solid(9)) },
+    Item { id: 15, name: "minecraft:dirt", block: Some(Block::Solid(10)) },
+    Item { id: 21, name: "minecraft:cobblestone", block: Some(Block::Solid(14)) },
+    Item { id: 22, name: "minecraft:oak_planks", block: Some(Block::Solid(15)) },
+    Item { id: 143, name: "minecraft:glass", block: Some(Block::Transparent(262)) },
+    Item { id: 157, name: "minecraft:white_wool", block: Some(Block::Solid(1440)) },
+    Item { id: 158, name: "minecraft:orange_wool", block: Some(Block::Solid(1441)) },
+    Item { id: 159, name: "minecraft:magenta_wool", block: Some(Block::Solid(1442)) },
+    Item { id: 160, name: "minecraft:light_blue_wool", block: Some(Block::Solid(1443)) },
+    Item { id: 161, name: "minecraft:yellow_wool", block: Some(Block::Solid(1444)) },
+    Item { id: 162, name: "minecraft:lime_wool", block: Some(Block::Solid(1445)) },
+    Item { id: 163, name: "minecraft:pink_wool", block: Some(Block::Solid(1446)) },
+    Item { id: 164, name: "minecraft:gray_wool", block: Some(Block::Solid(1447)) },
+    Item { id: 165, name: "minecraft:light_gray_wool", block: Some(Block::Solid(1448)) },
+    Item { id: 166, name: "minecraft:cyan_wool", block: Some(Block::Solid(1449)) },
+    Item { id: 167, name: "minecraft:purple_wool", block: Some(Block::Solid(1450)) },
+    Item { id: 168, name: "minecraft:blue_wool", block: Some(Block::Solid(1451)) },
+    Item { id: 169, name: "minecraft:brown_wool", block: Some(Block::Solid(1452)) },
+    Item { id: 170, name: "minecraft:green_wool", block: Some(Block::Solid(1453)) },
+    Item { id: 171, name: "minecraft:red_wool", block: Some(Block::Solid(1454)) },
+    Item { id: 172, name: "minecraft:black_wool", block: Some(Block::Solid(1455)) },
+    Item { id: 585, name: "minecraft:redstone", block: Some(Block::RedstoneWire(WIRE)) },
+    Item { id: 586, name: "minecraft:redstone_torch", block: Some(Block::RedstoneTorch { lit: true }) },
+    Item { id: 587, name: "minecraft:redstone_block", block: Some(Block::RedstoneBlock) },
+    Item {
+        id: 588,
+        name: "minecraft:repeater",
+        block: Some(Block::Repeater { delay: 1, facing: Direction::North, locked: false, powered: false }),
+    },
+    Item {
+        id: 589,
+        name: "minecraft:comparator",
+        block: Some(Block::Comparator { facing: Direction::North, mode: ComparatorMode::Compare, powered: false }),
+    },
+    Item {
+        id: 590,
+        name: "minecraft:piston",
+        block: Some(Block::Piston { sticky: false, facing: Direction::North, extended: false }),
+    },
+    Item {
+        id: 591,
+        name: "minecraft:sticky_piston",
+        block: Some(Block::Piston { sticky: true, facing: Direction::North, extended: false }),
+    },
+    Item { id: 592, name: "minecraft:slime_block", block: Some(Block::SlimeBlock) },
+    Item { id: 593, name: "minecraft:honey_block", block: Some(Block::HoneyBlock) },
+    Item {
+        id: 594,
+        name: "minecraft:observer",
+        block: Some(Block::Observer { facing: Direction::North, powered: false }),
+    },
+    Item { id: 595, name: "minecraft:hopper", block: Some(Block::Hopper { facing: Direction::Down, enabled: true }) },
+    Item {
+        id: 596,
+        name: "minecraft:dispenser",
+        block: Some(Block::Dispenser { facing: Direction::North, triggered: false }),
+    },
+    Item {
+        id: 597,
+        name: "minecraft:dropper",
+        block: Some(Block::Dropper { facing: Direction::North, triggered: false }),
+    },
+    Item { id: 599, name: "minecraft:target", block: Some(Block::Target { power: 0 }) },
+    Item {
+        id: 600,
+        name: "minecraft:lever",
+        block: Some(Block::Lever { face: AttachFace::Floor, facing: Direction::North, powered: false }),
+    },
+    Item {
+        id: 602,
+        name: "minecraft:daylight_detector",
+        block: Some(Block::DaylightDetector { inverted: false, power: 0 }),
+    },
+    Item {
+        id: 604,
+        name: "minecraft:tripwire_hook",
+        block: Some(Block::TripwireHook { facing: Direction::North, attached: false, powered: false }),
+    },
+    Item { id: 606, name: "minecraft:tnt", block: Some(Block::Tnt) },
+    Item { id: 607, name: "minecraft:redstone_lamp", block: Some(Block::RedstoneLamp { lit: false }) },
+    Item {
+        id: 608,
+        name: "minecraft:note_block",
+        block: Some(Block::NoteBlock { instrument: Instrument::Harp, note: 0, powered: false }),
+    },
+    Item {
+        id: 609,
+        name: "minecraft:stone_button",
+        block: Some(Block::Button {
+            kind: ButtonKind::Stone,
+            face: AttachFace::Floor,
+            facing: Direction::North,
+            powered: false,
+        }),
+    },
+    Item {
+        id: 611,
+        name: "minecraft:oak_button",
+        block: Some(Block::Button {
+            kind: ButtonKind::Oak,
+            face: AttachFace::Floor,
+            facing: Direction::North,
+            powered: false,
+        }),
+    },
+    Item {
+        id: 619,
+        name: "minecraft:stone_pressure_plate",
+        block: Some(Block::PressurePlate { kind: PlateKind::Stone, powered: false }),
+    },
+    Item {
+        id: 621,
+        name: "minecraft:light_weighted_pressure_plate",
+        block: Some(Block::WeightedPressurePlate { heavy: false, power: 0 }),
+    },
+    Item {
+        id: 622,
+        name: "minecraft:heavy_weighted_pressure_plate",
+        block: Some(Block::WeightedPressurePlate { heavy: true, power: 0 }),
+    },
+    Item {
+        id: 623,
+        name: "minecraft:oak_pressure_plate",
+        block: Some(Block::PressurePlate { kind: PlateKind::Oak, powered: false }),
+    },
+    Item {
+        id: 631,
+        name: "minecraft:iron_door",
+        block: Some(Block::Door {
+            kind: DoorKind::Iron,
+            facing: Direction::North,
+            half: Half::Lower,
+            hinge: Hinge::Left,
+            open: false,
+            powered: false,
+        }),
+    },
+    Item {
+        id: 632,
+        name: "minecraft:oak_door",
+        block: Some(Block::Door {
+            kind: DoorKind::Oak,
+            facing: Direction::North,
+            half: Half::Lower,
+            hinge: Hinge::Left,
+            open: false,
+            powered: false,
+        }),
+    },
+    Item {
+        id: 639,
+        name: "minecraft:iron_trapdoor",
+        block: Some(Block::Trapdoor {
+            kind: DoorKind::Iron,
+            facing: Direction::North,
+            half: Half::Lower,
+            open: false,
+            powered: false,
+        }),
+    },
+    Item {
+        id: 640,
+        name: "minecraft:oak_trapdoor",
+        block: Some(Block::Trapdoor {
+            kind: DoorKind::Oak,
+            facing: Direction::North,
+            half: Half::Lower,
+            open: false,
+            powered: false,
+        }),
+    },
+];
+
+impl Item {
+    pub fn from_id(id: i32) -> Option<&'static Self> {
+        ITEMS.iter().find(|i| i.id == id)
+    }
+
+    pub fn from_name(name: &str) -> Option<&'static Self> {
+        ITEMS.iter().find(|i| i.name == name)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ItemStack {
+    pub id: i32,
+    pub count: u8,
+    pub nbt: Option<NbtCompound>,
+}
+
+impl ItemStack {
+    pub fn item(&self) -> Option<&'static Item> {
+        Item::from_id(self.id)
+    }
+
+    pub fn block(&self) -> Option<Block> {
+        self.item().and_then(|i| i.block)
+    }
+
+    /// Every item the server places stacks up to 64, and other items are
+    /// taken to as well.
+    pub fn max_count(&self) -> u8 {
+        64
+    }
+
+    /// Whether the two stacks hold the same kind of item and could merge.
+    pub fn is_same_item(&self, other: &ItemStack) -> bool {
+        self.id == other.id && self.nbt == other.nbt
+    }
+}
@@ -1,88 +1,528 @@
+pub mod access;
+pub mod chat;
+pub mod command;
 pub mod config;
 pub mod client;
+pub mod debug;
+pub mod edit;
+pub mod inventory;
+pub mod item;
+pub mod network;
 pub mod packet;
+pub mod permission;
+pub mod player;
+pub mod plot;
+pub mod plugin;
+pub mod query;
+pub mod rcon;
+pub mod redstone;
+pub mod schematic;
+pub mod world;
 
-use std::net::{TcpListener, TcpStream};
-use std::sync::mpsc;
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc};
 use std::thread;
+use std::time::{Duration, Instant};
+use client::{ClientId, Clients, Lifecycle};
+
+const TICK_DURATION: Duration = Duration::from_millis(50);
 
 pub struct ServerClient {
-    pub tcp: TcpStream,
-    pub packets: mpsc::Receiver<Box<dyn packet::ServerPacket>>,
+    /// Packets sent since the last flush, which go out together.
+    buffer: Vec<u8>,
+    /// Flushed packets on their way to the client, see [`network`].
+    outgoing: tokio::sync::mpsc::UnboundedSender<Vec<u8>>,
+    /// Bytes flushed but not written to the client yet.
+    queued: Arc<AtomicUsize>,
+    kill: Arc<tokio::sync::Notify>,
+    pub address: SocketAddr,
+    pub state: client::State,
+    /// Whether the connection is gone or being closed.
+    pub closed: bool,
+    pub player: Option<player::Player>,
 }
 
 impl ServerClient {
+    /// Adds a packet to the ones sent with the next flush. Packets to a
+    /// closed connection are dropped.
     pub fn send_packet(&mut self, packet: &impl packet::ClientPacket) -> std::io::Result<()> {
-        use std::io::Write;
-        packet.write(&mut self.tcp)?;
-        self.tcp.flush()?;
+        if self.closed {
+            return Ok(());
+        }
+
+        packet.write(&mut self.buffer)?;
         info!("sent packet {:?}", packet);
         Ok(())
     }
+
+    /// Hands the packets sent since the last flush to the network. Returns
+    /// false if the client has more than `cap` bytes it hasn't received yet.
+    pub fn flush(&mut self, cap: usize) -> bool {
+        if self.buffer.is_empty() {
+            return true;
+        }
+
+        let len = self.buffer.len();
+        let queued = self.queued.fetch_add(len, Ordering::SeqCst) + len;
+        let _ = self.outgoing.send(std::mem::take(&mut self.buffer));
+        queued <= cap
+    }
+
+    /// Closes the connection once the packets sent so far are.
+    pub fn close(&mut self) {
+        if !self.closed {
+            self.flush(usize::MAX);
+            self.closed = true;
+            let _ = self.outgoing.send(Vec::new());
+        }
+    }
+
+    /// Drops the connection right away, for a client too far behind to
+    /// receive anything more.
+    pub fn kill(&mut self) {
+        self.closed = true;
+        self.buffer.clear();
+        self.kill.notify_one();
+    }
+
+    pub fn ip(&self) -> String {
+        self.address.ip().to_string()
+    }
 }
 
 pub struct Server {
-    pub new_clients: mpsc::Receiver<ServerClient>,
-    pub old_clients: Vec<ServerClient>,
+    pub events: mpsc::Receiver<network::Event>,
+    pub clients: Clients,
 
     pub config: config::Config,
+    pub world: world::World,
+    /// Claims of the plot world, empty unless plots are enabled.
+    pub plots: plot::Plots,
+    pub ops: permission::Ops,
+    /// The whitelist and bans.
+    pub access: access::Access,
+    pub bus: plugin::Bus,
+    throttle: client::Throttle,
+    /// The server icon as a data URI, if there is one.
+    pub icon: Option<String>,
+    /// What the console was told while running a command, see
+    /// [`command::run_console`].
+    console: Option<Vec<String>>,
+    scripts: plugin::script::Scripts,
+    /// Threads the plots tick on, if there are plots and cores to spare.
+    workers: Option<redstone::island::Workers>,
+    next_entity_id: i32,
+
+    /// Who started the running trace and profile, and where they report to.
+    pub trace_output: Option<(ClientId, debug::Output)>,
+    pub profile_output: Option<(ClientId, debug::Output)>,
+    last_save: Instant,
 }
 
 impl Server {
-    fn listen(address: String, clients: mpsc::Sender<ServerClient>) {
-        let listener = TcpListener::bind(address).unwrap();
+    pub fn new(config: config::Config) -> Self {
+        let (events_t, events_r) = mpsc::channel();
 
-        loop {
-            let (socket, ip) = listener.accept().unwrap();
-            info!("new client from {}", ip);
+        let mut world = Self::load_world(&config.world.path);
+        let mut plots = plot::Plots::default();
+        let mut workers = None;
+        if config.plots.enabled {
+            world.plots = Some(plot::Layout::new(&config.plots));
+            plots = plot::Plots::load(plot::Plots::path_for(&config.world.path)).unwrap_or_else(|e| {
+                error!("{}", e);
+                std::process::exit(1);
+            });
+            workers = redstone::island::Workers::new(config.plots.threads);
+        }
 
-            let (packets_t, packets_r) = mpsc::channel();
-            let socket_2 = socket.try_clone().unwrap();
+        let ops = permission::Ops::load(config.permissions.ops.clone()).unwrap_or_else(|e| {
+            error!("{}", e);
+            std::process::exit(1);
+        });
 
-            let mut client = client::Client {
-                tcp: socket,
-                state: client::State::Handshake,
-                packets: packets_t
-            };
+        let access = access::Access::load(&config.access).unwrap_or_else(|e| {
+            error!("{}", e);
+            std::process::exit(1);
+        });
 
-            thread::spawn(move || client.listen());
+        let icon = packet::status::load_icon(&config.status.icon).unwrap_or_else(|e| {
+            warn!("{}, showing no icon", e);
+            None
+        });
 
-            clients.send(ServerClient {
-                tcp: socket_2,
-                packets: packets_r
-            }).unwrap();
+        if config.rcon.enabled {
+            let (rcon, events) = (config.rcon.clone(), events_t.clone());
+            thread::Builder::new().name("rcon".to_string()).spawn(move || rcon::run(rcon, events)).unwrap();
         }
-    }
 
-    pub fn new(config: config::Config) -> Self {
-        let (clients_t, clients_r) = mpsc::channel();
+        if config.query.enabled {
+            let (query, address, events) = (config.query.clone(), config.address.clone(), events_t.clone());
+            thread::Builder::new().name("query".to_string()).spawn(move || query::run(query, address, events)).unwrap();
+        }
 
         let address = config.address.clone();
-        thread::spawn(move || Self::listen(address, clients_t));
+        let limits = config.connection.clone();
+        thread::Builder::new().name("network".to_string()).spawn(move || network::run(address, limits, events_t)).unwrap();
+
+        let mut server = Self {
+            events: events_r,
+            clients: Clients::default(),
 
-        Self {
-            new_clients: clients_r,
-            old_clients: Vec::new(),
+            config,
+            world,
+            plots,
+            ops,
+            access,
+            bus: plugin::Bus::default(),
+            throttle: client::Throttle::default(),
+            icon,
+            console: None,
+            scripts: plugin::script::Scripts::default(),
+            workers,
+            next_entity_id: 0,
+
+            trace_output: None,
+            profile_output: None,
+            last_save: Instant::now(),
+        };
+        server.enable_plugins();
+        server.reload_scripts();
+        server
+    }
 
-            config
+    /// Reads the world file, or starts a new world when there is none yet.
+    /// A broken world file stops the server rather than being saved over.
+    pub fn load_world(path: &str) -> world::World {
+        if !std::path::Path::new(path).exists() {
+            info!("{} doesn't exist yet, starting a new world", path);
+            return world::World::new();
+        }
+
+        match world::World::load(path) {
+            Ok(world) => {
+                info!("loaded the world from {}", path);
+                world
+            },
+            Err(e) => {
+                error!("{}", e);
+                std::process::exit(1);
+            },
         }
     }
 
+    pub fn save_world(&mut self) -> Result<(), String> {
+        self.last_save = Instant::now();
+        self.world.save(&self.config.world.path)?;
+        info!("saved the world to {}", self.config.world.path);
+        Ok(())
+    }
+
+    pub fn new_entity_id(&mut self) -> i32 {
+        self.next_entity_id += 1;
+        self.next_entity_id
+    }
+
     pub fn update(&mut self) {
+        let mut next_tick = Instant::now();
+
         loop {
-            self.update_clients();
+            // waits for the clients until the next tick is due
+            match self.events.recv_timeout(next_tick.saturating_duration_since(Instant::now())) {
+                Ok(event) => self.handle_event(event),
+                Err(mpsc::RecvTimeoutError::Timeout) => {},
+                Err(mpsc::RecvTimeoutError::Disconnected) => {
+                    error!("the network thread stopped");
+                    std::process::exit(1);
+                },
+            }
+
+            if Instant::now() >= next_tick {
+                redstone::island::tick(&mut self.world, self.workers.as_ref());
+                self.run_tasks();
+                self.refresh_scripts();
+                if self.bus.has_listeners(plugin::Kind::RedstoneUpdate) {
+                    for pos in self.world.changes.clone() {
+                        self.fire(&plugin::Event::RedstoneUpdate { pos });
+                    }
+                }
+                self.finish_debugging();
+                self.update_overlays();
+                self.refresh_access();
+                next_tick += TICK_DURATION;
+                self.flush_block_changes();
+                self.flush_clients();
 
-            for i in 0..self.old_clients.len() {
-                if let Ok(p) = self.old_clients[i].packets.try_recv() {
-                    p.handle(i, self);
+                let autosave = self.config.world.autosave;
+                if autosave > 0 && self.last_save.elapsed() >= Duration::from_secs(autosave) {
+                    if let Err(e) = self.save_world() {
+                        error!("{}", e);
+                    }
                 }
             }
+
+            self.flush_block_changes();
         }
     }
 
-    pub fn update_clients(&mut self) {
-        if let Ok(client) = self.new_clients.try_recv() {
-            self.old_clients.push(client);
+    fn handle_event(&mut self, event: network::Event) {
+        match event {
+            network::Event::Connected(id, client) => {
+                self.clients.insert(id, *client);
+                self.lifecycle(id, Lifecycle::Connected);
+            },
+            network::Event::Packet(id, packet) => {
+                if self.clients.get(id).is_some_and(|c| !c.closed) {
+                    packet.handle(id, self);
+                }
+            },
+            network::Event::StateChanged(id, state) => {
+                let Some(client) = self.clients.get_mut(id) else { return };
+                client.state = state;
+                self.lifecycle(id, Lifecycle::StateChanged(state));
+            },
+            network::Event::Disconnected(id) => {
+                self.lifecycle(id, Lifecycle::Disconnected);
+                self.clients.remove(id);
+            },
+            network::Event::Console(line, reply) => {
+                let _ = reply.send(command::run_console(self, &line));
+            },
+            network::Event::Status(reply) => {
+                let _ = reply.send(packet::status::ServerStatus::new(self));
+            },
+        }
+    }
+
+    /// Sends what every client got since the last flush, dropping the ones
+    /// too far behind.
+    pub fn flush_clients(&mut self) {
+        let cap = self.config.connection.send_buffer;
+        for client in self.clients.values_mut().filter(|c| !c.closed) {
+            if client.flush(cap) {
+                continue;
+            }
+
+            let name = client.player.as_ref().map_or_else(|| client.ip(), |p| p.name.clone());
+            warn!("{} fell too far behind, disconnecting them", name);
+            client.kill();
+        }
+    }
+
+    /// Sends every changed block to the players that can see it.
+    pub fn flush_block_changes(&mut self) {
+        // block actions go first, so pistons start moving before their blocks change
+        for event in std::mem::take(&mut self.world.block_actions) {
+            let packet = packet::play::BlockActionPacket {
+                location: event.pos,
+                action_id: event.id,
+                action_param: event.param,
+                block_type: event.block,
+            };
+
+            for client in self.clients.values_mut() {
+                if !client.player.as_ref().is_some_and(|p| p.can_see(event.pos.chunk())) {
+                    continue;
+                }
+
+                if let Err(e) = client.send_packet(&packet) {
+                    error!("failed to send block action: {}", e);
+                }
+            }
+        }
+
+        // blocks in the same chunk section go out together, so large edits don't flood clients
+        let mut sections: BTreeMap<(i32, i32, i32), Vec<(world::BlockPos, u32)>> = BTreeMap::new();
+        for pos in std::mem::take(&mut self.world.changes) {
+            let section = (pos.x >> 4, pos.y >> 4, pos.z >> 4);
+            sections.entry(section).or_default().push((pos, self.world.get_block_id(pos)));
+        }
+
+        for (section, blocks) in sections {
+            let chunk = (section.0, section.2);
+
+            for client in self.clients.values_mut() {
+                if !client.player.as_ref().is_some_and(|p| p.can_see(chunk)) {
+                    continue;
+                }
+
+                let result = match blocks[..] {
+                    [(location, block)] => client.send_packet(&packet::play::BlockChangePacket { location, block }),
+                    _ => client.send_packet(&packet::play::MultiBlockChangePacket { section, blocks: blocks.clone() }),
+                };
+
+                if let Err(e) = result {
+                    error!("failed to send block changes: {}", e);
+                }
+            }
+        }
+    }
+
+    /// Shows a system message to one player. Messages to the console are
+    /// logged, or kept while it runs a command.
+    pub fn send_message(&mut self, client_id: ClientId, message: &chat::Component) {
+        if client_id == client::CONSOLE {
+            match &mut self.console {
+                Some(output) => output.push(message.plain()),
+                None => {
+                    info!("{}", message.plain());
+                },
+            }
+            return;
+        }
+
+        let message = message.to_json().dump();
+        self.clients[client_id].send_packet(&packet::play::ChatPacket { message, position: 1 }).unwrap();
+    }
+
+    /// Players in the world, not counting the ones leaving.
+    pub fn online(&self) -> usize {
+        self.clients.values().filter(|c| !c.closed && c.player.is_some()).count()
+    }
+
+    /// The client of the player with a name, which is matched ignoring case.
+    pub fn find_player(&self, name: &str) -> Option<ClientId> {
+        self.clients.iter()
+            .find(|(_, c)| !c.closed && c.player.as_ref().is_some_and(|p| p.name.eq_ignore_ascii_case(name)))
+            .map(|(id, _)| id)
+    }
+
+    /// Shows a client why it's being disconnected and closes its connection.
+    /// Players logging in are refused, players in the world are kicked.
+    pub fn disconnect(&mut self, client_id: ClientId, reason: &chat::Component) {
+        let client = &mut self.clients[client_id];
+        let reason = reason.to_json().dump();
+        let result = match &client.player {
+            Some(player) => {
+                info!("kicked {}", player.name);
+                client.send_packet(&packet::play::DisconnectPacket { reason })
+            },
+            None => client.send_packet(&packet::login::LoginDisconnectPacket { reason }),
+        };
+
+        if let Err(e) = result {
+            error!("failed to send disconnect: {}", e);
+        }
+        // packets it sent meanwhile are dropped
+        client.close();
+    }
+
+    /// Puts a player that just logged in into the world.
+    pub fn spawn_player(&mut self, client_id: ClientId, mut player: player::Player) {
+        if let Some(layout) = self.world.plots {
+            (player.x, player.y, player.z) = layout.spawn();
+            player.chunk = ((player.x.floor() as i32) >> 4, (player.z.floor() as i32) >> 4);
+        }
+
+        let client = &mut self.clients[client_id];
+        let (cx, cz) = player.chunk;
+
+        client.send_packet(&packet::play::UpdateViewPositionPacket { x: cx, z: cz }).unwrap();
+        for x in cx - player::VIEW_DISTANCE..=cx + player::VIEW_DISTANCE {
+            for z in cz - player::VIEW_DISTANCE..=cz + player::VIEW_DISTANCE {
+                self.world.load_chunk(x, z);
+                client.send_packet(&packet::play::ChunkDataPacket {
+                    x,
+                    z,
+                    chunk: &self.world.chunks[&(x, z)],
+                    block_entities: self.world.client_block_entities(x, z),
+                }).unwrap();
+            }
+        }
+
+        client.send_packet(&packet::play::PlayerPositionAndLookPacket {
+            x: player.x,
+            y: player.y,
+            z: player.z,
+            yaw: player.yaw,
+            pitch: 0.0,
+            teleport_id: 0,
+        }).unwrap();
+
+        client.send_packet(&packet::play::WindowItemsPacket {
+            window_id: 0,
+            state_id: player.inventory.state_id,
+            slots: player.inventory.slots.clone(),
+            carried: None,
+        }).unwrap();
+
+        client.player = Some(player);
+    }
+
+    /// Moves a player, keeping where they look.
+    pub fn teleport(&mut self, client_id: ClientId, (x, y, z): (f64, f64, f64)) {
+        let client = &mut self.clients[client_id];
+        let Some(player) = &mut client.player else { return };
+        (player.x, player.y, player.z) = (x, y, z);
+        let (yaw, pitch) = (player.yaw, player.pitch);
+
+        client.send_packet(&packet::play::PlayerPositionAndLookPacket { x, y, z, yaw, pitch, teleport_id: 0 }).unwrap();
+        self.update_view(client_id);
+    }
+
+    /// Shows the item a player is holding to the players that can see them.
+    pub fn broadcast_equipment(&mut self, client_id: ClientId) {
+        let Some(player) = &self.clients[client_id].player else { return };
+        let chunk = player.chunk;
+        let packet = packet::play::EntityEquipmentPacket {
+            entity_id: player.entity_id,
+            main_hand: player.inventory.held().cloned(),
+        };
+
+        for (id, client) in self.clients.iter_mut() {
+            if id == client_id || !client.player.as_ref().is_some_and(|p| p.can_see(chunk)) {
+                continue;
+            }
+
+            if let Err(e) = client.send_packet(&packet) {
+                error!("failed to send equipment: {}", e);
+            }
+        }
+    }
+
+    /// Sends the chunks a player moved into view of and unloads the ones it
+    /// can't see anymore.
+    pub fn update_view(&mut self, client_id: ClientId) {
+        let client = &mut self.clients[client_id];
+        let player = match &mut client.player {
+            Some(p) => p,
+            None => return,
+        };
+
+        let old = player.chunk;
+        let new = ((player.x.floor() as i32) >> 4, (player.z.floor() as i32) >> 4);
+        if old == new {
+            return;
+        }
+
+        player.chunk = new;
+        client.send_packet(&packet::play::UpdateViewPositionPacket { x: new.0, z: new.1 }).unwrap();
+
+        let in_view = |(cx, cz): (i32, i32), x: i32, z: i32| {
+            (x - cx).abs() <= player::VIEW_DISTANCE && (z - cz).abs() <= player::VIEW_DISTANCE
+        };
+
+        for x in new.0 - player::VIEW_DISTANCE..=new.0 + player::VIEW_DISTANCE {
+            for z in new.1 - player::VIEW_DISTANCE..=new.1 + player::VIEW_DISTANCE {
+                if !in_view(old, x, z) {
+                    self.world.load_chunk(x, z);
+                    client.send_packet(&packet::play::ChunkDataPacket {
+                        x,
+                        z,
+                        chunk: &self.world.chunks[&(x, z)],
+                        block_entities: self.world.client_block_entities(x, z),
+                    }).unwrap();
+                }
+            }
+        }
+
+        for x in old.0 - player::VIEW_DISTANCE..=old.0 + player::VIEW_DISTANCE {
+            for z in old.1 - player::VIEW_DISTANCE..=old.1 + player::VIEW_DISTANCE {
+                if !in_view(new, x, z) {
+                    client.send_packet(&packet::play::UnloadChunkPacket { x, z }).unwrap();
+                }
+            }
         }
     }
 }
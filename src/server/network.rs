@@ -0,0 +1,352 @@
+//! The network side of the server. One thread runs every connection as a
+//! task, reading packets into a single channel of events the game loop
+//! waits on, and writing out what the game queues for each connection.
+
+use std::io;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc as queue, oneshot, Notify};
+use tokio::time;
+use super::ServerClient;
+use super::client::{ClientId, Pending, PendingCount, State};
+use super::config::ConnectionConfig;
+use super::packet::{self, GenericPacket, ServerPacket};
+use super::packet::status::ServerStatus;
+
+/// First byte of a ping from before 1.7.
+const LEGACY_PING: u8 = 0xfe;
+/// The kick packet legacy pings are answered with.
+const LEGACY_KICK: u8 = 0xff;
+/// Protocol version told to legacy pings, newer than any of theirs, so they
+/// show the server as one for a newer version.
+const LEGACY_PROTOCOL: i32 = 127;
+/// How long to wait for what follows the first byte of a legacy ping.
+const LEGACY_WAIT: Duration = Duration::from_millis(200);
+/// The plugin message 1.6 clients follow their ping with.
+const LEGACY_PLUGIN_MESSAGE: u8 = 0xfa;
+/// Most bytes of the plugin message taken, far more than a host name and
+/// port need.
+const LEGACY_MAX_MESSAGE: u64 = 1024;
+
+/// What happened to the connections.
+pub enum Event {
+    Connected(ClientId, Box<ServerClient>),
+    Packet(ClientId, Box<dyn ServerPacket>),
+    /// Comes after the packet that changed the state.
+    StateChanged(ClientId, State),
+    Disconnected(ClientId),
+    /// A command from the remote console, answered with what it printed.
+    Console(String, oneshot::Sender<String>),
+    /// A query asking what the server list would be told.
+    Status(oneshot::Sender<ServerStatus>),
+}
+
+/// Accepts connections on `address` until the server stops.
+pub fn run(address: String, limits: ConnectionConfig, events: mpsc::Sender<Event>) {
+    let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+    runtime.block_on(async {
+        let listener = match TcpListener::bind(&address).await {
+            Ok(listener) => listener,
+            Err(e) => {
+                error!("can't listen on {}: {}", address, e);
+                std::process::exit(1);
+            },
+        };
+
+        accept(listener, limits, events).await;
+    });
+}
+
+async fn accept(listener: TcpListener, limits: ConnectionConfig, events: mpsc::Sender<Event>) {
+    let pending = Arc::new(Mutex::new(PendingCount::default()));
+    let mut next_id = ClientId(0);
+
+    loop {
+        let (socket, ip) = match listener.accept().await {
+            Ok(connection) => connection,
+            Err(e) => {
+                warn!("failed to accept a connection: {}", e);
+                continue;
+            },
+        };
+
+        let deadline = Instant::now() + Duration::from_secs(limits.login_timeout);
+        let pending = match Pending::new(&pending, ip.ip(), limits.max_pending, limits.max_pending_per_ip, deadline) {
+            Ok(pending) => pending,
+            Err(reason) => {
+                warn!("refused the connection from {}, {}", ip, reason);
+                continue;
+            },
+        };
+
+        let (outgoing, outgoing_r) = queue::unbounded_channel();
+        let writer = Writer { outgoing: outgoing_r, queued: Arc::new(AtomicUsize::new(0)), kill: Arc::new(Notify::new()) };
+        let client = ServerClient {
+            buffer: Vec::new(),
+            outgoing,
+            queued: Arc::clone(&writer.queued),
+            kill: Arc::clone(&writer.kill),
+            address: ip,
+            state: State::Handshake,
+            closed: false,
+            player: None,
+        };
+        if events.send(Event::Connected(next_id, Box::new(client))).is_err() {
+            return;
+        }
+
+        tokio::spawn(connection(next_id, socket, writer, pending, events.clone()));
+        next_id.0 += 1;
+    }
+}
+
+/// The sending end of a connection, fed by [`ServerClient::flush`].
+struct Writer {
+    outgoing: queue::UnboundedReceiver<Vec<u8>>,
+    /// Bytes queued but not written yet.
+    queued: Arc<AtomicUsize>,
+    /// Drops the connection right away, without sending what's queued.
+    kill: Arc<Notify>,
+}
+
+/// Reads and writes the packets of one client until either side closes it.
+async fn connection(id: ClientId, socket: TcpStream, writer: Writer, pending: Pending, events: mpsc::Sender<Event>) {
+    let _disconnect = Disconnect { id, events: events.clone() };
+    let _ = socket.set_nodelay(true);
+
+    // a packet's length could start with it too, but only for packets far longer than a handshake
+    let mut first = [0];
+    let peeked = time::timeout_at(pending.deadline.into(), socket.peek(&mut first)).await;
+    if matches!(peeked, Ok(Ok(1))) && first[0] == LEGACY_PING {
+        legacy_ping(socket, &events).await;
+        return;
+    }
+
+    let (tcp_reader, tcp_writer) = socket.into_split();
+    let kill = Arc::clone(&writer.kill);
+    tokio::select! {
+        _ = read_packets(id, tcp_reader, pending, &events) => {},
+        _ = write_packets(tcp_writer, writer) => {},
+        _ = kill.notified() => {},
+    }
+}
+
+/// Tells the game a connection is gone when its task ends, even if the
+/// task panicked, so no client is left behind.
+struct Disconnect {
+    id: ClientId,
+    events: mpsc::Sender<Event>,
+}
+
+impl Drop for Disconnect {
+    fn drop(&mut self) {
+        let _ = self.events.send(Event::Disconnected(self.id));
+    }
+}
+
+/// Answers the server list ping of clients from before 1.7 with the kick
+/// packet they expect the status in, then closes the connection. Beta
+/// clients send 0xfe alone, later ones follow it with 0x01, and 1.6 ones
+/// with a plugin message after that, which isn't needed to answer.
+async fn legacy_ping(mut socket: TcpStream, events: &mpsc::Sender<Event>) {
+    let _ = socket.read_u8().await;
+    let beta = !matches!(time::timeout(LEGACY_WAIT, socket.read_u8()).await, Ok(Ok(1)));
+    info!("client sent a legacy ping{}", if beta { " from beta" } else { "" });
+
+    // closing with the plugin message unread would reset the connection,
+    // which can lose the answer on the way
+    if !beta {
+        let _ = time::timeout(LEGACY_WAIT, skip_plugin_message(&mut socket)).await;
+    }
+
+    let (reply, status) = oneshot::channel();
+    if events.send(Event::Status(reply)).is_err() {
+        return;
+    }
+    let Ok(status) = status.await else { return };
+
+    let _ = socket.write_all(&legacy_status(&status, beta)).await;
+    let _ = socket.shutdown().await;
+}
+
+/// Reads the plugin message of a 1.6 ping, if one follows.
+async fn skip_plugin_message(socket: &mut TcpStream) -> io::Result<()> {
+    if socket.read_u8().await? != LEGACY_PLUGIN_MESSAGE {
+        return Ok(());
+    }
+
+    // the channel name, in UTF-16, and the data
+    let channel = socket.read_u16().await? as u64 * 2;
+    skip(socket, channel).await?;
+    let data = socket.read_u16().await? as u64;
+    skip(socket, data).await
+}
+
+async fn skip(socket: &mut TcpStream, len: u64) -> io::Result<()> {
+    if len > LEGACY_MAX_MESSAGE {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("legacy ping has {} more bytes", len)));
+    }
+
+    let mut bytes = vec![0; len as usize];
+    socket.read_exact(&mut bytes).await.map(|_| ())
+}
+
+/// The kick packet with the status, in the format of beta clients or of
+/// the later ones.
+fn legacy_status(status: &ServerStatus, beta: bool) -> Vec<u8> {
+    // these clients show a single line
+    let motd = status.motd().lines().next().unwrap_or_default().to_string();
+    let text = if beta {
+        // beta splits on the section sign, so formatting codes go
+        let mut plain = String::new();
+        let mut chars = motd.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '\u{a7}' => {
+                    chars.next();
+                },
+                c => plain.push(c),
+            }
+        }
+        format!("{}\u{a7}{}\u{a7}{}", plain, status.online, status.max)
+    } else {
+        format!("\u{a7}1\0{}\0{}\0{}\0{}\0{}", LEGACY_PROTOCOL, status.version, motd, status.online, status.max)
+    };
+
+    let text: Vec<u16> = text.encode_utf16().collect();
+    let mut bytes = vec![LEGACY_KICK];
+    bytes.extend((text.len() as u16).to_be_bytes());
+    for unit in text {
+        bytes.extend(unit.to_be_bytes());
+    }
+
+    bytes
+}
+
+async fn read_packets(id: ClientId, mut tcp: OwnedReadHalf, pending: Pending, events: &mpsc::Sender<Event>) {
+    let mut state = State::Handshake;
+    let mut pending = Some(pending);
+
+    loop {
+        // a connection that doesn't log in in time is dropped, however slowly it sends
+        let frame = match &pending {
+            Some(p) => match time::timeout_at(p.deadline.into(), read_frame(&mut tcp)).await {
+                Ok(frame) => frame,
+                Err(_) => {
+                    info!("client took too long to log in");
+                    return;
+                },
+            },
+            None => read_frame(&mut tcp).await,
+        };
+
+        let old_state = state;
+        let packet = match frame.and_then(|f| packet::parse_packet(GenericPacket::new(&f)?, &mut state)) {
+            Ok(packet) => packet,
+            Err(e) => {
+                info!("client is dead: {}", e);
+                return;
+            },
+        };
+
+        info!("client sent packet {:?} with state {:?}", packet, state);
+        if let State::Play = state {
+            pending = None;
+        }
+
+        if events.send(Event::Packet(id, packet)).is_err() {
+            return;
+        }
+        if state != old_state && events.send(Event::StateChanged(id, state)).is_err() {
+            return;
+        }
+    }
+}
+
+/// Reads a packet without its length. Like in vanilla, the length takes at
+/// most 3 bytes, so no packet is longer than 2 MiB.
+async fn read_frame(tcp: &mut OwnedReadHalf) -> io::Result<Vec<u8>> {
+    let mut size = 0;
+    for i in 0..3 {
+        let byte = tcp.read_u8().await?;
+        size |= (byte as usize & 0x7f) << (i * 7);
+        if byte & 0x80 != 0 {
+            continue;
+        }
+
+        let mut frame = vec![0; size];
+        tcp.read_exact(&mut frame).await?;
+        return Ok(frame);
+    }
+
+    Err(io::Error::new(io::ErrorKind::InvalidData, "packet is too long"))
+}
+
+/// Sends what the game queues, until it queues an empty message to close
+/// the connection.
+async fn write_packets(mut tcp: OwnedWriteHalf, mut writer: Writer) {
+    while let Some(bytes) = writer.outgoing.recv().await {
+        if bytes.is_empty() {
+            break;
+        }
+
+        if let Err(e) = tcp.write_all(&bytes).await {
+            info!("failed to send to client: {}", e);
+            return;
+        }
+        writer.queued.fetch_sub(bytes.len(), Ordering::SeqCst);
+    }
+
+    let _ = tcp.shutdown().await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status() -> ServerStatus {
+        ServerStatus {
+            description: json::object! { text: "A \u{a7}cred\u{a7}r server", extra: ["\n", "second line"] },
+            version: "1.18.2",
+            online: 3,
+            max: 20,
+            players: Vec::new(),
+            map: "world".to_string(),
+        }
+    }
+
+    /// The text of a kick packet, checking its length.
+    fn text(bytes: &[u8]) -> String {
+        assert_eq!(bytes[0], LEGACY_KICK);
+        let len = u16::from_be_bytes([bytes[1], bytes[2]]) as usize;
+        let units: Vec<u16> = bytes[3..].chunks(2).map(|c| u16::from_be_bytes([c[0], c[1]])).collect();
+        assert_eq!(units.len(), len);
+        String::from_utf16(&units).unwrap()
+    }
+
+    #[test]
+    fn legacy_status_for_beta_drops_formatting() {
+        assert_eq!(text(&legacy_status(&status(), true)), "A red server\u{a7}3\u{a7}20");
+    }
+
+    #[test]
+    fn legacy_status_tells_version_and_players() {
+        assert_eq!(
+            text(&legacy_status(&status(), false)),
+            "\u{a7}1\u{0}127\u{0}1.18.2\u{0}A \u{a7}cred\u{a7}r server\u{0}3\u{0}20",
+        );
+    }
+
+    #[test]
+    fn legacy_status_counts_utf16_units() {
+        let mut status = status();
+        status.description = "\u{1f4a1} lamps".into();
+        let bytes = legacy_status(&status, true);
+        assert_eq!(u16::from_be_bytes([bytes[1], bytes[2]]), 13);
+        assert_eq!(text(&bytes), "\u{1f4a1} lamps\u{a7}3\u{a7}20");
+    }
+}
@@ -8,8 +8,16 @@ pub struct HandshakePacket {
     pub next: i32
 }
 
+/// Protocol version of 1.18.2.
+pub const PROTOCOL_VERSION: i32 = 758;
+pub const VERSION_NAME: &str = "1.18.2";
+
 impl ServerPacket for HandshakePacket {
-    fn handle(&self, _client_idx: usize, _server: &mut super::super::Server) { }
+    fn handle(&self, _client_id: ClientId, _server: &mut super::super::Server) {
+        if self.next == 2 && self.proto != PROTOCOL_VERSION {
+            warn!("client connecting to {}:{} uses protocol {}", self.address, self.port, self.proto);
+        }
+    }
 }
 
 impl HandshakePacket {
@@ -1,4 +1,6 @@
 use super::*;
+use super::super::chat::Component;
+use super::super::plugin::{Event, Outcome};
 use quartz_nbt::{NbtCompound, NbtList, compound};
 
 #[derive(Debug)]
@@ -7,9 +9,24 @@ pub struct LoginStartPacket {
 }
 
 impl ServerPacket for LoginStartPacket {
-    fn handle(&self, client_idx: usize, server: &mut super::super::Server) {
-        server.old_clients[client_idx].send_packet(&LoginSuccessPacket {
-            uuid: 0,
+    fn handle(&self, client_id: ClientId, server: &mut super::super::Server) {
+        let wait = std::time::Duration::from_millis(server.config.connection.throttle);
+        let ip = server.clients[client_id].address.ip();
+        if !server.throttle.allow(ip, wait) {
+            info!("throttled the login of {} from {}", self.player_name, ip);
+            server.disconnect(client_id, &Component::text("Connection throttled! Please wait before reconnecting."));
+            return;
+        }
+
+        let join = Event::Join { client: client_id, name: self.player_name.clone() };
+        if let Outcome::Cancel(reason) = server.fire(&join) {
+            let reason = reason.unwrap_or_else(|| Component::text("You may not join this server"));
+            server.disconnect(client_id, &reason);
+            return;
+        }
+
+        server.clients[client_id].send_packet(&LoginSuccessPacket {
+            uuid: super::super::player::offline_uuid(&self.player_name),
             player_name: self.player_name.clone()
         }).unwrap();
 
@@ -31,8 +48,8 @@ impl ServerPacket for LoginStartPacket {
                                 "effects": "minecraft:overworld",
                                 "has_raids": 0_i8,
                                 "min_y": 0_i32,
-                                "height": 1_i32,
-                                "logical_height": 0_i32,
+                                "height": 256_i32,
+                                "logical_height": 256_i32,
                                 "coordinate_scale": 1.0_f64,
                                 "ultrawarm": 0_i8,
                                 "has_ceiling": 0_i8,
@@ -65,8 +82,9 @@ impl ServerPacket for LoginStartPacket {
                 },
             }.to_pretty_snbt());
 
-        server.old_clients[client_idx].send_packet(&JoinGamePacket {
-            eid: 0,
+        let eid = server.new_entity_id();
+        server.clients[client_id].send_packet(&JoinGamePacket {
+            eid,
             hardcore: false,
             cgm: 1,
             pgm: -1,
@@ -89,8 +107,8 @@ impl ServerPacket for LoginStartPacket {
                                 "effects": "minecraft:overworld",
                                 "has_raids": 0_i8,
                                 "min_y": 0_i32,
-                                "height": 1_i32,
-                                "logical_height": 0_i32,
+                                "height": 256_i32,
+                                "logical_height": 256_i32,
                                 "coordinate_scale": 1.0_f64,
                                 "ultrawarm": 0_i8,
                                 "has_ceiling": 0_i8,
@@ -133,22 +151,25 @@ impl ServerPacket for LoginStartPacket {
                 "effects": "minecraft:overworld",
                 "has_raids": 0_i8,
                 "min_y": 0_i32,
-                "height": 1_i32,
-                "logical_height": 0_i32,
+                "height": 256_i32,
+                "logical_height": 256_i32,
                 "coordinate_scale": 1.0_f64,
                 "ultrawarm": 0_i8,
                 "has_ceiling": 0_i8,
             },
             dim_current: "minecraft:ocean".to_string(),
             seed_hash: 0,
-            max_players: 1,
-            view_dist: 8, // TODO:
-            sim_dist: 8,
+            max_players: server.config.connection.max_players as i32,
+            view_dist: super::super::player::VIEW_DISTANCE,
+            sim_dist: super::super::player::VIEW_DISTANCE,
             reduce_debug: false,
             respawn_screen: false,
             debug_world: false,
             flat_world: true,
         }).unwrap();
+
+        server.spawn_player(client_id, super::super::player::Player::new(eid, self.player_name.clone()));
+        server.lifecycle(client_id, super::super::client::Lifecycle::LoggedIn);
     }
 }
 
@@ -163,6 +184,22 @@ impl LoginStartPacket {
     }
 }
 
+/// Refuses a player that is logging in, showing them why.
+#[derive(Debug)]
+pub struct LoginDisconnectPacket {
+    /// Chat component as json.
+    pub reason: String,
+}
+
+impl ClientPacket for LoginDisconnectPacket {
+    fn write<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        let mut p = PacketWriter::new(0x00);
+        p.write_string(&self.reason);
+
+        p.export(w)
+    }
+}
+
 #[derive(Debug)]
 pub struct LoginSuccessPacket {
     pub uuid: u128,
@@ -1,5 +1,7 @@
 use std::io::{self, Read, Write};
-use super::client::State;
+use super::client::{ClientId, State};
+use super::item::ItemStack;
+use super::world::BlockPos;
 use quartz_nbt as nbt;
 
 #[derive(Debug, Clone)]
@@ -8,6 +10,10 @@ pub struct GenericPacket {
     pub data: Vec<u8>
 }
 
+/// Longest string in bytes, the 32767 characters vanilla allows at up to 4
+/// bytes each.
+const MAX_STRING: i32 = 32767 * 4;
+
 impl<T: Read + Sized> PacketReader for T {}
 pub trait PacketReader where Self: Read + Sized {
     fn read_bytes(&mut self, size: usize) -> io::Result<Vec<u8>> {
@@ -25,77 +31,104 @@ pub trait PacketReader where Self: Read + Sized {
         Ok(self._read_varint_with_size()?.0)
     }
 
-    #[inline(never)]
-    fn read_varint_with_size(&mut self) -> io::Result<(i32, usize)> {
-        self._read_varint_with_size()
-    }
-
     #[inline(always)]
     fn _read_varint_with_size(&mut self) -> io::Result<(i32, usize)> {
         let mut result = 0i32;
-        let mut size = 0;
-        for i in 0.. {
+        for i in 0..5 {
             let read = self.read_be::<u8, 1>()?;
             result |= (read as i32 & 0x7f) << (i * 7);
-            if i > 5 {
-                panic!("too big");
-            }
-
-            size = i + 1;
-
             if read & 0x80 == 0 {
-                break;
+                return Ok((result, i + 1));
             }
         }
 
-        Ok((result, size))
+        Err(io::Error::new(io::ErrorKind::InvalidData, "varint is too big"))
     }
 
     fn read_string(&mut self) -> io::Result<String> {
         let size = self.read_varint()?;
-        Ok(String::from_utf8(self.read_bytes(size as usize)?).unwrap())
-    }
-
-    fn read_generic_packet(&mut self) -> io::Result<GenericPacket> {
-        let size = self.read_varint()?;
-        let (typ, ts) = self.read_varint_with_size()?;
+        if !(0..=MAX_STRING).contains(&size) {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("string has a length of {}", size)));
+        }
 
-        Ok(GenericPacket {
-            typ,
-            data: self.read_bytes((size - ts as i32) as usize)?,
-        })
+        String::from_utf8(self.read_bytes(size as usize)?).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
     }
 
-    fn read_packet(&mut self, state: &mut State) -> io::Result<Box<dyn ServerPacket>> {
-        let packet = self.read_generic_packet()?;
+    fn read_position(&mut self) -> io::Result<BlockPos> {
+        let p = self.read_be::<i64, 8>()?;
+        Ok(BlockPos::new((p >> 38) as i32, (p << 52 >> 52) as i32, (p << 26 >> 38) as i32))
+    }
 
-        match state {
-            State::Handshake if packet.typ == 0 => {
-                let packet = HandshakePacket::new(packet)?;
-                *state = match packet.next {
-                    1 => State::Status,
-                    2 => State::Login,
-                    _ => State::Handshake
-                };
+    fn read_slot(&mut self) -> io::Result<Option<ItemStack>> {
+        if !self.read_be::<bool, 1>()? {
+            return Ok(None);
+        }
 
-                Ok(Box::new(packet))
+        let id = self.read_varint()?;
+        let count = self.read_be::<u8, 1>()?;
+        let nbt = match self.read_be::<u8, 1>()? {
+            0 => None,
+            tag => {
+                let tag = [tag];
+                let mut data = (&tag[..]).chain(&mut *self);
+                let (nbt, _) = nbt::io::read_nbt(&mut data, nbt::io::Flavor::Uncompressed)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+                Some(nbt)
             },
-            State::Status if packet.typ == 0 => Ok(Box::new(StatusRequestPacket::new())),
-            State::Status if packet.typ == 1 => Ok(Box::new(PingPacket::new(packet)?)),
-            State::Login if packet.typ == 0 => {
-                *state = State::Login;
-                Ok(Box::new(LoginStartPacket::new(packet)?))
-            },
-            _ => {
-                warn!("unknown packet at state {:?} and content {:?}", state, packet);
-                Ok(Box::new(UnknownPacket))
-            },
-        }
+        };
+
+        Ok(Some(ItemStack { id, count, nbt }))
+    }
+}
+
+impl GenericPacket {
+    /// Splits a packet, without its length, into its type and data.
+    pub fn new(frame: &[u8]) -> io::Result<Self> {
+        let mut data = frame;
+        let typ = data.read_varint()?;
+        Ok(Self { typ, data: data.to_vec() })
+    }
+}
+
+/// Reads what a client sent, which depends on and can change the state of
+/// the connection.
+pub fn parse_packet(packet: GenericPacket, state: &mut State) -> io::Result<Box<dyn ServerPacket>> {
+    match state {
+        State::Handshake if packet.typ == 0 => {
+            let packet = HandshakePacket::new(packet)?;
+            *state = match packet.next {
+                1 => State::Status,
+                2 => State::Login,
+                _ => State::Handshake
+            };
+
+            Ok(Box::new(packet))
+        },
+        State::Status if packet.typ == 0 => Ok(Box::new(StatusRequestPacket::new())),
+        State::Status if packet.typ == 1 => Ok(Box::new(PingPacket::new(packet)?)),
+        State::Login if packet.typ == 0 => {
+            *state = State::Play;
+            Ok(Box::new(LoginStartPacket::new(packet)?))
+        },
+        State::Play if packet.typ == 0x03 => Ok(Box::new(ChatMessagePacket::new(packet)?)),
+        State::Play if packet.typ == 0x11 => Ok(Box::new(PlayerPositionPacket::new(packet)?)),
+        State::Play if packet.typ == 0x12 => Ok(Box::new(PlayerPositionAndRotationPacket::new(packet)?)),
+        State::Play if packet.typ == 0x13 => Ok(Box::new(PlayerRotationPacket::new(packet)?)),
+        State::Play if packet.typ == 0x17 => Ok(Box::new(PickItemPacket::new(packet)?)),
+        State::Play if packet.typ == 0x1a => Ok(Box::new(PlayerDiggingPacket::new(packet)?)),
+        State::Play if packet.typ == 0x1b => Ok(Box::new(EntityActionPacket::new(packet)?)),
+        State::Play if packet.typ == 0x25 => Ok(Box::new(HeldItemChangePacket::new(packet)?)),
+        State::Play if packet.typ == 0x28 => Ok(Box::new(CreativeInventoryActionPacket::new(packet)?)),
+        State::Play if packet.typ == 0x2e => Ok(Box::new(PlayerBlockPlacementPacket::new(packet)?)),
+        _ => {
+            warn!("unknown packet at state {:?} and content {:?}", state, packet);
+            Ok(Box::new(UnknownPacket))
+        },
     }
 }
 
 pub trait ServerPacket where Self: std::fmt::Debug + Send {
-    fn handle(&self, client_idx: usize, server: &mut super::Server);
+    fn handle(&self, client_id: ClientId, server: &mut super::Server);
 }
 
 pub struct PacketWriter {
@@ -116,9 +149,9 @@ impl PacketWriter {
     }
 
     pub fn write_varint<T: Into<i32>>(&mut self, d: T) {
-        let mut d = d.into();
-        for _ in 1.. {
-            if d & 0x80 == 0 {
+        let mut d = d.into() as u32;
+        loop {
+            if d & !0x7f == 0 {
                 self.write_be(d as u8);
                 return;
             }
@@ -128,6 +161,25 @@ impl PacketWriter {
         }
     }
 
+    pub fn write_varlong<T: Into<i64>>(&mut self, d: T) {
+        let mut d = d.into() as u64;
+        loop {
+            if d & !0x7f == 0 {
+                self.write_be(d as u8);
+                return;
+            }
+
+            self.write_be(d as u8 | 0x80);
+            d >>= 7;
+        }
+    }
+
+    pub fn write_position(&mut self, pos: BlockPos) {
+        self.write_be(
+            ((pos.x as i64 & 0x3ffffff) << 38) | ((pos.z as i64 & 0x3ffffff) << 12) | (pos.y as i64 & 0xfff)
+        );
+    }
+
     pub fn write_nbt_compound(
         &mut self,
         nbt: &nbt::NbtCompound,
@@ -137,16 +189,32 @@ impl PacketWriter {
         nbt::io::write_nbt(&mut self.buffer, name, nbt, flavor).unwrap();
     }
 
+    pub fn write_slot(&mut self, slot: Option<&ItemStack>) {
+        let Some(stack) = slot else {
+            self.write_be(false);
+            return;
+        };
+
+        self.write_be(true);
+        self.write_varint(stack.id);
+        self.write_be(stack.count);
+        match &stack.nbt {
+            Some(nbt) => self.write_nbt_compound(nbt, Some(""), nbt::io::Flavor::Uncompressed),
+            None => self.write_be(0_u8),
+        }
+    }
+
     pub fn write_string(&mut self, d: &str) {
         self.write_varint(d.len() as i32);
         self.buffer.extend(d.as_bytes());
     }
 
     pub fn export<W: Write>(&mut self, w: &mut W) -> io::Result<()> {
-        fn write_varint(mut d: i32) -> Vec<u8> {
+        fn write_varint(d: i32) -> Vec<u8> {
+            let mut d = d as u32;
             let mut w = Vec::new();
             loop {
-                if d & 0x80 == 0 {
+                if d & !0x7f == 0 {
                     w.push(d as u8);
                     return w;
                 }
@@ -223,6 +291,16 @@ impl BigEndianNumeric<16> for i128 {
     fn _from_be_bytes(b: [u8; 16]) -> Self { Self::from_be_bytes(b) }
 }
 
+impl BigEndianNumeric<4> for f32 {
+    fn _to_be_bytes(self) -> [u8; 4] { self.to_be_bytes() }
+    fn _from_be_bytes(b: [u8; 4]) -> Self { Self::from_be_bytes(b) }
+}
+
+impl BigEndianNumeric<8> for f64 {
+    fn _to_be_bytes(self) -> [u8; 8] { self.to_be_bytes() }
+    fn _from_be_bytes(b: [u8; 8]) -> Self { Self::from_be_bytes(b) }
+}
+
 pub trait ClientPacket where Self: std::fmt::Debug + Send {
     fn write<W: Write>(&self, w: &mut W) -> io::Result<()>;
 }
@@ -233,11 +311,81 @@ pub mod status;
 use status::*;
 pub mod login;
 use login::*;
+pub mod play;
+use play::*;
 
 #[derive(Debug)]
 pub struct UnknownPacket;
 
 impl ServerPacket for UnknownPacket {
-    fn handle(&self, _client_idx: usize, _server: &mut super::Server) {
+    fn handle(&self, _client_id: ClientId, _server: &mut super::Server) {
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn varint(value: i32) -> Vec<u8> {
+        let mut p = PacketWriter::new(0);
+        p.write_varint(value);
+        p.buffer
+    }
+
+    #[test]
+    fn varints_round_trip() {
+        for value in [0, 1, 127, 128, 255, 25565, 2097151, i32::MAX, -1, i32::MIN] {
+            let bytes = varint(value);
+            assert_eq!((&bytes[..])._read_varint_with_size().unwrap(), (value, bytes.len()));
+        }
+    }
+
+    #[test]
+    fn known_varints() {
+        assert_eq!(varint(300), [0xac, 0x02]);
+        assert_eq!(varint(-1), [0xff, 0xff, 0xff, 0xff, 0x0f]);
+    }
+
+    #[test]
+    fn long_varints_are_refused() {
+        let bytes = [0xff, 0xff, 0xff, 0xff, 0xff, 0x01];
+        assert_eq!((&bytes[..]).read_varint().unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn cut_off_varints_are_refused() {
+        let bytes = [0x80, 0x80];
+        assert_eq!((&bytes[..]).read_varint().unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn strings_round_trip() {
+        let mut p = PacketWriter::new(0);
+        p.write_string("redstone ⚡");
+        assert_eq!((&p.buffer[..]).read_string().unwrap(), "redstone ⚡");
+    }
+
+    #[test]
+    fn negative_string_lengths_are_refused() {
+        let bytes = varint(-1);
+        assert_eq!((&bytes[..]).read_string().unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn oversized_strings_are_refused() {
+        let bytes = varint(i32::MAX);
+        assert_eq!((&bytes[..]).read_string().unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn invalid_utf8_is_refused() {
+        let bytes = [0x02, 0xc3, 0x28];
+        assert_eq!((&bytes[..]).read_string().unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn strings_longer_than_the_data_are_refused() {
+        let bytes = [0x05, b'a', b'b'];
+        assert!((&bytes[..]).read_string().is_err());
     }
 }
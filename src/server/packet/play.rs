@@ -0,0 +1,731 @@
+use super::*;
+use super::super::command;
+use super::super::inventory::{HOTBAR, OFFHAND};
+use super::super::item::ItemStack;
+use super::super::plugin::Event;
+use super::super::redstone;
+use super::super::world::block::{Direction, PlaceContext};
+use super::super::world::chunk::Chunk;
+use quartz_nbt::{NbtCompound, NbtTag};
+
+#[derive(Debug)]
+pub struct ChatMessagePacket {
+    pub message: String,
+}
+
+impl ServerPacket for ChatMessagePacket {
+    fn handle(&self, client_id: ClientId, server: &mut super::super::Server) {
+        let Some(player) = &server.clients[client_id].player else { return };
+
+        if let Some(line) = self.message.strip_prefix('/') {
+            info!("{} issued command /{}", player.name, line);
+            server.allowed(&Event::Command { client: client_id, line: line.to_string() });
+            return;
+        }
+
+        server.allowed(&Event::Chat { client: client_id, message: self.message.clone() });
+    }
+}
+
+impl ChatMessagePacket {
+    pub fn new(packet: GenericPacket) -> io::Result<Self> {
+        let mut data = &packet.data[..];
+        let message = data.read_string()?;
+
+        Ok(Self { message })
+    }
+}
+
+#[derive(Debug)]
+pub struct PlayerPositionPacket {
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+}
+
+impl ServerPacket for PlayerPositionPacket {
+    fn handle(&self, client_id: ClientId, server: &mut super::super::Server) {
+        if !server.may_enter(client_id, (self.x, self.y, self.z)) {
+            return;
+        }
+
+        if let Some(player) = &mut server.clients[client_id].player {
+            (player.x, player.y, player.z) = (self.x, self.y, self.z);
+            redstone::move_entity(&mut server.world, player.entity_id, (self.x, self.y, self.z));
+        }
+
+        server.update_view(client_id);
+    }
+}
+
+impl PlayerPositionPacket {
+    pub fn new(packet: GenericPacket) -> io::Result<Self> {
+        let mut data = &packet.data[..];
+        let x = data.read_be::<f64, 8>()?;
+        let y = data.read_be::<f64, 8>()?;
+        let z = data.read_be::<f64, 8>()?;
+
+        Ok(Self { x, y, z })
+    }
+}
+
+#[derive(Debug)]
+pub struct PlayerPositionAndRotationPacket {
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+    pub yaw: f32,
+    pub pitch: f32,
+}
+
+impl ServerPacket for PlayerPositionAndRotationPacket {
+    fn handle(&self, client_id: ClientId, server: &mut super::super::Server) {
+        if !server.may_enter(client_id, (self.x, self.y, self.z)) {
+            return;
+        }
+
+        if let Some(player) = &mut server.clients[client_id].player {
+            (player.x, player.y, player.z) = (self.x, self.y, self.z);
+            (player.yaw, player.pitch) = (self.yaw, self.pitch);
+            redstone::move_entity(&mut server.world, player.entity_id, (self.x, self.y, self.z));
+        }
+
+        server.update_view(client_id);
+    }
+}
+
+impl PlayerPositionAndRotationPacket {
+    pub fn new(packet: GenericPacket) -> io::Result<Self> {
+        let mut data = &packet.data[..];
+        let x = data.read_be::<f64, 8>()?;
+        let y = data.read_be::<f64, 8>()?;
+        let z = data.read_be::<f64, 8>()?;
+        let yaw = data.read_be::<f32, 4>()?;
+        let pitch = data.read_be::<f32, 4>()?;
+
+        Ok(Self { x, y, z, yaw, pitch })
+    }
+}
+
+#[derive(Debug)]
+pub struct PlayerRotationPacket {
+    pub yaw: f32,
+    pub pitch: f32,
+}
+
+impl ServerPacket for PlayerRotationPacket {
+    fn handle(&self, client_id: ClientId, server: &mut super::super::Server) {
+        if let Some(player) = &mut server.clients[client_id].player {
+            (player.yaw, player.pitch) = (self.yaw, self.pitch);
+        }
+    }
+}
+
+impl PlayerRotationPacket {
+    pub fn new(packet: GenericPacket) -> io::Result<Self> {
+        let mut data = &packet.data[..];
+        let yaw = data.read_be::<f32, 4>()?;
+        let pitch = data.read_be::<f32, 4>()?;
+
+        Ok(Self { yaw, pitch })
+    }
+}
+
+#[derive(Debug)]
+pub struct PlayerDiggingPacket {
+    pub status: i32,
+    pub location: BlockPos,
+}
+
+impl ServerPacket for PlayerDiggingPacket {
+    fn handle(&self, client_id: ClientId, server: &mut super::super::Server) {
+        // players are in creative, so blocks break as soon as they start digging
+        if self.status != 0 {
+            return;
+        }
+
+        let wand = server.clients[client_id].player.as_ref().is_some_and(|p| p.edit.is_wand(p.inventory.held()));
+        if wand {
+            server.clients[client_id].send_packet(&AcknowledgePlayerDiggingPacket {
+                location: self.location,
+                block: server.world.get_block_id(self.location),
+                status: self.status,
+                successful: false,
+            }).unwrap();
+
+            if let Ok(message) = command::select(server, client_id, true, self.location) {
+                server.send_message(client_id, &message);
+            }
+            return;
+        }
+
+        let allowed = server.allowed(&Event::BlockBreak { client: client_id, pos: self.location });
+        if allowed {
+            redstone::break_block(&mut server.world, self.location);
+        }
+
+        server.clients[client_id].send_packet(&AcknowledgePlayerDiggingPacket {
+            location: self.location,
+            block: server.world.get_block_id(self.location),
+            status: self.status,
+            successful: allowed,
+        }).unwrap();
+    }
+}
+
+impl PlayerDiggingPacket {
+    pub fn new(packet: GenericPacket) -> io::Result<Self> {
+        let mut data = &packet.data[..];
+        let status = data.read_varint()?;
+        let location = data.read_position()?;
+
+        Ok(Self { status, location })
+    }
+}
+
+#[derive(Debug)]
+pub struct PlayerBlockPlacementPacket {
+    pub hand: i32,
+    pub location: BlockPos,
+    pub face: i32,
+    pub cursor: (f32, f32, f32),
+}
+
+impl ServerPacket for PlayerBlockPlacementPacket {
+    fn handle(&self, client_id: ClientId, server: &mut super::super::Server) {
+        let (player, face) = match (&server.clients[client_id].player, Direction::from_id(self.face)) {
+            (Some(p), Some(f)) if self.hand == 0 => (p, f),
+            _ => return,
+        };
+
+        if player.edit.is_wand(player.inventory.held()) {
+            server.world.changes.insert(self.location);
+            server.world.changes.insert(self.location.offset(face));
+            if let Ok(message) = command::select(server, client_id, false, self.location) {
+                server.send_message(client_id, &message);
+            }
+            return;
+        }
+
+        let mut pos = self.location;
+        if !server.world.get_block(pos).is_replaceable() {
+            pos = pos.offset(face);
+        }
+
+        // the client already shows its guess of the result, so correct it either way
+        server.world.changes.insert(self.location);
+        server.world.changes.insert(pos);
+
+        // sneaking lets players place against blocks they could otherwise
+        // use, and only blocks they may build at can be used
+        let usable = !player.sneaking && server.can_build(client_id, self.location);
+        if usable && redstone::use_block(&mut server.world, self.location) {
+            return;
+        }
+
+        let Some(block) = player.inventory.held().and_then(|s| s.block()) else { return };
+        if !server.world.get_block(pos).is_replaceable() {
+            return;
+        }
+
+        if !server.allowed(&Event::BlockPlace { client: client_id, pos, block }) {
+            return;
+        }
+
+        let Some(player) = &server.clients[client_id].player else { return };
+        let ctx = PlaceContext {
+            face,
+            facing: player.facing(),
+            look: player.look(),
+            cursor: self.cursor,
+            sneaking: player.sneaking,
+        };
+        if !redstone::place_block(&mut server.world, pos, block, &ctx) {
+            info!("{} can't place {:?} at {:?}", player.name, block, pos);
+        }
+    }
+}
+
+impl PlayerBlockPlacementPacket {
+    pub fn new(packet: GenericPacket) -> io::Result<Self> {
+        let mut data = &packet.data[..];
+        let hand = data.read_varint()?;
+        let location = data.read_position()?;
+        let face = data.read_varint()?;
+        let cursor_x = data.read_be::<f32, 4>()?;
+        let cursor_y = data.read_be::<f32, 4>()?;
+        let cursor_z = data.read_be::<f32, 4>()?;
+
+        Ok(Self { hand, location, face, cursor: (cursor_x, cursor_y, cursor_z) })
+    }
+}
+
+#[derive(Debug)]
+pub struct EntityActionPacket {
+    pub action: i32,
+}
+
+impl ServerPacket for EntityActionPacket {
+    fn handle(&self, client_id: ClientId, server: &mut super::super::Server) {
+        if let Some(player) = &mut server.clients[client_id].player {
+            match self.action {
+                0 => player.sneaking = true,
+                1 => player.sneaking = false,
+                _ => {},
+            }
+        }
+    }
+}
+
+impl EntityActionPacket {
+    pub fn new(packet: GenericPacket) -> io::Result<Self> {
+        let mut data = &packet.data[..];
+        let _entity_id = data.read_varint()?;
+        let action = data.read_varint()?;
+
+        Ok(Self { action })
+    }
+}
+
+#[derive(Debug)]
+pub struct PickItemPacket {
+    pub slot: i32,
+}
+
+impl ServerPacket for PickItemPacket {
+    fn handle(&self, client_id: ClientId, server: &mut super::super::Server) {
+        let client = &mut server.clients[client_id];
+        let Some(player) = &mut client.player else { return };
+        if !(9..HOTBAR as i32).contains(&self.slot) {
+            return;
+        }
+
+        let slot = self.slot as usize;
+        let target = player.inventory.pick(slot);
+        let state_id = player.inventory.state_id;
+        let selected = player.inventory.selected;
+        let items = [target, slot].map(|s| (s, player.inventory.slots[s].clone()));
+
+        for (s, item) in items {
+            client.send_packet(&SetSlotPacket { window_id: 0, state_id, slot: s as i16, item }).unwrap();
+        }
+
+        client.send_packet(&SetHeldItemPacket { slot: selected as i8 }).unwrap();
+        server.broadcast_equipment(client_id);
+    }
+}
+
+impl PickItemPacket {
+    pub fn new(packet: GenericPacket) -> io::Result<Self> {
+        let mut data = &packet.data[..];
+        let slot = data.read_varint()?;
+
+        Ok(Self { slot })
+    }
+}
+
+#[derive(Debug)]
+pub struct HeldItemChangePacket {
+    pub slot: i16,
+}
+
+impl ServerPacket for HeldItemChangePacket {
+    fn handle(&self, client_id: ClientId, server: &mut super::super::Server) {
+        let Some(player) = &mut server.clients[client_id].player else { return };
+        if !(0..9).contains(&self.slot) {
+            warn!("{} selected invalid hotbar slot {}", player.name, self.slot);
+            return;
+        }
+
+        player.inventory.selected = self.slot as usize;
+        server.broadcast_equipment(client_id);
+    }
+}
+
+impl HeldItemChangePacket {
+    pub fn new(packet: GenericPacket) -> io::Result<Self> {
+        let mut data = &packet.data[..];
+        let slot = data.read_be::<i16, 2>()?;
+
+        Ok(Self { slot })
+    }
+}
+
+#[derive(Debug)]
+pub struct CreativeInventoryActionPacket {
+    pub slot: i16,
+    pub item: Option<ItemStack>,
+}
+
+impl ServerPacket for CreativeInventoryActionPacket {
+    fn handle(&self, client_id: ClientId, server: &mut super::super::Server) {
+        let client = &mut server.clients[client_id];
+        let Some(player) = &mut client.player else { return };
+
+        // -1 drops the item, which creative players have no use for here
+        if !(1..=OFFHAND as i16).contains(&self.slot) {
+            return;
+        }
+
+        let slot = self.slot as usize;
+        if self.item.as_ref().is_some_and(|i| i.count == 0 || i.count > 64) {
+            let item = player.inventory.slots[slot].clone();
+            let state_id = player.inventory.state_id;
+            client.send_packet(&SetSlotPacket { window_id: 0, state_id, slot: self.slot, item }).unwrap();
+            return;
+        }
+
+        if let Some(item) = self.item.as_ref().and_then(|i| i.item()) {
+            info!("{} put {} in slot {}", player.name, item.name, slot);
+        }
+
+        player.inventory.slots[slot] = self.item.clone();
+        if slot == player.inventory.held_slot() {
+            server.broadcast_equipment(client_id);
+        }
+    }
+}
+
+impl CreativeInventoryActionPacket {
+    pub fn new(packet: GenericPacket) -> io::Result<Self> {
+        let mut data = &packet.data[..];
+        let slot = data.read_be::<i16, 2>()?;
+        let item = data.read_slot()?;
+
+        Ok(Self { slot, item })
+    }
+}
+
+#[derive(Debug)]
+pub struct AcknowledgePlayerDiggingPacket {
+    pub location: BlockPos,
+    pub block: u32,
+    pub status: i32,
+    pub successful: bool,
+}
+
+impl ClientPacket for AcknowledgePlayerDiggingPacket {
+    fn write<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        let mut p = PacketWriter::new(0x08);
+        p.write_position(self.location);
+        p.write_varint(self.block as i32);
+        p.write_varint(self.status);
+        p.write_be(self.successful);
+
+        p.export(w)
+    }
+}
+
+#[derive(Debug)]
+pub struct BlockActionPacket {
+    pub location: BlockPos,
+    pub action_id: u8,
+    pub action_param: u8,
+    pub block_type: i32,
+}
+
+impl ClientPacket for BlockActionPacket {
+    fn write<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        let mut p = PacketWriter::new(0x0b);
+        p.write_position(self.location);
+        p.write_be(self.action_id);
+        p.write_be(self.action_param);
+        p.write_varint(self.block_type);
+
+        p.export(w)
+    }
+}
+
+#[derive(Debug)]
+pub struct BlockChangePacket {
+    pub location: BlockPos,
+    pub block: u32,
+}
+
+impl ClientPacket for BlockChangePacket {
+    fn write<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        let mut p = PacketWriter::new(0x0c);
+        p.write_position(self.location);
+        p.write_varint(self.block as i32);
+
+        p.export(w)
+    }
+}
+
+/// Changes of several blocks in one chunk section.
+#[derive(Debug)]
+pub struct MultiBlockChangePacket {
+    /// Section coordinates, in units of 16 blocks.
+    pub section: (i32, i32, i32),
+    pub blocks: Vec<(BlockPos, u32)>,
+}
+
+impl ClientPacket for MultiBlockChangePacket {
+    fn write<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        let (x, y, z) = self.section;
+        let mut p = PacketWriter::new(0x3f);
+        p.write_be(((x as i64 & 0x3fffff) << 42) | ((z as i64 & 0x3fffff) << 20) | (y as i64 & 0xfffff));
+        p.write_be(false); // suppress light updates
+        p.write_varint(self.blocks.len() as i32);
+        for (pos, block) in &self.blocks {
+            let local = ((pos.x & 15) << 8) | ((pos.z & 15) << 4) | (pos.y & 15);
+            p.write_varlong(((*block as i64) << 12) | local as i64);
+        }
+
+        p.export(w)
+    }
+}
+
+#[derive(Debug)]
+pub struct ChatPacket {
+    /// Chat component as json.
+    pub message: String,
+    /// 0 for chat, 1 for system messages and 2 for the action bar.
+    pub position: u8,
+}
+
+impl ClientPacket for ChatPacket {
+    fn write<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        let mut p = PacketWriter::new(0x0f);
+        p.write_string(&self.message);
+        p.write_be(self.position);
+        p.write_be(0_u128); // sender
+
+        p.export(w)
+    }
+}
+
+#[derive(Debug)]
+pub struct WindowItemsPacket {
+    pub window_id: u8,
+    pub state_id: i32,
+    pub slots: Vec<Option<ItemStack>>,
+    pub carried: Option<ItemStack>,
+}
+
+impl ClientPacket for WindowItemsPacket {
+    fn write<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        let mut p = PacketWriter::new(0x14);
+        p.write_be(self.window_id);
+        p.write_varint(self.state_id);
+        p.write_varint(self.slots.len() as i32);
+        for slot in self.slots.iter() {
+            p.write_slot(slot.as_ref());
+        }
+        p.write_slot(self.carried.as_ref());
+
+        p.export(w)
+    }
+}
+
+#[derive(Debug)]
+pub struct SetSlotPacket {
+    pub window_id: i8,
+    pub state_id: i32,
+    pub slot: i16,
+    pub item: Option<ItemStack>,
+}
+
+impl ClientPacket for SetSlotPacket {
+    fn write<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        let mut p = PacketWriter::new(0x16);
+        p.write_be(self.window_id);
+        p.write_varint(self.state_id);
+        p.write_be(self.slot);
+        p.write_slot(self.item.as_ref());
+
+        p.export(w)
+    }
+}
+
+/// Kicks a player, showing them why.
+#[derive(Debug)]
+pub struct DisconnectPacket {
+    /// Chat component as json.
+    pub reason: String,
+}
+
+impl ClientPacket for DisconnectPacket {
+    fn write<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        let mut p = PacketWriter::new(0x1a);
+        p.write_string(&self.reason);
+
+        p.export(w)
+    }
+}
+
+/// Something that happened to an entity. For the player's own entity,
+/// statuses 24 to 28 set their op level from 0 to 4.
+#[derive(Debug)]
+pub struct EntityStatusPacket {
+    pub entity_id: i32,
+    pub status: i8,
+}
+
+impl ClientPacket for EntityStatusPacket {
+    fn write<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        let mut p = PacketWriter::new(0x1b);
+        p.write_be(self.entity_id);
+        p.write_be(self.status);
+
+        p.export(w)
+    }
+}
+
+#[derive(Debug)]
+pub struct UnloadChunkPacket {
+    pub x: i32,
+    pub z: i32,
+}
+
+impl ClientPacket for UnloadChunkPacket {
+    fn write<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        let mut p = PacketWriter::new(0x1d);
+        p.write_be(self.x);
+        p.write_be(self.z);
+
+        p.export(w)
+    }
+}
+
+pub struct ChunkDataPacket<'a> {
+    pub x: i32,
+    pub z: i32,
+    pub chunk: &'a Chunk,
+    /// Positions and type ids of the block entities the client draws.
+    pub block_entities: Vec<(BlockPos, i32)>,
+}
+
+impl std::fmt::Debug for ChunkDataPacket<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ChunkDataPacket").field("x", &self.x).field("z", &self.z).finish()
+    }
+}
+
+impl ClientPacket for ChunkDataPacket<'_> {
+    fn write<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        let mut sections = PacketWriter::new(0);
+        self.chunk.write(&mut sections);
+
+        let mut heightmaps = NbtCompound::new();
+        heightmaps.insert("MOTION_BLOCKING", NbtTag::LongArray(vec![0; 37]));
+
+        let mut p = PacketWriter::new(0x22);
+        p.write_be(self.x);
+        p.write_be(self.z);
+        p.write_nbt_compound(&heightmaps, Some(""), quartz_nbt::io::Flavor::Uncompressed);
+        p.write_varint(sections.buffer.len() as i32);
+        p.buffer.extend(sections.buffer);
+        p.write_varint(self.block_entities.len() as i32);
+        for (pos, kind) in &self.block_entities {
+            p.write_be(((pos.x & 15) << 4 | (pos.z & 15)) as u8);
+            p.write_be(pos.y as i16);
+            p.write_varint(*kind);
+            p.write_be(0_u8); // no nbt
+        }
+        p.write_be(true); // trust edges
+        for _ in 0..4 {
+            p.write_varint(0); // light masks
+        }
+        p.write_varint(0); // sky light arrays
+        p.write_varint(0); // block light arrays
+
+        p.export(w)
+    }
+}
+
+#[derive(Debug)]
+pub struct PlayerPositionAndLookPacket {
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+    pub yaw: f32,
+    pub pitch: f32,
+    pub teleport_id: i32,
+}
+
+impl ClientPacket for PlayerPositionAndLookPacket {
+    fn write<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        let mut p = PacketWriter::new(0x38);
+        p.write_be(self.x);
+        p.write_be(self.y);
+        p.write_be(self.z);
+        p.write_be(self.yaw);
+        p.write_be(self.pitch);
+        p.write_be(0_u8); // absolute coordinates
+        p.write_varint(self.teleport_id);
+        p.write_be(false); // dismount vehicle
+
+        p.export(w)
+    }
+}
+
+#[derive(Debug)]
+pub struct SetHeldItemPacket {
+    pub slot: i8,
+}
+
+impl ClientPacket for SetHeldItemPacket {
+    fn write<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        let mut p = PacketWriter::new(0x48);
+        p.write_be(self.slot);
+
+        p.export(w)
+    }
+}
+
+#[derive(Debug)]
+pub struct UpdateViewPositionPacket {
+    pub x: i32,
+    pub z: i32,
+}
+
+impl ClientPacket for UpdateViewPositionPacket {
+    fn write<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        let mut p = PacketWriter::new(0x49);
+        p.write_varint(self.x);
+        p.write_varint(self.z);
+
+        p.export(w)
+    }
+}
+
+#[derive(Debug)]
+pub struct EntityEquipmentPacket {
+    pub entity_id: i32,
+    pub main_hand: Option<ItemStack>,
+}
+
+impl ClientPacket for EntityEquipmentPacket {
+    fn write<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        let mut p = PacketWriter::new(0x50);
+        p.write_varint(self.entity_id);
+        p.write_be(0_u8); // main hand, and no more slots after it
+        p.write_slot(self.main_hand.as_ref());
+
+        p.export(w)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn multi_block_change_packs_positions_and_states() {
+        let packet = MultiBlockChangePacket {
+            section: (1, -1, -2),
+            blocks: vec![(BlockPos::new(17, -13, -30), 1), (BlockPos::new(16, -16, -32), 20000)],
+        };
+
+        let mut bytes = Vec::new();
+        packet.write(&mut bytes).unwrap();
+        assert_eq!(bytes, [
+            0x11, 0x3f,
+            0x00, 0x00, 0x07, 0xff, 0xff, 0xef, 0xff, 0xff,
+            0x00, 0x02,
+            0xa3, 0x22,
+            0x80, 0x80, 0x88, 0x27,
+        ]);
+    }
+}
@@ -1,29 +1,152 @@
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
 use super::*;
+use super::super::Server;
+use super::super::player;
+
+/// Players listed when hovering over the player count.
+const SAMPLE_SIZE: usize = 12;
+
+/// What the server list and queries are told about the server.
+#[derive(Debug, Clone)]
+pub struct ServerStatus {
+    /// The MOTD as a chat component, with one of the configured lines
+    /// under it.
+    pub description: json::JsonValue,
+    pub version: &'static str,
+    pub online: usize,
+    pub max: usize,
+    /// Names and UUIDs of the players online.
+    pub players: Vec<(String, String)>,
+    /// Name of the world, after its file.
+    pub map: String,
+}
+
+impl ServerStatus {
+    pub fn new(server: &Server) -> Self {
+        let players = server.clients.values()
+            .filter(|c| !c.closed)
+            .filter_map(|c| c.player.as_ref())
+            .map(|p| (p.name.clone(), player::format_uuid(player::offline_uuid(&p.name))))
+            .collect();
+        let path = std::path::Path::new(&server.config.world.path);
+
+        // the line goes next to the MOTD rather than into it, so it doesn't take on its style
+        let lines = &server.config.status.lines;
+        let description = match lines.len() {
+            0 => server.config.motd.to_json(),
+            len => {
+                let line = &lines[RandomState::new().hash_one(()) as usize % len];
+                json::object! { text: "", extra: [server.config.motd.to_json(), "\n", line.to_json()] }
+            },
+        };
+
+        Self {
+            description,
+            version: super::handshake::VERSION_NAME,
+            online: server.online(),
+            max: server.config.connection.max_players,
+            players,
+            map: path.file_stem().map_or_else(|| "world".to_string(), |s| s.to_string_lossy().into_owned()),
+        }
+    }
+
+    /// The description without formatting, for queries.
+    pub fn motd(&self) -> String {
+        let mut text = String::new();
+        plain(&self.description, &mut text);
+        text
+    }
+}
+
+/// Appends the text of a chat component and its extras.
+fn plain(component: &json::JsonValue, text: &mut String) {
+    if let Some(s) = component.as_str() {
+        text.push_str(s);
+        return;
+    }
+
+    for part in component.members() {
+        plain(part, text);
+    }
+    if let Some(s) = component["text"].as_str() {
+        text.push_str(s);
+    }
+    for extra in component["extra"].members() {
+        plain(extra, text);
+    }
+}
+
+/// Reads the server icon, a 64x64 PNG, into the data URI the status is
+/// sent with. Having no icon is fine.
+pub fn load_icon(path: &str) -> Result<Option<String>, String> {
+    let bytes = match std::fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(format!("can't read {}: {}", path, e)),
+    };
+
+    // the signature, then the IHDR chunk with the width and height
+    if bytes.len() < 24 || bytes[..8] != *b"\x89PNG\r\n\x1a\n" || bytes[12..16] != *b"IHDR" {
+        return Err(format!("{} isn't a PNG", path));
+    }
+
+    let width = u32::from_be_bytes(bytes[16..20].try_into().unwrap());
+    let height = u32::from_be_bytes(bytes[20..24].try_into().unwrap());
+    if (width, height) != (64, 64) {
+        return Err(format!("{} is {}x{}, server icons have to be 64x64", path, width, height));
+    }
+
+    Ok(Some(format!("data:image/png;base64,{}", base64(&bytes))))
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut text = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk.iter().enumerate().fold(0u32, |n, (i, b)| n | (*b as u32) << (16 - i * 8));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                text.push(ALPHABET[(n >> (18 - i * 6)) as usize & 0x3f] as char);
+            } else {
+                text.push('=');
+            }
+        }
+    }
+    text
+}
 
 #[derive(Debug)]
 pub struct StatusRequestPacket {
 }
 
 impl ServerPacket for StatusRequestPacket {
-    fn handle(&self, client_idx: usize, server: &mut super::super::Server) {
-        let responce = json::object! {
+    fn handle(&self, client_id: ClientId, server: &mut Server) {
+        let status = ServerStatus::new(server);
+        let sample: Vec<_> = status.players.iter()
+            .take(SAMPLE_SIZE)
+            .map(|(name, id)| json::object! { name: name.as_str(), id: id.as_str() })
+            .collect();
+
+        let mut responce = json::object! {
             version: {
-                name: "1.18.2",
-                protocol: 758,
+                name: status.version,
+                protocol: super::handshake::PROTOCOL_VERSION,
             },
             players: {
-                max: 1,
-                online: 0,
-                sample: [],
-            },
-            description: {
-                text: server.config.motd.clone(),
-                color: "white",
-                bold: true,
+                max: status.max,
+                online: status.online,
+                sample: sample,
             },
-        }.dump();
+            description: status.description,
+        };
+        if let Some(icon) = &server.icon {
+            responce["favicon"] = icon.as_str().into();
+        }
 
-        server.old_clients[client_idx].send_packet(&StatusRespondPacket { responce }).unwrap();
+        let responce = responce.dump();
+        server.clients[client_id].send_packet(&StatusRespondPacket { responce }).unwrap();
     }
 }
 
@@ -39,8 +162,8 @@ pub struct PingPacket {
 }
 
 impl ServerPacket for PingPacket {
-    fn handle(&self, client_idx: usize, server: &mut super::super::Server) {
-        server.old_clients[client_idx].send_packet(&PongPacket { payload: self.payload }).unwrap();
+    fn handle(&self, client_id: ClientId, server: &mut super::super::Server) {
+        server.clients[client_id].send_packet(&PongPacket { payload: self.payload }).unwrap();
     }
 }
 
@@ -0,0 +1,145 @@
+//! Who may do what. Operators come from an `ops.json` like vanilla's and
+//! may do everything their op level allows, everyone else gets the
+//! permission nodes of their groups in the config.
+
+use std::fs;
+use std::path::Path;
+use super::Server;
+use super::client::{ClientId, CONSOLE};
+use super::packet::play::EntityStatusPacket;
+use super::player;
+
+/// Highest op level, which may do anything.
+pub const MAX_LEVEL: u8 = 4;
+
+/// Entity status that sets a player's op level to 0, the next ones to 1 to 4.
+const OP_LEVEL_STATUS: i8 = 24;
+
+#[derive(Debug, Clone)]
+pub struct Op {
+    pub uuid: String,
+    pub name: String,
+    pub level: u8,
+    pub bypasses_player_limit: bool,
+}
+
+/// The operators and the file they're kept in.
+#[derive(Debug, Default)]
+pub struct Ops {
+    pub ops: Vec<Op>,
+    path: String,
+}
+
+impl Ops {
+    pub fn load(path: String) -> Result<Self, String> {
+        let mut ops = Self { ops: Vec::new(), path };
+        if !Path::new(&ops.path).exists() {
+            return Ok(ops);
+        }
+
+        let text = fs::read_to_string(&ops.path).map_err(|e| format!("can't read {}: {}", ops.path, e))?;
+        let root = json::parse(&text).map_err(|e| format!("{} isn't valid JSON: {}", ops.path, e))?;
+        for op in root.members() {
+            let (Some(name), Some(level)) = (op["name"].as_str(), op["level"].as_u8()) else {
+                return Err(format!("{} has a broken op: {}", ops.path, op.dump()));
+            };
+
+            ops.ops.push(Op {
+                uuid: op["uuid"].as_str().map_or_else(|| player::format_uuid(player::offline_uuid(name)), str::to_string),
+                name: name.to_string(),
+                level: level.min(MAX_LEVEL),
+                bypasses_player_limit: op["bypassesPlayerLimit"].as_bool().unwrap_or(false),
+            });
+        }
+
+        Ok(ops)
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let mut root = json::JsonValue::new_array();
+        for op in &self.ops {
+            let op = json::object! {
+                uuid: op.uuid.as_str(),
+                name: op.name.as_str(),
+                level: op.level,
+                bypassesPlayerLimit: op.bypasses_player_limit,
+            };
+            root.push(op).unwrap();
+        }
+
+        fs::write(&self.path, root.pretty(2)).map_err(|e| format!("can't write {}: {}", self.path, e))
+    }
+
+    /// Names are matched like vanilla does, ignoring case.
+    pub fn get(&self, name: &str) -> Option<&Op> {
+        self.ops.iter().find(|o| o.name.eq_ignore_ascii_case(name))
+    }
+
+    pub fn level(&self, name: &str) -> u8 {
+        self.get(name).map_or(0, |o| o.level)
+    }
+
+    /// Makes a player an operator, or changes their level if they are one.
+    pub fn set(&mut self, name: &str, level: u8) {
+        self.remove(name);
+        self.ops.push(Op {
+            uuid: player::format_uuid(player::offline_uuid(name)),
+            name: name.to_string(),
+            level: level.min(MAX_LEVEL),
+            bypasses_player_limit: false,
+        });
+    }
+
+    /// Returns whether they were an operator.
+    pub fn remove(&mut self, name: &str) -> bool {
+        let count = self.ops.len();
+        self.ops.retain(|o| !o.name.eq_ignore_ascii_case(name));
+        self.ops.len() != count
+    }
+}
+
+/// Whether `nodes` grant `node`. A node ending in `*` grants everything
+/// starting with what comes before it.
+pub fn grants(nodes: &[String], node: &str) -> bool {
+    nodes.iter().any(|n| match n.strip_suffix('*') {
+        Some(prefix) => node.starts_with(prefix),
+        None => n == node,
+    })
+}
+
+impl Server {
+    pub fn op_level(&self, client_id: ClientId) -> u8 {
+        if client_id == CONSOLE {
+            return MAX_LEVEL;
+        }
+
+        self.clients.player(client_id).map_or(0, |p| self.ops.level(&p.name))
+    }
+
+    /// Whether the player has the permission `node`, which operators of
+    /// `level` or higher have without it.
+    pub fn has_permission(&self, client_id: ClientId, node: &str, level: u8) -> bool {
+        if client_id == CONSOLE {
+            return true;
+        }
+
+        let Some(player) = self.clients.player(client_id) else { return false };
+        if self.ops.level(&player.name) >= level {
+            return true;
+        }
+
+        self.config.permissions.groups.iter()
+            .filter(|(name, g)| *name == "default" || g.players.iter().any(|p| p.eq_ignore_ascii_case(&player.name)))
+            .any(|(_, g)| grants(&g.nodes, node))
+    }
+
+    /// Tells the player's client their op level, which decides what it lets
+    /// them try, like opening command blocks.
+    pub fn send_op_level(&mut self, client_id: ClientId) {
+        let level = self.op_level(client_id);
+        let client = &mut self.clients[client_id];
+        let Some(player) = &client.player else { return };
+        let packet = EntityStatusPacket { entity_id: player.entity_id, status: OP_LEVEL_STATUS + level as i8 };
+        client.send_packet(&packet).unwrap();
+    }
+}
@@ -0,0 +1,132 @@
+use super::edit::Session;
+use super::inventory::Inventory;
+use super::world::block::Direction;
+
+pub const VIEW_DISTANCE: i32 = 8;
+
+pub struct Player {
+    pub entity_id: i32,
+    pub name: String,
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+    pub yaw: f32,
+    pub pitch: f32,
+    pub sneaking: bool,
+
+    /// Chunk the loaded chunks are centered around.
+    pub chunk: (i32, i32),
+    pub inventory: Inventory,
+    /// Last power overlay sent, if the player turned it on.
+    pub overlay: Option<String>,
+    pub edit: Session,
+}
+
+impl Player {
+    pub fn new(entity_id: i32, name: String) -> Self {
+        Self {
+            entity_id,
+            name,
+            x: 0.5,
+            y: 1.0,
+            z: 0.5,
+            yaw: 0.0,
+            pitch: 0.0,
+            sneaking: false,
+
+            chunk: (0, 0),
+            inventory: Inventory::new(),
+            overlay: None,
+            edit: Session::default(),
+        }
+    }
+
+    pub fn facing(&self) -> Direction {
+        Direction::from_yaw(self.yaw)
+    }
+
+    pub fn look(&self) -> Direction {
+        Direction::from_look(self.yaw, self.pitch)
+    }
+
+    pub fn can_see(&self, (x, z): (i32, i32)) -> bool {
+        (x - self.chunk.0).abs() <= VIEW_DISTANCE && (z - self.chunk.1).abs() <= VIEW_DISTANCE
+    }
+}
+
+/// The UUID vanilla servers in offline mode give a player, a version 3 UUID
+/// of `OfflinePlayer:<name>`.
+pub fn offline_uuid(name: &str) -> u128 {
+    let mut hash = md5(format!("OfflinePlayer:{}", name).as_bytes());
+    hash[6] = (hash[6] & 0x0f) | 0x30;
+    hash[8] = (hash[8] & 0x3f) | 0x80;
+    u128::from_be_bytes(hash)
+}
+
+/// A UUID the way it's written out, with hyphens.
+pub fn format_uuid(uuid: u128) -> String {
+    let hex = format!("{:032x}", uuid);
+    format!("{}-{}-{}-{}-{}", &hex[..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..])
+}
+
+fn md5(data: &[u8]) -> [u8; 16] {
+    const SHIFTS: [u32; 16] = [7, 12, 17, 22, 5, 9, 14, 20, 4, 11, 16, 23, 6, 10, 15, 21];
+    let k: Vec<u32> = (0..64).map(|i| ((i as f64 + 1.0).sin().abs() * 4294967296.0) as u32).collect();
+
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&((data.len() as u64) * 8).to_le_bytes());
+
+    let mut state: [u32; 4] = [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476];
+    for block in message.chunks(64) {
+        let words: Vec<u32> = block.chunks(4).map(|w| u32::from_le_bytes([w[0], w[1], w[2], w[3]])).collect();
+        let [mut a, mut b, mut c, mut d] = state;
+        for i in 0..64 {
+            let (f, g) = match i / 16 {
+                0 => ((b & c) | (!b & d), i),
+                1 => ((d & b) | (!d & c), (5 * i + 1) % 16),
+                2 => (b ^ c ^ d, (3 * i + 5) % 16),
+                _ => (c ^ (b | !d), (7 * i) % 16),
+            };
+
+            let rotated = a.wrapping_add(f).wrapping_add(k[i]).wrapping_add(words[g]).rotate_left(SHIFTS[i / 16 * 4 + i % 4]);
+            (a, b, c, d) = (d, b.wrapping_add(rotated), b, c);
+        }
+
+        for (s, v) in state.iter_mut().zip([a, b, c, d]) {
+            *s = s.wrapping_add(v);
+        }
+    }
+
+    let mut hash = [0; 16];
+    for (i, s) in state.iter().enumerate() {
+        hash[i * 4..i * 4 + 4].copy_from_slice(&s.to_le_bytes());
+    }
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(bytes: [u8; 16]) -> String {
+        bytes.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    #[test]
+    fn md5_matches_known_digests() {
+        assert_eq!(hex(md5(b"")), "d41d8cd98f00b204e9800998ecf8427e");
+        assert_eq!(hex(md5(b"The quick brown fox jumps over the lazy dog")), "9e107d9d372bb6826bd81d3542a419d6");
+        // a whole block of data, so the padding needs a block of its own
+        assert_eq!(hex(md5(&[b'a'; 64])), "014842d480b571495a4a0363793f7367");
+    }
+
+    #[test]
+    fn offline_uuids_match_vanilla() {
+        assert_eq!(format_uuid(offline_uuid("Notch")), "b50ad385-829d-3141-a216-7e7d7539ba7f");
+        assert_eq!(format_uuid(offline_uuid("Alice")), "10920508-d5d8-3eed-93d2-92f193afe7d7");
+    }
+}
@@ -0,0 +1,337 @@
+//! The plot world: a grid of square plots split by roads, which players claim
+//! to build in. Redstone can't reach across the roads, so every plot is a
+//! sandbox of its own.
+
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use super::Server;
+use super::client::ClientId;
+use super::chat::Component;
+use super::config::PlotConfig;
+use super::plugin::{Event, Kind, Outcome, Plugin, Priority, Registry};
+use super::redstone::compiled::{self, Region};
+use super::world::block::Block;
+use super::world::chunk::Chunk;
+use super::world::{BlockPos, World, HEIGHT};
+
+const BEDROCK: u32 = 33;
+const DIRT: u32 = 10;
+const GRASS: u32 = 9;
+/// Stone bricks.
+const ROAD: u32 = 4564;
+/// Polished andesite, along the edges of the plots.
+const BORDER: u32 = 7;
+
+/// Permission node to change blocks at all.
+const BUILD: &str = "build";
+/// Permission node to build in any plot and on the roads.
+pub const BYPASS: &str = "plot.bypass";
+
+/// Roads narrower than this would let a block touch two plots at once.
+const MIN_ROAD: i32 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct PlotId {
+    pub x: i32,
+    pub z: i32,
+}
+
+impl fmt::Display for PlotId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{};{}", self.x, self.z)
+    }
+}
+
+/// Where the plots and roads are.
+#[derive(Debug, Clone, Copy)]
+pub struct Layout {
+    /// Width of a plot.
+    pub size: i32,
+    /// Width of the roads between plots.
+    pub road: i32,
+    /// Height of the ground, which the top block is at.
+    pub height: i32,
+}
+
+impl Layout {
+    pub fn new(config: &PlotConfig) -> Self {
+        if config.road < MIN_ROAD {
+            warn!("roads of {} blocks would let redstone reach across them, using {}", config.road, MIN_ROAD);
+        }
+
+        Self {
+            size: config.size.max(1),
+            road: config.road.max(MIN_ROAD),
+            height: config.height.clamp(1, HEIGHT - 2),
+        }
+    }
+
+    fn period(&self) -> i32 {
+        self.size + self.road
+    }
+
+    /// The plot a position is in, or none on the roads.
+    pub fn plot_at(&self, pos: BlockPos) -> Option<PlotId> {
+        let (lx, lz) = (pos.x.rem_euclid(self.period()), pos.z.rem_euclid(self.period()));
+        (lx < self.size && lz < self.size).then(|| PlotId {
+            x: pos.x.div_euclid(self.period()),
+            z: pos.z.div_euclid(self.period()),
+        })
+    }
+
+    pub fn bounds(&self, id: PlotId) -> Region {
+        let (x, z) = (id.x * self.period(), id.z * self.period());
+        Region::new(BlockPos::new(x, 0, z), BlockPos::new(x + self.size - 1, HEIGHT - 1, z + self.size - 1))
+    }
+
+    /// Where players start, on the road west of the first plot.
+    pub fn spawn(&self) -> (f64, f64, f64) {
+        (-(self.road / 2) as f64 - 0.5, self.height as f64 + 1.0, 0.5)
+    }
+
+    /// The block a fresh plot world has at a position.
+    fn ground(&self, x: i32, y: i32, z: i32) -> u32 {
+        let (lx, lz) = (x.rem_euclid(self.period()), z.rem_euclid(self.period()));
+        let edge = |l: i32| l == self.size || l == self.period() - 1;
+        match y {
+            0 => BEDROCK,
+            y if y < self.height => DIRT,
+            y if y > self.height => 0,
+            _ if lx < self.size && lz < self.size => GRASS,
+            _ if (edge(lx) && lz < self.size) || (edge(lz) && lx < self.size) => BORDER,
+            _ => ROAD,
+        }
+    }
+
+    pub fn generate(&self, cx: i32, cz: i32) -> Chunk {
+        let mut chunk = Chunk::flat(0);
+        for x in 0..16 {
+            for z in 0..16 {
+                for y in 0..=self.height {
+                    let block = self.ground(cx * 16 + x as i32, y, cz * 16 + z as i32);
+                    chunk.set_block(x, y as usize, z, block);
+                }
+            }
+        }
+
+        chunk
+    }
+}
+
+/// Who owns a plot and who else may build in it or not even enter it.
+#[derive(Debug, Clone)]
+pub struct Claim {
+    pub owner: String,
+    pub trusted: Vec<String>,
+    pub denied: Vec<String>,
+}
+
+/// Names are matched like vanilla does, ignoring case.
+impl Claim {
+    pub fn is_owner(&self, name: &str) -> bool {
+        self.owner.eq_ignore_ascii_case(name)
+    }
+
+    /// Whether a player may build here, as the owner or trusted.
+    pub fn trusts(&self, name: &str) -> bool {
+        self.is_owner(name) || self.trusted.iter().any(|t| t.eq_ignore_ascii_case(name))
+    }
+
+    pub fn denies(&self, name: &str) -> bool {
+        self.denied.iter().any(|d| d.eq_ignore_ascii_case(name))
+    }
+}
+
+/// The claims of a plot world and the file they're kept in.
+#[derive(Debug, Default)]
+pub struct Plots {
+    pub claims: BTreeMap<PlotId, Claim>,
+    path: String,
+}
+
+impl Plots {
+    /// The claims file of the world at `world_path`, which sits next to it.
+    pub fn path_for(world_path: &str) -> String {
+        Path::new(world_path).with_extension("plots.json").to_string_lossy().into_owned()
+    }
+
+    pub fn load(path: String) -> Result<Self, String> {
+        let mut plots = Self { claims: BTreeMap::new(), path };
+        if !Path::new(&plots.path).exists() {
+            return Ok(plots);
+        }
+
+        let text = fs::read_to_string(&plots.path).map_err(|e| format!("can't read {}: {}", plots.path, e))?;
+        let root = json::parse(&text).map_err(|e| format!("{} isn't valid JSON: {}", plots.path, e))?;
+        let names = |value: &json::JsonValue| value.members().filter_map(|n| n.as_str()).map(str::to_string).collect();
+        for claim in root.members() {
+            let (Some(x), Some(z), Some(owner)) = (claim["x"].as_i32(), claim["z"].as_i32(), claim["owner"].as_str()) else {
+                return Err(format!("{} has a broken claim: {}", plots.path, claim.dump()));
+            };
+
+            plots.claims.insert(PlotId { x, z }, Claim {
+                owner: owner.to_string(),
+                trusted: names(&claim["trusted"]),
+                denied: names(&claim["denied"]),
+            });
+        }
+
+        Ok(plots)
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let mut root = json::JsonValue::new_array();
+        for (id, claim) in &self.claims {
+            let claim = json::object! {
+                x: id.x,
+                z: id.z,
+                owner: claim.owner.as_str(),
+                trusted: claim.trusted.clone(),
+                denied: claim.denied.clone(),
+            };
+            root.push(claim).unwrap();
+        }
+
+        fs::write(&self.path, root.pretty(2)).map_err(|e| format!("can't write {}: {}", self.path, e))
+    }
+
+    /// Whether a player may change the block at `pos`.
+    pub fn can_build(&self, layout: &Layout, name: &str, pos: BlockPos) -> bool {
+        let Some(id) = layout.plot_at(pos) else { return false };
+        self.claims.get(&id).is_some_and(|c| c.trusts(name))
+    }
+
+    pub fn is_denied(&self, layout: &Layout, name: &str, pos: BlockPos) -> bool {
+        let Some(id) = layout.plot_at(pos) else { return false };
+        self.claims.get(&id).is_some_and(|c| c.denies(name))
+    }
+}
+
+/// Where a player may build, to check many positions at once.
+pub struct BuildCheck {
+    /// Whether they may build at all.
+    build: bool,
+    /// No layout means anywhere.
+    layout: Option<Layout>,
+    plots: HashSet<PlotId>,
+}
+
+impl BuildCheck {
+    pub fn allows(&self, pos: BlockPos) -> bool {
+        let Some(layout) = self.layout else { return self.build };
+        self.build && layout.plot_at(pos).is_some_and(|id| self.plots.contains(&id))
+    }
+}
+
+/// Keeps players from changing blocks where they may not build.
+pub struct PlotPlugin;
+
+impl Plugin for PlotPlugin {
+    fn name(&self) -> &str {
+        "plots"
+    }
+
+    fn enable(&self, registry: &mut Registry) {
+        for kind in [Kind::BlockPlace, Kind::BlockBreak] {
+            registry.listen(kind, Priority::Early, |server, event| {
+                let (Event::BlockPlace { client, pos, .. } | Event::BlockBreak { client, pos }) = event else {
+                    return Outcome::Continue;
+                };
+
+                match server.build_denial(*client, *pos) {
+                    Some(reason) => Outcome::Cancel(Some(Component::text(reason).color("red"))),
+                    None => Outcome::Continue,
+                }
+            });
+        }
+    }
+}
+
+/// Puts the blocks of a plot back to fresh ground, or only removes what was
+/// built on top with `keep_ground`.
+pub fn regenerate(world: &mut World, layout: &Layout, id: PlotId, keep_ground: bool) {
+    let region = layout.bounds(id);
+    while let Some(i) = world.circuits.iter().position(|c| c.region.intersects(&region)) {
+        compiled::decompile(world, i);
+    }
+
+    world.ticks.take_pending(|p| region.contains(p));
+    let bottom = if keep_ground { layout.height + 1 } else { 0 };
+    for x in region.min.x..=region.max.x {
+        for z in region.min.z..=region.max.z {
+            for y in bottom..HEIGHT {
+                world.set_block(BlockPos::new(x, y, z), Block::from_id(layout.ground(x, y, z)));
+            }
+        }
+    }
+}
+
+impl Server {
+    /// Whether the player may change the block at `pos`.
+    pub fn can_build(&self, client_id: ClientId, pos: BlockPos) -> bool {
+        if !self.has_permission(client_id, BUILD, 1) {
+            return false;
+        }
+
+        let Some(layout) = self.world.plots else { return true };
+        if self.has_permission(client_id, BYPASS, 3) {
+            return true;
+        }
+
+        let Some(player) = &self.clients[client_id].player else { return false };
+        self.plots.can_build(&layout, &player.name, pos)
+    }
+
+    pub fn build_check(&self, client_id: ClientId) -> BuildCheck {
+        let build = self.has_permission(client_id, BUILD, 1);
+        if self.has_permission(client_id, BYPASS, 3) {
+            return BuildCheck { build, layout: None, plots: HashSet::new() };
+        }
+
+        let name = self.clients[client_id].player.as_ref().map_or("", |p| p.name.as_str());
+        let plots = self.plots.claims.iter()
+            .filter(|(_, c)| c.trusts(name))
+            .map(|(id, _)| *id)
+            .collect();
+        BuildCheck { build, layout: self.world.plots, plots }
+    }
+
+    /// Why the player may not build at `pos`, if they may not.
+    pub fn build_denial(&self, client_id: ClientId, pos: BlockPos) -> Option<String> {
+        if self.can_build(client_id, pos) {
+            return None;
+        }
+
+        Some(match self.world.plots {
+            _ if !self.has_permission(client_id, BUILD, 1) => "You don't have permission to build".to_string(),
+            None => return None,
+            Some(layout) => match layout.plot_at(pos) {
+                None => "You can't build on the roads".to_string(),
+                Some(id) if !self.plots.claims.contains_key(&id) => format!("Plot {} isn't claimed, see /plot claim", id),
+                Some(id) => format!("You aren't trusted on plot {}", id),
+            },
+        })
+    }
+
+    /// Whether the player may move to `pos`, sending them back if not.
+    pub fn may_enter(&mut self, client_id: ClientId, (x, y, z): (f64, f64, f64)) -> bool {
+        let Some(layout) = self.world.plots else { return true };
+        let Some(player) = &self.clients[client_id].player else { return true };
+        let pos = BlockPos::new(x.floor() as i32, y.floor() as i32, z.floor() as i32);
+        if !self.plots.is_denied(&layout, &player.name, pos) {
+            return true;
+        }
+
+        // back where they came from, or to spawn if they're already inside
+        let from = BlockPos::new(player.x.floor() as i32, player.y.floor() as i32, player.z.floor() as i32);
+        let back = if self.plots.is_denied(&layout, &player.name, from) {
+            layout.spawn()
+        } else {
+            (player.x, player.y, player.z)
+        };
+        self.teleport(client_id, back);
+        false
+    }
+}
@@ -0,0 +1,493 @@
+//! Extending the server. What players do goes out as events on a bus, where
+//! listeners see them one after another and may cancel them. Plugins add
+//! listeners and commands when they're enabled, and the server's own
+//! subsystems, like the whitelist and the plots, listen on the same bus.
+//!
+//! Plugins are either built into the server and enabled by name in the
+//! config, WebAssembly modules run in a sandbox, see [`wasm`], or scripts
+//! reloaded as they're edited, see [`script`].
+
+pub mod script;
+pub mod wasm;
+
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::rc::Rc;
+use super::Server;
+use super::access::AccessPlugin;
+use super::chat::Component;
+use super::client::ClientId;
+use super::command::{self, CommandResult};
+use super::packet::play::ChatPacket;
+use super::plot::PlotPlugin;
+use super::world::BlockPos;
+use super::world::block::Block;
+
+/// Something that happened, which listeners may cancel unless it says
+/// otherwise.
+#[derive(Debug, Clone)]
+pub enum Event {
+    /// A player is logging in. Cancelling refuses them with the reason.
+    Join { client: ClientId, name: String },
+    /// A player left. Can't be cancelled.
+    Quit { client: ClientId, name: String },
+    Chat { client: ClientId, message: String },
+    BlockPlace { client: ClientId, pos: BlockPos, block: Block },
+    BlockBreak { client: ClientId, pos: BlockPos },
+    /// A block changed during a game tick. Can't be cancelled, the tick is
+    /// over by then.
+    RedstoneUpdate { pos: BlockPos },
+    /// A player runs a command, given without its slash.
+    Command { client: ClientId, line: String },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Join,
+    Quit,
+    Chat,
+    BlockPlace,
+    BlockBreak,
+    RedstoneUpdate,
+    Command,
+}
+
+impl Event {
+    pub fn kind(&self) -> Kind {
+        match self {
+            Event::Join { .. } => Kind::Join,
+            Event::Quit { .. } => Kind::Quit,
+            Event::Chat { .. } => Kind::Chat,
+            Event::BlockPlace { .. } => Kind::BlockPlace,
+            Event::BlockBreak { .. } => Kind::BlockBreak,
+            Event::RedstoneUpdate { .. } => Kind::RedstoneUpdate,
+            Event::Command { .. } => Kind::Command,
+        }
+    }
+
+    /// The client of the player who caused it, if one did.
+    pub fn client(&self) -> Option<ClientId> {
+        match self {
+            Event::Join { client, .. } | Event::Quit { client, .. } | Event::Chat { client, .. }
+            | Event::BlockPlace { client, .. } | Event::BlockBreak { client, .. }
+            | Event::Command { client, .. } => Some(*client),
+            Event::RedstoneUpdate { .. } => None,
+        }
+    }
+
+    pub fn cancellable(&self) -> bool {
+        !matches!(self.kind(), Kind::Quit | Kind::RedstoneUpdate)
+    }
+}
+
+/// What a listener decides about an event.
+#[derive(Debug, Clone)]
+pub enum Outcome {
+    Continue,
+    /// Stops the event and the listeners after this one, telling the player
+    /// why if there's a reason.
+    Cancel(Option<Component>),
+}
+
+/// When a listener runs compared to the others. Listeners of the same
+/// priority run in the order they were added.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+    /// For checks, which cancel before anything happens.
+    Early,
+    Normal,
+    /// For acting on events nothing cancelled.
+    Late,
+    /// For recording events after every listener that could cancel them.
+    /// Their cancels are ignored.
+    Monitor,
+}
+
+pub type ListenerFn = Rc<dyn Fn(&mut Server, &Event) -> Outcome>;
+pub type CommandFn = Rc<dyn Fn(&mut Server, ClientId, &[&str]) -> CommandResult>;
+pub type Task = Box<dyn FnOnce(&mut Server)>;
+
+struct Listener {
+    /// The plugin that added it.
+    plugin: String,
+    kind: Kind,
+    priority: Priority,
+    run: ListenerFn,
+}
+
+/// A command added by a plugin, like the ones in [`command::COMMANDS`].
+pub struct PluginCommand {
+    pub name: String,
+    pub usage: String,
+    pub description: String,
+    pub permission: String,
+    pub level: u8,
+    pub run: CommandFn,
+}
+
+/// The listeners, plugin commands and scheduled tasks.
+#[derive(Default)]
+pub struct Bus {
+    /// Sorted by priority.
+    listeners: Vec<Listener>,
+    /// With the plugins that added them.
+    commands: Vec<(String, PluginCommand)>,
+    /// Tasks by the game time they're due at.
+    tasks: Vec<(u64, Task)>,
+}
+
+impl Bus {
+    pub fn command(&self, name: &str) -> Option<&PluginCommand> {
+        self.commands().find(|c| c.name == name)
+    }
+
+    pub fn commands(&self) -> impl Iterator<Item = &PluginCommand> {
+        self.commands.iter().map(|(_, c)| c)
+    }
+
+    /// Takes away the listeners and commands of a plugin.
+    pub fn remove(&mut self, plugin: &str) {
+        self.listeners.retain(|l| l.plugin != plugin);
+        self.commands.retain(|(p, _)| p != plugin);
+    }
+
+    pub fn has_listeners(&self, kind: Kind) -> bool {
+        self.listeners.iter().any(|l| l.kind == kind)
+    }
+}
+
+/// What a plugin registers its listeners and commands with.
+pub struct Registry<'a> {
+    bus: &'a mut Bus,
+    plugin: &'a str,
+    /// The game time, for scheduling.
+    time: u64,
+}
+
+impl Registry<'_> {
+    pub fn listen(&mut self, kind: Kind, priority: Priority, listener: impl Fn(&mut Server, &Event) -> Outcome + 'static) {
+        let index = self.bus.listeners.partition_point(|l| l.priority <= priority);
+        self.bus.listeners.insert(index, Listener { plugin: self.plugin.to_string(), kind, priority, run: Rc::new(listener) });
+    }
+
+    /// Adds a command, unless there already is one with its name.
+    pub fn command(&mut self, command: PluginCommand) {
+        let taken = command::COMMANDS.iter().any(|c| c.name == command.name) || self.bus.command(&command.name).is_some();
+        if taken {
+            warn!("{} can't add /{}, there already is one", self.plugin, command.name);
+            return;
+        }
+
+        self.bus.commands.push((self.plugin.to_string(), command));
+    }
+
+    /// Like [`Server::schedule`].
+    pub fn schedule(&mut self, delay: u64, task: impl FnOnce(&mut Server) + 'static) {
+        self.bus.tasks.push((self.time + delay, Box::new(task)));
+    }
+}
+
+pub trait Plugin {
+    fn name(&self) -> &str;
+
+    /// Adds the plugin's listeners and commands.
+    fn enable(&self, registry: &mut Registry);
+}
+
+type Constructor = fn() -> Box<dyn Plugin>;
+
+/// Parts of the server working through the bus, always enabled first.
+const BUILTIN: &[Constructor] = &[
+    || Box::new(AccessPlugin),
+    || Box::new(PlotPlugin),
+    || Box::new(ChatPlugin),
+    || Box::new(CommandPlugin),
+];
+
+/// Plugins the config can enable, by name.
+pub const PLUGINS: &[(&str, Constructor)] = &[
+    ("join-messages", || Box::new(JoinMessages)),
+    ("block-log", || Box::new(BlockLog)),
+];
+
+impl Server {
+    /// Enables the built in plugins and the ones in the config.
+    pub fn enable_plugins(&mut self) {
+        let mut plugins: Vec<_> = BUILTIN.iter().map(|p| p()).collect();
+        for name in &self.config.plugins.enabled {
+            match PLUGINS.iter().find(|(n, _)| n == name) {
+                Some((_, plugin)) => plugins.push(plugin()),
+                None => {
+                    error!("there is no plugin named {}", name);
+                    std::process::exit(1);
+                },
+            }
+        }
+        plugins.extend(wasm::load_all(&self.config.plugins.wasm));
+
+        for plugin in plugins {
+            plugin.enable(&mut Registry { bus: &mut self.bus, plugin: plugin.name(), time: self.world.time });
+            info!("enabled plugin {}", plugin.name());
+        }
+    }
+
+    /// Hands an event to its listeners until one cancels it.
+    pub fn fire(&mut self, event: &Event) -> Outcome {
+        let kind = event.kind();
+        let listeners: Vec<_> = self.bus.listeners.iter()
+            .filter(|l| l.kind == kind)
+            .map(|l| (l.priority, Rc::clone(&l.run)))
+            .collect();
+
+        for (priority, listener) in listeners {
+            if let Outcome::Cancel(reason) = listener(self, event) {
+                if event.cancellable() && priority != Priority::Monitor {
+                    return Outcome::Cancel(reason);
+                }
+            }
+        }
+
+        Outcome::Continue
+    }
+
+    /// Fires an event, returning whether it went through and telling the
+    /// player why not if it didn't.
+    pub fn allowed(&mut self, event: &Event) -> bool {
+        let Outcome::Cancel(reason) = self.fire(event) else { return true };
+        if let (Some(reason), Some(client)) = (reason, event.client()) {
+            self.send_message(client, &reason);
+        }
+
+        false
+    }
+
+    /// Runs `task` once `delay` game ticks have passed.
+    pub fn schedule(&mut self, delay: u64, task: impl FnOnce(&mut Server) + 'static) {
+        self.bus.tasks.push((self.world.time + delay, Box::new(task)));
+    }
+
+    /// Runs the scheduled tasks that are due, in the order they were
+    /// scheduled.
+    pub fn run_tasks(&mut self) {
+        let time = self.world.time;
+        let (due, later) = std::mem::take(&mut self.bus.tasks).into_iter().partition(|(t, _)| *t <= time);
+        self.bus.tasks = later;
+        for (_, task) in due {
+            task(self);
+        }
+    }
+
+    /// Shows a message to every player.
+    pub fn broadcast(&mut self, message: &Component, position: u8) {
+        let message = message.to_json().dump();
+        for client in self.clients.values_mut().filter(|c| c.player.is_some()) {
+            if let Err(e) = client.send_packet(&ChatPacket { message: message.clone(), position }) {
+                error!("failed to send chat message: {}", e);
+            }
+        }
+    }
+}
+
+/// Chat, which goes to every player when nothing cancels it.
+struct ChatPlugin;
+
+impl Plugin for ChatPlugin {
+    fn name(&self) -> &str {
+        "chat"
+    }
+
+    fn enable(&self, registry: &mut Registry) {
+        registry.listen(Kind::Chat, Priority::Late, |server, event| {
+            let Event::Chat { client, message } = event else { return Outcome::Continue };
+            let Some(player) = &server.clients[*client].player else { return Outcome::Continue };
+
+            info!("<{}> {}", player.name, message);
+            let with = vec![Component::text(player.name.clone()), Component::text(message.clone())];
+            server.broadcast(&Component::translate("chat.type.text", with), 0);
+            Outcome::Continue
+        });
+    }
+}
+
+/// Runs the commands nothing cancelled.
+struct CommandPlugin;
+
+impl Plugin for CommandPlugin {
+    fn name(&self) -> &str {
+        "commands"
+    }
+
+    fn enable(&self, registry: &mut Registry) {
+        registry.listen(Kind::Command, Priority::Late, |server, event| {
+            let Event::Command { client, line } = event else { return Outcome::Continue };
+            command::run(server, *client, line);
+            Outcome::Continue
+        });
+    }
+}
+
+/// Tells everyone when players join and leave, like vanilla does.
+struct JoinMessages;
+
+impl Plugin for JoinMessages {
+    fn name(&self) -> &str {
+        "join-messages"
+    }
+
+    fn enable(&self, registry: &mut Registry) {
+        registry.listen(Kind::Join, Priority::Late, |server, event| {
+            let Event::Join { name, .. } = event else { return Outcome::Continue };
+            let message = Component::translate("multiplayer.player.joined", vec![Component::text(name.clone())]);
+            server.broadcast(&message.color("yellow"), 1);
+            Outcome::Continue
+        });
+
+        registry.listen(Kind::Quit, Priority::Late, |server, event| {
+            let Event::Quit { name, .. } = event else { return Outcome::Continue };
+            let message = Component::translate("multiplayer.player.left", vec![Component::text(name.clone())]);
+            server.broadcast(&message.color("yellow"), 1);
+            Outcome::Continue
+        });
+    }
+}
+
+/// Changes kept per block by the block log.
+const BLOCK_LOG_DEPTH: usize = 8;
+/// Blocks the block log keeps changes of. Past that it forgets the half
+/// that changed longest ago.
+const BLOCK_LOG_SIZE: usize = 1 << 14;
+
+/// Remembers who changed blocks, and whether redstone did, to look up with
+/// `/blocklog`. Kept only while the server runs.
+struct BlockLog;
+
+struct Change {
+    time: u64,
+    /// Who changed it, none for redstone.
+    player: Option<String>,
+    /// What it became.
+    block: Block,
+}
+
+type Changes = Rc<RefCell<HashMap<BlockPos, VecDeque<Change>>>>;
+
+fn log_change(changes: &Changes, pos: BlockPos, change: Change) {
+    let mut changes = changes.borrow_mut();
+    let log = changes.entry(pos).or_default();
+    if log.len() == BLOCK_LOG_DEPTH {
+        log.pop_front();
+    }
+    log.push_back(change);
+
+    if changes.len() > BLOCK_LOG_SIZE {
+        let mut last: Vec<u64> = changes.values().filter_map(|l| l.back()).map(|c| c.time).collect();
+        let (_, cutoff, _) = last.select_nth_unstable(BLOCK_LOG_SIZE / 2);
+        let cutoff = *cutoff;
+        changes.retain(|p, l| *p == pos || l.back().is_some_and(|c| c.time > cutoff));
+    }
+}
+
+impl Plugin for BlockLog {
+    fn name(&self) -> &str {
+        "block-log"
+    }
+
+    fn enable(&self, registry: &mut Registry) {
+        let changes = Changes::default();
+
+        let log = Rc::clone(&changes);
+        registry.listen(Kind::BlockPlace, Priority::Monitor, move |server, event| {
+            let Event::BlockPlace { client, pos, block } = event else { return Outcome::Continue };
+            let player = server.clients.player(*client).map(|p| p.name.clone());
+            log_change(&log, *pos, Change { time: server.world.time, player, block: *block });
+            Outcome::Continue
+        });
+
+        let log = Rc::clone(&changes);
+        registry.listen(Kind::BlockBreak, Priority::Monitor, move |server, event| {
+            let Event::BlockBreak { client, pos } = event else { return Outcome::Continue };
+            let player = server.clients.player(*client).map(|p| p.name.clone());
+            log_change(&log, *pos, Change { time: server.world.time, player, block: Block::Air });
+            Outcome::Continue
+        });
+
+        let log = Rc::clone(&changes);
+        registry.listen(Kind::RedstoneUpdate, Priority::Normal, move |server, event| {
+            let Event::RedstoneUpdate { pos } = event else { return Outcome::Continue };
+            log_change(&log, *pos, Change { time: server.world.time, player: None, block: server.world.get_block(*pos) });
+            Outcome::Continue
+        });
+
+        registry.command(PluginCommand {
+            name: "blocklog".to_string(),
+            usage: "/blocklog <x> <y> <z>".to_string(),
+            description: "Shows who changed a block lately".to_string(),
+            permission: "command.blocklog".to_string(),
+            level: 2,
+            run: Rc::new(move |server, client_id, args| {
+                if args.len() != 3 {
+                    return Err("Usage: /blocklog <x> <y> <z>".to_string());
+                }
+
+                let pos = command::parse_pos(server, client_id, args)?;
+                let at = format!("{} {} {}", pos.x, pos.y, pos.z);
+                let changes = changes.borrow();
+                let Some(log) = changes.get(&pos).filter(|l| !l.is_empty()) else {
+                    return Ok(Component::text(format!("Nothing changed {} lately", at)));
+                };
+
+                let mut lines = vec![format!("Changes of {}:", at)];
+                for change in log.iter().rev() {
+                    let ago = (server.world.time - change.time) / 20;
+                    let who = change.player.as_deref().unwrap_or("redstone");
+                    lines.push(format!("{}s ago, {} made it {:?}", ago, who, change.block));
+                }
+                Ok(Component::text(lines.join("\n")))
+            }),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn change(time: u64) -> Change {
+        Change { time, player: None, block: Block::Air }
+    }
+
+    #[test]
+    fn block_log_keeps_the_latest_changes_of_a_block() {
+        let changes = Changes::default();
+        let pos = BlockPos::new(0, 0, 0);
+        for time in 0..BLOCK_LOG_DEPTH as u64 + 3 {
+            log_change(&changes, pos, change(time));
+        }
+
+        let changes = changes.borrow();
+        let times: Vec<_> = changes[&pos].iter().map(|c| c.time).collect();
+        assert_eq!(times, (3..BLOCK_LOG_DEPTH as u64 + 3).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn block_log_forgets_the_blocks_changed_longest_ago() {
+        let changes = Changes::default();
+        for x in 0..=BLOCK_LOG_SIZE as i32 {
+            log_change(&changes, BlockPos::new(x, 0, 0), change(x as u64));
+        }
+
+        let changes = changes.borrow();
+        assert!(changes.len() <= BLOCK_LOG_SIZE / 2);
+        assert!(changes.contains_key(&BlockPos::new(BLOCK_LOG_SIZE as i32, 0, 0)));
+        assert!(!changes.contains_key(&BlockPos::new(0, 0, 0)));
+    }
+
+    #[test]
+    fn block_log_stays_bounded_when_everything_changes_at_once() {
+        let changes = Changes::default();
+        for x in 0..BLOCK_LOG_SIZE as i32 * 3 {
+            log_change(&changes, BlockPos::new(x, 0, 0), change(7));
+            assert!(changes.borrow().len() <= BLOCK_LOG_SIZE);
+        }
+    }
+}
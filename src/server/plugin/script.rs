@@ -0,0 +1,382 @@
+//! Rhai scripts, for automating things like resetting test rigs. Every
+//! `.rhai` file in the script directory runs when the server starts and
+//! again whenever it changes, after what the old version added is taken
+//! away. Scripts that fail are logged and left out, never stopping the
+//! server.
+//!
+//! Besides Rhai's own, scripts have these functions:
+//!
+//! - `broadcast(text)` and `send_message(player, text)`.
+//! - `players()`, the players as maps of their `name`, `x`, `y` and `z`.
+//! - `teleport(player, x, y, z)`.
+//! - `get_block(x, y, z)`, the block's state id, and
+//!   `set_block(x, y, z, block)` with a block name or state id.
+//! - `paste(schematic, x, y, z)`, returning how many blocks changed.
+//! - `time()`, the game time in ticks.
+//! - `listen(kind, |event| ...)` for the events named in [`KINDS`]. The
+//!   event is a map of its `kind` and what else it has of `player`, `x`,
+//!   `y`, `z`, `block` and `text`. Returning `true` cancels it, returning
+//!   a string cancels it and tells the player why.
+//! - `command(name, description, |player, args| ...)` adds a command,
+//!   which tells the player what the function returns if it's a string.
+//! - `schedule(delay, || ...)` runs a function after `delay` game ticks.
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use std::rc::{Rc, Weak};
+use std::time::SystemTime;
+use rhai::{Array, Dynamic, Engine, EvalAltResult, FnPtr, Map, AST};
+use super::{Event, Kind, Outcome, PluginCommand, Priority, Registry};
+use super::super::Server;
+use super::super::chat::Component;
+use super::super::client::ClientId;
+use super::super::config::ScriptConfig;
+use super::super::edit;
+use super::super::redstone;
+use super::super::schematic;
+use super::super::world::{BlockPos, World};
+
+/// Event kinds by the names scripts know them by.
+pub const KINDS: [(&str, Kind); 7] = [
+    ("join", Kind::Join),
+    ("quit", Kind::Quit),
+    ("chat", Kind::Chat),
+    ("block_place", Kind::BlockPlace),
+    ("block_break", Kind::BlockBreak),
+    ("redstone_update", Kind::RedstoneUpdate),
+    ("command", Kind::Command),
+];
+
+/// Game ticks between looking for changed scripts.
+const RELOAD_INTERVAL: u64 = 20;
+
+type ScriptResult<T> = Result<T, Box<EvalAltResult>>;
+
+/// What a script asked the server to do, which is done once it returns.
+enum Action {
+    Message(Option<String>, String),
+    Teleport(String, (f64, f64, f64)),
+    Listen(Kind, FnPtr),
+    Command(String, String, FnPtr),
+    Schedule(u64, FnPtr),
+}
+
+/// What a script's functions work with.
+#[derive(Default)]
+struct Host {
+    /// The world, only while the script runs.
+    world: Option<World>,
+    players: Array,
+    time: u64,
+    actions: Vec<Action>,
+}
+
+struct Script {
+    /// Its plugin name, `script:` and the file name without the extension.
+    name: String,
+    engine: Engine,
+    ast: AST,
+    host: Rc<RefCell<Host>>,
+}
+
+struct Loaded {
+    modified: SystemTime,
+    /// None if the script doesn't compile.
+    script: Option<Rc<Script>>,
+}
+
+/// The scripts and the versions of their files that were loaded.
+#[derive(Default)]
+pub struct Scripts {
+    loaded: BTreeMap<PathBuf, Loaded>,
+}
+
+impl Server {
+    /// Runs the scripts that are new or changed, and takes away the ones
+    /// that were removed.
+    pub fn reload_scripts(&mut self) {
+        let config = self.config.plugins.scripts.clone();
+        let mut files = BTreeMap::new();
+        if let Ok(entries) = fs::read_dir(&config.dir) {
+            for path in entries.filter_map(|e| e.ok().map(|e| e.path())) {
+                if path.extension().is_some_and(|e| e == "rhai") {
+                    let modified = fs::metadata(&path).and_then(|m| m.modified()).unwrap_or(SystemTime::UNIX_EPOCH);
+                    files.insert(path, modified);
+                }
+            }
+        }
+
+        let removed: Vec<_> = self.scripts.loaded.keys().filter(|p| !files.contains_key(*p)).cloned().collect();
+        for path in removed {
+            if let Some(script) = self.scripts.loaded.remove(&path).and_then(|l| l.script) {
+                self.bus.remove(&script.name);
+                info!("unloaded {}", script.name);
+            }
+        }
+
+        for (path, modified) in files {
+            if self.scripts.loaded.get(&path).is_some_and(|l| l.modified == modified) {
+                continue;
+            }
+
+            // a script that doesn't compile leaves the old version running
+            let script = match Script::compile(&path, &config) {
+                Ok(script) => Rc::new(script),
+                Err(e) => {
+                    error!("can't load script {}: {}", path.display(), e);
+                    let loaded = self.scripts.loaded.entry(path).or_insert(Loaded { modified, script: None });
+                    loaded.modified = modified;
+                    continue;
+                },
+            };
+
+            let old = self.scripts.loaded.insert(path, Loaded { modified, script: Some(Rc::clone(&script)) });
+            if let Some(old) = old.and_then(|l| l.script) {
+                self.bus.remove(&old.name);
+            }
+
+            script.run(self, |engine, ast| engine.run_ast(ast).map(|_| Dynamic::UNIT));
+            info!("loaded {}", script.name);
+        }
+    }
+
+    /// Looks for changed scripts now and then.
+    pub fn refresh_scripts(&mut self) {
+        if self.world.time.is_multiple_of(RELOAD_INTERVAL) {
+            self.reload_scripts();
+        }
+    }
+}
+
+impl Script {
+    fn compile(path: &PathBuf, config: &ScriptConfig) -> Result<Self, String> {
+        let stem = path.file_stem().map_or_else(String::new, |s| s.to_string_lossy().into_owned());
+        let name = format!("script:{}", stem);
+        let source = fs::read_to_string(path).map_err(|e| e.to_string())?;
+
+        let host = Rc::new(RefCell::new(Host::default()));
+        let mut engine = Engine::new();
+        engine.set_max_operations(config.max_operations);
+        register(&mut engine, &name, &host);
+        let ast = engine.compile(source).map_err(|e| e.to_string())?;
+
+        Ok(Self { name, engine, ast, host })
+    }
+
+    /// Runs some of the script, lending it the world, and does what it
+    /// asked for afterwards. Returns none if it failed.
+    fn run(self: &Rc<Self>, server: &mut Server, f: impl FnOnce(&Engine, &AST) -> ScriptResult<Dynamic>) -> Option<Dynamic> {
+        {
+            let mut host = self.host.borrow_mut();
+            host.time = server.world.time;
+            host.players = players(server);
+            host.world = Some(std::mem::replace(&mut server.world, World::new()));
+        }
+
+        let result = f(&self.engine, &self.ast);
+
+        let actions = {
+            let mut host = self.host.borrow_mut();
+            server.world = host.world.take().unwrap();
+            std::mem::take(&mut host.actions)
+        };
+        self.finish(server, actions);
+
+        match result {
+            Ok(result) => Some(result),
+            Err(e) => {
+                error!("{} failed: {}", self.name, e);
+                None
+            },
+        }
+    }
+
+    fn finish(self: &Rc<Self>, server: &mut Server, actions: Vec<Action>) {
+        let time = server.world.time;
+        for action in actions {
+            match action {
+                Action::Message(player, text) => {
+                    let message = Component::text(text);
+                    match player.map(|p| server.find_player(&p)) {
+                        Some(Some(client)) => server.send_message(client, &message),
+                        Some(None) => {},
+                        None => server.broadcast(&message, 1),
+                    }
+                },
+                Action::Teleport(player, pos) => {
+                    if let Some(client) = server.find_player(&player) {
+                        server.teleport(client, pos);
+                    }
+                },
+                Action::Listen(kind, callback) => {
+                    let script = Rc::clone(self);
+                    let mut registry = Registry { bus: &mut server.bus, plugin: &self.name, time };
+                    registry.listen(kind, Priority::Normal, move |server, event| script.on_event(server, event, &callback));
+                },
+                Action::Command(name, description, callback) => {
+                    let script = Rc::clone(self);
+                    let mut registry = Registry { bus: &mut server.bus, plugin: &self.name, time };
+                    registry.command(PluginCommand {
+                        usage: format!("/{}", name),
+                        description,
+                        permission: format!("command.{}", name),
+                        level: 2,
+                        run: Rc::new(move |server, client_id, args| script.on_command(server, client_id, args, &callback)),
+                        name,
+                    });
+                },
+                Action::Schedule(delay, callback) => {
+                    // a script reloaded meanwhile doesn't run the old version's tasks
+                    let script = Rc::downgrade(self);
+                    server.schedule(delay, move |server| run_task(server, script, callback));
+                },
+            }
+        }
+    }
+
+    fn on_event(self: &Rc<Self>, server: &mut Server, event: &Event, callback: &FnPtr) -> Outcome {
+        let mut map = Map::new();
+        let kind = KINDS.iter().find(|(_, k)| *k == event.kind()).map_or("", |(n, _)| *n);
+        map.insert("kind".into(), kind.into());
+        if let Some(player) = event.client().and_then(|c| server.clients.get(c)?.player.as_ref()) {
+            map.insert("player".into(), player.name.clone().into());
+        }
+
+        let (pos, text) = match event {
+            Event::Join { name, .. } | Event::Quit { name, .. } => (None, Some(name)),
+            Event::Chat { message, .. } => (None, Some(message)),
+            Event::Command { line, .. } => (None, Some(line)),
+            Event::BlockPlace { pos, block, .. } => {
+                map.insert("block".into(), (block.get_id() as i64).into());
+                (Some(pos), None)
+            },
+            Event::BlockBreak { pos, .. } | Event::RedstoneUpdate { pos } => (Some(pos), None),
+        };
+
+        if let Some(pos) = pos {
+            map.insert("x".into(), (pos.x as i64).into());
+            map.insert("y".into(), (pos.y as i64).into());
+            map.insert("z".into(), (pos.z as i64).into());
+        }
+        if let Some(text) = text {
+            map.insert("text".into(), text.clone().into());
+        }
+
+        let result = self.run(server, |engine, ast| callback.call::<Dynamic>(engine, ast, (map,)));
+        match result {
+            Some(r) if r.as_bool() == Ok(true) => Outcome::Cancel(None),
+            Some(r) if r.is_string() => Outcome::Cancel(Some(Component::text(r.to_string()).color("red"))),
+            _ => Outcome::Continue,
+        }
+    }
+
+    fn on_command(self: &Rc<Self>, server: &mut Server, client_id: ClientId, args: &[&str], callback: &FnPtr) -> Result<Component, String> {
+        let player = server.clients.player(client_id).map_or_else(String::new, |p| p.name.clone());
+        let args: Array = args.iter().map(|a| a.to_string().into()).collect();
+        match self.run(server, |engine, ast| callback.call::<Dynamic>(engine, ast, (player, args))) {
+            Some(r) if r.is_string() => Ok(Component::text(r.to_string())),
+            Some(_) => Ok(Component::text("Done")),
+            None => Err("The script failed, see the server log".to_string()),
+        }
+    }
+}
+
+fn run_task(server: &mut Server, script: Weak<Script>, callback: FnPtr) {
+    if let Some(script) = script.upgrade() {
+        script.run(server, |engine, ast| callback.call::<Dynamic>(engine, ast, ()));
+    }
+}
+
+fn players(server: &Server) -> Array {
+    server.clients.values()
+        .filter(|c| !c.closed)
+        .filter_map(|c| c.player.as_ref())
+        .map(|p| {
+            let mut map = Map::new();
+            map.insert("name".into(), p.name.clone().into());
+            map.insert("x".into(), p.x.into());
+            map.insert("y".into(), p.y.into());
+            map.insert("z".into(), p.z.into());
+            map.into()
+        })
+        .collect()
+}
+
+/// Runs `f` on the world the script was lent.
+fn with_world<T>(host: &Rc<RefCell<Host>>, f: impl FnOnce(&mut World) -> T) -> ScriptResult<T> {
+    let mut host = host.borrow_mut();
+    let world = host.world.as_mut().ok_or("the world isn't there")?;
+    Ok(f(world))
+}
+
+/// The functions scripts can call.
+fn register(engine: &mut Engine, name: &str, host: &Rc<RefCell<Host>>) {
+    let script = name.to_string();
+    engine.on_print(move |text| {
+        info!("[{}] {}", script, text);
+    });
+
+    let h = Rc::clone(host);
+    engine.register_fn("broadcast", move |text: &str| {
+        h.borrow_mut().actions.push(Action::Message(None, text.to_string()));
+    });
+
+    let h = Rc::clone(host);
+    engine.register_fn("send_message", move |player: &str, text: &str| {
+        h.borrow_mut().actions.push(Action::Message(Some(player.to_string()), text.to_string()));
+    });
+
+    let h = Rc::clone(host);
+    engine.register_fn("players", move || h.borrow().players.clone());
+
+    let h = Rc::clone(host);
+    engine.register_fn("teleport", move |player: &str, x: f64, y: f64, z: f64| {
+        h.borrow_mut().actions.push(Action::Teleport(player.to_string(), (x, y, z)));
+    });
+
+    let h = Rc::clone(host);
+    engine.register_fn("time", move || h.borrow().time as i64);
+
+    let h = Rc::clone(host);
+    engine.register_fn("get_block", move |x: i64, y: i64, z: i64| -> ScriptResult<i64> {
+        with_world(&h, |world| world.get_block_id(BlockPos::new(x as i32, y as i32, z as i32)) as i64)
+    });
+
+    let h = Rc::clone(host);
+    engine.register_fn("set_block", move |x: i64, y: i64, z: i64, block: Dynamic| -> ScriptResult<()> {
+        let block = edit::parse_block(&block.to_string())?;
+        with_world(&h, |world| redstone::set_block(world, BlockPos::new(x as i32, y as i32, z as i32), block, true))
+    });
+
+    let h = Rc::clone(host);
+    engine.register_fn("paste", move |name: &str, x: i64, y: i64, z: i64| -> ScriptResult<i64> {
+        let schematic = schematic::load(&schematic::path(name)?)?;
+        let blocks = schematic.to_clipboard().blocks.into_iter()
+            .map(|(p, snapshot)| (BlockPos::new(x as i32 + p.x, y as i32 + p.y, z as i32 + p.z), snapshot));
+        with_world(&h, |world| edit::apply(world, blocks).len() as i64)
+    });
+
+    let h = Rc::clone(host);
+    engine.register_fn("listen", move |kind: &str, callback: FnPtr| -> ScriptResult<()> {
+        let (_, kind) = KINDS.iter().find(|(n, _)| *n == kind).ok_or_else(|| format!("there are no {} events", kind))?;
+        h.borrow_mut().actions.push(Action::Listen(*kind, callback));
+        Ok(())
+    });
+
+    let h = Rc::clone(host);
+    engine.register_fn("command", move |name: &str, description: &str, callback: FnPtr| -> ScriptResult<()> {
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+            return Err(format!("{} is not a valid command name", name).into());
+        }
+
+        h.borrow_mut().actions.push(Action::Command(name.to_string(), description.to_string(), callback));
+        Ok(())
+    });
+
+    let h = Rc::clone(host);
+    engine.register_fn("schedule", move |delay: i64, callback: FnPtr| {
+        h.borrow_mut().actions.push(Action::Schedule(delay.max(0) as u64, callback));
+    });
+}
@@ -0,0 +1,462 @@
+//! Plugins compiled to WebAssembly, for extensions the server shouldn't
+//! have to trust with native code. Every `.wasm` file in the plugin
+//! directory gets its own sandbox with limits on its memory, and on the
+//! fuel it may burn each time the server calls it.
+//!
+//! Plugins import these functions from the module `server`. Strings are
+//! passed as a pointer and a length into the plugin's exported `memory`,
+//! clients as their id, and blocks as their state id.
+//!
+//! - `log(ptr, len)` writes to the server log.
+//! - `send_message(client: i64, ptr, len)` shows a player a message.
+//! - `broadcast(ptr, len)` shows every player a message.
+//! - `reply(ptr, len)` sets what the player running a command is told.
+//! - `input(ptr, cap) -> len` copies the text the plugin was called with,
+//!   like a chat message or command arguments, returning its full length.
+//! - `get_block(x, y, z) -> state` and `set_block(x, y, z, state)`, only
+//!   in loaded chunks and, in plot worlds, off the roads.
+//! - `listen(kind)` listens to events, see [`KINDS`], only from `enable`.
+//! - `register_command(ptr, len)` adds a command, only from `enable`.
+//! - `schedule(delay, task)` calls `on_task(task)` after `delay` game ticks.
+//!
+//! And export the ones they need of these:
+//!
+//! - `enable()`, called once when the plugin is loaded.
+//! - `on_event(kind, client: i64, x, y, z, block) -> i32`, cancelling the
+//!   event when it returns anything but 0. The client is -1 for events no
+//!   player caused.
+//! - `on_command(client: i64) -> i32`, with the command line without its
+//!   slash as input, failing when it returns anything but 0.
+//! - `on_task(task)`.
+
+use std::cell::RefCell;
+use std::fs;
+use std::path::Path;
+use std::rc::Rc;
+use wasmi::{Caller, Config, Engine, Extern, Instance, Linker, Module, Store, StoreLimits, StoreLimitsBuilder};
+use wasmi::{WasmParams, WasmResults};
+use super::{Event, Kind, Outcome, Plugin, PluginCommand, Priority, Registry};
+use super::super::Server;
+use super::super::chat::Component;
+use super::super::client::ClientId;
+use super::super::config::WasmConfig;
+use super::super::redstone;
+use super::super::world::block::Block;
+use super::super::world::{BlockPos, World, HEIGHT};
+
+/// Event kinds by the number plugins know them by.
+pub const KINDS: [Kind; 7] = [
+    Kind::Join,
+    Kind::Quit,
+    Kind::Chat,
+    Kind::BlockPlace,
+    Kind::BlockBreak,
+    Kind::RedstoneUpdate,
+    Kind::Command,
+];
+
+/// Longest string a plugin may hand the server.
+const MAX_STRING: usize = 32 << 10;
+
+/// What a plugin asked the server to do, which is done once it returns.
+enum Action {
+    Message(Option<ClientId>, String),
+    Listen(Kind),
+    Command(String),
+    Schedule(u64, i32),
+}
+
+/// What a plugin's host functions work with.
+struct Host {
+    name: String,
+    limits: StoreLimits,
+    /// The world, only while the plugin handles something.
+    world: Option<World>,
+    /// Text the plugin reads with `input`.
+    input: String,
+    reply: Option<String>,
+    actions: Vec<Action>,
+    enabling: bool,
+}
+
+/// A loaded plugin, with its memory and the fuel it gets per call.
+struct Runtime {
+    store: Store<Host>,
+    instance: Instance,
+    fuel: u64,
+}
+
+type Shared = Rc<RefCell<Runtime>>;
+
+pub struct WasmPlugin {
+    name: String,
+    runtime: Shared,
+}
+
+/// Loads the plugins in the plugin directory, in the order of their names.
+/// Ones that can't be loaded are left out.
+pub fn load_all(config: &WasmConfig) -> Vec<Box<dyn Plugin>> {
+    let Ok(entries) = fs::read_dir(&config.dir) else { return Vec::new() };
+    let mut paths: Vec<_> = entries
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| p.extension().is_some_and(|e| e == "wasm"))
+        .collect();
+    paths.sort();
+
+    let mut plugins: Vec<Box<dyn Plugin>> = Vec::new();
+    for path in paths {
+        match WasmPlugin::load(&path, config) {
+            Ok(plugin) => plugins.push(Box::new(plugin)),
+            Err(e) => {
+                error!("can't load plugin {}: {}", path.display(), e);
+            },
+        }
+    }
+
+    plugins
+}
+
+impl WasmPlugin {
+    pub fn load(path: &Path, config: &WasmConfig) -> Result<Self, String> {
+        let name = path.file_stem().map_or_else(String::new, |s| s.to_string_lossy().into_owned());
+        let bytes = fs::read(path).map_err(|e| e.to_string())?;
+        Self::new(name, &bytes, config)
+    }
+
+    /// Instantiates a plugin from the bytes of its module.
+    pub fn new(name: String, bytes: &[u8], config: &WasmConfig) -> Result<Self, String> {
+        let limits = config.limits.get(&name).cloned().unwrap_or_default();
+
+        let mut engine_config = Config::default();
+        engine_config.consume_fuel(true);
+        let engine = Engine::new(&engine_config);
+        let module = Module::new(&engine, bytes).map_err(|e| e.to_string())?;
+
+        let host = Host {
+            name: name.clone(),
+            limits: StoreLimitsBuilder::new().memory_size(limits.memory.unwrap_or(config.memory)).build(),
+            world: None,
+            input: String::new(),
+            reply: None,
+            actions: Vec::new(),
+            enabling: false,
+        };
+        let mut store = Store::new(&engine, host);
+        store.limiter(|host| &mut host.limits);
+
+        let fuel = limits.fuel.unwrap_or(config.fuel);
+        store.set_fuel(fuel).map_err(|e| e.to_string())?;
+        let linker = linker(&engine).map_err(|e| e.to_string())?;
+        let instance = linker.instantiate(&mut store, &module)
+            .and_then(|i| i.start(&mut store))
+            .map_err(|e| e.to_string())?;
+
+        Ok(Self { name, runtime: Rc::new(RefCell::new(Runtime { store, instance, fuel })) })
+    }
+}
+
+impl Plugin for WasmPlugin {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn enable(&self, registry: &mut Registry) {
+        let actions = {
+            let mut runtime = self.runtime.borrow_mut();
+            runtime.store.data_mut().enabling = true;
+            runtime.call::<(), ()>(None, "enable", (), String::new());
+            runtime.store.data_mut().enabling = false;
+            std::mem::take(&mut runtime.store.data_mut().actions)
+        };
+
+        for action in actions {
+            match action {
+                Action::Listen(kind) => {
+                    let runtime = Rc::clone(&self.runtime);
+                    registry.listen(kind, Priority::Normal, move |server, event| on_event(&runtime, server, event));
+                },
+                Action::Command(name) => {
+                    let runtime = Rc::clone(&self.runtime);
+                    let command = name.clone();
+                    registry.command(PluginCommand {
+                        usage: format!("/{}", name),
+                        description: format!("Added by {}", self.name),
+                        permission: format!("command.{}", name),
+                        level: 2,
+                        run: Rc::new(move |server, client_id, args| {
+                            let line = std::iter::once(command.as_str()).chain(args.iter().copied()).collect::<Vec<_>>().join(" ");
+                            on_command(&runtime, server, client_id, line)
+                        }),
+                        name,
+                    });
+                },
+                Action::Schedule(delay, task) => {
+                    let runtime = Rc::clone(&self.runtime);
+                    registry.schedule(delay, move |server| run_task(&runtime, server, task));
+                },
+                // nobody to send it to before the server runs
+                Action::Message(..) => {},
+            }
+        }
+    }
+}
+
+fn on_event(runtime: &Shared, server: &mut Server, event: &Event) -> Outcome {
+    let kind = KINDS.iter().position(|k| *k == event.kind()).unwrap() as i32;
+    let client = event.client().map_or(-1, |c| c.0 as i64);
+    let (pos, block, input) = match event {
+        Event::Join { name, .. } | Event::Quit { name, .. } => (None, 0, name.clone()),
+        Event::Chat { message, .. } => (None, 0, message.clone()),
+        Event::Command { line, .. } => (None, 0, line.clone()),
+        Event::BlockPlace { pos, block, .. } => (Some(*pos), block.get_id(), String::new()),
+        Event::BlockBreak { pos, .. } => (Some(*pos), 0, String::new()),
+        Event::RedstoneUpdate { pos } => (Some(*pos), server.world.get_block_id(*pos), String::new()),
+    };
+    let pos = pos.unwrap_or(BlockPos::new(0, 0, 0));
+
+    let params = (kind, client, pos.x, pos.y, pos.z, block as i32);
+    let result = runtime.borrow_mut().call::<_, i32>(Some(server), "on_event", params, input);
+    finish(runtime, server);
+
+    match result {
+        Some(cancel) if cancel != 0 => Outcome::Cancel(None),
+        _ => Outcome::Continue,
+    }
+}
+
+fn on_command(runtime: &Shared, server: &mut Server, client_id: ClientId, line: String) -> Result<Component, String> {
+    let result = runtime.borrow_mut().call::<_, i32>(Some(server), "on_command", client_id.0 as i64, line);
+    let reply = runtime.borrow_mut().store.data_mut().reply.take();
+    finish(runtime, server);
+
+    match result {
+        Some(0) => Ok(Component::text(reply.unwrap_or_else(|| "Done".to_string()))),
+        _ => Err(reply.unwrap_or_else(|| "The command failed".to_string())),
+    }
+}
+
+fn run_task(runtime: &Shared, server: &mut Server, task: i32) {
+    runtime.borrow_mut().call::<_, ()>(Some(server), "on_task", task, String::new());
+    finish(runtime, server);
+}
+
+/// Does what the plugin asked for while it was running.
+fn finish(runtime: &Shared, server: &mut Server) {
+    let actions = std::mem::take(&mut runtime.borrow_mut().store.data_mut().actions);
+    for action in actions {
+        match action {
+            Action::Message(Some(client), message) => {
+                if server.clients.get(client).is_some() {
+                    server.send_message(client, &Component::text(message));
+                }
+            },
+            Action::Message(None, message) => server.broadcast(&Component::text(message), 1),
+            Action::Schedule(delay, task) => {
+                let runtime = Rc::clone(runtime);
+                server.schedule(delay, move |server| run_task(&runtime, server, task));
+            },
+            // too late to listen or add commands
+            Action::Listen(..) | Action::Command(..) => {},
+        }
+    }
+}
+
+impl Runtime {
+    /// Calls an export of the plugin with fresh fuel, lending it the world.
+    /// Returns none if the plugin doesn't export it or fails.
+    fn call<P: WasmParams, R: WasmResults>(&mut self, server: Option<&mut Server>, export: &str, params: P, input: String) -> Option<R> {
+        let func = self.instance.get_typed_func::<P, R>(&self.store, export).ok()?;
+        if let Err(e) = self.store.set_fuel(self.fuel) {
+            error!("can't fuel plugin {}: {}", self.store.data().name, e);
+            return None;
+        }
+
+        let mut server = server;
+        let host = self.store.data_mut();
+        host.input = input;
+        host.world = server.as_mut().map(|s| std::mem::replace(&mut s.world, World::new()));
+
+        let result = func.call(&mut self.store, params);
+
+        let host = self.store.data_mut();
+        if let (Some(server), Some(world)) = (server, host.world.take()) {
+            server.world = world;
+        }
+
+        match result {
+            Ok(result) => Some(result),
+            Err(e) => {
+                error!("plugin {} failed in {}: {}", self.store.data().name, export, e);
+                None
+            },
+        }
+    }
+}
+
+/// Reads a string out of the plugin's memory.
+fn read_string(caller: &Caller<Host>, ptr: i32, len: i32) -> Result<String, wasmi::Error> {
+    let Some(Extern::Memory(memory)) = caller.get_export("memory") else {
+        return Err(wasmi::Error::new("the plugin exports no memory"));
+    };
+
+    let len = usize::try_from(len).ok().filter(|l| *l <= MAX_STRING).ok_or_else(|| wasmi::Error::new("string too long"))?;
+    let mut bytes = vec![0; len];
+    memory.read(caller, ptr as u32 as usize, &mut bytes).map_err(|e| wasmi::Error::new(e.to_string()))?;
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+fn world<'a>(caller: &'a mut Caller<Host>) -> Result<&'a mut World, wasmi::Error> {
+    caller.data_mut().world.as_mut().ok_or_else(|| wasmi::Error::new("the world isn't there while enabling"))
+}
+
+/// Keeps plugins to blocks players could reach, so they can't make the
+/// server generate chunks or build on the roads.
+fn reachable(world: &World, pos: BlockPos) -> Result<(), wasmi::Error> {
+    if !(0..HEIGHT).contains(&pos.y) || !world.chunks.contains_key(&pos.chunk()) {
+        return Err(wasmi::Error::new(format!("{} {} {} isn't loaded", pos.x, pos.y, pos.z)));
+    }
+
+    if world.on_road(pos) {
+        return Err(wasmi::Error::new(format!("{} {} {} is on a road", pos.x, pos.y, pos.z)));
+    }
+
+    Ok(())
+}
+
+fn enabling(caller: &Caller<Host>, function: &str) -> Result<(), wasmi::Error> {
+    match caller.data().enabling {
+        true => Ok(()),
+        false => Err(wasmi::Error::new(format!("{} only works from enable", function))),
+    }
+}
+
+/// The functions plugins import.
+fn linker(engine: &Engine) -> Result<Linker<Host>, wasmi::Error> {
+    let mut linker = Linker::new(engine);
+
+    linker.func_wrap("server", "log", |caller: Caller<Host>, ptr: i32, len: i32| {
+        let message = read_string(&caller, ptr, len)?;
+        info!("[{}] {}", caller.data().name, message);
+        Ok(())
+    })?;
+
+    linker.func_wrap("server", "send_message", |mut caller: Caller<Host>, client: i64, ptr: i32, len: i32| {
+        let message = read_string(&caller, ptr, len)?;
+        caller.data_mut().actions.push(Action::Message(Some(ClientId(client as u64)), message));
+        Ok(())
+    })?;
+
+    linker.func_wrap("server", "broadcast", |mut caller: Caller<Host>, ptr: i32, len: i32| {
+        let message = read_string(&caller, ptr, len)?;
+        caller.data_mut().actions.push(Action::Message(None, message));
+        Ok(())
+    })?;
+
+    linker.func_wrap("server", "reply", |mut caller: Caller<Host>, ptr: i32, len: i32| {
+        let message = read_string(&caller, ptr, len)?;
+        caller.data_mut().reply = Some(message);
+        Ok(())
+    })?;
+
+    linker.func_wrap("server", "input", |mut caller: Caller<Host>, ptr: i32, cap: i32| {
+        let Some(Extern::Memory(memory)) = caller.get_export("memory") else {
+            return Err(wasmi::Error::new("the plugin exports no memory"));
+        };
+
+        let input = caller.data().input.clone().into_bytes();
+        let count = input.len().min(cap.max(0) as usize);
+        memory.write(&mut caller, ptr as u32 as usize, &input[..count]).map_err(|e| wasmi::Error::new(e.to_string()))?;
+        Ok(input.len() as i32)
+    })?;
+
+    linker.func_wrap("server", "get_block", |mut caller: Caller<Host>, x: i32, y: i32, z: i32| {
+        let pos = BlockPos::new(x, y, z);
+        let world = world(&mut caller)?;
+        reachable(world, pos)?;
+        Ok(world.get_block_id(pos) as i32)
+    })?;
+
+    linker.func_wrap("server", "set_block", |mut caller: Caller<Host>, x: i32, y: i32, z: i32, state: i32| {
+        let pos = BlockPos::new(x, y, z);
+        let world = world(&mut caller)?;
+        reachable(world, pos)?;
+        redstone::set_block(world, pos, Block::from_id(state as u32), true);
+        Ok(())
+    })?;
+
+    linker.func_wrap("server", "listen", |mut caller: Caller<Host>, kind: i32| {
+        enabling(&caller, "listen")?;
+        let kind = usize::try_from(kind).ok().and_then(|k| KINDS.get(k)).ok_or_else(|| wasmi::Error::new("no such event kind"))?;
+        caller.data_mut().actions.push(Action::Listen(*kind));
+        Ok(())
+    })?;
+
+    linker.func_wrap("server", "register_command", |mut caller: Caller<Host>, ptr: i32, len: i32| {
+        enabling(&caller, "register_command")?;
+        let name = read_string(&caller, ptr, len)?;
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+            return Err(wasmi::Error::new(format!("{} is not a valid command name", name)));
+        }
+
+        caller.data_mut().actions.push(Action::Command(name));
+        Ok(())
+    })?;
+
+    linker.func_wrap("server", "schedule", |mut caller: Caller<Host>, delay: i32, task: i32| {
+        caller.data_mut().actions.push(Action::Schedule(delay.max(0) as u64, task));
+        Ok(())
+    })?;
+
+    Ok(linker)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Exports `spin`, which loops forever.
+    const SPIN: &[u8] = &[
+        0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00,
+        0x01, 0x04, 0x01, 0x60, 0x00, 0x00,
+        0x03, 0x02, 0x01, 0x00,
+        0x07, 0x08, 0x01, 0x04, b's', b'p', b'i', b'n', 0x00, 0x00,
+        0x0a, 0x09, 0x01, 0x07, 0x00, 0x03, 0x40, 0x0c, 0x00, 0x0b, 0x0b,
+    ];
+
+    /// Exports a page of `memory` and `grow`, which asks for 1024 more pages.
+    const GROW: &[u8] = &[
+        0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00,
+        0x01, 0x05, 0x01, 0x60, 0x00, 0x01, 0x7f,
+        0x03, 0x02, 0x01, 0x00,
+        0x05, 0x03, 0x01, 0x00, 0x01,
+        0x07, 0x11, 0x02,
+        0x06, b'm', b'e', b'm', b'o', b'r', b'y', 0x02, 0x00,
+        0x04, b'g', b'r', b'o', b'w', 0x00, 0x00,
+        0x0a, 0x09, 0x01, 0x07, 0x00, 0x41, 0x80, 0x08, 0x40, 0x00, 0x0b,
+    ];
+
+    fn runtime(bytes: &[u8]) -> Shared {
+        WasmPlugin::new("test".to_string(), bytes, &WasmConfig::default()).unwrap().runtime
+    }
+
+    #[test]
+    fn running_out_of_fuel_fails_the_call() {
+        let runtime = runtime(SPIN);
+        {
+            let runtime = runtime.borrow();
+            assert!(runtime.instance.get_typed_func::<(), ()>(&runtime.store, "spin").is_ok());
+        }
+        assert_eq!(runtime.borrow_mut().call::<(), ()>(None, "spin", (), String::new()), None);
+        // and it gets fresh fuel the next time
+        assert_eq!(runtime.borrow_mut().call::<(), ()>(None, "spin", (), String::new()), None);
+    }
+
+    #[test]
+    fn memory_stays_within_its_limit() {
+        let runtime = runtime(GROW);
+        assert_eq!(runtime.borrow_mut().call::<(), i32>(None, "grow", (), String::new()), Some(-1));
+
+        let config = WasmConfig { memory: 1 << 10, ..Default::default() };
+        assert!(WasmPlugin::new("test".to_string(), GROW, &config).is_err());
+    }
+}
@@ -0,0 +1,273 @@
+//! GameSpy4 queries over UDP, which server lists and monitoring poll for
+//! the status the server list shows and who is online. Clients first get a
+//! challenge token for their address, which every stat request has to
+//! repeat, so the server can't be used to flood addresses it never heard
+//! from.
+
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{mpsc, Arc};
+use std::time::{Duration, Instant};
+use tokio::net::UdpSocket;
+use tokio::sync::oneshot;
+use super::config::QueryConfig;
+use super::network::Event;
+use super::packet::status::ServerStatus;
+
+const MAGIC: [u8; 2] = [0xfe, 0xfd];
+const HANDSHAKE: u8 = 9;
+const STAT: u8 = 0;
+
+/// How long before tokens change. A token stays valid until the one after
+/// it is handed out too.
+const TOKEN_LIFETIME: Duration = Duration::from_secs(30);
+
+/// Longest request taken, like vanilla.
+const MAX_REQUEST: usize = 1460;
+
+/// Opens the key-value section of a full stat.
+const SPLITNUM: &[u8] = b"splitnum\0\x80\0";
+/// Opens the player section of a full stat.
+const PLAYERS: &[u8] = b"\x01player_\0\0";
+
+/// Derives every address's token from a secret that changes every
+/// [`TOKEN_LIFETIME`], so nothing has to be kept per address.
+struct Tokens {
+    current: RandomState,
+    previous: RandomState,
+    rotated: Instant,
+}
+
+impl Tokens {
+    fn new() -> Self {
+        Self { current: RandomState::new(), previous: RandomState::new(), rotated: Instant::now() }
+    }
+
+    fn rotate(&mut self) {
+        let elapsed = self.rotated.elapsed();
+        if elapsed < TOKEN_LIFETIME {
+            return;
+        }
+
+        self.previous = std::mem::replace(&mut self.current, RandomState::new());
+        if elapsed >= TOKEN_LIFETIME * 2 {
+            self.previous = RandomState::new();
+        }
+        self.rotated = Instant::now();
+    }
+
+    /// Vanilla's tokens are below 2^24, which some clients count on.
+    fn token(secret: &RandomState, ip: IpAddr) -> i32 {
+        (secret.hash_one(ip) & 0xff_ffff) as i32
+    }
+
+    fn issue(&mut self, ip: IpAddr) -> i32 {
+        self.rotate();
+        Self::token(&self.current, ip)
+    }
+
+    fn check(&mut self, ip: IpAddr, token: i32) -> bool {
+        self.rotate();
+        token == Self::token(&self.current, ip) || token == Self::token(&self.previous, ip)
+    }
+}
+
+/// Where players connect to, which full stats tell.
+#[derive(Clone)]
+struct Host {
+    ip: String,
+    port: u16,
+}
+
+/// Answers queries until the server stops. `game_address` is where the
+/// server takes players.
+pub fn run(config: QueryConfig, game_address: String, events: mpsc::Sender<Event>) {
+    let (ip, port) = game_address.rsplit_once(':').unwrap_or((&game_address, ""));
+    let host = Host { ip: ip.to_string(), port: port.parse().unwrap_or(25565) };
+
+    let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+    runtime.block_on(async {
+        let socket = match UdpSocket::bind(&config.address).await {
+            Ok(socket) => Arc::new(socket),
+            Err(e) => {
+                error!("can't listen for queries on {}: {}", config.address, e);
+                return;
+            },
+        };
+        info!("answering queries on {}", config.address);
+
+        let mut tokens = Tokens::new();
+        let mut buffer = [0; MAX_REQUEST];
+        loop {
+            let (len, address) = match socket.recv_from(&mut buffer).await {
+                Ok(received) => received,
+                Err(e) => {
+                    warn!("failed to receive a query: {}", e);
+                    continue;
+                },
+            };
+
+            let request = &buffer[..len];
+            if len < 7 || request[..2] != MAGIC {
+                continue;
+            }
+
+            let session = &request[3..7];
+            let payload = &request[7..];
+            match request[2] {
+                HANDSHAKE => {
+                    let mut response = vec![HANDSHAKE];
+                    response.extend(session);
+                    response.extend(tokens.issue(address.ip()).to_string().as_bytes());
+                    response.push(0);
+                    let _ = socket.send_to(&response, address).await;
+                },
+                STAT if payload.len() >= 4 => {
+                    let token = i32::from_be_bytes(payload[..4].try_into().unwrap());
+                    if !tokens.check(address.ip(), token) {
+                        continue;
+                    }
+
+                    // full stats pad the token to 8 bytes
+                    let full = payload.len() >= 8;
+                    let session = session.try_into().unwrap();
+                    tokio::spawn(stat(Arc::clone(&socket), address, session, full, host.clone(), events.clone()));
+                },
+                _ => {},
+            }
+        }
+    });
+}
+
+/// Asks the game loop for the status and sends it back.
+async fn stat(socket: Arc<UdpSocket>, address: SocketAddr, session: [u8; 4], full: bool, host: Host, events: mpsc::Sender<Event>) {
+    let (reply, status) = oneshot::channel();
+    if events.send(Event::Status(reply)).is_err() {
+        return;
+    }
+    let Ok(status) = status.await else { return };
+
+    let mut response = vec![STAT];
+    response.extend(session);
+    if full {
+        full_stat(&mut response, &status, &host);
+    } else {
+        basic_stat(&mut response, &status, &host);
+    }
+
+    if let Err(e) = socket.send_to(&response, address).await {
+        info!("failed to answer the query of {}: {}", address, e);
+    }
+}
+
+fn push_string(response: &mut Vec<u8>, value: &str) {
+    response.extend(value.as_bytes());
+    response.push(0);
+}
+
+fn basic_stat(response: &mut Vec<u8>, status: &ServerStatus, host: &Host) {
+    push_string(response, &status.motd());
+    push_string(response, "SMP");
+    push_string(response, &status.map);
+    push_string(response, &status.online.to_string());
+    push_string(response, &status.max.to_string());
+    response.extend(host.port.to_le_bytes());
+    push_string(response, &host.ip);
+}
+
+fn full_stat(response: &mut Vec<u8>, status: &ServerStatus, host: &Host) {
+    response.extend(SPLITNUM);
+    let values = [
+        ("hostname", status.motd()),
+        ("gametype", "SMP".to_string()),
+        ("game_id", "MINECRAFT".to_string()),
+        ("version", status.version.to_string()),
+        ("plugins", String::new()),
+        ("map", status.map.clone()),
+        ("numplayers", status.online.to_string()),
+        ("maxplayers", status.max.to_string()),
+        ("hostport", host.port.to_string()),
+        ("hostip", host.ip.clone()),
+    ];
+    for (key, value) in values {
+        push_string(response, key);
+        push_string(response, &value);
+    }
+    response.push(0);
+
+    response.extend(PLAYERS);
+    for (name, _) in &status.players {
+        push_string(response, name);
+    }
+    response.push(0);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status() -> ServerStatus {
+        ServerStatus {
+            description: json::object! { text: "Redstone ", extra: [{ text: "server" }] },
+            version: "1.18.2",
+            online: 2,
+            max: 20,
+            players: vec![
+                ("Alice".to_string(), String::new()),
+                ("Bob".to_string(), String::new()),
+            ],
+            map: "world".to_string(),
+        }
+    }
+
+    fn host() -> Host {
+        Host { ip: "127.0.0.1".to_string(), port: 25565 }
+    }
+
+    #[test]
+    fn basic_stat_lists_values_in_order() {
+        let mut response = Vec::new();
+        basic_stat(&mut response, &status(), &host());
+        assert_eq!(response, b"Redstone server\0SMP\0world\x002\x0020\0\xdd\x63127.0.0.1\0");
+    }
+
+    #[test]
+    fn full_stat_lists_keys_values_and_players() {
+        let mut response = Vec::new();
+        full_stat(&mut response, &status(), &host());
+
+        let mut expected = SPLITNUM.to_vec();
+        expected.extend(b"hostname\0Redstone server\0gametype\0SMP\0game_id\0MINECRAFT\0");
+        expected.extend(b"version\x001.18.2\0plugins\0\0map\0world\0numplayers\x002\0maxplayers\x0020\0");
+        expected.extend(b"hostport\x0025565\0hostip\x00127.0.0.1\0\0");
+        expected.extend(PLAYERS);
+        expected.extend(b"Alice\0Bob\0\0");
+        assert_eq!(response, expected);
+    }
+
+    #[test]
+    fn tokens_are_checked_per_address() {
+        let mut tokens = Tokens::new();
+        let ip: IpAddr = "203.0.113.7".parse().unwrap();
+        let token = tokens.issue(ip);
+        assert!((0..1 << 24).contains(&token));
+        assert!(tokens.check(ip, token));
+        assert!(!tokens.check("203.0.113.8".parse().unwrap(), token));
+    }
+
+    #[test]
+    fn tokens_outlive_one_rotation() {
+        let mut tokens = Tokens::new();
+        let ip: IpAddr = "203.0.113.7".parse().unwrap();
+        let token = tokens.issue(ip);
+
+        tokens.rotated -= TOKEN_LIFETIME;
+        tokens.rotate();
+        assert!(tokens.check(ip, token));
+
+        tokens.rotated -= TOKEN_LIFETIME * 2;
+        tokens.rotate();
+        assert!(!tokens.check(ip, token));
+    }
+}
@@ -0,0 +1,190 @@
+//! The remote console, speaking Source's RCON protocol so tools can run
+//! commands like an operator would. It listens on its own port and thread
+//! and hands each command to the game loop, which runs it as the console.
+
+use std::io;
+use std::net::SocketAddr;
+use std::sync::mpsc;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::oneshot;
+use super::config::RconConfig;
+use super::network::Event;
+
+const AUTH: i32 = 3;
+const AUTH_RESPONSE: i32 = 2;
+const EXEC_COMMAND: i32 = 2;
+const RESPONSE_VALUE: i32 = 0;
+
+/// Longest request taken, counting its id and type, like vanilla.
+const MAX_REQUEST: i32 = 1460;
+/// Longest body of one response. Longer output is sent in several, with
+/// the same id.
+const MAX_RESPONSE: usize = 4096;
+
+struct Packet {
+    id: i32,
+    kind: i32,
+    body: Vec<u8>,
+}
+
+/// Accepts RCON clients until the server stops.
+pub fn run(config: RconConfig, events: mpsc::Sender<Event>) {
+    if config.password.is_empty() {
+        warn!("RCON needs a password, leaving it off");
+        return;
+    }
+
+    let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+    runtime.block_on(async {
+        let listener = match TcpListener::bind(&config.address).await {
+            Ok(listener) => listener,
+            Err(e) => {
+                error!("can't listen for RCON on {}: {}", config.address, e);
+                return;
+            },
+        };
+        info!("RCON listening on {}", config.address);
+
+        loop {
+            let (socket, address) = match listener.accept().await {
+                Ok(connection) => connection,
+                Err(e) => {
+                    warn!("failed to accept an RCON connection: {}", e);
+                    continue;
+                },
+            };
+
+            tokio::spawn(session(socket, address, config.password.clone(), events.clone()));
+        }
+    });
+}
+
+async fn session(mut tcp: TcpStream, address: SocketAddr, password: String, events: mpsc::Sender<Event>) {
+    match serve(&mut tcp, address, &password, &events).await {
+        Ok(()) => {},
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {},
+        Err(e) => {
+            info!("RCON client {} is dead: {}", address, e);
+        },
+    }
+
+    let _ = tcp.shutdown().await;
+}
+
+/// Answers a client's requests until it leaves or fails to log in.
+async fn serve(tcp: &mut TcpStream, address: SocketAddr, password: &str, events: &mpsc::Sender<Event>) -> io::Result<()> {
+    let mut logged_in = false;
+
+    loop {
+        let packet = read_packet(tcp).await?;
+        match packet.kind {
+            AUTH if packet.body == password.as_bytes() => {
+                info!("{} logged into RCON", address);
+                logged_in = true;
+                write_packet(tcp, packet.id, AUTH_RESPONSE, &[]).await?;
+            },
+            AUTH => {
+                warn!("{} used a wrong RCON password", address);
+                return write_packet(tcp, -1, AUTH_RESPONSE, &[]).await;
+            },
+            _ if !logged_in => return write_packet(tcp, -1, AUTH_RESPONSE, &[]).await,
+            EXEC_COMMAND => {
+                let line = String::from_utf8_lossy(&packet.body).into_owned();
+                info!("{} ran /{} over RCON", address, line.trim_start_matches('/'));
+
+                let (reply, output) = oneshot::channel();
+                if events.send(Event::Console(line, reply)).is_err() {
+                    return Ok(());
+                }
+                let Ok(output) = output.await else { return Ok(()) };
+
+                for fragment in fragments(&output) {
+                    write_packet(tcp, packet.id, RESPONSE_VALUE, fragment.as_bytes()).await?;
+                }
+            },
+            // clients send one after a command and wait for its answer, to
+            // know when the output of the command ended
+            RESPONSE_VALUE => {
+                write_packet(tcp, packet.id, RESPONSE_VALUE, &[]).await?;
+                write_packet(tcp, packet.id, RESPONSE_VALUE, &[0, 1, 0, 0]).await?;
+            },
+            kind => {
+                let message = format!("Unknown request {:x}", kind);
+                write_packet(tcp, packet.id, RESPONSE_VALUE, message.as_bytes()).await?;
+            },
+        }
+    }
+}
+
+async fn read_packet(tcp: &mut TcpStream) -> io::Result<Packet> {
+    let len = tcp.read_i32_le().await?;
+    if !(10..=MAX_REQUEST).contains(&len) {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("request has a length of {}", len)));
+    }
+
+    let mut frame = vec![0; len as usize];
+    tcp.read_exact(&mut frame).await?;
+    let id = i32::from_le_bytes(frame[0..4].try_into().unwrap());
+    let kind = i32::from_le_bytes(frame[4..8].try_into().unwrap());
+    // the body ends with a null byte, and one more follows it
+    let body = frame[8..].split(|b| *b == 0).next().unwrap_or_default().to_vec();
+    Ok(Packet { id, kind, body })
+}
+
+async fn write_packet(tcp: &mut TcpStream, id: i32, kind: i32, body: &[u8]) -> io::Result<()> {
+    let mut bytes = Vec::with_capacity(body.len() + 14);
+    bytes.extend((body.len() as i32 + 10).to_le_bytes());
+    bytes.extend(id.to_le_bytes());
+    bytes.extend(kind.to_le_bytes());
+    bytes.extend(body);
+    bytes.extend([0, 0]);
+    tcp.write_all(&bytes).await
+}
+
+/// Splits output into response bodies, without cutting characters apart.
+/// Even no output is sent as one empty response.
+fn fragments(mut output: &str) -> Vec<&str> {
+    let mut fragments = Vec::new();
+    while output.len() > MAX_RESPONSE {
+        let mut end = MAX_RESPONSE;
+        while !output.is_char_boundary(end) {
+            end -= 1;
+        }
+
+        let (fragment, rest) = output.split_at(end);
+        fragments.push(fragment);
+        output = rest;
+    }
+
+    fragments.push(output);
+    fragments
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn short_output_is_one_fragment() {
+        assert_eq!(fragments(""), [""]);
+        assert_eq!(fragments("Announced it"), ["Announced it"]);
+    }
+
+    #[test]
+    fn long_output_is_split_at_the_limit() {
+        let output = "a".repeat(MAX_RESPONSE * 2 + 10);
+        let lens: Vec<_> = fragments(&output).iter().map(|f| f.len()).collect();
+        assert_eq!(lens, [MAX_RESPONSE, MAX_RESPONSE, 10]);
+    }
+
+    #[test]
+    fn fragments_keep_characters_whole() {
+        // the three bytes of a euro sign straddle the limit
+        let output = format!("{}\u{20ac}rest", "a".repeat(MAX_RESPONSE - 1));
+        let fragments = fragments(&output);
+        assert_eq!(fragments[0].len(), MAX_RESPONSE - 1);
+        assert_eq!(fragments[1], "\u{20ac}rest");
+        assert_eq!(fragments.concat(), output);
+    }
+}
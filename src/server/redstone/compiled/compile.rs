@@ -0,0 +1,513 @@
+//! Turns the blocks of a region into a circuit.
+
+use std::collections::{HashMap, VecDeque};
+use std::collections::hash_map::Entry;
+use super::*;
+
+/// Most blocks a region can have, so a typo can't stall the server.
+const MAX_VOLUME: u64 = 1 << 22;
+
+/// Most problems listed when a region can't be compiled.
+const MAX_PROBLEMS: usize = 5;
+
+/// Where a signal comes from: a node, or a block that's left alone, like a
+/// component outside the region, which is read once.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Source {
+    Node(NodeId),
+    Constant(u8),
+}
+
+/// A source and the power lost on the way from it.
+type Input = (Source, u8);
+
+/// What went into a circuit.
+#[derive(Debug, Clone, Copy)]
+pub struct Stats {
+    pub nodes: usize,
+    /// Nodes folded away because they never change.
+    pub constant: usize,
+    /// Dust, which only shows power and isn't on the way of any signal.
+    pub wires: usize,
+    pub links: usize,
+}
+
+fn constant(power: u8) -> Vec<Input> {
+    if power > 0 {
+        vec![(Source::Constant(power), 0)]
+    } else {
+        Vec::new()
+    }
+}
+
+/// Keeps the closest link to each node, and the strongest constant.
+fn merge(inputs: Vec<Input>) -> Vec<Input> {
+    let mut closest: HashMap<NodeId, u8> = HashMap::new();
+    let mut strongest = 0;
+    for (source, distance) in inputs {
+        match source {
+            Source::Node(id) if distance < 15 => {
+                let d = closest.entry(id).or_insert(distance);
+                *d = (*d).min(distance);
+            },
+            Source::Node(_) => {},
+            Source::Constant(power) => strongest = strongest.max(power.saturating_sub(distance)),
+        }
+    }
+
+    let mut merged: Vec<_> = closest.into_iter().map(|(id, d)| (Source::Node(id), d)).collect();
+    merged.sort_by_key(|(s, _)| match s {
+        Source::Node(id) => *id,
+        Source::Constant(_) => usize::MAX,
+    });
+    merged.extend(constant(strongest));
+    merged
+}
+
+/// The node a block compiles to, in the state the block is in.
+fn new_node(world: &World, pos: BlockPos, block: Block) -> Option<Node> {
+    let (kind, powered, output) = match block {
+        Block::RedstoneBlock => (NodeKind::Constant, true, 15),
+        Block::Lever { powered, .. } => (NodeKind::Lever, powered, powered as u8 * 15),
+        Block::Button { kind, powered, .. } => (NodeKind::Button { duration: button_duration(kind) }, powered, powered as u8 * 15),
+        Block::RedstoneTorch { lit } | Block::RedstoneWallTorch { lit, .. } => (NodeKind::Torch, lit, lit as u8 * 15),
+        Block::Repeater { delay, powered, .. } => {
+            let prioritized = diode::should_prioritize(world, pos, block);
+            (NodeKind::Repeater { delay, prioritized }, powered, powered as u8 * 15)
+        },
+        Block::Comparator { mode, powered, .. } => {
+            let prioritized = diode::should_prioritize(world, pos, block);
+            let output = if powered { diode::comparator_output(world, pos) } else { 0 };
+            (NodeKind::Comparator { mode, far: None, prioritized }, powered, output)
+        },
+        Block::RedstoneLamp { lit } => (NodeKind::Lamp, lit, 0),
+        Block::RedstoneWire(w) => (NodeKind::Wire, false, w.power),
+        _ => return None,
+    };
+
+    Some(Node {
+        pos,
+        block,
+        kind,
+        inputs: Vec::new(),
+        side_inputs: Vec::new(),
+        constant_input: 0,
+        constant_side: 0,
+        outputs: Vec::new(),
+
+        output,
+        powered,
+        locked: matches!(block, Block::Repeater { locked: true, .. }),
+        value: diode::comparator_output(world, pos),
+
+        dirty: false,
+        edge: false,
+    })
+}
+
+/// Blocks that change on their own or move things around, which compiled
+/// regions can't have.
+fn is_unsupported(block: Block) -> bool {
+    matches!(
+        block,
+        Block::Piston { .. }
+            | Block::PistonHead { .. }
+            | Block::MovingPiston { .. }
+            | Block::Observer { .. }
+            | Block::NoteBlock { .. }
+            | Block::Door { .. }
+            | Block::Trapdoor { .. }
+            | Block::Tnt
+            | Block::DaylightDetector { .. }
+            | Block::PressurePlate { .. }
+            | Block::WeightedPressurePlate { .. }
+            | Block::TripwireHook { .. }
+            | Block::Tripwire(_)
+            | Block::Hopper { .. }
+            | Block::Dispenser { .. }
+            | Block::Dropper { .. }
+    )
+}
+
+/// Finds where the signals each node reads come from, following the rules
+/// of the block-accurate engine.
+struct Builder<'a> {
+    world: &'a World,
+    region: Region,
+    ids: HashMap<BlockPos, NodeId>,
+    /// Sources of each piece of dust, since a run of dust shares them.
+    wires: HashMap<BlockPos, Vec<Input>>,
+    problems: Vec<String>,
+}
+
+impl Builder<'_> {
+    fn node(&self, pos: BlockPos) -> Vec<Input> {
+        vec![(Source::Node(self.ids[&pos]), 0)]
+    }
+
+    /// Sources of what the neighbour towards `dir` gets from `pos`, see [`get_signal`].
+    fn signal_inputs(&mut self, pos: BlockPos, dir: Direction, dust_power: bool) -> Vec<Input> {
+        let block = self.world.get_block(pos);
+        let mut inputs = self.weak_inputs(pos, block, dir, dust_power);
+        if block.is_solid() {
+            for d in Direction::ALL {
+                inputs.extend(self.strong_inputs(pos.offset(d), d, dust_power));
+            }
+        }
+
+        inputs
+    }
+
+    /// See [`get_weak_power`].
+    fn weak_inputs(&mut self, pos: BlockPos, block: Block, dir: Direction, dust_power: bool) -> Vec<Input> {
+        if !self.region.contains(pos) {
+            return constant(get_weak_power(self.world, pos, block, dir, dust_power));
+        }
+
+        match block {
+            Block::RedstoneTorch { .. } if dir != Direction::Up => self.node(pos),
+            Block::RedstoneWallTorch { facing, .. } if dir != facing => self.node(pos),
+            Block::Lever { .. } | Block::Button { .. } | Block::RedstoneBlock => self.node(pos),
+            Block::Repeater { facing, .. } | Block::Comparator { facing, .. } if facing == dir => self.node(pos),
+            Block::RedstoneWire(w) if dust_power && (dir == Direction::Up || w.side(dir.opposite()).is_connected()) => {
+                self.wire_inputs(pos)
+            },
+            Block::Target { power } => constant(power),
+            _ => Vec::new(),
+        }
+    }
+
+    /// See [`get_strong_power`].
+    fn strong_inputs(&mut self, pos: BlockPos, dir: Direction, dust_power: bool) -> Vec<Input> {
+        let block = self.world.get_block(pos);
+        if !self.region.contains(pos) {
+            return constant(get_strong_power(self.world, pos, block, dir, dust_power));
+        }
+
+        match block {
+            Block::RedstoneTorch { .. } | Block::RedstoneWallTorch { .. } if dir == Direction::Down => {
+                self.weak_inputs(pos, block, dir, dust_power)
+            },
+            Block::Lever { face, facing, .. } | Block::Button { face, facing, .. }
+                if attached_direction(face, facing) == dir => self.node(pos),
+            Block::Repeater { .. } | Block::Comparator { .. } | Block::RedstoneWire(_) => {
+                self.weak_inputs(pos, block, dir, dust_power)
+            },
+            _ => Vec::new(),
+        }
+    }
+
+    /// Dust a piece of dust takes power from, like `calculate_target_strength`.
+    fn wire_neighbors(&self, pos: BlockPos) -> Vec<BlockPos> {
+        let world = self.world;
+        let is_wire = |p: BlockPos| matches!(world.get_block(p), Block::RedstoneWire(_));
+        let above_solid = world.get_block(pos.offset(Direction::Up)).is_solid();
+
+        let mut neighbors = Vec::new();
+        for d in Direction::HORIZONTAL {
+            let n = pos.offset(d);
+            let solid = world.get_block(n).is_solid();
+            let candidates = [
+                Some(n),
+                (solid && !above_solid).then(|| n.offset(Direction::Up)),
+                (!solid).then(|| n.offset(Direction::Down)),
+            ];
+
+            neighbors.extend(candidates.into_iter().flatten().filter(|p| is_wire(*p)));
+        }
+
+        neighbors
+    }
+
+    /// Sources powering the dust at `pos`, through the run of dust it's in.
+    fn wire_inputs(&mut self, pos: BlockPos) -> Vec<Input> {
+        if let Some(inputs) = self.wires.get(&pos) {
+            return inputs.clone();
+        }
+
+        let mut distances = HashMap::from([(pos, 0)]);
+        let mut queue = VecDeque::from([pos]);
+        let mut inputs = Vec::new();
+
+        while let Some(p) = queue.pop_front() {
+            let distance = distances[&p];
+            for d in Direction::ALL {
+                let found = self.signal_inputs(p.offset(d), d, false);
+                inputs.extend(found.into_iter().map(|(s, k)| (s, k + distance)));
+            }
+
+            if distance >= 14 {
+                continue;
+            }
+
+            for n in self.wire_neighbors(p) {
+                if !self.region.contains(n) {
+                    self.problems.push(format!("dust leaves the region at {} {} {}", n.x, n.y, n.z));
+                } else if let Entry::Vacant(e) = distances.entry(n) {
+                    e.insert(distance + 1);
+                    queue.push_back(n);
+                }
+            }
+        }
+
+        let inputs = merge(inputs);
+        self.wires.insert(pos, inputs.clone());
+        inputs
+    }
+
+    /// Input of a repeater or comparator, see `get_input_signal`.
+    fn diode_inputs(&mut self, pos: BlockPos, facing: Direction) -> Vec<Input> {
+        let n = pos.offset(facing);
+        let mut inputs = self.signal_inputs(n, facing, true);
+        match self.world.get_block(n) {
+            Block::RedstoneWire(_) if self.region.contains(n) => inputs.extend(self.wire_inputs(n)),
+            Block::RedstoneWire(w) => inputs.extend(constant(w.power)),
+            _ => {},
+        }
+
+        inputs
+    }
+
+    /// Side signal of a repeater or comparator, see `get_alternate_signal`.
+    fn side_inputs(&mut self, pos: BlockPos, facing: Direction, diode: Block) -> Vec<Input> {
+        let mut inputs = Vec::new();
+        for dir in [facing.clockwise(), facing.counter_clockwise()] {
+            let n = pos.offset(dir);
+            if !self.region.contains(n) {
+                inputs.extend(constant(diode::get_alternate_signal_at(self.world, n, dir, diode)));
+                continue;
+            }
+
+            let block = self.world.get_block(n);
+            let is_input = match diode {
+                Block::Repeater { .. } => block.is_diode(),
+                _ => block.is_signal_source(),
+            };
+
+            inputs.extend(match block {
+                _ if !is_input => Vec::new(),
+                Block::RedstoneBlock => self.node(n),
+                Block::RedstoneWire(_) => self.wire_inputs(n),
+                _ => self.strong_inputs(n, dir, true),
+            });
+        }
+
+        inputs
+    }
+
+    /// Input and side signal sources of a node, and what a comparator reads
+    /// from a container behind a block.
+    fn node_inputs(&mut self, node: &Node) -> (Vec<Input>, Vec<Input>, Option<u8>) {
+        let pos = node.pos;
+        match node.block {
+            Block::RedstoneTorch { .. } => (self.signal_inputs(pos.offset(Direction::Down), Direction::Down, true), Vec::new(), None),
+            Block::RedstoneWallTorch { facing, .. } => {
+                let dir = facing.opposite();
+                (self.signal_inputs(pos.offset(dir), dir, true), Vec::new(), None)
+            },
+            b @ Block::Repeater { facing, .. } => (self.diode_inputs(pos, facing), self.side_inputs(pos, facing, b), None),
+            b @ Block::Comparator { facing, .. } => {
+                let side = self.side_inputs(pos, facing, b);
+                let n = pos.offset(facing);
+                let behind = self.world.get_block(n);
+                if behind.has_analog_output() {
+                    return (constant(container::analog_output(self.world, n)), side, None);
+                }
+
+                let far = n.offset(facing);
+                let far = (behind.is_solid() && self.world.get_block(far).has_analog_output())
+                    .then(|| container::analog_output(self.world, far));
+                (self.diode_inputs(pos, facing), side, far)
+            },
+            Block::RedstoneLamp { .. } => {
+                let inputs = Direction::ALL.iter().flat_map(|d| self.signal_inputs(pos.offset(*d), *d, true)).collect();
+                (inputs, Vec::new(), None)
+            },
+            Block::RedstoneWire(_) => (self.wire_inputs(pos), Vec::new(), None),
+            _ => (Vec::new(), Vec::new(), None),
+        }
+    }
+}
+
+/// Splits inputs into links and the strongest constant signal.
+fn links(inputs: Vec<Input>) -> (Vec<Link>, u8) {
+    let mut links = Vec::new();
+    let mut strongest = 0;
+    for (source, distance) in merge(inputs) {
+        match source {
+            Source::Node(source) => links.push(Link { source, distance }),
+            Source::Constant(power) => strongest = strongest.max(power.saturating_sub(distance)),
+        }
+    }
+
+    (links, strongest)
+}
+
+/// Moves the links from nodes that never change into the constant signals.
+fn fold_links(links: &mut Vec<Link>, constant: &mut u8, nodes: &[Node], fixed: &[bool]) {
+    links.retain(|l| {
+        if !fixed[l.source] {
+            return true;
+        }
+
+        *constant = (*constant).max(nodes[l.source].output.saturating_sub(l.distance));
+        false
+    });
+}
+
+impl Circuit {
+    /// Puts a node that never changes in the state it ends up in.
+    fn settle(&mut self, id: NodeId) {
+        let (input, side) = self.signals(id);
+        let node = &mut self.nodes[id];
+
+        match node.kind {
+            NodeKind::Torch => {
+                node.powered = input == 0;
+                node.output = if input == 0 { 15 } else { 0 };
+            },
+            NodeKind::Repeater { .. } => {
+                node.locked = side > 0;
+                if !node.locked {
+                    node.powered = input > 0;
+                }
+                node.output = if node.powered { 15 } else { 0 };
+            },
+            NodeKind::Comparator { mode, .. } => {
+                node.value = diode::compare(mode, input, side);
+                node.powered = diode::compare_powered(mode, input, side);
+                node.output = if node.powered { node.value } else { 0 };
+            },
+            NodeKind::Lamp => node.powered = input > 0,
+            NodeKind::Wire => node.output = input,
+            _ => {},
+        }
+
+        self.mark_dirty(id);
+    }
+}
+
+/// Compiles the redstone in `region`. The pending ticks of its components
+/// move to the circuit.
+pub fn compile(world: &mut World, region: Region) -> Result<(Circuit, Stats), String> {
+    if region.volume() > MAX_VOLUME {
+        return Err(format!("{} has {} blocks, at most {} can be compiled", region, region.volume(), MAX_VOLUME));
+    }
+    if region.min.y < 0 || region.max.y >= HEIGHT {
+        return Err(format!("{} reaches out of the world", region));
+    }
+
+    let mut nodes = Vec::new();
+    let mut ids = HashMap::new();
+    let mut problems = Vec::new();
+
+    for x in region.min.x..=region.max.x {
+        for y in region.min.y.max(0)..=region.max.y.min(HEIGHT - 1) {
+            for z in region.min.z..=region.max.z {
+                let pos = BlockPos::new(x, y, z);
+                let block = world.get_block(pos);
+                if is_unsupported(block) {
+                    problems.push(format!("{:?} at {} {} {}", block, x, y, z));
+                } else if let Some(mut node) = new_node(world, pos, block) {
+                    node.edge = region.is_edge(pos);
+                    ids.insert(pos, nodes.len());
+                    nodes.push(node);
+                }
+            }
+        }
+    }
+
+    let mut builder = Builder { world, region, ids, wires: HashMap::new(), problems };
+    let mut comparator_far = Vec::with_capacity(nodes.len());
+    for node in nodes.iter_mut() {
+        let (inputs, side, far) = builder.node_inputs(node);
+        (node.inputs, node.constant_input) = links(inputs);
+        (node.side_inputs, node.constant_side) = links(side);
+        comparator_far.push(far);
+    }
+
+    let Builder { ids, mut problems, .. } = builder;
+    if !problems.is_empty() {
+        problems.dedup();
+        let more = problems.len().saturating_sub(MAX_PROBLEMS);
+        problems.truncate(MAX_PROBLEMS);
+        if more > 0 {
+            problems.push(format!("{} more", more));
+        }
+        return Err(format!("{} can't be compiled: {}", region, problems.join(", ")));
+    }
+
+    for (node, far) in nodes.iter_mut().zip(comparator_far) {
+        if let NodeKind::Comparator { far: f, .. } = &mut node.kind {
+            *f = far;
+        }
+    }
+
+    let mut circuit = Circuit {
+        region,
+        nodes,
+        ids,
+        ticks: TickScheduler::default(),
+        time: world.time,
+        dirty: Vec::new(),
+    };
+
+    // fold nodes that only read nodes that never change, until there are no more
+    let mut fixed: Vec<_> = circuit.nodes.iter().map(|n| n.kind == NodeKind::Constant).collect();
+    loop {
+        let mut folded = false;
+        for id in 0..circuit.nodes.len() {
+            let node = &circuit.nodes[id];
+            if fixed[id] || matches!(node.kind, NodeKind::Lever | NodeKind::Button { .. }) {
+                continue;
+            }
+
+            if node.inputs.iter().chain(&node.side_inputs).all(|l| fixed[l.source]) {
+                circuit.settle(id);
+                fixed[id] = true;
+                folded = true;
+            }
+        }
+
+        if !folded {
+            break;
+        }
+    }
+
+    for id in 0..circuit.nodes.len() {
+        let node = &mut circuit.nodes[id];
+        let (mut inputs, mut input) = (std::mem::take(&mut node.inputs), node.constant_input);
+        let (mut side_inputs, mut side) = (std::mem::take(&mut node.side_inputs), node.constant_side);
+        fold_links(&mut inputs, &mut input, &circuit.nodes, &fixed);
+        fold_links(&mut side_inputs, &mut side, &circuit.nodes, &fixed);
+
+        for link in inputs.iter().chain(&side_inputs) {
+            let outputs = &mut circuit.nodes[link.source].outputs;
+            if !outputs.contains(&id) {
+                outputs.push(id);
+            }
+        }
+
+        let node = &mut circuit.nodes[id];
+        (node.inputs, node.constant_input) = (inputs, input);
+        (node.side_inputs, node.constant_side) = (side_inputs, side);
+    }
+
+    let time = world.time;
+    let ids = &circuit.ids;
+    for (t, priority, pos) in world.ticks.take_pending(|p| ids.contains_key(&p)) {
+        let id = ids[&pos];
+        if !fixed[id] {
+            circuit.ticks.schedule(id, t.max(time + 1), priority);
+        }
+    }
+
+    let stats = Stats {
+        nodes: circuit.nodes.len(),
+        constant: fixed.iter().filter(|f| **f).count(),
+        wires: circuit.nodes.iter().filter(|n| n.kind == NodeKind::Wire).count(),
+        links: circuit.nodes.iter().map(|n| n.inputs.len() + n.side_inputs.len()).sum(),
+    };
+
+    Ok((circuit, stats))
+}
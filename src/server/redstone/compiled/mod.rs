@@ -0,0 +1,478 @@
+//! Compiled redstone. The components of a region are turned into a graph of
+//! nodes that is ticked instead of the blocks: dust is folded into the links
+//! between the components it connects, and nodes that can never change are
+//! folded into the nodes reading them. The blocks are kept in step with the
+//! nodes so players see the circuit run, and the region goes back to the
+//! block-accurate engine as soon as anything else changes a block in it.
+
+mod compile;
+
+use std::collections::HashMap;
+use std::fmt;
+use super::*;
+use super::super::world::{TickScheduler, HEIGHT};
+use super::super::world::block::RedstoneWire;
+
+pub use compile::Stats;
+
+type NodeId = usize;
+
+/// A box of blocks, with both corners included.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Region {
+    pub min: BlockPos,
+    pub max: BlockPos,
+}
+
+impl Region {
+    pub fn new(a: BlockPos, b: BlockPos) -> Self {
+        Self {
+            min: BlockPos::new(a.x.min(b.x), a.y.min(b.y), a.z.min(b.z)),
+            max: BlockPos::new(a.x.max(b.x), a.y.max(b.y), a.z.max(b.z)),
+        }
+    }
+
+    pub fn contains(&self, pos: BlockPos) -> bool {
+        (self.min.x..=self.max.x).contains(&pos.x)
+            && (self.min.y..=self.max.y).contains(&pos.y)
+            && (self.min.z..=self.max.z).contains(&pos.z)
+    }
+
+    pub fn intersects(&self, other: &Region) -> bool {
+        self.min.x <= other.max.x && other.min.x <= self.max.x
+            && self.min.y <= other.max.y && other.min.y <= self.max.y
+            && self.min.z <= other.max.z && other.min.z <= self.max.z
+    }
+
+    /// How many blocks it has, at most `u64::MAX`.
+    pub fn volume(&self) -> u64 {
+        let size = |a: i32, b: i32| (b as i64 - a as i64) as u64 + 1;
+        size(self.min.x, self.max.x)
+            .saturating_mul(size(self.min.y, self.max.y))
+            .saturating_mul(size(self.min.z, self.max.z))
+    }
+
+    /// Whether `pos` is close enough to the outside for its changes to reach it.
+    fn is_edge(&self, pos: BlockPos) -> bool {
+        pos.x - self.min.x < 2 || self.max.x - pos.x < 2
+            || pos.y - self.min.y < 2 || self.max.y - pos.y < 2
+            || pos.z - self.min.z < 2 || self.max.z - pos.z < 2
+    }
+}
+
+impl fmt::Display for Region {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "({}, {}, {}) to ({}, {}, {})",
+            self.min.x, self.min.y, self.min.z, self.max.x, self.max.y, self.max.z,
+        )
+    }
+}
+
+/// A node reading another, and the power lost on the way through dust.
+#[derive(Debug, Clone, Copy)]
+struct Link {
+    source: NodeId,
+    distance: u8,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum NodeKind {
+    /// Redstone blocks.
+    Constant,
+    Lever,
+    Button { duration: u64 },
+    Torch,
+    Repeater { delay: u8, prioritized: bool },
+    /// `far` is what the comparator reads from a container behind the block
+    /// in front of it.
+    Comparator { mode: ComparatorMode, far: Option<u8>, prioritized: bool },
+    Lamp,
+    /// Dust is only kept around to show its power.
+    Wire,
+}
+
+#[derive(Debug, Clone)]
+struct Node {
+    pos: BlockPos,
+    /// The block the node was compiled from.
+    block: Block,
+    kind: NodeKind,
+    inputs: Vec<Link>,
+    side_inputs: Vec<Link>,
+    /// Strongest input and side signal from nodes that never change.
+    constant_input: u8,
+    constant_side: u8,
+    /// Nodes reading this one.
+    outputs: Vec<NodeId>,
+
+    /// Signal the node gives the nodes reading it, or the power of dust.
+    output: u8,
+    /// Whether the torch or lamp is lit, or the component is powered.
+    powered: bool,
+    locked: bool,
+    /// Output of a comparator, which it keeps while unpowered.
+    value: u8,
+
+    /// Whether the block is out of date.
+    dirty: bool,
+    /// Whether blocks outside the region can see the node change.
+    edge: bool,
+}
+
+impl Node {
+    /// The block showing the state of the node.
+    fn block(&self) -> Block {
+        let powered = self.powered;
+        match self.block {
+            Block::RedstoneTorch { .. } => Block::RedstoneTorch { lit: powered },
+            Block::RedstoneWallTorch { facing, .. } => Block::RedstoneWallTorch { facing, lit: powered },
+            Block::Repeater { delay, facing, .. } => Block::Repeater { delay, facing, locked: self.locked, powered },
+            Block::Comparator { facing, mode, .. } => Block::Comparator { facing, mode, powered },
+            Block::RedstoneLamp { .. } => Block::RedstoneLamp { lit: powered },
+            Block::Lever { face, facing, .. } => Block::Lever { face, facing, powered },
+            Block::Button { kind, face, facing, .. } => Block::Button { kind, face, facing, powered },
+            Block::RedstoneWire(w) => Block::RedstoneWire(RedstoneWire { power: self.output, ..w }),
+            b => b,
+        }
+    }
+}
+
+/// The compiled redstone of a region.
+pub struct Circuit {
+    pub region: Region,
+    nodes: Vec<Node>,
+    ids: HashMap<BlockPos, NodeId>,
+    ticks: TickScheduler<NodeId>,
+    time: u64,
+    /// Nodes whose blocks are out of date.
+    dirty: Vec<NodeId>,
+}
+
+impl Circuit {
+    fn input(&self, links: &[Link], constant: u8) -> u8 {
+        links.iter().fold(constant, |power, l| power.max(self.nodes[l.source].output.saturating_sub(l.distance)))
+    }
+
+    /// Input and side signal of a node.
+    fn signals(&self, id: NodeId) -> (u8, u8) {
+        let node = &self.nodes[id];
+        let input = self.input(&node.inputs, node.constant_input);
+        let side = self.input(&node.side_inputs, node.constant_side);
+
+        match node.kind {
+            // containers are only read while the block in front is not fully powered
+            NodeKind::Comparator { far: Some(far), .. } if input < 15 => (far, side),
+            _ => (input, side),
+        }
+    }
+
+    fn mark_dirty(&mut self, id: NodeId) {
+        if !self.nodes[id].dirty {
+            self.nodes[id].dirty = true;
+            self.dirty.push(id);
+        }
+    }
+
+    fn schedule(&mut self, id: NodeId, delay: u64, priority: TickPriority) {
+        self.ticks.schedule(id, self.time + delay, priority);
+    }
+
+    /// Changes the signal of a node, and lets the nodes reading it react.
+    fn set_output(&mut self, id: NodeId, output: u8) {
+        self.mark_dirty(id);
+        if self.nodes[id].output == output {
+            return;
+        }
+
+        self.nodes[id].output = output;
+        for i in 0..self.nodes[id].outputs.len() {
+            self.update(self.nodes[id].outputs[i]);
+        }
+    }
+
+    /// Lets a node react to its inputs changing, like a block to a neighbour.
+    fn update(&mut self, id: NodeId) {
+        let (input, side) = self.signals(id);
+        let node = &self.nodes[id];
+
+        match node.kind {
+            NodeKind::Torch if node.powered == (input > 0) && !self.ticks.will_tick_this_tick(id) => {
+                self.schedule(id, 2, TickPriority::Normal);
+            },
+            NodeKind::Repeater { delay, prioritized } => {
+                if node.locked != (side > 0) {
+                    self.nodes[id].locked = side > 0;
+                    self.mark_dirty(id);
+                }
+
+                let node = &self.nodes[id];
+                if self.ticks.will_tick_this_tick(id) || node.locked || node.powered == (input > 0) {
+                    return;
+                }
+
+                let priority = if prioritized {
+                    TickPriority::ExtremelyHigh
+                } else if node.powered {
+                    TickPriority::VeryHigh
+                } else {
+                    TickPriority::High
+                };
+                self.schedule(id, delay as u64 * 2, priority);
+            },
+            NodeKind::Comparator { mode, prioritized, .. } => {
+                if self.ticks.will_tick_this_tick(id)
+                    || (diode::compare(mode, input, side) == node.value
+                        && node.powered == diode::compare_powered(mode, input, side)) {
+                    return;
+                }
+
+                let priority = if prioritized { TickPriority::High } else { TickPriority::Normal };
+                self.schedule(id, 2, priority);
+            },
+            NodeKind::Lamp if node.powered && input == 0 => self.schedule(id, 4, TickPriority::Normal),
+            NodeKind::Lamp if !node.powered && input > 0 => {
+                self.nodes[id].powered = true;
+                self.mark_dirty(id);
+            },
+            NodeKind::Wire if node.output != input => {
+                self.nodes[id].output = input;
+                self.mark_dirty(id);
+            },
+            _ => {},
+        }
+    }
+
+    fn tick_node(&mut self, id: NodeId) {
+        let (input, side) = self.signals(id);
+        let node = &mut self.nodes[id];
+
+        match node.kind {
+            NodeKind::Torch if node.powered == (input > 0) => {
+                node.powered = input == 0;
+                self.set_output(id, if input == 0 { 15 } else { 0 });
+            },
+            NodeKind::Repeater { delay, .. } if !node.locked => {
+                if node.powered && input == 0 {
+                    node.powered = false;
+                    self.set_output(id, 0);
+                } else if !node.powered {
+                    node.powered = true;
+                    self.set_output(id, 15);
+                    if input == 0 {
+                        self.schedule(id, delay as u64 * 2, TickPriority::VeryHigh);
+                    }
+                }
+            },
+            NodeKind::Comparator { mode, .. } => {
+                let value = diode::compare(mode, input, side);
+                let old = std::mem::replace(&mut node.value, value);
+                if old == value && mode == ComparatorMode::Subtract {
+                    return;
+                }
+
+                let powered = diode::compare_powered(mode, input, side);
+                node.powered = powered;
+                self.set_output(id, if powered { value } else { 0 });
+            },
+            NodeKind::Lamp if node.powered && input == 0 => {
+                node.powered = false;
+                self.mark_dirty(id);
+            },
+            NodeKind::Button { .. } if node.powered => {
+                node.powered = false;
+                self.set_output(id, 0);
+            },
+            _ => {},
+        }
+    }
+
+    /// Runs the ticks due at `time`.
+    fn tick(&mut self, time: u64) {
+        self.time = time;
+        self.ticks.prepare(time);
+        while let Some(id) = self.ticks.next() {
+            self.tick_node(id);
+        }
+    }
+
+    /// Right clicks the lever or button at `pos`. Returns whether there was one.
+    fn use_block(&mut self, pos: BlockPos) -> bool {
+        let Some(&id) = self.ids.get(&pos) else { return false };
+        let node = &mut self.nodes[id];
+
+        match node.kind {
+            NodeKind::Lever => {
+                let powered = !node.powered;
+                node.powered = powered;
+                self.set_output(id, if powered { 15 } else { 0 });
+            },
+            NodeKind::Button { duration } if !node.powered => {
+                node.powered = true;
+                self.set_output(id, 15);
+                self.schedule(id, duration, TickPriority::Normal);
+            },
+            NodeKind::Button { .. } => {},
+            _ => return false,
+        }
+
+        true
+    }
+
+    /// Writes the nodes that changed back into the world. Returns the ones
+    /// blocks outside the region might react to.
+    fn sync(&mut self, world: &mut World) -> Vec<BlockPos> {
+        let mut edges = Vec::new();
+        for id in std::mem::take(&mut self.dirty) {
+            let node = &mut self.nodes[id];
+            node.dirty = false;
+
+            if world.set_block(node.pos, node.block()) {
+                debug::record(world, Event::BlockChange, node.pos);
+            }
+            if let Some(BlockEntity::Comparator { output }) = world.block_entities.get_mut(&node.pos) {
+                *output = node.value;
+            }
+
+            if node.edge {
+                edges.push(node.pos);
+            }
+        }
+
+        edges
+    }
+
+    /// Time and priority of the pending tick of the node at `pos`.
+    pub fn pending_at(&self, pos: BlockPos) -> Option<(u64, TickPriority)> {
+        self.ids.get(&pos).and_then(|id| self.ticks.pending_at(*id))
+    }
+
+    /// Hands the pending ticks back to the world.
+    fn restore(mut self, world: &mut World) {
+        for (time, priority, id) in self.ticks.take_pending(|_| true) {
+            world.ticks.schedule(self.nodes[id].pos, time, priority);
+        }
+    }
+}
+
+/// Index of the compiled region `pos` is in.
+pub fn find(world: &World, pos: BlockPos) -> Option<usize> {
+    world.circuits.iter().position(|c| c.region.contains(pos))
+}
+
+/// Compiles the redstone in `region`, taking over from compiled regions it
+/// overlaps. Fails if the region has components that can't be compiled.
+pub fn compile(world: &mut World, region: Region) -> Result<Stats, String> {
+    while let Some(i) = world.circuits.iter().position(|c| c.region.intersects(&region)) {
+        decompile(world, i);
+    }
+
+    let (circuit, stats) = compile::compile(world, region)?;
+    world.circuits.push(circuit);
+    flush(world);
+    Ok(stats)
+}
+
+/// Hands a compiled region back to the block-accurate engine.
+pub fn decompile(world: &mut World, index: usize) -> Region {
+    let circuit = world.circuits.remove(index);
+    let region = circuit.region;
+    circuit.restore(world);
+    region
+}
+
+/// Hands the compiled region `pos` is in back to the block-accurate engine,
+/// before something other than the circuit changes a block in it.
+pub fn decompile_at(world: &mut World, pos: BlockPos) {
+    if let Some(i) = find(world, pos) {
+        let region = decompile(world, i);
+        warn!("block at {:?} changed, {} is back to block-accurate redstone", pos, region);
+    }
+}
+
+/// Runs the ticks of every compiled region.
+pub fn tick(world: &mut World) {
+    let time = world.time;
+    for circuit in world.circuits.iter_mut() {
+        circuit.tick(time);
+    }
+
+    flush(world);
+}
+
+/// Right clicks a lever or button in a compiled region. Returns whether
+/// there was one.
+pub fn use_block(world: &mut World, pos: BlockPos) -> bool {
+    let Some(i) = find(world, pos) else { return false };
+    if !world.circuits[i].use_block(pos) {
+        return false;
+    }
+
+    flush(world);
+    true
+}
+
+/// Brings the blocks of every compiled region up to date, and updates the
+/// blocks around them.
+fn flush(world: &mut World) {
+    let mut circuits = std::mem::take(&mut world.circuits);
+    let edges: Vec<_> = circuits.iter_mut().flat_map(|c| c.sync(world)).collect();
+    world.circuits = circuits;
+
+    for pos in edges {
+        notify_outside(world, pos);
+    }
+}
+
+/// Updates the blocks outside compiled regions that a change at `pos` reaches,
+/// directly or through a neighbour.
+fn notify_outside(world: &mut World, pos: BlockPos) {
+    for d in UPDATE_ORDER {
+        let n = pos.offset(d);
+        if find(world, n).is_none() {
+            update_shape(world, n, d.opposite());
+            neighbor_changed(world, n);
+            update_neighbors(world, n);
+            continue;
+        }
+
+        for d in UPDATE_ORDER {
+            let m = n.offset(d);
+            if find(world, m).is_none() {
+                neighbor_changed(world, m);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn volume_counts_both_corners() {
+        assert_eq!(Region::new(BlockPos::new(0, 0, 0), BlockPos::new(0, 0, 0)).volume(), 1);
+        assert_eq!(Region::new(BlockPos::new(3, 1, -2), BlockPos::new(-1, 4, 2)).volume(), 5 * 4 * 5);
+    }
+
+    #[test]
+    fn volume_of_huge_regions_saturates() {
+        let line = Region::new(BlockPos::new(i32::MIN, 0, 0), BlockPos::new(i32::MAX, 0, 0));
+        assert_eq!(line.volume(), 1 << 32);
+        let all = Region::new(BlockPos::new(i32::MIN, i32::MIN, i32::MIN), BlockPos::new(i32::MAX, i32::MAX, i32::MAX));
+        assert_eq!(all.volume(), u64::MAX);
+    }
+
+    #[test]
+    fn compile_refuses_huge_regions_and_ones_out_of_the_world() {
+        let mut world = World::new();
+        let line = Region::new(BlockPos::new(i32::MIN, 0, 0), BlockPos::new(i32::MAX, 0, 0));
+        assert!(compile(&mut world, line).is_err());
+        let below = Region::new(BlockPos::new(0, -1, 0), BlockPos::new(1, 1, 1));
+        assert!(compile(&mut world, below).is_err());
+        let above = Region::new(BlockPos::new(0, HEIGHT - 1, 0), BlockPos::new(1, HEIGHT, 1));
+        assert!(compile(&mut world, above).is_err());
+        assert!(compile(&mut world, Region::new(BlockPos::new(0, 0, 0), BlockPos::new(1, 1, 1))).is_ok());
+    }
+}
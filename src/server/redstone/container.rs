@@ -0,0 +1,340 @@
+//! Chests, barrels, hoppers, droppers and dispensers, and how comparators
+//! read them. Items that leave a container without going into another are
+//! lost, as item entities aren't simulated.
+
+use super::*;
+use super::super::item::ItemStack;
+use super::super::world::block::ChestType;
+
+/// A slot of the container at some position.
+type Slot = (BlockPos, usize);
+
+/// Time a hopper waits after moving an item.
+const HOPPER_COOLDOWN: i32 = 8;
+
+/// The direction of the other half of a double chest.
+fn connected_direction(facing: Direction, kind: ChestType) -> Direction {
+    if kind == ChestType::Left {
+        facing.clockwise()
+    } else {
+        facing.counter_clockwise()
+    }
+}
+
+/// Picks the facing and type of a chest, joining it with a chest next to it.
+/// Sneaking players only join it to the chest they clicked.
+pub fn chest_placement(world: &World, pos: BlockPos, facing: Direction, ctx: &PlaceContext) -> (Direction, ChestType) {
+    let partner = |d: Direction| match world.get_block(pos.offset(d)) {
+        Block::Chest { facing, kind: ChestType::Single } => Some(facing),
+        _ => None,
+    };
+
+    let face = ctx.face;
+    if ctx.sneaking && face != Direction::Up && face != Direction::Down {
+        if let Some(f) = partner(face.opposite()).filter(|f| *f != face && *f != face.opposite()) {
+            let kind = if f.counter_clockwise() == face.opposite() { ChestType::Right } else { ChestType::Left };
+            return (f, kind);
+        }
+    }
+
+    if !ctx.sneaking {
+        if partner(facing.clockwise()) == Some(facing) {
+            return (facing, ChestType::Left);
+        }
+
+        if partner(facing.counter_clockwise()) == Some(facing) {
+            return (facing, ChestType::Right);
+        }
+    }
+
+    (facing, ChestType::Single)
+}
+
+/// Joins the chest at `pos` with a new chest towards `dir`, or splits it
+/// from the other half of it that went away.
+pub fn updated_chest_shape(world: &World, pos: BlockPos, block: Block, dir: Direction) -> Block {
+    let Block::Chest { facing, kind } = block else { return block };
+
+    match world.get_block(pos.offset(dir)) {
+        Block::Chest { facing: f, kind: k } if dir != Direction::Up && dir != Direction::Down => {
+            if kind == ChestType::Single && k != ChestType::Single && f == facing && connected_direction(f, k) == dir.opposite() {
+                Block::Chest { facing, kind: k.opposite() }
+            } else {
+                block
+            }
+        },
+        _ if connected_direction(facing, kind) == dir => Block::Chest { facing, kind: ChestType::Single },
+        _ => block,
+    }
+}
+
+fn slots_of(world: &World, pos: BlockPos) -> Vec<Slot> {
+    let len = world.block_entities.get(&pos).and_then(|e| e.items()).map_or(0, |i| i.len());
+    (0..len).map(|i| (pos, i)).collect()
+}
+
+/// The slots of the container at `pos`, with both halves of a double chest.
+/// Chests with a solid block on top can't be opened, so comparators don't
+/// see into them, but hoppers still do.
+fn container_at(world: &World, pos: BlockPos, ignore_blocked: bool) -> Option<Vec<Slot>> {
+    let blocked = |p: BlockPos| !ignore_blocked && world.get_block(p.offset(Direction::Up)).is_solid();
+
+    match world.get_block(pos) {
+        Block::Chest { facing, kind } => {
+            if blocked(pos) {
+                return None;
+            }
+
+            if kind == ChestType::Single {
+                return Some(slots_of(world, pos));
+            }
+
+            let other = pos.offset(connected_direction(facing, kind));
+            match world.get_block(other) {
+                Block::Chest { facing: f, kind: k } if f == facing && k == kind.opposite() => {
+                    if blocked(other) {
+                        return None;
+                    }
+
+                    // the right half comes first
+                    let (first, second) = if kind == ChestType::Right { (pos, other) } else { (other, pos) };
+                    let mut slots = slots_of(world, first);
+                    slots.extend(slots_of(world, second));
+                    Some(slots)
+                },
+                _ => Some(slots_of(world, pos)),
+            }
+        },
+        b if b.has_analog_output() => Some(slots_of(world, pos)),
+        _ => None,
+    }
+}
+
+fn item(world: &World, (pos, i): Slot) -> Option<&ItemStack> {
+    world.block_entities.get(&pos).and_then(|e| e.items()).and_then(|items| items[i].as_ref())
+}
+
+fn item_mut(world: &mut World, (pos, i): Slot) -> &mut Option<ItemStack> {
+    let items = world.block_entities.get_mut(&pos).and_then(|e| e.items_mut()).expect("slot of a container");
+    &mut items[i]
+}
+
+fn is_empty(world: &World, slots: &[Slot]) -> bool {
+    slots.iter().all(|s| item(world, *s).is_none())
+}
+
+fn is_full(world: &World, slots: &[Slot]) -> bool {
+    slots.iter().all(|s| item(world, *s).is_some_and(|i| i.count >= i.max_count()))
+}
+
+/// Signal a comparator reads from the container at `pos`, by how full it is.
+pub fn analog_output(world: &World, pos: BlockPos) -> u8 {
+    let Some(slots) = container_at(world, pos, false) else { return 0 };
+    if slots.is_empty() {
+        return 0;
+    }
+
+    let mut fullness = 0.0;
+    let mut stacks = 0;
+    for s in &slots {
+        if let Some(i) = item(world, *s) {
+            fullness += i.count as f32 / i.max_count() as f32;
+            stacks += 1;
+        }
+    }
+
+    fullness /= slots.len() as f32;
+    (fullness * 14.0).floor() as u8 + (stacks > 0) as u8
+}
+
+/// Updates the comparators reading the container at `pos` after its
+/// contents changed.
+pub fn update_comparators(world: &mut World, pos: BlockPos) {
+    for d in Direction::HORIZONTAL {
+        let n = pos.offset(d);
+        match world.get_block(n) {
+            Block::Comparator { .. } => neighbor_changed(world, n),
+            b if b.is_solid() => {
+                let n = n.offset(d);
+                if let Block::Comparator { .. } = world.get_block(n) {
+                    neighbor_changed(world, n);
+                }
+            },
+            _ => {},
+        }
+    }
+}
+
+fn containers_changed(world: &mut World, slots: &[Slot]) {
+    let mut positions: Vec<_> = slots.iter().map(|(p, _)| *p).collect();
+    positions.dedup();
+    for p in positions {
+        update_comparators(world, p);
+    }
+}
+
+/// Puts as much of `stack` as fits into one slot, and returns the rest.
+/// `source` is the container the items come from.
+fn try_move_in(world: &mut World, source: BlockPos, slot: Slot, mut stack: ItemStack) -> Option<ItemStack> {
+    let was_empty = is_empty(world, &slots_of(world, slot.0));
+
+    let moved = match item_mut(world, slot) {
+        existing @ None => {
+            *existing = Some(stack);
+            return after_move_in(world, source, slot.0, was_empty, None);
+        },
+        Some(existing) if existing.is_same_item(&stack) && existing.count < existing.max_count() => {
+            let moved = stack.count.min(existing.max_count() - existing.count);
+            existing.count += moved;
+            stack.count -= moved;
+            moved
+        },
+        Some(_) => 0,
+    };
+
+    let rest = if stack.count > 0 { Some(stack) } else { None };
+    if moved > 0 {
+        after_move_in(world, source, slot.0, was_empty, rest)
+    } else {
+        rest
+    }
+}
+
+/// A hopper that just got its first item waits before passing it on, one
+/// tick less if it comes from a hopper that ticks after it.
+fn after_move_in(world: &mut World, source: BlockPos, dest: BlockPos, was_empty: bool, rest: Option<ItemStack>) -> Option<ItemStack> {
+    let source_ticked = match world.block_entities.get(&source) {
+        Some(BlockEntity::Hopper(h)) => Some(h.last_ticked),
+        _ => None,
+    };
+
+    if let Some(BlockEntity::Hopper(hopper)) = world.block_entities.get_mut(&dest) {
+        if was_empty && hopper.cooldown <= HOPPER_COOLDOWN {
+            let early = source_ticked.is_some_and(|t| hopper.last_ticked >= t);
+            hopper.cooldown = HOPPER_COOLDOWN - early as i32;
+        }
+    }
+
+    rest
+}
+
+/// Puts `stack` into the first slots it fits in, and returns the rest.
+fn add_item(world: &mut World, source: BlockPos, slots: &[Slot], stack: ItemStack) -> Option<ItemStack> {
+    let mut stack = Some(stack);
+    for s in slots {
+        let Some(rest) = stack.take() else { break };
+        stack = try_move_in(world, source, *s, rest);
+    }
+
+    stack
+}
+
+/// Moves one item from the first slot of `from` that can give one into `to`.
+fn move_one(world: &mut World, source: BlockPos, from: &[Slot], to: &[Slot]) -> bool {
+    for s in from {
+        let Some(stack) = item(world, *s).cloned() else { continue };
+
+        let mut rest = stack.clone();
+        rest.count -= 1;
+        *item_mut(world, *s) = if rest.count > 0 { Some(rest) } else { None };
+
+        if add_item(world, source, to, ItemStack { count: 1, ..stack.clone() }).is_none() {
+            containers_changed(world, to);
+            return true;
+        }
+
+        *item_mut(world, *s) = Some(stack);
+    }
+
+    false
+}
+
+/// Checks whether a hopper is locked by power.
+pub fn check_hopper_power(world: &mut World, pos: BlockPos, block: Block) {
+    let Block::Hopper { facing, enabled } = block else { return };
+    let unpowered = !has_neighbor_signal(world, pos);
+    if unpowered != enabled {
+        set_block(world, pos, Block::Hopper { facing, enabled: unpowered }, false);
+    }
+}
+
+/// Lets a hopper push an item into the container it faces and pull one from
+/// the container above it, once it's done waiting.
+pub fn hopper_tick(world: &mut World, pos: BlockPos) {
+    let time = world.time;
+    let Some(BlockEntity::Hopper(hopper)) = world.block_entities.get_mut(&pos) else { return };
+    hopper.cooldown -= 1;
+    hopper.last_ticked = time;
+    if hopper.cooldown > 0 {
+        return;
+    }
+    hopper.cooldown = 0;
+
+    let Block::Hopper { facing, enabled: true } = world.get_block(pos) else { return };
+    let own = slots_of(world, pos);
+    let mut moved = false;
+
+    if !is_empty(world, &own) {
+        if let Some(dest) = container_at(world, pos.offset(facing), true) {
+            moved = !is_full(world, &dest) && move_one(world, pos, &own, &dest);
+        }
+    }
+
+    if !is_full(world, &own) {
+        let above = pos.offset(Direction::Up);
+        if let Some(source) = container_at(world, above, true) {
+            if !is_empty(world, &source) && move_one(world, above, &source, &own) {
+                containers_changed(world, &source);
+                moved = true;
+            }
+        }
+    }
+
+    if moved {
+        if let Some(BlockEntity::Hopper(hopper)) = world.block_entities.get_mut(&pos) {
+            hopper.cooldown = HOPPER_COOLDOWN;
+        }
+        update_comparators(world, pos);
+    }
+}
+
+/// Makes a dropper pass a random item into the container it faces, or drop
+/// it. Dispensers drop their items too.
+pub fn dispense(world: &mut World, pos: BlockPos, block: Block) {
+    let (Block::Dispenser { facing, .. } | Block::Dropper { facing, .. }) = block else { return };
+    let own = slots_of(world, pos);
+
+    let mut chosen = None;
+    let mut stacks = 0;
+    for s in &own {
+        if item(world, *s).is_some() {
+            stacks += 1;
+            if world.random(pos, stacks) == 0 {
+                chosen = Some(*s);
+            }
+        }
+    }
+
+    let Some(slot) = chosen else { return };
+    let dest = match block {
+        Block::Dropper { .. } => container_at(world, pos.offset(facing), true),
+        _ => None,
+    };
+
+    match dest {
+        Some(dest) => {
+            move_one(world, pos, &[slot], &dest);
+        },
+        None => {
+            let slot = item_mut(world, slot);
+            if let Some(stack) = slot {
+                stack.count -= 1;
+                if stack.count == 0 {
+                    *slot = None;
+                }
+            }
+        },
+    }
+
+    update_comparators(world, pos);
+}
@@ -0,0 +1,171 @@
+//! Recording what the engine does, for tracing and profiling circuits.
+
+use std::collections::HashMap;
+use std::fmt;
+use super::*;
+use super::compiled::{self, Region};
+use super::super::chat::Component;
+
+/// Most events a trace keeps, so a busy region can't eat all the memory.
+const MAX_TRACE_EVENTS: usize = 100_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    NeighborUpdate,
+    ShapeUpdate,
+    BlockChange,
+    ScheduledTick,
+    BlockAction,
+}
+
+impl Event {
+    pub const ALL: [Event; 5] = [
+        Event::NeighborUpdate, Event::ShapeUpdate, Event::BlockChange, Event::ScheduledTick, Event::BlockAction,
+    ];
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Event::NeighborUpdate => "neighbor update",
+            Event::ShapeUpdate => "shape update",
+            Event::BlockChange => "block change",
+            Event::ScheduledTick => "scheduled tick",
+            Event::BlockAction => "block action",
+        })
+    }
+}
+
+/// Every event in a region, for a number of ticks.
+#[derive(Debug)]
+pub struct Trace {
+    pub region: Region,
+    pub start: u64,
+    pub end: u64,
+    /// Time, event, position and the block there when it happened.
+    pub events: Vec<(u64, Event, BlockPos, Block)>,
+    /// Events that didn't fit.
+    pub dropped: usize,
+}
+
+/// How often the blocks at a position got each kind of event.
+#[derive(Debug, Clone)]
+pub struct Counts {
+    /// The block there when it was last counted.
+    pub block: Block,
+    pub total: u32,
+    pub events: [u32; Event::ALL.len()],
+    /// Most events in one tick.
+    pub peak: u32,
+    tick: u64,
+    this_tick: u32,
+}
+
+/// Counts of the events everywhere, for a number of ticks.
+#[derive(Debug)]
+pub struct Profile {
+    pub start: u64,
+    pub end: u64,
+    pub counts: HashMap<BlockPos, Counts>,
+}
+
+impl Profile {
+    /// The positions with the most events, most first.
+    pub fn busiest(&self, count: usize) -> Vec<(BlockPos, &Counts)> {
+        let mut busiest: Vec<_> = self.counts.iter().map(|(p, c)| (*p, c)).collect();
+        busiest.sort_by(|a, b| b.1.total.cmp(&a.1.total).then(a.0.cmp(&b.0)));
+        busiest.truncate(count);
+        busiest
+    }
+}
+
+/// Notes an event at `pos` for the running trace and profile.
+pub fn record(world: &mut World, event: Event, pos: BlockPos) {
+    if world.trace.is_none() && world.profile.is_none() {
+        return;
+    }
+
+    let (time, block) = (world.time, world.get_block(pos));
+
+    if let Some(trace) = &mut world.trace {
+        if trace.region.contains(pos) && time < trace.end {
+            if trace.events.len() < MAX_TRACE_EVENTS {
+                trace.events.push((time, event, pos, block));
+            } else {
+                trace.dropped += 1;
+            }
+        }
+    }
+
+    if let Some(profile) = &mut world.profile {
+        if time >= profile.end {
+            return;
+        }
+
+        let counts = profile.counts.entry(pos).or_insert(Counts {
+            block,
+            total: 0,
+            events: [0; Event::ALL.len()],
+            peak: 0,
+            tick: time,
+            this_tick: 0,
+        });
+
+        if counts.tick != time {
+            counts.tick = time;
+            counts.this_tick = 0;
+        }
+
+        counts.block = block;
+        counts.total += 1;
+        counts.events[event.index()] += 1;
+        counts.this_tick += 1;
+        counts.peak = counts.peak.max(counts.this_tick);
+    }
+}
+
+/// Name of the kind of a block, without its properties.
+pub fn block_name(block: Block) -> String {
+    let name = format!("{:?}", block);
+    match name.find([' ', '(', '{']) {
+        Some(end) => name[..end].to_string(),
+        None => name,
+    }
+}
+
+/// What a block is doing right now: the power it gives, the signal it
+/// gets and its pending tick.
+pub fn inspect(world: &World, pos: BlockPos) -> Component {
+    let block = world.get_block(pos);
+    let output = match block {
+        Block::RedstoneWire(w) => w.power,
+        Block::Comparator { .. } => diode::comparator_output(world, pos),
+        _ => Direction::ALL.iter().map(|d| get_weak_power(world, pos, block, *d, true)).max().unwrap_or(0),
+    };
+    let input = get_best_neighbor_signal(world, pos, !matches!(block, Block::RedstoneWire(_)));
+
+    let mut text = Component::text(format!("{} ", block_name(block))).color("gray");
+    text.push(Component::text(format!("power {} ", output)).color(if output > 0 { "red" } else { "dark_red" }));
+    text.push(Component::text(format!("receives {}", input)).color("gray"));
+
+    let circuit = compiled::find(world, pos);
+    let pending = match circuit {
+        Some(i) => world.circuits[i].pending_at(pos),
+        None => world.ticks.pending_at(pos),
+    };
+
+    if let Some((time, priority)) = pending {
+        let delay = time.saturating_sub(world.time);
+        text.push(Component::text(format!(" tick in {} ({:?})", delay, priority)).color("yellow"));
+    }
+
+    if circuit.is_some() {
+        text.push(Component::text(" compiled").color("aqua"));
+    }
+
+    text
+}
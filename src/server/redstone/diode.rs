@@ -0,0 +1,203 @@
+//! Repeaters and comparators.
+
+use super::*;
+use super::super::world::block::ComparatorMode;
+
+fn facing_of(block: Block) -> Direction {
+    match block {
+        Block::Repeater { facing, .. } | Block::Comparator { facing, .. } => facing,
+        _ => unreachable!("{:?} is not a diode", block),
+    }
+}
+
+fn is_powered(block: Block) -> bool {
+    matches!(block, Block::Repeater { powered: true, .. } | Block::Comparator { powered: true, .. })
+}
+
+fn with_powered(block: Block, powered: bool) -> Block {
+    match block {
+        Block::Repeater { delay, facing, locked, .. } => Block::Repeater { delay, facing, locked, powered },
+        Block::Comparator { facing, mode, .. } => Block::Comparator { facing, mode, powered },
+        b => b,
+    }
+}
+
+pub fn comparator_output(world: &World, pos: BlockPos) -> u8 {
+    match world.block_entities.get(&pos) {
+        Some(BlockEntity::Comparator { output }) => *output,
+        _ => 0,
+    }
+}
+
+fn get_input_signal(world: &World, pos: BlockPos, block: Block) -> u8 {
+    let facing = facing_of(block);
+    let n = pos.offset(facing);
+    let mut power = get_signal(world, n, facing, true);
+    if power < 15 {
+        power = power.max(wire::power_of(world.get_block(n)));
+    }
+
+    // comparators read containers, even through a solid block
+    if let Block::Comparator { .. } = block {
+        let behind = world.get_block(n);
+        if behind.has_analog_output() {
+            return container::analog_output(world, n);
+        }
+
+        let far = n.offset(facing);
+        if power < 15 && behind.is_solid() && world.get_block(far).has_analog_output() {
+            return container::analog_output(world, far);
+        }
+    }
+
+    power
+}
+
+pub fn get_alternate_signal_at(world: &World, pos: BlockPos, dir: Direction, diode: Block) -> u8 {
+    let block = world.get_block(pos);
+    let is_input = match diode {
+        Block::Repeater { .. } => block.is_diode(),
+        _ => block.is_signal_source(),
+    };
+
+    match block {
+        _ if !is_input => 0,
+        Block::RedstoneBlock => 15,
+        Block::RedstoneWire(w) => w.power,
+        _ => get_direct_signal(world, pos, dir),
+    }
+}
+
+fn get_alternate_signal(world: &World, pos: BlockPos, block: Block) -> u8 {
+    let facing = facing_of(block);
+    let left = facing.clockwise();
+    let right = facing.counter_clockwise();
+
+    get_alternate_signal_at(world, pos.offset(left), left, block)
+        .max(get_alternate_signal_at(world, pos.offset(right), right, block))
+}
+
+pub fn is_locked(world: &World, pos: BlockPos, block: Block) -> bool {
+    matches!(block, Block::Repeater { .. }) && get_alternate_signal(world, pos, block) > 0
+}
+
+/// Whether a comparator with these input and side signals is powered.
+pub fn compare_powered(mode: ComparatorMode, input: u8, side: u8) -> bool {
+    input > 0 && (input > side || (input == side && mode == ComparatorMode::Compare))
+}
+
+/// Output of a comparator with these input and side signals.
+pub fn compare(mode: ComparatorMode, input: u8, side: u8) -> u8 {
+    match mode {
+        _ if input == 0 || side > input => 0,
+        ComparatorMode::Subtract => input - side,
+        ComparatorMode::Compare => input,
+    }
+}
+
+pub fn should_turn_on(world: &World, pos: BlockPos, block: Block) -> bool {
+    let input = get_input_signal(world, pos, block);
+    match block {
+        Block::Comparator { mode, .. } => compare_powered(mode, input, get_alternate_signal(world, pos, block)),
+        _ => input > 0,
+    }
+}
+
+fn calculate_comparator_output(world: &World, pos: BlockPos, block: Block) -> u8 {
+    let Block::Comparator { mode, .. } = block else { return 0 };
+    compare(mode, get_input_signal(world, pos, block), get_alternate_signal(world, pos, block))
+}
+
+/// Whether the diode outputs into the side of another diode.
+pub fn should_prioritize(world: &World, pos: BlockPos, block: Block) -> bool {
+    let dir = facing_of(block).opposite();
+    let front = world.get_block(pos.offset(dir));
+    front.is_diode() && facing_of(front) != dir
+}
+
+pub fn update_neighbors_in_front(world: &mut World, pos: BlockPos, block: Block) {
+    let facing = facing_of(block);
+    let front = pos.offset(facing.opposite());
+    neighbor_changed(world, front);
+    update_neighbors_except(world, front, facing);
+}
+
+pub fn check_tick_on_neighbor(world: &mut World, pos: BlockPos, block: Block) {
+    if world.ticks.will_tick_this_tick(pos) {
+        return;
+    }
+
+    match block {
+        Block::Repeater { delay, powered, .. } => {
+            if is_locked(world, pos, block) || powered == should_turn_on(world, pos, block) {
+                return;
+            }
+
+            let priority = if should_prioritize(world, pos, block) {
+                TickPriority::ExtremelyHigh
+            } else if powered {
+                TickPriority::VeryHigh
+            } else {
+                TickPriority::High
+            };
+
+            world.schedule_tick(pos, delay as u64 * 2, priority);
+        },
+        Block::Comparator { powered, .. } => {
+            if calculate_comparator_output(world, pos, block) == comparator_output(world, pos)
+                && powered == should_turn_on(world, pos, block) {
+                return;
+            }
+
+            let priority = if should_prioritize(world, pos, block) {
+                TickPriority::High
+            } else {
+                TickPriority::Normal
+            };
+
+            world.schedule_tick(pos, 2, priority);
+        },
+        _ => {},
+    }
+}
+
+pub fn repeater_tick(world: &mut World, pos: BlockPos, block: Block) {
+    if is_locked(world, pos, block) {
+        return;
+    }
+
+    let Block::Repeater { delay, powered, .. } = block else { return };
+    let should_power = should_turn_on(world, pos, block);
+
+    if powered && !should_power {
+        set_block(world, pos, with_powered(block, false), false);
+    } else if !powered {
+        set_block(world, pos, with_powered(block, true), false);
+        if !should_power {
+            world.schedule_tick(pos, delay as u64 * 2, TickPriority::VeryHigh);
+        }
+    }
+}
+
+pub fn comparator_tick(world: &mut World, pos: BlockPos, block: Block) {
+    let output = calculate_comparator_output(world, pos, block);
+    let old = comparator_output(world, pos);
+    if let Some(BlockEntity::Comparator { output: o }) = world.block_entities.get_mut(&pos) {
+        *o = output;
+    }
+
+    if old == output && matches!(block, Block::Comparator { mode: ComparatorMode::Subtract, .. }) {
+        return;
+    }
+
+    let should_power = should_turn_on(world, pos, block);
+    let powered = is_powered(block);
+
+    if powered && !should_power {
+        set_block(world, pos, with_powered(block, false), false);
+    } else if !powered && should_power {
+        set_block(world, pos, with_powered(block, true), false);
+    }
+
+    update_neighbors_in_front(world, pos, block);
+}
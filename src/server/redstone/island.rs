@@ -0,0 +1,223 @@
+//! Ticking the plots of a plot world in parallel. Redstone can't reach across
+//! the roads, so every plot with something going on is split off into a
+//! world of its own, an island, and ticked on a worker thread. The islands
+//! are merged back in the order of their plots, so a tick plays out the same
+//! however the threads were scheduled. Plots draw random numbers from
+//! generators of their own, so ticking them here or on the main thread
+//! makes no difference either.
+
+use std::collections::BTreeSet;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use super::super::plot::{Layout, PlotId};
+use super::super::world::{TickScheduler, World};
+
+/// Threads that tick the islands they are sent.
+pub struct Workers {
+    jobs: mpsc::Sender<(usize, World)>,
+    /// Islands back from ticking, and whether they ticked without panicking.
+    done: mpsc::Receiver<(usize, World, bool)>,
+}
+
+impl Workers {
+    /// Starts `threads` workers, or one per core with 0. Returns none when
+    /// that is a single thread, which might as well tick everything itself.
+    pub fn new(threads: usize) -> Option<Self> {
+        let threads = match threads {
+            0 => thread::available_parallelism().map_or(1, |n| n.get()),
+            n => n,
+        };
+
+        if threads < 2 {
+            return None;
+        }
+
+        let (jobs, jobs_r) = mpsc::channel();
+        let (done_t, done) = mpsc::channel();
+        let jobs_r = Arc::new(Mutex::new(jobs_r));
+
+        for i in 0..threads {
+            let jobs = Arc::clone(&jobs_r);
+            let done = done_t.clone();
+            thread::Builder::new().name(format!("redstone-{}", i)).spawn(move || loop {
+                let Ok((index, mut island)) = jobs.lock().unwrap().recv() else { return };
+                let ticked = panic::catch_unwind(AssertUnwindSafe(|| super::tick(&mut island)));
+                if done.send((index, island, ticked.is_ok())).is_err() {
+                    return;
+                }
+            }).unwrap();
+        }
+
+        info!("ticking plots on {} threads", threads);
+        Some(Self { jobs, done })
+    }
+}
+
+/// Advances the world by one game tick like [`super::tick`], ticking the
+/// plots on the workers when there are enough of them busy.
+pub fn tick(world: &mut World, workers: Option<&Workers>) {
+    let (Some(workers), Some(layout)) = (workers, world.plots) else {
+        return super::tick(world);
+    };
+
+    // traces and profiles are kept by the whole world
+    let active = active_plots(world, &layout);
+    if active.len() < 2 || world.trace.is_some() || world.profile.is_some() {
+        return super::tick(world);
+    }
+
+    // islands get copies of their chunks, so the roads around them keep
+    // seeing blocks there while they tick here
+    let active: Vec<_> = active.into_iter().collect();
+    for (index, id) in active.iter().enumerate() {
+        let chunks = chunks_of(world, &layout, *id)
+            .into_iter()
+            .map(|c| (c, world.chunks[&c].clone()))
+            .collect();
+
+        let island = world.split_off(chunks, |p| layout.plot_at(p) == Some(*id));
+        workers.jobs.send((index, island)).unwrap();
+    }
+
+    // the roads and the plots with nothing to do tick here meanwhile
+    super::tick(world);
+
+    // a plot whose redstone panicked keeps its blocks, but not what it
+    // was about to do, which would most likely panic again
+    let mut islands: Vec<Option<World>> = active.iter().map(|_| None).collect();
+    for _ in 0..active.len() {
+        let (index, mut island, ticked) = workers.done.recv().unwrap();
+        if !ticked {
+            error!("the redstone of plot {} panicked, stopping it", active[index]);
+            island.ticks = TickScheduler::default();
+            island.block_events.clear();
+            island.circuits.clear();
+        }
+        islands[index] = Some(island);
+    }
+
+    for island in islands {
+        world.merge(island.unwrap());
+    }
+}
+
+/// Plots with ticks, block events, ticking block entities or circuits, in order.
+fn active_plots(world: &World, layout: &Layout) -> BTreeSet<PlotId> {
+    world.ticks.pending()
+        .chain(world.block_events.iter().map(|e| e.pos))
+        .chain(world.tickers.iter().map(|(p, _)| *p))
+        .chain(world.circuits.iter().map(|c| c.region.min))
+        .filter_map(|p| layout.plot_at(p))
+        .collect()
+}
+
+/// The loaded chunks a plot's redstone can see, which reaches a block into
+/// the roads around it.
+fn chunks_of(world: &World, layout: &Layout, id: PlotId) -> Vec<(i32, i32)> {
+    let bounds = layout.bounds(id);
+    let mut chunks = Vec::new();
+    for cx in (bounds.min.x - 1) >> 4..=(bounds.max.x + 1) >> 4 {
+        for cz in (bounds.min.z - 1) >> 4..=(bounds.max.z + 1) >> 4 {
+            if world.chunks.contains_key(&(cx, cz)) {
+                chunks.push((cx, cz));
+            }
+        }
+    }
+
+    chunks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::super::config::PlotConfig;
+    use super::super::super::item::ItemStack;
+    use super::super::super::world::{BlockEntity, BlockPos, TickPriority};
+    use super::super::super::world::block::{AttachFace, Block, ButtonKind, Direction};
+
+    /// A dropper on each of three plots, which are 20 blocks apart.
+    const DROPPERS: [BlockPos; 3] = [
+        BlockPos { x: 2, y: 10, z: 2 },
+        BlockPos { x: 22, y: 10, z: 2 },
+        BlockPos { x: 2, y: 10, z: 22 },
+    ];
+
+    /// A plot world with droppers full of different items at `droppers`.
+    fn world(droppers: &[BlockPos]) -> World {
+        let mut world = World::new();
+        world.plots = Some(Layout::new(&PlotConfig { size: 16, road: 4, ..Default::default() }));
+        for pos in droppers {
+            world.set_block(*pos, Block::Dropper { facing: Direction::Up, triggered: false });
+            let items = (1..=9).map(|id| Some(ItemStack { id, count: 64, nbt: None })).collect();
+            world.block_entities.insert(*pos, BlockEntity::Container(items));
+        }
+
+        world
+    }
+
+    /// Has every dropper drop a random item each tick, and returns what they
+    /// have left.
+    fn run(world: &mut World, workers: Option<&Workers>) -> Vec<Vec<Option<ItemStack>>> {
+        for _ in 0..100 {
+            for pos in DROPPERS {
+                if world.block_entities.contains_key(&pos) {
+                    world.schedule_tick(pos, 1, TickPriority::Normal);
+                }
+            }
+            tick(world, workers);
+        }
+
+        DROPPERS.iter().filter_map(|p| world.block_entities.get(p)?.items().cloned()).collect()
+    }
+
+    #[test]
+    fn parallel_ticking_matches_serial_ticking() {
+        let workers = Workers::new(2).unwrap();
+        let serial = run(&mut world(&DROPPERS), None);
+        assert_eq!(run(&mut world(&DROPPERS), Some(&workers)), serial);
+        // the plots don't all draw the same numbers either
+        assert_ne!(serial[0], serial[1]);
+    }
+
+    #[test]
+    fn plots_draw_regardless_of_other_plots() {
+        assert_eq!(run(&mut world(&DROPPERS[..1]), None)[0], run(&mut world(&DROPPERS), None)[0]);
+    }
+
+    /// Every block of the loaded chunks, up to where the tests build.
+    fn blocks(world: &World) -> Vec<((i32, i32), Vec<u32>)> {
+        let mut chunks: Vec<_> = world.chunks.keys().copied().collect();
+        chunks.sort();
+        chunks.into_iter()
+            .map(|(cx, cz)| {
+                let chunk = &world.chunks[&(cx, cz)];
+                let ids = (0..16).flat_map(|y| (0..256).map(move |i| (y, i)))
+                    .map(|(y, i)| chunk.get_block(i & 15, y, i >> 4))
+                    .collect();
+                ((cx, cz), ids)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn roads_next_to_busy_plots_tick_alike() {
+        // a button on the road, in a chunk only the first plot uses
+        let button = BlockPos::new(17, 10, 2);
+        let pressed = Block::Button { kind: ButtonKind::Stone, face: AttachFace::Floor, facing: Direction::North, powered: true };
+        let workers = Workers::new(2).unwrap();
+
+        let runs: Vec<_> = [None, Some(&workers)].into_iter()
+            .map(|workers| {
+                let mut world = world(&[DROPPERS[0], DROPPERS[2]]);
+                world.set_block(button.offset(Direction::Down), Block::Solid(1));
+                world.set_block(button, pressed);
+                world.schedule_tick(button, 5, TickPriority::Normal);
+                let items = run(&mut world, workers);
+                assert!(matches!(world.get_block(button), Block::Button { powered: false, .. }));
+                (items, blocks(&world))
+            })
+            .collect();
+        assert!(runs[0] == runs[1]);
+    }
+}
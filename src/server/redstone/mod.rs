@@ -0,0 +1,837 @@
+//! Block-accurate redstone, following the update order of vanilla 1.18.2.
+
+pub mod compiled;
+pub mod container;
+pub mod debug;
+pub mod diode;
+pub mod island;
+pub mod piston;
+pub mod pressure_plate;
+pub mod tripwire;
+pub mod wire;
+
+use super::world::{World, BlockEntity, BlockEvent, BlockPos, TickPriority};
+use debug::Event;
+use super::world::block::{
+    AttachFace, Block, ButtonKind, ComparatorMode, Direction, DoorKind, Half, Hinge, Instrument, PlaceContext,
+};
+
+/// Order in which a block notifies its neighbours.
+const UPDATE_ORDER: [Direction; 6] = [
+    Direction::West, Direction::East, Direction::Down, Direction::Up, Direction::North, Direction::South,
+];
+
+/// Order in which a block updates the shape of its neighbours.
+const SHAPE_UPDATE_ORDER: [Direction; 6] = [
+    Direction::West, Direction::East, Direction::North, Direction::South, Direction::Down, Direction::Up,
+];
+
+/// Players are this wide and tall.
+const PLAYER_SIZE: (f64, f64) = (0.6, 1.8);
+
+/// Advances the world by one game tick and runs the block ticks due.
+pub fn tick(world: &mut World) {
+    world.time += 1;
+    world.ticks.prepare(world.time);
+
+    world.handling_ticks = true;
+    while let Some(pos) = world.ticks.next() {
+        debug::record(world, Event::ScheduledTick, pos);
+        match world.get_block(pos) {
+            b @ (Block::RedstoneTorch { .. } | Block::RedstoneWallTorch { .. }) => torch_tick(world, pos, b),
+            b @ Block::Repeater { .. } => diode::repeater_tick(world, pos, b),
+            b @ Block::Comparator { .. } => diode::comparator_tick(world, pos, b),
+            Block::Button { kind, face, facing, powered: true } => {
+                set_block(world, pos, Block::Button { kind, face, facing, powered: false }, true);
+                update_attached_neighbors(world, pos, face, facing);
+            },
+            Block::Observer { facing, powered } => observer_tick(world, pos, facing, powered),
+            Block::RedstoneLamp { lit: true } if !has_neighbor_signal(world, pos) => {
+                set_block(world, pos, Block::RedstoneLamp { lit: false }, false);
+            },
+            Block::Target { power } if power != 0 => set_block(world, pos, Block::Target { power: 0 }, true),
+            b @ (Block::PressurePlate { .. } | Block::WeightedPressurePlate { .. }) if pressure_plate::signal(b) > 0 => {
+                pressure_plate::check_pressed(world, pos, b);
+            },
+            b @ Block::TripwireHook { .. } => tripwire::calculate_state(world, pos, b, false, true, None),
+            Block::Tripwire(w) if w.powered => tripwire::check_pressed(world, pos),
+            b @ (Block::Dispenser { .. } | Block::Dropper { .. }) => container::dispense(world, pos, b),
+            _ => {},
+        }
+    }
+    compiled::tick(world);
+    world.handling_ticks = false;
+
+    while let Some(event) = world.block_events.pop_front() {
+        let triggered = match world.get_block(event.pos) {
+            b if b.block_type() != Some(event.block) => false,
+            Block::NoteBlock { .. } => true,
+            _ => piston::trigger_event(world, event),
+        };
+
+        if triggered {
+            debug::record(world, Event::BlockAction, event.pos);
+            world.block_actions.push(event);
+        }
+    }
+
+    tick_block_entities(world);
+}
+
+/// Ticks the block entities there were before this, in the order they were
+/// created.
+fn tick_block_entities(world: &mut World) {
+    let tickers = std::mem::take(&mut world.tickers);
+    let mut ticking = Vec::with_capacity(tickers.len());
+
+    for (pos, serial) in tickers {
+        if !world.is_ticker(pos, serial) {
+            continue;
+        }
+
+        match world.block_entities.get(&pos) {
+            Some(BlockEntity::MovingPiston(_)) => piston::tick_moving_block(world, pos),
+            Some(BlockEntity::Hopper(_)) => container::hopper_tick(world, pos),
+            Some(BlockEntity::DaylightDetector) if world.time.is_multiple_of(20) => update_daylight_detector(world, pos),
+            _ => {},
+        }
+
+        if world.is_ticker(pos, serial) {
+            ticking.push((pos, serial));
+        }
+    }
+
+    // block entities created just now tick from the next tick on
+    ticking.append(&mut world.tickers);
+    world.tickers = ticking;
+}
+
+/// Moves the entity `id` to `pos`, letting the blocks it ends up in react.
+pub fn move_entity(world: &mut World, id: i32, pos: (f64, f64, f64)) {
+    world.entities.insert(id, pos);
+
+    let (min, max) = entity_box(pos);
+    let (min, max) = (min.map(|v| (v + 1e-7).floor() as i32), max.map(|v| (v - 1e-7).floor() as i32));
+    for x in min[0]..=max[0] {
+        for y in min[1]..=max[1] {
+            for z in min[2]..=max[2] {
+                entity_inside(world, BlockPos::new(x, y, z));
+            }
+        }
+    }
+}
+
+/// Takes the entity `id` out of the world. The blocks it pressed notice on
+/// their next check.
+pub fn remove_entity(world: &mut World, id: i32) {
+    world.entities.remove(&id);
+}
+
+fn entity_box((x, y, z): (f64, f64, f64)) -> ([f64; 3], [f64; 3]) {
+    let (width, height) = PLAYER_SIZE;
+    ([x - width / 2.0, y, z - width / 2.0], [x + width / 2.0, y + height, z + width / 2.0])
+}
+
+/// Number of entities touching the box from `min` to `max`.
+fn count_entities(world: &World, min: [f64; 3], max: [f64; 3]) -> usize {
+    world.entities.values()
+        .filter(|p| {
+            let (emin, emax) = entity_box(**p);
+            (0..3).all(|i| emin[i] < max[i] && emax[i] > min[i])
+        })
+        .count()
+}
+
+fn entity_inside(world: &mut World, pos: BlockPos) {
+    match world.get_block(pos) {
+        b @ (Block::PressurePlate { .. } | Block::WeightedPressurePlate { .. }) if pressure_plate::signal(b) == 0 => {
+            pressure_plate::check_pressed(world, pos, b);
+        },
+        Block::Tripwire(w) if !w.powered => tripwire::check_pressed(world, pos),
+        _ => {},
+    }
+}
+
+/// Places a block the way a player would. Returns whether it could be placed.
+pub fn place_block(world: &mut World, pos: BlockPos, block: Block, ctx: &PlaceContext) -> bool {
+    let block = match block.for_placement(ctx) {
+        Some(b) if can_survive(world, pos, b) => b,
+        _ => return false,
+    };
+
+    let above = pos.offset(Direction::Up);
+    let block = match block {
+        Block::RedstoneWire(w) => Block::RedstoneWire(wire::get_connection_state(world, pos, w)),
+        Block::Repeater { delay, facing, powered, .. } => Block::Repeater {
+            delay,
+            facing,
+            locked: diode::is_locked(world, pos, block),
+            powered,
+        },
+        Block::NoteBlock { note, powered, .. } => Block::NoteBlock {
+            instrument: Instrument::below(world.get_block(pos.offset(Direction::Down))),
+            note,
+            powered,
+        },
+        Block::Door { kind, facing, half, .. } => {
+            if !world.get_block(above).is_replaceable() {
+                return false;
+            }
+
+            let powered = has_neighbor_signal(world, pos) || has_neighbor_signal(world, above);
+            Block::Door { kind, facing, half, hinge: door_hinge(world, pos, facing, ctx.cursor), open: powered, powered }
+        },
+        Block::Trapdoor { kind, facing, half, .. } => {
+            let powered = has_neighbor_signal(world, pos);
+            Block::Trapdoor { kind, facing, half, open: powered, powered }
+        },
+        Block::RedstoneLamp { .. } => Block::RedstoneLamp { lit: has_neighbor_signal(world, pos) },
+        Block::Tripwire(mut t) => {
+            for d in Direction::HORIZONTAL {
+                t.set_side(d, tripwire::connects_to(world.get_block(pos.offset(d)), d));
+            }
+            Block::Tripwire(t)
+        },
+        Block::Chest { facing, .. } => {
+            let (facing, kind) = container::chest_placement(world, pos, facing, ctx);
+            Block::Chest { facing, kind }
+        },
+        b => b,
+    };
+
+    set_block(world, pos, block, true);
+
+    if let Block::TripwireHook { .. } = block {
+        tripwire::calculate_state(world, pos, block, false, false, None);
+    }
+
+    if block.is_diode() && diode::should_turn_on(world, pos, block) {
+        world.schedule_tick(pos, 1, TickPriority::Normal);
+    }
+
+    if let Block::Door { kind, facing, hinge, open, powered, .. } = block {
+        set_block(world, above, Block::Door { kind, facing, half: Half::Upper, hinge, open, powered }, true);
+    }
+
+    true
+}
+
+/// Picks the side of the hinge of a door, so it opens away from walls and
+/// doubles up with a door next to it.
+fn door_hinge(world: &World, pos: BlockPos, facing: Direction, cursor: (f32, f32, f32)) -> Hinge {
+    let above = pos.offset(Direction::Up);
+    let left = facing.counter_clockwise();
+    let right = facing.clockwise();
+
+    let full = |p: BlockPos| world.get_block(p).is_sturdy() as i32;
+    let walls = full(pos.offset(right)) + full(above.offset(right)) - full(pos.offset(left)) - full(above.offset(left));
+
+    let is_door = |p: BlockPos| matches!(world.get_block(p), Block::Door { half: Half::Lower, .. });
+    let (left_door, right_door) = (is_door(pos.offset(left)), is_door(pos.offset(right)));
+
+    if (left_door && !right_door) || walls > 0 {
+        return Hinge::Right;
+    }
+
+    if (right_door && !left_door) || walls < 0 {
+        return Hinge::Left;
+    }
+
+    let (x, _, z) = facing.offset();
+    let (cx, cz) = (cursor.0, cursor.2);
+    if (x < 0 && cz < 0.5) || (x > 0 && cz > 0.5) || (z < 0 && cx > 0.5) || (z > 0 && cx < 0.5) {
+        Hinge::Right
+    } else {
+        Hinge::Left
+    }
+}
+
+/// Right clicks the block at `pos`. Returns whether the block reacted to it,
+/// in which case the click shouldn't place anything.
+pub fn use_block(world: &mut World, pos: BlockPos) -> bool {
+    if compiled::use_block(world, pos) {
+        return true;
+    }
+
+    match world.get_block(pos) {
+        Block::Lever { face, facing, powered } => {
+            set_block(world, pos, Block::Lever { face, facing, powered: !powered }, true);
+            update_attached_neighbors(world, pos, face, facing);
+        },
+        Block::Button { powered: true, .. } => {},
+        Block::Button { kind, face, facing, powered: false } => {
+            set_block(world, pos, Block::Button { kind, face, facing, powered: true }, true);
+            update_attached_neighbors(world, pos, face, facing);
+            world.schedule_tick(pos, button_duration(kind), TickPriority::Normal);
+        },
+        Block::Repeater { delay, facing, locked, powered } => {
+            set_block(world, pos, Block::Repeater { delay: delay % 4 + 1, facing, locked, powered }, true);
+        },
+        Block::Comparator { facing, mode, powered } => {
+            let mode = match mode {
+                ComparatorMode::Compare => ComparatorMode::Subtract,
+                ComparatorMode::Subtract => ComparatorMode::Compare,
+            };
+
+            let block = Block::Comparator { facing, mode, powered };
+            set_block(world, pos, block, false);
+            diode::comparator_tick(world, pos, block);
+        },
+        Block::NoteBlock { instrument, note, powered } => {
+            set_block(world, pos, Block::NoteBlock { instrument, note: (note + 1) % 25, powered }, true);
+            play_note(world, pos);
+        },
+        Block::DaylightDetector { inverted, power } => {
+            set_block(world, pos, Block::DaylightDetector { inverted: !inverted, power }, false);
+            update_daylight_detector(world, pos);
+        },
+        Block::Door { kind: DoorKind::Oak, facing, half, hinge, open, powered } => {
+            set_block(world, pos, Block::Door { kind: DoorKind::Oak, facing, half, hinge, open: !open, powered }, false);
+        },
+        Block::Trapdoor { kind: DoorKind::Oak, facing, half, open, powered } => {
+            set_block(world, pos, Block::Trapdoor { kind: DoorKind::Oak, facing, half, open: !open, powered }, false);
+        },
+        Block::RedstoneWire(w) => return wire::toggle_shape(world, pos, w),
+        _ => return false,
+    }
+
+    true
+}
+
+/// How long a button stays pressed.
+fn button_duration(kind: ButtonKind) -> u64 {
+    match kind {
+        ButtonKind::Stone => 20,
+        ButtonKind::Oak => 30,
+    }
+}
+
+pub fn break_block(world: &mut World, pos: BlockPos) {
+    set_block(world, pos, Block::Air, true);
+}
+
+/// Sets a block, running the callbacks of the old and new block. Neighbours
+/// only get block updates if `notify` is set, but always get shape updates.
+pub fn set_block(world: &mut World, pos: BlockPos, block: Block, notify: bool) {
+    set_block_with(world, pos, block, Update { notify, shapes: true, moving: false });
+}
+
+/// Which updates a block change causes, like the flags vanilla sets blocks with.
+#[derive(Debug, Clone, Copy)]
+pub struct Update {
+    /// Notify the neighbours.
+    pub notify: bool,
+    /// Update the shape of the neighbours.
+    pub shapes: bool,
+    /// The block is being moved by a piston, so it shouldn't act as if it
+    /// was removed.
+    pub moving: bool,
+}
+
+/// Roads keep plots apart by ignoring updates, see [`neighbor_changed`] and
+/// [`update_shape`], not by refusing changes, which players who may build
+/// on them make.
+pub fn set_block_with(world: &mut World, pos: BlockPos, block: Block, update: Update) {
+    let old = world.get_block(pos);
+    if old != block && !world.circuits.is_empty() {
+        compiled::decompile_at(world, pos);
+    }
+
+    if !world.set_block(pos, block) {
+        return;
+    }
+
+    debug::record(world, Event::BlockChange, pos);
+    on_remove(world, pos, old, block, update.moving);
+    on_place(world, pos, block, old);
+
+    if update.notify {
+        update_neighbors(world, pos);
+    }
+
+    if update.shapes {
+        update_neighbor_shapes(world, pos, old, block);
+    }
+}
+
+/// Updates the shape of the neighbours of a block that changed from `old` to `new`.
+fn update_neighbor_shapes(world: &mut World, pos: BlockPos, old: Block, new: Block) {
+    if let Block::RedstoneWire(w) = old {
+        wire::update_indirect_neighbor_shapes(world, pos, w);
+    }
+
+    for d in SHAPE_UPDATE_ORDER {
+        update_shape(world, pos.offset(d), d.opposite());
+    }
+
+    if let Block::RedstoneWire(w) = new {
+        wire::update_indirect_neighbor_shapes(world, pos, w);
+    }
+}
+
+fn is_same_block(a: Block, b: Block) -> bool {
+    std::mem::discriminant(&a) == std::mem::discriminant(&b)
+}
+
+fn on_remove(world: &mut World, pos: BlockPos, old: Block, new: Block, moving: bool) {
+    match old {
+        Block::Observer { facing, powered: true } if !is_same_block(old, new) && world.ticks.is_scheduled(pos) => {
+            update_observed_neighbors(world, pos, facing);
+        },
+        b if b.has_analog_output() && !is_same_block(old, new) => container::update_comparators(world, pos),
+        Block::PistonHead { sticky, facing, .. } if !is_same_block(old, new) => {
+            let base = pos.offset(facing.opposite());
+            if world.get_block(base) == (Block::Piston { sticky, facing, extended: true }) {
+                break_block(world, base);
+            }
+        },
+        _ if moving => {},
+        Block::RedstoneWire(w) if !is_same_block(old, new) => wire::on_remove(world, pos, w),
+        Block::RedstoneTorch { .. } | Block::RedstoneWallTorch { .. } => {
+            for d in Direction::ALL {
+                update_neighbors(world, pos.offset(d));
+            }
+        },
+        b if b.is_diode() && !is_same_block(old, new) => diode::update_neighbors_in_front(world, pos, b),
+        Block::Lever { face, facing, powered: true } | Block::Button { face, facing, powered: true, .. }
+            if !is_same_block(old, new) => update_attached_neighbors(world, pos, face, facing),
+        b @ (Block::PressurePlate { .. } | Block::WeightedPressurePlate { .. })
+            if !is_same_block(old, new) && pressure_plate::signal(b) > 0 => {
+            update_neighbors(world, pos);
+            update_neighbors(world, pos.offset(Direction::Down));
+        },
+        Block::TripwireHook { facing, attached, powered } if !is_same_block(old, new) => {
+            if attached || powered {
+                tripwire::calculate_state(world, pos, old, true, false, None);
+            }
+
+            if powered {
+                update_neighbors(world, pos);
+                update_neighbors(world, pos.offset(facing.opposite()));
+            }
+        },
+        Block::Tripwire(mut w) if !is_same_block(old, new) => {
+            w.powered = true;
+            tripwire::update_source(world, pos, w);
+        },
+        _ => {},
+    }
+}
+
+fn on_place(world: &mut World, pos: BlockPos, new: Block, old: Block) {
+    match new {
+        Block::RedstoneWire(_) if !is_same_block(old, new) => wire::on_place(world, pos),
+        Block::RedstoneTorch { .. } | Block::RedstoneWallTorch { .. } => {
+            for d in Direction::ALL {
+                update_neighbors(world, pos.offset(d));
+            }
+        },
+        b if b.is_diode() => diode::update_neighbors_in_front(world, pos, b),
+        b @ Block::Piston { .. } if !is_same_block(old, new) && !world.block_entities.contains_key(&pos) => {
+            piston::check_if_extend(world, pos, b);
+        },
+        // an observer moved while powered turns off where it lands
+        Block::Observer { facing, powered: true } if !is_same_block(old, new) && !world.ticks.is_scheduled(pos) => {
+            let block = Block::Observer { facing, powered: false };
+            set_block_with(world, pos, block, Update { notify: false, shapes: false, moving: false });
+            update_observed_neighbors(world, pos, facing);
+        },
+        Block::Tnt if !is_same_block(old, new) && has_neighbor_signal(world, pos) => prime_tnt(world, pos),
+        Block::Target { power } if power > 0 && !is_same_block(old, new) && !world.ticks.is_scheduled(pos) => {
+            set_block_with(world, pos, Block::Target { power: 0 }, Update { notify: false, shapes: false, moving: false });
+        },
+        b @ Block::Hopper { .. } if !is_same_block(old, new) => container::check_hopper_power(world, pos, b),
+        Block::Tripwire(w) if !is_same_block(old, new) => tripwire::update_source(world, pos, w),
+        _ => {},
+    }
+}
+
+/// Notifies every neighbour of `pos` that it has changed.
+pub fn update_neighbors(world: &mut World, pos: BlockPos) {
+    for d in UPDATE_ORDER {
+        neighbor_changed(world, pos.offset(d));
+    }
+}
+
+pub fn update_neighbors_except(world: &mut World, pos: BlockPos, except: Direction) {
+    for d in UPDATE_ORDER {
+        if d != except {
+            neighbor_changed(world, pos.offset(d));
+        }
+    }
+}
+
+/// Updates the neighbours of a lever or button and of the block it's attached to.
+pub fn update_attached_neighbors(world: &mut World, pos: BlockPos, face: AttachFace, facing: Direction) {
+    update_neighbors(world, pos);
+    update_neighbors(world, pos.offset(attached_direction(face, facing).opposite()));
+}
+
+fn neighbor_changed(world: &mut World, pos: BlockPos) {
+    if world.on_road(pos) {
+        return;
+    }
+
+    debug::record(world, Event::NeighborUpdate, pos);
+    let block = world.get_block(pos);
+    match block {
+        Block::RedstoneWire(w) => {
+            if can_survive(world, pos, block) {
+                wire::update_power_strength(world, pos, w);
+            } else {
+                break_block(world, pos);
+            }
+        },
+        Block::RedstoneTorch { lit } | Block::RedstoneWallTorch { lit, .. }
+            if lit == torch_powered(world, pos, block) && !world.ticks.will_tick_this_tick(pos) => {
+            world.schedule_tick(pos, 2, TickPriority::Normal);
+        },
+        b if b.is_diode() => {
+            if can_survive(world, pos, b) {
+                diode::check_tick_on_neighbor(world, pos, b);
+            } else {
+                break_block(world, pos);
+            }
+        },
+        Block::Door { kind, facing, half, hinge, powered, .. } => {
+            let other = pos.offset(if half == Half::Lower { Direction::Up } else { Direction::Down });
+            let signal = has_neighbor_signal(world, pos) || has_neighbor_signal(world, other);
+            if signal != powered {
+                set_block(world, pos, Block::Door { kind, facing, half, hinge, open: signal, powered: signal }, false);
+            }
+        },
+        Block::Trapdoor { kind, facing, half, open, powered } => {
+            let signal = has_neighbor_signal(world, pos);
+            if signal != powered {
+                let open = if open != signal { signal } else { open };
+                set_block(world, pos, Block::Trapdoor { kind, facing, half, open, powered: signal }, false);
+            }
+        },
+        b @ Block::Piston { .. } => piston::check_if_extend(world, pos, b),
+        Block::NoteBlock { instrument, note, powered } => {
+            let signal = has_neighbor_signal(world, pos);
+            if signal != powered {
+                if signal {
+                    play_note(world, pos);
+                }
+
+                set_block(world, pos, Block::NoteBlock { instrument, note, powered: signal }, true);
+            }
+        },
+        Block::RedstoneLamp { lit } if lit != has_neighbor_signal(world, pos) => {
+            if lit {
+                world.schedule_tick(pos, 4, TickPriority::Normal);
+            } else {
+                set_block(world, pos, Block::RedstoneLamp { lit: true }, false);
+            }
+        },
+        Block::Tnt if has_neighbor_signal(world, pos) => prime_tnt(world, pos),
+        b @ Block::Hopper { .. } => container::check_hopper_power(world, pos, b),
+        Block::Dispenser { facing, triggered } | Block::Dropper { facing, triggered } => {
+            let signal = has_neighbor_signal(world, pos) || has_neighbor_signal(world, pos.offset(Direction::Up));
+            let block = match block {
+                Block::Dispenser { .. } => Block::Dispenser { facing, triggered: signal },
+                _ => Block::Dropper { facing, triggered: signal },
+            };
+
+            if signal && !triggered {
+                world.schedule_tick(pos, 4, TickPriority::Normal);
+                set_block(world, pos, block, false);
+            } else if !signal && triggered {
+                set_block(world, pos, block, false);
+            }
+        },
+        // the base hears about everything around the head
+        Block::PistonHead { facing, .. } if can_survive(world, pos, block) => {
+            neighbor_changed(world, pos.offset(facing.opposite()));
+        },
+        _ => {},
+    }
+}
+
+/// Lets the block at `pos` react to a change of its neighbour towards `dir`.
+fn update_shape(world: &mut World, pos: BlockPos, dir: Direction) {
+    if world.on_road(pos) {
+        return;
+    }
+
+    debug::record(world, Event::ShapeUpdate, pos);
+    let block = world.get_block(pos);
+    observe(world, pos, block, dir);
+    let new = updated_shape(world, pos, block, dir);
+
+    if new == Block::Air && block != Block::Air {
+        break_block(world, pos);
+    } else if new != block {
+        set_block(world, pos, new, false);
+    }
+}
+
+/// What `block` at `pos` turns into after its neighbour towards `dir`
+/// changed, with [`Block::Air`] meaning it breaks.
+fn updated_shape(world: &World, pos: BlockPos, block: Block, dir: Direction) -> Block {
+    match block {
+        Block::RedstoneWire(w) if dir != Direction::Down => Block::RedstoneWire(wire::get_connection_state(world, pos, w)),
+        Block::RedstoneTorch { .. } if dir == Direction::Down && !can_survive(world, pos, block) => Block::Air,
+        Block::RedstoneWallTorch { facing, .. } if dir == facing.opposite() && !can_survive(world, pos, block) => Block::Air,
+        Block::Lever { face, facing, .. } | Block::Button { face, facing, .. }
+            if dir == attached_direction(face, facing).opposite() && !can_survive(world, pos, block) => Block::Air,
+        Block::Repeater { delay, facing, powered, .. } if dir != facing && dir != facing.opposite() => Block::Repeater {
+            delay,
+            facing,
+            locked: diode::is_locked(world, pos, block),
+            powered,
+        },
+        // the two halves of a door copy each other
+        Block::Door { half, .. } if (dir == Direction::Up && half == Half::Lower) || (dir == Direction::Down && half == Half::Upper) => {
+            match world.get_block(pos.offset(dir)) {
+                Block::Door { kind, facing, half: h, hinge, open, powered } if h != half => {
+                    Block::Door { kind, facing, half, hinge, open, powered }
+                },
+                _ => Block::Air,
+            }
+        },
+        Block::Door { half: Half::Lower, .. } if dir == Direction::Down && !can_survive(world, pos, block) => Block::Air,
+        Block::PistonHead { facing, .. } if dir == facing.opposite() && !can_survive(world, pos, block) => Block::Air,
+        Block::NoteBlock { note, powered, .. } if dir == Direction::Down => Block::NoteBlock {
+            instrument: Instrument::below(world.get_block(pos.offset(dir))),
+            note,
+            powered,
+        },
+        Block::PressurePlate { .. } | Block::WeightedPressurePlate { .. }
+            if dir == Direction::Down && !can_survive(world, pos, block) => Block::Air,
+        Block::TripwireHook { facing, .. } if dir == facing.opposite() && !can_survive(world, pos, block) => Block::Air,
+        Block::Tripwire(mut w) if dir != Direction::Up && dir != Direction::Down => {
+            w.set_side(dir, tripwire::connects_to(world.get_block(pos.offset(dir)), dir));
+            Block::Tripwire(w)
+        },
+        Block::Chest { .. } => container::updated_chest_shape(world, pos, block, dir),
+        b => b,
+    }
+}
+
+/// Lets an observer notice its neighbour towards `dir` changing.
+fn observe(world: &mut World, pos: BlockPos, block: Block, dir: Direction) {
+    if let Block::Observer { facing, powered: false } = block {
+        if facing == dir && !world.ticks.is_scheduled(pos) {
+            world.schedule_tick(pos, 2, TickPriority::Normal);
+        }
+    }
+}
+
+/// What `block` turns into when it's put down at `pos` without being placed
+/// by a player, like at the end of a piston move.
+fn updated_shape_from_neighbors(world: &mut World, pos: BlockPos, block: Block) -> Block {
+    SHAPE_UPDATE_ORDER.iter().fold(block, |b, d| {
+        observe(world, pos, b, *d);
+        updated_shape(world, pos, b, *d)
+    })
+}
+
+/// Turns an observer on or off, which makes a two tick pulse out of the back.
+fn observer_tick(world: &mut World, pos: BlockPos, facing: Direction, powered: bool) {
+    set_block(world, pos, Block::Observer { facing, powered: !powered }, false);
+    if !powered {
+        world.schedule_tick(pos, 2, TickPriority::Normal);
+    }
+
+    update_observed_neighbors(world, pos, facing);
+}
+
+/// Updates the block behind an observer and its neighbours.
+fn update_observed_neighbors(world: &mut World, pos: BlockPos, facing: Direction) {
+    let behind = pos.offset(facing.opposite());
+    neighbor_changed(world, behind);
+    update_neighbors_except(world, behind, facing);
+}
+
+/// Note blocks only sound with air above them.
+fn play_note(world: &mut World, pos: BlockPos) {
+    if world.get_block(pos.offset(Direction::Up)) == Block::Air {
+        world.block_event(BlockEvent { pos, block: 80, id: 0, param: 0 });
+    }
+}
+
+/// Lights TNT. Entities aren't simulated, so it just disappears instead of
+/// turning into primed TNT and exploding.
+fn prime_tnt(world: &mut World, pos: BlockPos) {
+    break_block(world, pos);
+}
+
+/// Sets the power of a daylight detector from the time of day, taking the
+/// sky to be clear and the detector to see all of it.
+fn update_daylight_detector(world: &mut World, pos: BlockPos) {
+    use std::f32::consts::{PI, TAU};
+
+    let Block::DaylightDetector { inverted, power } = world.get_block(pos) else { return };
+
+    let day = (world.time % 24000) as f64 / 24000.0 - 0.25;
+    let day = day - day.floor();
+    let time_of_day = ((day * 2.0 + (0.5 - (day * std::f64::consts::PI).cos() / 2.0)) / 3.0) as f32;
+
+    let brightness = 0.5 + 2.0 * ((time_of_day * TAU).cos() as f64).clamp(-0.25, 0.25);
+    let mut signal = 15 - ((1.0 - brightness) * 11.0) as i32;
+
+    if inverted {
+        signal = 15 - signal;
+    } else if signal > 0 {
+        let mut angle = time_of_day * TAU;
+        let noon = if angle < PI { 0.0 } else { TAU };
+        angle += (noon - angle) * 0.2;
+        signal = (signal as f32 * angle.cos() + 0.5).floor() as i32;
+    }
+
+    let signal = signal.clamp(0, 15) as u8;
+    if signal != power {
+        set_block(world, pos, Block::DaylightDetector { inverted, power: signal }, true);
+    }
+}
+
+/// The direction from the supporting block of a lever or button to it.
+fn attached_direction(face: AttachFace, facing: Direction) -> Direction {
+    match face {
+        AttachFace::Floor => Direction::Up,
+        AttachFace::Ceiling => Direction::Down,
+        AttachFace::Wall => facing,
+    }
+}
+
+pub fn can_survive(world: &World, pos: BlockPos, block: Block) -> bool {
+    let support = match block {
+        // wire also stays on hoppers
+        Block::RedstoneWire(_) => {
+            let below = world.get_block(pos.offset(Direction::Down));
+            return below.is_sturdy() || matches!(below, Block::Hopper { .. });
+        },
+        Block::RedstoneTorch { .. }
+            | Block::Repeater { .. }
+            | Block::Comparator { .. }
+            | Block::PressurePlate { .. }
+            | Block::WeightedPressurePlate { .. } => pos.offset(Direction::Down),
+        Block::TripwireHook { facing, .. } => pos.offset(facing.opposite()),
+        Block::RedstoneWallTorch { facing, .. } => pos.offset(facing.opposite()),
+        Block::Lever { face, facing, .. } | Block::Button { face, facing, .. } => {
+            pos.offset(attached_direction(face, facing).opposite())
+        },
+        Block::Door { half: Half::Upper, .. } => {
+            return matches!(world.get_block(pos.offset(Direction::Down)), Block::Door { half: Half::Lower, .. });
+        },
+        Block::Door { .. } => pos.offset(Direction::Down),
+        Block::PistonHead { sticky, facing, .. } => {
+            return match world.get_block(pos.offset(facing.opposite())) {
+                Block::Piston { sticky: s, facing: f, extended: true } => s == sticky && f == facing,
+                Block::MovingPiston { facing: f, .. } => f == facing,
+                _ => false,
+            };
+        },
+        _ => return true,
+    };
+
+    world.get_block(support).is_sturdy()
+}
+
+/// Power the block at `pos` gives to the neighbour looking at it towards `dir`.
+fn get_weak_power(world: &World, pos: BlockPos, block: Block, dir: Direction, dust_power: bool) -> u8 {
+    match block {
+        Block::RedstoneTorch { lit: true } if dir != Direction::Up => 15,
+        Block::RedstoneWallTorch { facing, lit: true } if dir != facing => 15,
+        Block::Lever { powered: true, .. } | Block::Button { powered: true, .. } => 15,
+        Block::RedstoneBlock => 15,
+        Block::DaylightDetector { power, .. } | Block::Target { power } => power,
+        Block::WeightedPressurePlate { power, .. } => power,
+        Block::PressurePlate { powered: true, .. } | Block::TripwireHook { powered: true, .. } => 15,
+        Block::Observer { facing, powered: true } if facing == dir => 15,
+        Block::Repeater { facing, powered: true, .. } if facing == dir => 15,
+        Block::Comparator { facing, powered: true, .. } if facing == dir => diode::comparator_output(world, pos),
+        Block::RedstoneWire(w) if dust_power && (dir == Direction::Up || w.side(dir.opposite()).is_connected()) => {
+            w.power
+        },
+        _ => 0,
+    }
+}
+
+/// Power the block at `pos` gives to the block it is looked at from, and
+/// through it to that block's neighbours.
+fn get_strong_power(world: &World, pos: BlockPos, block: Block, dir: Direction, dust_power: bool) -> u8 {
+    match block {
+        Block::RedstoneTorch { .. } | Block::RedstoneWallTorch { .. } if dir == Direction::Down => {
+            get_weak_power(world, pos, block, dir, dust_power)
+        },
+        Block::Lever { face, facing, powered: true } | Block::Button { face, facing, powered: true, .. }
+            if attached_direction(face, facing) == dir => 15,
+        Block::Repeater { .. } | Block::Comparator { .. } | Block::RedstoneWire(_) | Block::Observer { .. } => {
+            get_weak_power(world, pos, block, dir, dust_power)
+        },
+        Block::PressurePlate { .. } | Block::WeightedPressurePlate { .. } if dir == Direction::Up => {
+            get_weak_power(world, pos, block, dir, dust_power)
+        },
+        Block::TripwireHook { facing, powered: true, .. } if facing == dir => 15,
+        _ => 0,
+    }
+}
+
+pub fn get_direct_signal(world: &World, pos: BlockPos, dir: Direction) -> u8 {
+    get_strong_power(world, pos, world.get_block(pos), dir, true)
+}
+
+/// Strongest strong power the block at `pos` receives.
+fn get_direct_signal_to(world: &World, pos: BlockPos, dust_power: bool) -> u8 {
+    let mut power = 0;
+    for d in Direction::ALL {
+        let n = pos.offset(d);
+        power = power.max(get_strong_power(world, n, world.get_block(n), d, dust_power));
+        if power >= 15 {
+            break;
+        }
+    }
+
+    power
+}
+
+/// Power the neighbour looking at `pos` towards `dir` receives from it,
+/// including power conducted through solid blocks.
+pub fn get_signal(world: &World, pos: BlockPos, dir: Direction, dust_power: bool) -> u8 {
+    let block = world.get_block(pos);
+    let power = get_weak_power(world, pos, block, dir, dust_power);
+    if block.is_solid() {
+        power.max(get_direct_signal_to(world, pos, dust_power))
+    } else {
+        power
+    }
+}
+
+pub fn has_neighbor_signal(world: &World, pos: BlockPos) -> bool {
+    get_best_neighbor_signal(world, pos, true) > 0
+}
+
+pub fn get_best_neighbor_signal(world: &World, pos: BlockPos, dust_power: bool) -> u8 {
+    let mut power = 0;
+    for d in Direction::ALL {
+        power = power.max(get_signal(world, pos.offset(d), d, dust_power));
+        if power >= 15 {
+            break;
+        }
+    }
+
+    power
+}
+
+fn torch_powered(world: &World, pos: BlockPos, block: Block) -> bool {
+    let dir = match block {
+        Block::RedstoneWallTorch { facing, .. } => facing.opposite(),
+        _ => Direction::Down,
+    };
+
+    get_signal(world, pos.offset(dir), dir, true) > 0
+}
+
+fn torch_tick(world: &mut World, pos: BlockPos, block: Block) {
+    let powered = torch_powered(world, pos, block);
+    let new = match block {
+        Block::RedstoneTorch { lit } if lit == powered => Block::RedstoneTorch { lit: !powered },
+        Block::RedstoneWallTorch { facing, lit } if lit == powered => Block::RedstoneWallTorch { facing, lit: !powered },
+        _ => return,
+    };
+
+    set_block(world, pos, new, true);
+}
@@ -0,0 +1,500 @@
+//! Pistons and the blocks they move.
+
+use super::*;
+use super::super::world::{BlockEvent, HEIGHT};
+use super::super::world::block::PushReaction;
+
+/// Most blocks a piston can move at once.
+const MAX_PUSH: usize = 12;
+
+const EXTEND: u8 = 0;
+const RETRACT: u8 = 1;
+/// Retracting while the head is still on its way out, which leaves the
+/// block it was pushing behind.
+const RETRACT_EARLY: u8 = 2;
+
+/// Whether the piston at `pos` is powered. Pistons look for power one block
+/// above them too, which is what makes quasi-connectivity.
+fn get_neighbor_signal(world: &World, pos: BlockPos, facing: Direction) -> bool {
+    if Direction::ALL.iter().any(|d| *d != facing && get_signal(world, pos.offset(*d), *d, true) > 0) {
+        return true;
+    }
+
+    if get_signal(world, pos, Direction::Down, true) > 0 {
+        return true;
+    }
+
+    let above = pos.offset(Direction::Up);
+    Direction::ALL.iter().any(|d| *d != Direction::Down && get_signal(world, above.offset(*d), *d, true) > 0)
+}
+
+/// Whether a block can be moved. Positions out of the world or on the roads
+/// of a plot world can't even take a block.
+fn is_pushable(world: &World, block: Block, pos: BlockPos, dir: Direction, allow_destroy: bool) -> bool {
+    if !(0..HEIGHT).contains(&pos.y) || world.on_road(pos) {
+        return false;
+    }
+
+    if block == Block::Air {
+        return true;
+    }
+
+    if (dir == Direction::Down && pos.y == 0) || (dir == Direction::Up && pos.y == HEIGHT - 1) {
+        return false;
+    }
+
+    match block.push_reaction() {
+        PushReaction::Normal => true,
+        PushReaction::Destroy => allow_destroy,
+        PushReaction::Block => false,
+    }
+}
+
+/// Honey and slime don't stick to each other.
+fn can_stick_to_each_other(a: Block, b: Block) -> bool {
+    if matches!((a, b), (Block::HoneyBlock, Block::SlimeBlock) | (Block::SlimeBlock, Block::HoneyBlock)) {
+        return false;
+    }
+
+    a.is_sticky() || b.is_sticky()
+}
+
+/// Works out which blocks a piston moves and which it breaks.
+struct StructureResolver<'a> {
+    world: &'a World,
+    piston: BlockPos,
+    push: Direction,
+    start: BlockPos,
+    extending: bool,
+    to_push: Vec<BlockPos>,
+    to_destroy: Vec<BlockPos>,
+}
+
+impl<'a> StructureResolver<'a> {
+    fn new(world: &'a World, piston: BlockPos, facing: Direction, extending: bool) -> Self {
+        let (push, start) = if extending {
+            (facing, piston.offset(facing))
+        } else {
+            (facing.opposite(), piston.offset_by(facing, 2))
+        };
+
+        Self { world, piston, push, start, extending, to_push: Vec::new(), to_destroy: Vec::new() }
+    }
+
+    fn resolve(&mut self) -> bool {
+        let block = self.world.get_block(self.start);
+        if !is_pushable(self.world, block, self.start, self.push, false) {
+            if self.extending && block.push_reaction() == PushReaction::Destroy {
+                self.to_destroy.push(self.start);
+                return true;
+            }
+
+            return false;
+        }
+
+        if !self.add_block_line(self.start) {
+            return false;
+        }
+
+        let mut i = 0;
+        while i < self.to_push.len() {
+            let pos = self.to_push[i];
+            if self.world.get_block(pos).is_sticky() && !self.add_branching_blocks(pos) {
+                return false;
+            }
+
+            i += 1;
+        }
+
+        true
+    }
+
+    /// Adds the line of blocks starting at `origin` in the push direction,
+    /// and the sticky blocks dragged behind it.
+    fn add_block_line(&mut self, origin: BlockPos) -> bool {
+        let mut block = self.world.get_block(origin);
+        if block == Block::Air
+            || !is_pushable(self.world, block, origin, self.push, false)
+            || origin == self.piston
+            || self.to_push.contains(&origin) {
+            return true;
+        }
+
+        let back = self.push.opposite();
+        let mut behind = 1;
+        if behind + self.to_push.len() > MAX_PUSH {
+            return false;
+        }
+
+        while block.is_sticky() {
+            let pos = origin.offset_by(back, behind as i32);
+            let prev = block;
+            block = self.world.get_block(pos);
+            if block == Block::Air
+                || !can_stick_to_each_other(prev, block)
+                || !is_pushable(self.world, block, pos, self.push, false)
+                || pos == self.piston {
+                break;
+            }
+
+            behind += 1;
+            if behind + self.to_push.len() > MAX_PUSH {
+                return false;
+            }
+        }
+
+        let mut added = 0;
+        for i in (0..behind).rev() {
+            self.to_push.push(origin.offset_by(back, i as i32));
+            added += 1;
+        }
+
+        let mut ahead = 1;
+        loop {
+            let pos = origin.offset_by(self.push, ahead);
+            if let Some(collision) = self.to_push.iter().position(|p| *p == pos) {
+                self.reorder_list_at_collision(added, collision);
+                for i in 0..=collision + added {
+                    let p = self.to_push[i];
+                    if self.world.get_block(p).is_sticky() && !self.add_branching_blocks(p) {
+                        return false;
+                    }
+                }
+
+                return true;
+            }
+
+            let block = self.world.get_block(pos);
+            if block == Block::Air {
+                return true;
+            }
+
+            if !is_pushable(self.world, block, pos, self.push, true) || pos == self.piston {
+                return false;
+            }
+
+            if block.push_reaction() == PushReaction::Destroy {
+                self.to_destroy.push(pos);
+                return true;
+            }
+
+            if self.to_push.len() >= MAX_PUSH {
+                return false;
+            }
+
+            self.to_push.push(pos);
+            added += 1;
+            ahead += 1;
+        }
+    }
+
+    /// Moves the `added` blocks at the end of the list in front of the ones
+    /// from `index` on, which they ran into.
+    fn reorder_list_at_collision(&mut self, added: usize, index: usize) {
+        let end = self.to_push.len() - added;
+        self.to_push[index..].rotate_left(end - index);
+    }
+
+    /// Adds the blocks stuck to the sides of the sticky block at `pos`.
+    fn add_branching_blocks(&mut self, pos: BlockPos) -> bool {
+        let block = self.world.get_block(pos);
+        for d in Direction::ALL {
+            if d == self.push || d == self.push.opposite() {
+                continue;
+            }
+
+            let n = pos.offset(d);
+            if can_stick_to_each_other(self.world.get_block(n), block) && !self.add_block_line(n) {
+                return false;
+            }
+        }
+
+        true
+    }
+}
+
+/// Queues the piston at `pos` to extend or retract if its power changed.
+pub fn check_if_extend(world: &mut World, pos: BlockPos, block: Block) {
+    let Block::Piston { facing, extended, .. } = block else { return };
+    let signal = get_neighbor_signal(world, pos, facing);
+
+    let id = if signal && !extended {
+        if !StructureResolver::new(world, pos, facing, true).resolve() {
+            return;
+        }
+
+        EXTEND
+    } else if !signal && extended {
+        let head = pos.offset_by(facing, 2);
+        let early = matches!(world.get_block(head), Block::MovingPiston { facing: f, .. } if f == facing)
+            && world.moving_block(head).is_some_and(|m| {
+                m.extending && (m.progress_old == 0 || m.last_ticked == world.time || world.handling_ticks)
+            });
+
+        if early { RETRACT_EARLY } else { RETRACT }
+    } else {
+        return;
+    };
+
+    world.block_event(BlockEvent { pos, block: block.block_type().unwrap(), id, param: facing as u8 });
+}
+
+/// Runs a queued extend or retract. Returns whether it went through.
+pub fn trigger_event(world: &mut World, event: BlockEvent) -> bool {
+    let pos = event.pos;
+    let Block::Piston { sticky, facing, .. } = world.get_block(pos) else { return false };
+    let signal = get_neighbor_signal(world, pos, facing);
+
+    if signal && event.id != EXTEND {
+        // powered again before it could retract
+        set_block_with(world, pos, Block::Piston { sticky, facing, extended: true }, Update {
+            notify: false,
+            shapes: true,
+            moving: false,
+        });
+        return false;
+    }
+
+    if !signal && event.id == EXTEND {
+        return false;
+    }
+
+    if event.id == EXTEND {
+        if !move_blocks(world, pos, facing, true, sticky) {
+            return false;
+        }
+
+        set_block_with(world, pos, Block::Piston { sticky, facing, extended: true }, Update {
+            notify: true,
+            shapes: true,
+            moving: true,
+        });
+        return true;
+    }
+
+    let front = pos.offset(facing);
+    final_tick(world, front);
+
+    set_block_with(world, pos, Block::MovingPiston { sticky, facing }, Update {
+        notify: false,
+        shapes: false,
+        moving: false,
+    });
+    let base = Block::Piston {
+        sticky,
+        facing: Direction::from_id(event.param as i32 & 7).unwrap_or(facing),
+        extended: false,
+    };
+    world.add_moving_block(pos, base, facing, false, true);
+    update_neighbors(world, pos);
+    for d in SHAPE_UPDATE_ORDER {
+        update_shape(world, pos.offset(d), d.opposite());
+    }
+
+    if !sticky {
+        break_block(world, front);
+        return true;
+    }
+
+    // a block still on its way out is dropped where it is
+    let pulled = pos.offset_by(facing, 2);
+    let block = world.get_block(pulled);
+    if matches!(block, Block::MovingPiston { .. })
+        && world.moving_block(pulled).is_some_and(|m| m.facing == facing && m.extending) {
+        final_tick(world, pulled);
+        return true;
+    }
+
+    if event.id != RETRACT
+        || block == Block::Air
+        || !is_pushable(world, block, pulled, facing.opposite(), false)
+        || (block.push_reaction() != PushReaction::Normal && !matches!(block, Block::Piston { .. })) {
+        break_block(world, front);
+    } else {
+        move_blocks(world, pos, facing, false, sticky);
+    }
+
+    true
+}
+
+/// Bucket of `pos` in a Java `HashMap` of up to 12 entries, which decides
+/// the order vanilla clears the blocks a piston moved away from.
+fn hash_bucket(pos: BlockPos) -> i32 {
+    let h = pos.y.wrapping_add(pos.z.wrapping_mul(31)).wrapping_mul(31).wrapping_add(pos.x);
+    (h ^ ((h as u32) >> 16) as i32) & 15
+}
+
+/// Replaces the moved blocks with moving pistons. Returns whether the
+/// blocks could be moved.
+fn move_blocks(world: &mut World, pos: BlockPos, facing: Direction, extending: bool, sticky: bool) -> bool {
+    let front = pos.offset(facing);
+    if !extending && matches!(world.get_block(front), Block::PistonHead { .. }) {
+        set_block_with(world, front, Block::Air, Update { notify: false, shapes: false, moving: false });
+    }
+
+    let mut resolver = StructureResolver::new(world, pos, facing, extending);
+    if !resolver.resolve() {
+        return false;
+    }
+
+    let (to_push, to_destroy) = (resolver.to_push, resolver.to_destroy);
+    let moved: Vec<Block> = to_push.iter().map(|p| world.get_block(*p)).collect();
+    // blocks left behind, in the order they were found
+    let mut left: Vec<(BlockPos, Block)> = to_push.iter().copied().zip(moved.iter().copied()).collect();
+    let dir = if extending { facing } else { facing.opposite() };
+
+    let mut removed = Vec::new();
+    for p in to_destroy.iter().rev() {
+        removed.push(world.get_block(*p));
+        set_block_with(world, *p, Block::Air, Update { notify: false, shapes: false, moving: false });
+    }
+
+    for (p, block) in to_push.iter().zip(moved.iter()).rev() {
+        let dest = p.offset(dir);
+        left.retain(|(l, _)| *l != dest);
+        set_block_with(world, dest, Block::MovingPiston { sticky: false, facing }, Update {
+            notify: false,
+            shapes: true,
+            moving: true,
+        });
+        world.add_moving_block(dest, *block, facing, extending, false);
+    }
+
+    if extending {
+        left.retain(|(l, _)| *l != front);
+        set_block_with(world, front, Block::MovingPiston { sticky, facing }, Update {
+            notify: false,
+            shapes: true,
+            moving: true,
+        });
+        world.add_moving_block(front, Block::PistonHead { sticky, facing, short: false }, facing, true, true);
+    }
+
+    left.sort_by_key(|(p, _)| hash_bucket(*p));
+    for (p, _) in left.iter() {
+        set_block_with(world, *p, Block::Air, Update { notify: false, shapes: false, moving: true });
+    }
+
+    for (p, old) in left {
+        update_neighbor_shapes(world, p, old, Block::Air);
+    }
+
+    for (p, block) in to_destroy.iter().rev().zip(removed) {
+        if let Block::RedstoneWire(w) = block {
+            wire::update_indirect_neighbor_shapes(world, *p, w);
+        }
+
+        update_neighbors(world, *p);
+    }
+
+    for p in to_push.iter().rev() {
+        update_neighbors(world, *p);
+    }
+
+    if extending {
+        update_neighbors(world, front);
+    }
+
+    true
+}
+
+/// Finishes the move at `pos` right away.
+fn final_tick(world: &mut World, pos: BlockPos) {
+    let Some(moving) = world.moving_block(pos).cloned() else { return };
+    if moving.progress_old >= 2 {
+        return;
+    }
+
+    world.remove_block_entity(pos);
+    if matches!(world.get_block(pos), Block::MovingPiston { .. }) {
+        let block = if moving.source {
+            Block::Air
+        } else {
+            updated_shape_from_neighbors(world, pos, moving.block)
+        };
+
+        set_block(world, pos, block, true);
+        neighbor_changed(world, pos);
+    }
+}
+
+/// Advances a moving block by half a block, and puts it down once it has arrived.
+pub fn tick_moving_block(world: &mut World, pos: BlockPos) {
+    let time = world.time;
+    let Some(BlockEntity::MovingPiston(moving)) = world.block_entities.get_mut(&pos) else { return };
+    moving.last_ticked = time;
+    moving.progress_old = moving.progress;
+
+    if moving.progress_old < 2 {
+        moving.progress += 1;
+        return;
+    }
+
+    let moved = moving.block;
+    world.remove_block_entity(pos);
+    if !matches!(world.get_block(pos), Block::MovingPiston { .. }) {
+        return;
+    }
+
+    let block = updated_shape_from_neighbors(world, pos, moved);
+    if block == Block::Air {
+        // it can't stay where it ended up
+        set_block_with(world, pos, moved, Update { notify: false, shapes: false, moving: true });
+        break_block(world, pos);
+    } else {
+        set_block_with(world, pos, block, Update { notify: true, shapes: true, moving: true });
+        neighbor_changed(world, pos);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STONE: Block = Block::Solid(1);
+
+    /// Where a piston at the origin pushing east would move the blocks to
+    /// `blocks`, as `(x, y)` with z at 0.
+    fn push_order(blocks: &[((i32, i32), Block)]) -> Option<Vec<(i32, i32)>> {
+        let mut world = World::new();
+        for ((x, y), block) in blocks {
+            world.set_block(BlockPos::new(*x, 100 + *y, 0), *block);
+        }
+
+        let mut resolver = StructureResolver::new(&world, BlockPos::new(0, 100, 0), Direction::East, true);
+        resolver.resolve().then(|| resolver.to_push.iter().map(|p| (p.x, p.y - 100)).collect())
+    }
+
+    #[test]
+    fn lines_are_pushed_from_the_piston_out() {
+        let blocks = [((1, 0), STONE), ((2, 0), STONE), ((3, 0), STONE)];
+        assert_eq!(push_order(&blocks), Some(vec![(1, 0), (2, 0), (3, 0)]));
+    }
+
+    #[test]
+    fn slime_drags_blocks_after_its_line() {
+        let blocks = [((1, 0), Block::SlimeBlock), ((1, 1), STONE), ((2, 1), STONE)];
+        assert_eq!(push_order(&blocks), Some(vec![(1, 0), (1, 1), (2, 1)]));
+    }
+
+    #[test]
+    fn lines_running_into_moved_blocks_go_before_them() {
+        let blocks = [
+            ((1, 0), STONE),
+            ((2, 0), STONE),
+            ((3, 0), Block::SlimeBlock),
+            ((3, 1), Block::SlimeBlock),
+            ((2, 1), STONE),
+            ((2, 2), Block::SlimeBlock),
+            ((3, 2), Block::SlimeBlock),
+        ];
+        assert_eq!(push_order(&blocks), Some(vec![(1, 0), (2, 0), (3, 0), (2, 1), (3, 1), (2, 2), (3, 2)]));
+    }
+
+    #[test]
+    fn pushes_are_limited() {
+        let blocks: Vec<_> = (1..=MAX_PUSH as i32 + 1).map(|x| ((x, 0), STONE)).collect();
+        assert_eq!(push_order(&blocks[..MAX_PUSH]).map(|o| o.len()), Some(MAX_PUSH));
+        assert_eq!(push_order(&blocks), None);
+    }
+}
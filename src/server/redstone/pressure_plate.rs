@@ -0,0 +1,56 @@
+//! Pressure plates, which players press by standing on them.
+
+use super::*;
+
+/// Power of a pressure plate.
+pub fn signal(block: Block) -> u8 {
+    match block {
+        Block::PressurePlate { powered: true, .. } => 15,
+        Block::WeightedPressurePlate { power, .. } => power,
+        _ => 0,
+    }
+}
+
+fn with_signal(block: Block, signal: u8) -> Block {
+    match block {
+        Block::PressurePlate { kind, .. } => Block::PressurePlate { kind, powered: signal > 0 },
+        Block::WeightedPressurePlate { heavy, .. } => Block::WeightedPressurePlate { heavy, power: signal },
+        b => b,
+    }
+}
+
+/// Power the plate at `pos` should have from the entities on it. Weighted
+/// plates give more the more entities there are.
+fn signal_strength(world: &World, pos: BlockPos, block: Block) -> u8 {
+    let (x, y, z) = (pos.x as f64, pos.y as f64, pos.z as f64);
+    let count = count_entities(world, [x + 0.125, y, z + 0.125], [x + 0.875, y + 0.25, z + 0.875]);
+
+    match block {
+        Block::WeightedPressurePlate { heavy, .. } => {
+            let max = if heavy { 150 } else { 15 };
+            (count.min(max) as f32 / max as f32 * 15.0).ceil() as u8
+        },
+        _ if count > 0 => 15,
+        _ => 0,
+    }
+}
+
+/// Updates the power of a plate, and keeps checking while it's pressed.
+pub fn check_pressed(world: &mut World, pos: BlockPos, block: Block) {
+    let old = signal(block);
+    let new = signal_strength(world, pos, block);
+
+    if old != new {
+        set_block(world, pos, with_signal(block, new), false);
+        update_neighbors(world, pos);
+        update_neighbors(world, pos.offset(Direction::Down));
+    }
+
+    if new > 0 {
+        let delay = match block {
+            Block::WeightedPressurePlate { .. } => 10,
+            _ => 20,
+        };
+        world.schedule_tick(pos, delay, TickPriority::Normal);
+    }
+}
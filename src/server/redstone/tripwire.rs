@@ -0,0 +1,135 @@
+//! Tripwire hooks and the string between them.
+
+use super::*;
+use super::super::world::block::Tripwire;
+
+/// Furthest a hook looks for the hook across from it.
+const MAX_LENGTH: i32 = 42;
+
+/// Whether tripwire connects to `block` towards `dir`.
+pub fn connects_to(block: Block, dir: Direction) -> bool {
+    match block {
+        Block::TripwireHook { facing, .. } => facing == dir.opposite(),
+        Block::Tripwire(_) => true,
+        _ => false,
+    }
+}
+
+/// Works out the state of the hook at `pos` and of the hook across from it,
+/// and attaches the string between them. `wire` is the string at some
+/// distance from the hook that just changed, and `removing` is set if the
+/// hook is being removed.
+pub fn calculate_state(
+    world: &mut World,
+    pos: BlockPos,
+    hook: Block,
+    removing: bool,
+    notify: bool,
+    wire: Option<(i32, Tripwire)>,
+) {
+    let Block::TripwireHook { facing, attached: was_attached, .. } = hook else { return };
+
+    let mut attached = !removing;
+    let mut powered = false;
+    let mut other = 0;
+    let mut wires = [None; MAX_LENGTH as usize];
+
+    for i in 1..MAX_LENGTH {
+        let p = pos.offset_by(facing, i);
+        let block = world.get_block(p);
+        if let Block::TripwireHook { facing: f, .. } = block {
+            if f == facing.opposite() {
+                other = i;
+            }
+            break;
+        }
+
+        let changed = wire.filter(|(j, _)| *j == i).map(|(_, w)| w);
+        let w = match (changed, block) {
+            (Some(w), _) | (None, Block::Tripwire(w)) => w,
+            _ => {
+                attached = false;
+                continue;
+            },
+        };
+
+        let armed = !w.disarmed;
+        powered |= armed && w.powered;
+        wires[i as usize] = Some(w);
+
+        if changed.is_some() {
+            world.schedule_tick(pos, 10, TickPriority::Normal);
+            attached &= armed;
+        }
+    }
+
+    attached &= other > 1;
+    powered &= attached;
+
+    if other > 0 {
+        let p = pos.offset_by(facing, other);
+        set_block(world, p, Block::TripwireHook { facing: facing.opposite(), attached, powered }, true);
+        notify_neighbors(world, p, facing.opposite());
+    }
+
+    if !removing {
+        set_block(world, pos, Block::TripwireHook { facing, attached, powered }, true);
+        if notify {
+            notify_neighbors(world, pos, facing);
+        }
+    }
+
+    if was_attached != attached {
+        for i in 1..other {
+            if let Some(mut w) = wires[i as usize] {
+                w.attached = attached;
+                set_block(world, pos.offset_by(facing, i), Block::Tripwire(w), true);
+            }
+        }
+    }
+}
+
+fn notify_neighbors(world: &mut World, pos: BlockPos, facing: Direction) {
+    update_neighbors(world, pos);
+    update_neighbors(world, pos.offset(facing.opposite()));
+}
+
+/// Tells the hooks at the ends of the string at `pos` that it changed to
+/// `wire`. Only the hooks to the south and west look, and they update the
+/// hook across from them.
+pub fn update_source(world: &mut World, pos: BlockPos, wire: Tripwire) {
+    for d in [Direction::South, Direction::West] {
+        for i in 1..MAX_LENGTH {
+            let p = pos.offset_by(d, i);
+            match world.get_block(p) {
+                hook @ Block::TripwireHook { facing, .. } => {
+                    if facing == d.opposite() {
+                        calculate_state(world, p, hook, false, true, Some((i, wire)));
+                    }
+                    break;
+                },
+                Block::Tripwire(_) => {},
+                _ => break,
+            }
+        }
+    }
+}
+
+/// Powers the string at `pos` while entities touch it.
+pub fn check_pressed(world: &mut World, pos: BlockPos) {
+    let Block::Tripwire(mut wire) = world.get_block(pos) else { return };
+
+    let (x, y, z) = (pos.x as f64, pos.y as f64, pos.z as f64);
+    let (bottom, top) = if wire.attached { (1.0 / 16.0, 2.5 / 16.0) } else { (0.0, 0.5) };
+    let pressed = count_entities(world, [x, y + bottom, z], [x + 1.0, y + top, z + 1.0]) > 0;
+
+    if pressed != wire.powered {
+        wire.powered = pressed;
+        set_block(world, pos, Block::Tripwire(wire), true);
+        update_source(world, pos, wire);
+    }
+
+    if pressed {
+        world.schedule_tick(pos, 10, TickPriority::Normal);
+    }
+}
@@ -0,0 +1,205 @@
+use super::*;
+use super::super::world::block::{RedstoneWire, WireSide};
+
+pub fn power_of(block: Block) -> u8 {
+    match block {
+        Block::RedstoneWire(w) => w.power,
+        _ => 0,
+    }
+}
+
+fn connects_to(block: Block, dir: Option<Direction>) -> bool {
+    match block {
+        Block::RedstoneWire(_) => true,
+        Block::Repeater { facing, .. } => dir == Some(facing) || dir == Some(facing.opposite()),
+        Block::Observer { facing, .. } => dir == Some(facing),
+        b => b.is_signal_source() && dir.is_some(),
+    }
+}
+
+fn get_connecting_side(world: &World, pos: BlockPos, dir: Direction, above_not_solid: bool) -> WireSide {
+    let n = pos.offset(dir);
+    let block = world.get_block(n);
+
+    if above_not_solid && block.is_sturdy() && connects_to(world.get_block(n.offset(Direction::Up)), None) {
+        return WireSide::Up;
+    }
+
+    if !connects_to(block, Some(dir)) && (block.is_solid() || !connects_to(world.get_block(n.offset(Direction::Down)), None)) {
+        WireSide::None
+    } else {
+        WireSide::Side
+    }
+}
+
+/// Connects the wire to its neighbours. A wire connected on one side only
+/// extends to the opposite side, and one connected nowhere becomes a cross
+/// unless it already is a dot.
+pub fn get_connection_state(world: &World, pos: BlockPos, wire: RedstoneWire) -> RedstoneWire {
+    let was_dot = wire.is_dot();
+    let above_not_solid = !world.get_block(pos.offset(Direction::Up)).is_solid();
+
+    let mut state = RedstoneWire {
+        north: WireSide::None,
+        south: WireSide::None,
+        east: WireSide::None,
+        west: WireSide::None,
+        power: wire.power,
+    };
+
+    for d in Direction::HORIZONTAL {
+        state.set_side(d, get_connecting_side(world, pos, d, above_not_solid));
+    }
+
+    if was_dot && state.is_dot() {
+        return state;
+    }
+
+    let no_z = !state.north.is_connected() && !state.south.is_connected();
+    let no_x = !state.east.is_connected() && !state.west.is_connected();
+
+    if no_z {
+        if !state.west.is_connected() {
+            state.west = WireSide::Side;
+        }
+
+        if !state.east.is_connected() {
+            state.east = WireSide::Side;
+        }
+    }
+
+    if no_x {
+        if !state.north.is_connected() {
+            state.north = WireSide::Side;
+        }
+
+        if !state.south.is_connected() {
+            state.south = WireSide::Side;
+        }
+    }
+
+    state
+}
+
+/// Updates the shape of wires diagonally above and below the connected sides.
+pub fn update_indirect_neighbor_shapes(world: &mut World, pos: BlockPos, wire: RedstoneWire) {
+    for d in Direction::HORIZONTAL {
+        let n = pos.offset(d);
+        if !wire.side(d).is_connected() || matches!(world.get_block(n), Block::RedstoneWire(_)) {
+            continue;
+        }
+
+        update_shape(world, n.offset(Direction::Down), d.opposite());
+        update_shape(world, n.offset(Direction::Up), d.opposite());
+    }
+}
+
+fn calculate_target_strength(world: &World, pos: BlockPos) -> u8 {
+    let power = get_best_neighbor_signal(world, pos, false);
+    if power >= 15 {
+        return power;
+    }
+
+    let above_solid = world.get_block(pos.offset(Direction::Up)).is_solid();
+    let mut wire_power = 0;
+    for d in Direction::HORIZONTAL {
+        let n = pos.offset(d);
+        let block = world.get_block(n);
+        wire_power = wire_power.max(power_of(block));
+
+        if block.is_solid() && !above_solid {
+            wire_power = wire_power.max(power_of(world.get_block(n.offset(Direction::Up))));
+        } else if !block.is_solid() {
+            wire_power = wire_power.max(power_of(world.get_block(n.offset(Direction::Down))));
+        }
+    }
+
+    power.max(wire_power.saturating_sub(1))
+}
+
+pub fn update_power_strength(world: &mut World, pos: BlockPos, wire: RedstoneWire) {
+    let power = calculate_target_strength(world, pos);
+    if power == wire.power {
+        return;
+    }
+
+    if world.get_block(pos) == Block::RedstoneWire(wire) {
+        set_block(world, pos, Block::RedstoneWire(RedstoneWire { power, ..wire }), false);
+    }
+
+    update_neighbors(world, pos);
+    for d in Direction::ALL {
+        update_neighbors(world, pos.offset(d));
+    }
+}
+
+fn check_corner_change_at(world: &mut World, pos: BlockPos) {
+    if let Block::RedstoneWire(_) = world.get_block(pos) {
+        update_neighbors(world, pos);
+        for d in Direction::ALL {
+            update_neighbors(world, pos.offset(d));
+        }
+    }
+}
+
+fn update_neighbors_of_neighboring_wires(world: &mut World, pos: BlockPos) {
+    for d in Direction::HORIZONTAL {
+        check_corner_change_at(world, pos.offset(d));
+    }
+
+    for d in Direction::HORIZONTAL {
+        let n = pos.offset(d);
+        if world.get_block(n).is_solid() {
+            check_corner_change_at(world, n.offset(Direction::Up));
+        } else {
+            check_corner_change_at(world, n.offset(Direction::Down));
+        }
+    }
+}
+
+pub fn on_place(world: &mut World, pos: BlockPos) {
+    if let Block::RedstoneWire(w) = world.get_block(pos) {
+        update_power_strength(world, pos, w);
+    }
+
+    update_neighbors(world, pos.offset(Direction::Up));
+    update_neighbors(world, pos.offset(Direction::Down));
+    update_neighbors_of_neighboring_wires(world, pos);
+}
+
+pub fn on_remove(world: &mut World, pos: BlockPos, wire: RedstoneWire) {
+    for d in Direction::ALL {
+        update_neighbors(world, pos.offset(d));
+    }
+
+    update_power_strength(world, pos, wire);
+    update_neighbors_of_neighboring_wires(world, pos);
+}
+
+/// Switches a dot to a cross or a cross to a dot when it's clicked. Returns
+/// whether the shape changed.
+pub fn toggle_shape(world: &mut World, pos: BlockPos, wire: RedstoneWire) -> bool {
+    let is_cross = Direction::HORIZONTAL.iter().all(|d| wire.side(*d).is_connected());
+    if !is_cross && !wire.is_dot() {
+        return false;
+    }
+
+    let side = if is_cross { WireSide::None } else { WireSide::Side };
+    let toggled = RedstoneWire { north: side, south: side, east: side, west: side, power: wire.power };
+    let new = get_connection_state(world, pos, toggled);
+    if new == wire {
+        return false;
+    }
+
+    set_block(world, pos, Block::RedstoneWire(new), true);
+
+    // conductors the wire stopped or started pointing into change their power
+    for d in Direction::HORIZONTAL {
+        let n = pos.offset(d);
+        if wire.side(d).is_connected() != new.side(d).is_connected() && world.get_block(n).is_solid() {
+            update_neighbors_except(world, n, d.opposite());
+        }
+    }
+
+    true
+}
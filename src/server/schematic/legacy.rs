@@ -0,0 +1,290 @@
+//! MCEdit schematics (.schematic) from before 1.13, which store blocks by
+//! numeric id and metadata. They can only be read.
+
+use quartz_nbt::{NbtCompound, NbtList, NbtReprError};
+use super::Schematic;
+use super::super::edit::MAX_VOLUME;
+use super::super::redstone::wire;
+use super::super::world::block::{Block, RedstoneWire, WireSide};
+use super::super::world::storage;
+use super::super::world::{state, BlockPos, World};
+
+const FACING: [&str; 6] = ["down", "up", "north", "south", "west", "east"];
+/// Facing of repeaters, comparators and tripwire hooks.
+const FACING_SWNE: [&str; 4] = ["south", "west", "north", "east"];
+const COLORS: [&str; 16] = [
+    "white", "orange", "magenta", "light_blue", "yellow", "lime", "pink", "gray", "light_gray", "cyan",
+    "purple", "blue", "brown", "green", "red", "black",
+];
+
+fn err(e: NbtReprError) -> String {
+    e.to_string()
+}
+
+/// The state string of a legacy block, given the metadata of the block
+/// above or below for the halves of doors.
+fn state_of(id: u8, meta: u8, other_half: u8) -> Option<String> {
+    let facing = FACING.get(meta as usize & 7);
+    let state = match id {
+        0 | 36 => "air".to_string(),
+        1 => ["stone", "granite", "polished_granite", "diorite", "polished_diorite", "andesite", "polished_andesite"]
+            .get(meta as usize)?.to_string(),
+        2 => "grass_block".to_string(),
+        3 => ["dirt", "coarse_dirt"].get(meta as usize)?.to_string(),
+        4 => "cobblestone".to_string(),
+        5 => ["oak_planks", "spruce_planks", "birch_planks", "jungle_planks", "acacia_planks", "dark_oak_planks"]
+            .get(meta as usize)?.to_string(),
+        7 => "bedrock".to_string(),
+        20 => "glass".to_string(),
+        23 => format!("dispenser[facing={},triggered={}]", facing?, meta & 8 != 0),
+        24 => ["sandstone", "chiseled_sandstone", "cut_sandstone"].get(meta as usize)?.to_string(),
+        25 => "note_block".to_string(),
+        29 | 33 => format!(
+            "{}[facing={},extended={}]",
+            if id == 29 { "sticky_piston" } else { "piston" },
+            facing?,
+            meta & 8 != 0,
+        ),
+        34 => format!("piston_head[facing={},type={}]", facing?, if meta & 8 != 0 { "sticky" } else { "normal" }),
+        35 => format!("{}_wool", COLORS[meta as usize & 15]),
+        41 => "gold_block".to_string(),
+        42 => "iron_block".to_string(),
+        45 => "bricks".to_string(),
+        46 => "tnt".to_string(),
+        47 => "bookshelf".to_string(),
+        48 => "mossy_cobblestone".to_string(),
+        49 => "obsidian".to_string(),
+        54 => format!("chest[facing={}]", FACING.get(meta as usize).filter(|_| meta >= 2)?),
+        55 => format!("redstone_wire[power={}]", meta & 15),
+        57 => "diamond_block".to_string(),
+        64 | 71 => {
+            let name = if id == 64 { "oak_door" } else { "iron_door" };
+            let (lower, upper) = if meta & 8 != 0 { (other_half, meta) } else { (meta, other_half) };
+            format!(
+                "{}[facing={},half={},hinge={},open={},powered={}]",
+                name,
+                ["east", "south", "west", "north"][lower as usize & 3],
+                if meta & 8 != 0 { "upper" } else { "lower" },
+                if upper & 1 != 0 { "right" } else { "left" },
+                lower & 4 != 0,
+                upper & 2 != 0,
+            )
+        },
+        69 => {
+            let (face, facing) = [
+                ("ceiling", "west"), ("wall", "east"), ("wall", "west"), ("wall", "south"),
+                ("wall", "north"), ("floor", "north"), ("floor", "west"), ("ceiling", "north"),
+            ][meta as usize & 7];
+            format!("lever[face={},facing={},powered={}]", face, facing, meta & 8 != 0)
+        },
+        70 | 72 => format!(
+            "{}[powered={}]",
+            if id == 70 { "stone_pressure_plate" } else { "oak_pressure_plate" },
+            meta & 1 != 0,
+        ),
+        75 | 76 => {
+            let lit = id == 76;
+            match meta {
+                5 => format!("redstone_torch[lit={}]", lit),
+                1..=4 => format!("redstone_wall_torch[facing={},lit={}]", ["east", "west", "south", "north"][meta as usize - 1], lit),
+                _ => return None,
+            }
+        },
+        77 | 143 => {
+            let (face, facing) = match meta & 7 {
+                0 => ("ceiling", "north"),
+                5 => ("floor", "north"),
+                side @ 1..=4 => ("wall", ["east", "west", "south", "north"][side as usize - 1]),
+                _ => return None,
+            };
+            format!(
+                "{}[face={},facing={},powered={}]",
+                if id == 77 { "stone_button" } else { "oak_button" },
+                face,
+                facing,
+                meta & 8 != 0,
+            )
+        },
+        93 | 94 => format!(
+            "repeater[delay={},facing={},powered={}]",
+            (meta >> 2) + 1,
+            FACING_SWNE[meta as usize & 3],
+            id == 94,
+        ),
+        95 => format!("{}_stained_glass", COLORS[meta as usize & 15]),
+        96 | 167 => format!(
+            "{}[facing={},open={},half={}]",
+            if id == 96 { "oak_trapdoor" } else { "iron_trapdoor" },
+            ["north", "south", "west", "east"][meta as usize & 3],
+            meta & 4 != 0,
+            if meta & 8 != 0 { "top" } else { "bottom" },
+        ),
+        98 => ["stone_bricks", "mossy_stone_bricks", "cracked_stone_bricks", "chiseled_stone_bricks"]
+            .get(meta as usize)?.to_string(),
+        123 | 124 => format!("redstone_lamp[lit={}]", id == 124),
+        131 => format!(
+            "tripwire_hook[facing={},attached={},powered={}]",
+            FACING_SWNE[meta as usize & 3],
+            meta & 4 != 0,
+            meta & 8 != 0,
+        ),
+        132 => format!(
+            "tripwire[powered={},attached={},disarmed={}]",
+            meta & 1 != 0,
+            meta & 4 != 0,
+            meta & 8 != 0,
+        ),
+        133 => "emerald_block".to_string(),
+        147 | 148 => format!(
+            "{}_weighted_pressure_plate[power={}]",
+            if id == 147 { "light" } else { "heavy" },
+            meta & 15,
+        ),
+        149 | 150 => format!(
+            "comparator[facing={},mode={},powered={}]",
+            FACING_SWNE[meta as usize & 3],
+            if meta & 4 != 0 { "subtract" } else { "compare" },
+            meta & 8 != 0,
+        ),
+        151 | 178 => format!("daylight_detector[inverted={},power={}]", id == 178, meta & 15),
+        152 => "redstone_block".to_string(),
+        154 => format!("hopper[facing={},enabled={}]", facing?, meta & 8 == 0),
+        155 => ["quartz_block", "chiseled_quartz_block"].get(meta as usize)?.to_string(),
+        158 => format!("dropper[facing={},triggered={}]", facing?, meta & 8 != 0),
+        159 => format!("{}_terracotta", COLORS[meta as usize & 15]),
+        165 => "slime_block".to_string(),
+        172 => "terracotta".to_string(),
+        218 => format!("observer[facing={},powered={}]", facing?, meta & 8 != 0),
+        251 => format!("{}_concrete", COLORS[meta as usize & 15]),
+        _ => return None,
+    };
+
+    Some(format!("minecraft:{}", state))
+}
+
+pub fn read(nbt: &NbtCompound) -> Result<Schematic, String> {
+    let dimension = |name| nbt.get::<_, i16>(name).map(|d| d as u16 as i32).map_err(err);
+    let size = (dimension("Width")?, dimension("Height")?, dimension("Length")?);
+    if size.0 as u64 * size.1 as u64 * size.2 as u64 > MAX_VOLUME {
+        return Err(format!("{}x{}x{} blocks is more than {} blocks", size.0, size.1, size.2, MAX_VOLUME));
+    }
+
+    let offset = match (nbt.get("WEOffsetX"), nbt.get("WEOffsetY"), nbt.get("WEOffsetZ")) {
+        (Ok(x), Ok(y), Ok(z)) => BlockPos::new(x, y, z),
+        _ => BlockPos::new(0, 0, 0),
+    };
+
+    let ids: &[i8] = nbt.get("Blocks").map_err(err)?;
+    let metas: &[i8] = nbt.get("Data").map_err(err)?;
+    let volume = (size.0 * size.1 * size.2) as usize;
+    if ids.len() != volume || metas.len() != volume {
+        return Err(format!("block data has {} blocks instead of {}", ids.len(), volume));
+    }
+
+    let mut schematic = Schematic::new(size, offset);
+    let layer = (size.0 * size.2) as usize;
+    let mut unknown = 0;
+    for i in 0..volume {
+        let (id, meta) = (ids[i] as u8, metas[i] as u8 & 15);
+        // the halves of a door only know the whole door together
+        let other_half = if meta & 8 != 0 { i.checked_sub(layer) } else { Some(i + layer) };
+        let other_half = other_half.and_then(|j| metas.get(j)).map_or(0, |m| *m as u8 & 15);
+
+        match state_of(id, meta, other_half).and_then(|s| state::parse(&s)) {
+            Some(state) => schematic.blocks[i] = Block::from_id(state),
+            None => unknown += 1,
+        }
+    }
+
+    if unknown > 0 {
+        warn!("{} blocks of unknown kinds are left out", unknown);
+    }
+
+    connect_wires(&mut schematic);
+
+    let entities = nbt.get::<_, &NbtList>("TileEntities").ok();
+    for entity in entities.iter().flat_map(|l| l.iter_map::<&NbtCompound>()) {
+        let entity = entity.map_err(|e| e.to_string())?;
+        let (Ok(x), Ok(y), Ok(z)) = (entity.get("x"), entity.get("y"), entity.get("z")) else { continue };
+        let pos = BlockPos::new(x, y, z);
+        if !schematic.contains(pos) {
+            continue;
+        }
+
+        let i = schematic.index(pos.x, pos.y, pos.z);
+        match schematic.blocks[i] {
+            // notes were kept apart from the block back then
+            Block::NoteBlock { instrument, powered, .. } => {
+                let note = entity.get::<_, i8>("note").unwrap_or(0).clamp(0, 24) as u8;
+                schematic.blocks[i] = Block::NoteBlock { instrument, note, powered };
+            },
+            block => {
+                if let Some(entity) = storage::block_entity_from_nbt(block, entity) {
+                    schematic.block_entities.push((pos, entity));
+                }
+            },
+        }
+    }
+
+    Ok(schematic)
+}
+
+/// Gives wire the shape it had in game, which legacy ids didn't store.
+fn connect_wires(schematic: &mut Schematic) {
+    let mut world = World::new();
+    // above the ground of new chunks, which would otherwise hide air
+    let at = |x, y, z| BlockPos::new(x, y + 1, z);
+    let mut wires = Vec::new();
+    for y in 0..schematic.size.1 {
+        for z in 0..schematic.size.2 {
+            for x in 0..schematic.size.0 {
+                let block = schematic.blocks[schematic.index(x, y, z)];
+                world.set_block(at(x, y, z), block);
+                if let Block::RedstoneWire(wire) = block {
+                    wires.push((x, y, z, wire));
+                }
+            }
+        }
+    }
+
+    for (x, y, z, wire) in wires {
+        let cross = RedstoneWire {
+            north: WireSide::Side,
+            south: WireSide::Side,
+            east: WireSide::Side,
+            west: WireSide::Side,
+            power: wire.power,
+        };
+        let i = schematic.index(x, y, z);
+        schematic.blocks[i] = Block::RedstoneWire(wire::get_connection_state(&world, at(x, y, z), cross));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn nbt(size: (i16, i16, i16), ids: Vec<i8>, metas: Vec<i8>) -> NbtCompound {
+        let mut nbt = NbtCompound::new();
+        nbt.insert("Width", size.0);
+        nbt.insert("Height", size.1);
+        nbt.insert("Length", size.2);
+        nbt.insert("Blocks", ids);
+        nbt.insert("Data", metas);
+        nbt
+    }
+
+    #[test]
+    fn reads_blocks_by_id_and_metadata() {
+        // a lamp, then a repeater of delay 2 facing west
+        let schematic = read(&nbt((2, 1, 1), vec![123, 93], vec![0, 5])).unwrap();
+        assert_eq!(schematic.blocks[0], Block::RedstoneLamp { lit: false });
+        assert_eq!(state::name(schematic.blocks[1].get_id()).unwrap(),
+            "minecraft:repeater[delay=2,facing=west,locked=false,powered=false]");
+    }
+
+    #[test]
+    fn oversized_schematics_are_refused() {
+        assert!(read(&nbt((-1, -1, -1), Vec::new(), Vec::new())).is_err());
+    }
+}
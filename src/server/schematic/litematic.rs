@@ -0,0 +1,387 @@
+//! Litematica schematics (.litematic), which hold several named regions with
+//! their block states packed into long arrays.
+
+use std::collections::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+use quartz_nbt::{NbtCompound, NbtList, NbtReprError, NbtTag};
+use super::{Schematic, SubRegion};
+use super::super::edit::MAX_VOLUME;
+use super::super::world::block::Block;
+use super::super::world::storage::{self, DATA_VERSION};
+use super::super::world::{state, BlockPos};
+
+/// Version of the format Litematica writes for 1.18.
+const VERSION: i32 = 6;
+
+fn err(e: NbtReprError) -> String {
+    e.to_string()
+}
+
+fn read_vec(nbt: &NbtCompound, name: &str) -> Result<BlockPos, String> {
+    let vec: &NbtCompound = nbt.get(name).map_err(err)?;
+    Ok(BlockPos::new(vec.get("x").map_err(err)?, vec.get("y").map_err(err)?, vec.get("z").map_err(err)?))
+}
+
+fn write_vec(x: i32, y: i32, z: i32) -> NbtCompound {
+    let mut vec = NbtCompound::new();
+    vec.insert("x", x);
+    vec.insert("y", y);
+    vec.insert("z", z);
+    vec
+}
+
+/// Bits each palette index takes, which is never less than 2.
+fn bits(palette_len: usize) -> u32 {
+    (usize::BITS - palette_len.saturating_sub(1).leading_zeros()).max(2)
+}
+
+/// Reads the `index`th value of a bit array whose values may span two longs.
+fn unpack(longs: &[i64], bits: u32, index: usize) -> Option<u32> {
+    let start = index * bits as usize;
+    let end = start + bits as usize - 1;
+    let (first, last, shift) = (start >> 6, end >> 6, start & 63);
+    let mut value = *longs.get(first)? as u64 >> shift;
+    if first != last {
+        value |= (*longs.get(last)? as u64) << (64 - shift);
+    }
+
+    Some((value & ((1 << bits) - 1)) as u32)
+}
+
+fn pack(values: &[u32], bits: u32) -> Vec<i64> {
+    let mut longs = vec![0_u64; (values.len() * bits as usize).div_ceil(64)];
+    for (index, value) in values.iter().enumerate() {
+        let start = index * bits as usize;
+        let end = start + bits as usize - 1;
+        let (first, last, shift) = (start >> 6, end >> 6, start & 63);
+        longs[first] |= (*value as u64) << shift;
+        if first != last {
+            longs[last] |= (*value as u64) >> (64 - shift);
+        }
+    }
+
+    longs.into_iter().map(|l| l as i64).collect()
+}
+
+/// A palette entry as the state string [`state::parse`] reads.
+fn palette_state(entry: &NbtCompound) -> Result<String, String> {
+    let name: &str = entry.get("Name").map_err(err)?;
+    let Ok(properties) = entry.get::<_, &NbtCompound>("Properties") else { return Ok(name.to_string()) };
+
+    let properties = properties.inner().iter()
+        .map(|(k, v)| match v {
+            NbtTag::String(v) => Ok(format!("{}={}", k, v)),
+            _ => Err(format!("property {} of {} isn't a string", k, name)),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(format!("{}[{}]", name, properties.join(",")))
+}
+
+fn palette_entry(state: &str) -> NbtCompound {
+    let mut entry = NbtCompound::new();
+    let (name, properties) = match state.split_once('[') {
+        Some((name, rest)) => (name, rest.trim_end_matches(']')),
+        None => (state, ""),
+    };
+
+    entry.insert("Name", name);
+    if !properties.is_empty() {
+        let mut compound = NbtCompound::new();
+        for (k, v) in properties.split(',').filter_map(|p| p.split_once('=')) {
+            compound.insert(k, v);
+        }
+        entry.insert("Properties", compound);
+    }
+
+    entry
+}
+
+/// A region read from the file, with its lowest corner relative to the
+/// placement.
+struct Region<'a> {
+    name: &'a str,
+    nbt: &'a NbtCompound,
+    min: BlockPos,
+    size: (i32, i32, i32),
+}
+
+pub fn read(root: &NbtCompound) -> Result<Schematic, String> {
+    let version: i32 = root.get("Version").map_err(err)?;
+    if version > VERSION {
+        warn!("litematic version {} is newer than {}, reading it anyway", version, VERSION);
+    }
+
+    // sizes may be negative, when the region was selected backwards
+    let mut regions = Vec::new();
+    for (name, nbt) in root.get::<_, &NbtCompound>("Regions").map_err(err)?.inner() {
+        let NbtTag::Compound(nbt) = nbt else { return Err(format!("region {} isn't a compound", name)) };
+        let (pos, size) = (read_vec(nbt, "Position")?, read_vec(nbt, "Size")?);
+        let corner = |p: i32, s: i32| if s < 0 { p.checked_add(s)?.checked_add(1) } else { Some(p) };
+        let (Some(x), Some(y), Some(z)) = (corner(pos.x, size.x), corner(pos.y, size.y), corner(pos.z, size.z)) else {
+            return Err(format!("region {} is out of range", name));
+        };
+        let (Some(w), Some(h), Some(l)) = (size.x.checked_abs(), size.y.checked_abs(), size.z.checked_abs()) else {
+            return Err(format!("region {} is too big", name));
+        };
+        regions.push(Region { name, nbt, min: BlockPos::new(x, y, z), size: (w, h, l) });
+    }
+    regions.sort_by_key(|r| r.name);
+
+    let Some(first) = regions.first() else { return Err("there are no regions".to_string()) };
+    let (mut min, mut max) = (first.min, first.min);
+    for region in &regions {
+        let end = |p: i32, s: i32| p.checked_add(s).ok_or_else(|| format!("region {} is out of range", region.name));
+        min = BlockPos::new(min.x.min(region.min.x), min.y.min(region.min.y), min.z.min(region.min.z));
+        max = BlockPos::new(
+            max.x.max(end(region.min.x, region.size.0)?),
+            max.y.max(end(region.min.y, region.size.1)?),
+            max.z.max(end(region.min.z, region.size.2)?),
+        );
+    }
+
+    let span = |a: i32, b: i32| b.checked_sub(a).ok_or_else(|| "the regions are too far apart".to_string());
+    let size = (span(min.x, max.x)?, span(min.y, max.y)?, span(min.z, max.z)?);
+    if (size.0 as u64).saturating_mul(size.1 as u64).saturating_mul(size.2 as u64) > MAX_VOLUME {
+        return Err(format!("{}x{}x{} blocks is more than {} blocks", size.0, size.1, size.2, MAX_VOLUME));
+    }
+
+    let mut schematic = Schematic::new(size, min);
+    if let Ok(metadata) = root.get::<_, &NbtCompound>("Metadata") {
+        let text = |name| metadata.get::<_, &str>(name).unwrap_or_default().to_string();
+        schematic.metadata.name = text("Name");
+        schematic.metadata.author = text("Author");
+        schematic.metadata.description = text("Description");
+    }
+
+    let mut unknown = Vec::new();
+    for region in &regions {
+        let corner = BlockPos::new(region.min.x - min.x, region.min.y - min.y, region.min.z - min.z);
+        schematic.regions.push(SubRegion { name: region.name.to_string(), min: corner, size: region.size });
+
+        let mut palette = Vec::new();
+        for entry in region.nbt.get::<_, &NbtList>("BlockStatePalette").map_err(err)?.iter_map::<&NbtCompound>() {
+            let state = palette_state(entry.map_err(|e| e.to_string())?)?;
+            palette.push(state::parse(&state).map(Block::from_id).unwrap_or_else(|| {
+                unknown.push(state);
+                Block::Air
+            }));
+        }
+
+        let longs: &[i64] = region.nbt.get("BlockStates").map_err(err)?;
+        let bits = bits(palette.len());
+        let (w, h, l) = region.size;
+        for i in 0..(w * h * l) as usize {
+            let (x, y, z) = (i as i32 % w, i as i32 / (w * l), i as i32 / w % l);
+            let index = unpack(longs, bits, i).ok_or_else(|| format!("region {} has too few block states", region.name))?;
+            let block = *palette.get(index as usize)
+                .ok_or_else(|| format!("region {} uses {}, which isn't in its palette", region.name, index))?;
+            let at = schematic.index(corner.x + x, corner.y + y, corner.z + z);
+            schematic.blocks[at] = block;
+        }
+
+        let entities = region.nbt.get::<_, &NbtList>("TileEntities").ok();
+        for entity in entities.iter().flat_map(|l| l.iter_map::<&NbtCompound>()) {
+            let entity = entity.map_err(|e| e.to_string())?;
+            let (Ok(x), Ok(y), Ok(z)) = (entity.get::<_, i32>("x"), entity.get::<_, i32>("y"), entity.get::<_, i32>("z")) else {
+                continue;
+            };
+            let (Some(x), Some(y), Some(z)) = (corner.x.checked_add(x), corner.y.checked_add(y), corner.z.checked_add(z)) else {
+                continue;
+            };
+            let pos = BlockPos::new(x, y, z);
+            if !schematic.contains(pos) {
+                continue;
+            }
+
+            let block = schematic.blocks[schematic.index(pos.x, pos.y, pos.z)];
+            if let Some(entity) = storage::block_entity_from_nbt(block, entity) {
+                schematic.block_entities.push((pos, entity));
+            }
+        }
+    }
+
+    if !unknown.is_empty() {
+        unknown.sort();
+        unknown.dedup();
+        warn!("blocks of unknown kinds are left out: {}", unknown.join(", "));
+    }
+
+    Ok(schematic)
+}
+
+fn write_region(schematic: &Schematic, region: &SubRegion, unknown: &mut usize) -> NbtCompound {
+    // Litematica expects air first
+    let mut names = vec!["minecraft:air".to_string()];
+    let mut indices = HashMap::from([(Block::Air.get_id(), 0)]);
+    let mut values = Vec::new();
+    let (w, h, l) = region.size;
+    for y in 0..h {
+        for z in 0..l {
+            for x in 0..w {
+                let id = schematic.blocks[schematic.index(region.min.x + x, region.min.y + y, region.min.z + z)].get_id();
+                let index = *indices.entry(id).or_insert_with(|| match state::name(id) {
+                    Some(name) => {
+                        names.push(name);
+                        names.len() as u32 - 1
+                    },
+                    None => {
+                        *unknown += 1;
+                        0
+                    },
+                });
+                values.push(index);
+            }
+        }
+    }
+
+    let mut palette = NbtList::new();
+    for name in &names {
+        palette.push(palette_entry(name));
+    }
+
+    let mut entities = NbtList::new();
+    for (pos, entity) in &schematic.block_entities {
+        let local = BlockPos::new(pos.x - region.min.x, pos.y - region.min.y, pos.z - region.min.z);
+        if !((0..w).contains(&local.x) && (0..h).contains(&local.y) && (0..l).contains(&local.z)) {
+            continue;
+        }
+
+        let block = schematic.blocks[schematic.index(pos.x, pos.y, pos.z)];
+        let Some((id, mut nbt)) = storage::block_entity_to_nbt(block, entity) else { continue };
+        nbt.insert("id", id);
+        nbt.insert("x", local.x);
+        nbt.insert("y", local.y);
+        nbt.insert("z", local.z);
+        entities.push(nbt);
+    }
+
+    let offset = schematic.offset;
+    let mut nbt = NbtCompound::new();
+    nbt.insert("Position", write_vec(offset.x + region.min.x, offset.y + region.min.y, offset.z + region.min.z));
+    nbt.insert("Size", write_vec(w, h, l));
+    nbt.insert("BlockStatePalette", palette);
+    nbt.insert("BlockStates", pack(&values, bits(names.len())));
+    nbt.insert("TileEntities", entities);
+    nbt.insert("Entities", NbtList::new());
+    nbt.insert("PendingBlockTicks", NbtList::new());
+    nbt.insert("PendingFluidTicks", NbtList::new());
+    nbt
+}
+
+pub fn write(schematic: &Schematic) -> NbtCompound {
+    // schematics from other formats are one region
+    let whole = [SubRegion {
+        name: if schematic.metadata.name.is_empty() { "Unnamed".to_string() } else { schematic.metadata.name.clone() },
+        min: BlockPos::new(0, 0, 0),
+        size: schematic.size,
+    }];
+    let parts = if schematic.regions.is_empty() { &whole[..] } else { &schematic.regions[..] };
+
+    let mut unknown = 0;
+    let mut regions = NbtCompound::new();
+    for region in parts {
+        regions.insert(region.name.as_str(), write_region(schematic, region, &mut unknown));
+    }
+
+    if unknown > 0 {
+        warn!("{} blocks of unknown kinds were saved as air", unknown);
+    }
+
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_millis() as i64);
+    let metadata = &schematic.metadata;
+    let mut nbt = NbtCompound::new();
+    nbt.insert("Name", metadata.name.as_str());
+    nbt.insert("Author", metadata.author.as_str());
+    nbt.insert("Description", metadata.description.as_str());
+    nbt.insert("RegionCount", parts.len() as i32);
+    nbt.insert("TotalVolume", parts.iter().map(|r| r.size.0 * r.size.1 * r.size.2).sum::<i32>());
+    nbt.insert("TotalBlocks", schematic.blocks.iter().filter(|b| **b != Block::Air).count() as i32);
+    nbt.insert("TimeCreated", now);
+    nbt.insert("TimeModified", now);
+    nbt.insert("EnclosingSize", write_vec(schematic.size.0, schematic.size.1, schematic.size.2));
+
+    let mut root = NbtCompound::new();
+    root.insert("MinecraftDataVersion", DATA_VERSION);
+    root.insert("Version", VERSION);
+    root.insert("Metadata", nbt);
+    root.insert("Regions", regions);
+    root
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bits_fit_the_palette() {
+        assert_eq!(bits(1), 2);
+        assert_eq!(bits(4), 2);
+        assert_eq!(bits(5), 3);
+        assert_eq!(bits(17), 5);
+    }
+
+    #[test]
+    fn packed_values_round_trip() {
+        for bits in [2, 3, 5, 7, 13] {
+            let values: Vec<u32> = (0..200).map(|i| (i * 7919) % (1 << bits)).collect();
+            let longs = pack(&values, bits);
+            let unpacked: Vec<_> = (0..values.len()).map(|i| unpack(&longs, bits, i).unwrap()).collect();
+            assert_eq!(unpacked, values, "with {} bits", bits);
+        }
+    }
+
+    #[test]
+    fn values_span_longs() {
+        // with 5 bits, the 13th value takes the last 4 bits of the first long and 1 of the second
+        let mut values = vec![0; 14];
+        values[12] = 0b10110;
+        let longs = pack(&values, 5);
+        assert_eq!(longs[0] as u64 >> 60, 0b0110);
+        assert_eq!(longs[1] & 1, 1);
+        assert_eq!(unpack(&longs, 5, 12), Some(0b10110));
+    }
+
+    #[test]
+    fn unpacking_past_the_end_fails() {
+        assert_eq!(unpack(&pack(&[1, 2, 3], 2), 2, 32), None);
+    }
+
+    #[test]
+    fn schematics_round_trip() {
+        let mut schematic = Schematic::new((2, 3, 2), BlockPos::new(0, 0, 0));
+        schematic.blocks[0] = Block::RedstoneLamp { lit: true };
+        schematic.blocks[7] = Block::from_id(state::parse("minecraft:comparator[facing=north,mode=subtract]").unwrap());
+        schematic.regions.push(SubRegion { name: "main".to_string(), min: BlockPos::new(0, 0, 0), size: (2, 3, 2) });
+        schematic.metadata.name = "lamp".to_string();
+
+        let read = read(&write(&schematic)).unwrap();
+        assert_eq!(read.size, schematic.size);
+        assert_eq!(read.blocks, schematic.blocks);
+        assert_eq!(read.metadata.name, "lamp");
+    }
+
+    #[test]
+    fn regions_out_of_range_fail() {
+        let file = |regions: &[(BlockPos, BlockPos)]| {
+            let mut nbts = NbtCompound::new();
+            for (i, (pos, size)) in regions.iter().enumerate() {
+                let mut nbt = NbtCompound::new();
+                nbt.insert("Position", write_vec(pos.x, pos.y, pos.z));
+                nbt.insert("Size", write_vec(size.x, size.y, size.z));
+                nbts.insert(i.to_string(), nbt);
+            }
+
+            let mut root = NbtCompound::new();
+            root.insert("Version", VERSION);
+            root.insert("Regions", nbts);
+            root
+        };
+
+        let (one, origin) = (BlockPos::new(1, 1, 1), BlockPos::new(0, 0, 0));
+        let error = |regions: &[(BlockPos, BlockPos)]| read(&file(regions)).err().unwrap();
+        assert_eq!(error(&[(BlockPos::new(i32::MIN, 0, 0), BlockPos::new(-2, 1, 1))]), "region 0 is out of range");
+        assert_eq!(error(&[(origin, BlockPos::new(1, i32::MIN, 1))]), "region 0 is too big");
+        assert_eq!(error(&[(BlockPos::new(0, 0, i32::MAX), BlockPos::new(1, 1, 2))]), "region 0 is out of range");
+        assert_eq!(error(&[(BlockPos::new(i32::MIN, 0, 0), one), (BlockPos::new(i32::MAX - 1, 0, 0), one)]), "the regions are too far apart");
+    }
+}
@@ -0,0 +1,208 @@
+//! Structure files, read into and written from the clipboard of the region
+//! editing commands.
+
+pub mod legacy;
+pub mod litematic;
+pub mod sponge;
+
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use quartz_nbt::io::{self, Flavor};
+use quartz_nbt::NbtCompound;
+use super::edit::{self, Clipboard};
+use super::plot::Layout;
+use super::world::block::Block;
+use super::world::{BlockEntity, BlockPos, World};
+
+/// Where the region editing commands keep structure files.
+pub const DIRECTORY: &str = "schematics";
+
+/// A box of blocks, stored layer by layer from the bottom and row by row
+/// from the north like every format does.
+#[derive(Debug, Clone)]
+pub struct Schematic {
+    /// Width along x, height and length along z.
+    pub size: (i32, i32, i32),
+    /// Position of the lowest corner relative to where the schematic is
+    /// pasted.
+    pub offset: BlockPos,
+    pub blocks: Vec<Block>,
+    /// Block entities by position relative to the lowest corner.
+    pub block_entities: Vec<(BlockPos, BlockEntity)>,
+    pub metadata: Metadata,
+    /// Named parts of the box, which only Litematica files have.
+    pub regions: Vec<SubRegion>,
+}
+
+/// Who made a schematic and what it is.
+#[derive(Debug, Clone, Default)]
+pub struct Metadata {
+    pub name: String,
+    pub author: String,
+    pub description: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct SubRegion {
+    pub name: String,
+    /// Lowest corner, relative to the lowest corner of the schematic.
+    pub min: BlockPos,
+    pub size: (i32, i32, i32),
+}
+
+impl Schematic {
+    pub fn index(&self, x: i32, y: i32, z: i32) -> usize {
+        ((y * self.size.2 + z) * self.size.0 + x) as usize
+    }
+
+    /// An empty schematic of the given size.
+    pub fn new(size: (i32, i32, i32), offset: BlockPos) -> Self {
+        Self {
+            size,
+            offset,
+            blocks: vec![Block::Air; (size.0 * size.1 * size.2) as usize],
+            block_entities: Vec::new(),
+            metadata: Metadata::default(),
+            regions: Vec::new(),
+        }
+    }
+
+    pub fn from_clipboard(clipboard: &Clipboard) -> Self {
+        let positions = || clipboard.blocks.iter().map(|(p, _)| p);
+        let min = |f: fn(&BlockPos) -> i32| positions().map(f).min().unwrap_or(0);
+        let max = |f: fn(&BlockPos) -> i32| positions().map(f).max().unwrap_or(-1);
+        let offset = BlockPos::new(min(|p| p.x), min(|p| p.y), min(|p| p.z));
+        let size = (max(|p| p.x) - offset.x + 1, max(|p| p.y) - offset.y + 1, max(|p| p.z) - offset.z + 1);
+
+        let mut schematic = Self::new(size, offset);
+
+        for (pos, (block, entity)) in &clipboard.blocks {
+            let pos = BlockPos::new(pos.x - offset.x, pos.y - offset.y, pos.z - offset.z);
+            let i = schematic.index(pos.x, pos.y, pos.z);
+            schematic.blocks[i] = *block;
+            if let Some(entity) = entity {
+                schematic.block_entities.push((pos, entity.clone()));
+            }
+        }
+
+        schematic
+    }
+
+    pub fn to_clipboard(&self) -> Clipboard {
+        let mut blocks = Vec::with_capacity(self.blocks.len());
+        for y in 0..self.size.1 {
+            for z in 0..self.size.2 {
+                for x in 0..self.size.0 {
+                    let pos = BlockPos::new(self.offset.x + x, self.offset.y + y, self.offset.z + z);
+                    blocks.push((pos, (self.blocks[self.index(x, y, z)], None)));
+                }
+            }
+        }
+
+        for (pos, entity) in &self.block_entities {
+            let i = self.index(pos.x, pos.y, pos.z);
+            blocks[i].1.1 = Some(entity.clone());
+        }
+
+        Clipboard { blocks }
+    }
+
+    /// Whether `pos`, relative to the lowest corner, is inside.
+    pub fn contains(&self, pos: BlockPos) -> bool {
+        (0..self.size.0).contains(&pos.x) && (0..self.size.1).contains(&pos.y) && (0..self.size.2).contains(&pos.z)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// Sponge schematic version 2, as written by WorldEdit 7.2.
+    SpongeV2,
+    SpongeV3,
+    Litematic,
+}
+
+/// Reads a gzipped NBT file.
+fn read_nbt(path: &Path) -> Result<NbtCompound, String> {
+    let file = File::open(path).map_err(|e| format!("Can't open {}: {}", path.display(), e))?;
+    let (nbt, _) = io::read_nbt(&mut BufReader::new(file), Flavor::GzCompressed)
+        .map_err(|e| format!("Can't read {}: {}", path.display(), e))?;
+    Ok(nbt)
+}
+
+fn write_nbt(path: &Path, root_name: &str, nbt: &NbtCompound) -> Result<(), String> {
+    let file = File::create(path).map_err(|e| format!("Can't create {}: {}", path.display(), e))?;
+    io::write_nbt(&mut BufWriter::new(file), Some(root_name), nbt, Flavor::GzCompressed)
+        .map_err(|e| format!("Can't write {}: {}", path.display(), e))
+}
+
+/// Reads a schematic, in the format its extension tells.
+pub fn load(path: &Path) -> Result<Schematic, String> {
+    let nbt = read_nbt(path)?;
+    let schematic = match path.extension().and_then(|e| e.to_str()) {
+        Some("schem") => sponge::read(&nbt),
+        Some("schematic") => legacy::read(&nbt),
+        Some("litematic") => litematic::read(&nbt),
+        _ => return Err(format!("{} isn't a .schem, .schematic or .litematic file", path.display())),
+    };
+
+    schematic.map_err(|e| format!("{} is broken: {}", path.display(), e))
+}
+
+pub fn save(schematic: &Schematic, path: &Path, format: Format) -> Result<(), String> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).map_err(|e| format!("Can't create {}: {}", parent.display(), e))?;
+    }
+
+    match format {
+        Format::SpongeV2 => write_nbt(path, "Schematic", &sponge::write_v2(schematic)),
+        Format::SpongeV3 => write_nbt(path, "", &sponge::write_v3(schematic)),
+        Format::Litematic => write_nbt(path, "", &litematic::write(schematic)),
+    }
+}
+
+/// The file a schematic name given to a command stands for. Names without an
+/// extension are looked up as .schem first, then as .litematic and
+/// .schematic.
+pub fn path(name: &str) -> Result<PathBuf, String> {
+    let valid = name.chars().all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c));
+    if !valid || name.starts_with('.') {
+        return Err(format!("{} is not a valid file name", name));
+    }
+
+    let path = Path::new(DIRECTORY).join(name);
+    if path.extension().is_some() {
+        return Ok(path);
+    }
+
+    let found = ["schem", "litematic", "schematic"].iter()
+        .map(|e| path.with_extension(e))
+        .find(|p| p.exists());
+    Ok(found.unwrap_or_else(|| path.with_extension("schem")))
+}
+
+/// The names of the structure files in [`DIRECTORY`].
+pub fn list() -> Vec<String> {
+    let Ok(entries) = std::fs::read_dir(DIRECTORY) else { return Vec::new() };
+    let mut names: Vec<_> = entries
+        .filter_map(|e| e.ok()?.file_name().into_string().ok())
+        .filter(|n| [".schem", ".schematic", ".litematic"].iter().any(|e| n.ends_with(e)))
+        .collect();
+    names.sort();
+    names
+}
+
+/// Pastes a schematic into the world file at `world_path` while the server
+/// isn't running, returning how many blocks changed. New chunks are
+/// generated as plots if `plots` is set.
+pub fn paste_offline(world_path: &str, plots: Option<Layout>, schematic_path: &Path, pos: BlockPos) -> Result<usize, String> {
+    let schematic = load(schematic_path)?;
+    let mut world = if Path::new(world_path).exists() { World::load(world_path)? } else { World::new() };
+    world.plots = plots;
+
+    let blocks = schematic.to_clipboard().blocks.into_iter()
+        .map(|(p, snapshot)| (BlockPos::new(pos.x + p.x, pos.y + p.y, pos.z + p.z), snapshot));
+    let count = edit::apply(&mut world, blocks).len();
+    world.save(world_path)?;
+    Ok(count)
+}
@@ -0,0 +1,295 @@
+//! Sponge schematics (.schem), versions 1 to 3.
+
+use quartz_nbt::{NbtCompound, NbtList, NbtReprError};
+use super::{Metadata, Schematic};
+use super::super::edit::MAX_VOLUME;
+use super::super::world::block::Block;
+use super::super::world::storage::{self, DATA_VERSION};
+use super::super::world::{state, BlockPos};
+
+fn err(e: NbtReprError) -> String {
+    e.to_string()
+}
+
+fn read_varints(data: &[i8]) -> Result<Vec<u32>, String> {
+    let mut values = Vec::new();
+    let (mut value, mut shift) = (0_u32, 0);
+    for byte in data {
+        let byte = *byte as u8;
+        value |= ((byte & 0x7f) as u32) << shift;
+        if byte & 0x80 == 0 {
+            values.push(value);
+            (value, shift) = (0, 0);
+        } else {
+            shift += 7;
+            if shift > 28 {
+                return Err("block data has a varint that is too long".to_string());
+            }
+        }
+    }
+
+    Ok(values)
+}
+
+fn write_varint(data: &mut Vec<i8>, mut value: u32) {
+    loop {
+        if value & !0x7f == 0 {
+            data.push(value as i8);
+            return;
+        }
+
+        data.push((value as u8 | 0x80) as i8);
+        value >>= 7;
+    }
+}
+
+pub fn read(root: &NbtCompound) -> Result<Schematic, String> {
+    // version 3 nests everything in a compound of its own
+    let nbt = root.get::<_, &NbtCompound>("Schematic").unwrap_or(root);
+    let version: i32 = nbt.get("Version").map_err(err)?;
+    if !(1..=3).contains(&version) {
+        return Err(format!("version {} isn't supported", version));
+    }
+
+    let dimension = |name| nbt.get::<_, i16>(name).map(|d| d as u16 as i32).map_err(err);
+    let size = (dimension("Width")?, dimension("Height")?, dimension("Length")?);
+    if size.0 as u64 * size.1 as u64 * size.2 as u64 > MAX_VOLUME {
+        return Err(format!("{}x{}x{} blocks is more than {} blocks", size.0, size.1, size.2, MAX_VOLUME));
+    }
+
+    // WorldEdit keeps where the copy was made from in its own metadata
+    let metadata = nbt.get::<_, &NbtCompound>("Metadata").ok();
+    let we_offset = metadata.and_then(|m| Some(BlockPos::new(
+        m.get("WEOffsetX").ok()?,
+        m.get("WEOffsetY").ok()?,
+        m.get("WEOffsetZ").ok()?,
+    )));
+    let offset = match (we_offset, nbt.get::<_, &[i32]>("Offset")) {
+        (Some(offset), _) => offset,
+        (None, Ok([x, y, z])) => BlockPos::new(*x, *y, *z),
+        _ => BlockPos::new(0, 0, 0),
+    };
+
+    let (palette, data, entities) = if version == 3 {
+        let blocks: &NbtCompound = nbt.get("Blocks").map_err(err)?;
+        (blocks.get("Palette").map_err(err)?, blocks.get("Data").map_err(err)?, blocks.get("BlockEntities").ok())
+    } else {
+        let entities = nbt.get("BlockEntities").or_else(|_| nbt.get("TileEntities")).ok();
+        (nbt.get("Palette").map_err(err)?, nbt.get("BlockData").map_err(err)?, entities)
+    };
+    let (palette, data, entities): (&NbtCompound, &[i8], Option<&NbtList>) = (palette, data, entities);
+
+    // every index has a name, so none can be past the end of the palette
+    let mut blocks_by_index = vec![Block::Air; palette.len()];
+    let mut unknown = Vec::new();
+    for (name, index) in palette.inner() {
+        let index = i32::try_from(index).map_err(|e| e.to_string())?;
+        let Some(index) = usize::try_from(index).ok().filter(|i| *i < palette.len()) else {
+            return Err(format!("the palette has {} at index {}, which is out of range", name, index));
+        };
+
+        match state::parse(name) {
+            Some(id) => blocks_by_index[index] = Block::from_id(id),
+            None => unknown.push(name.as_str()),
+        }
+    }
+
+    if !unknown.is_empty() {
+        warn!("blocks of unknown kinds are left out: {}", unknown.join(", "));
+    }
+
+    let indices = read_varints(data)?;
+    if indices.len() != (size.0 * size.1 * size.2) as usize {
+        return Err(format!("block data has {} blocks instead of {}", indices.len(), size.0 * size.1 * size.2));
+    }
+
+    let blocks = indices.iter()
+        .map(|i| blocks_by_index.get(*i as usize).copied().ok_or_else(|| format!("block data uses {}, which isn't in the palette", i)))
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut schematic = Schematic::new(size, offset);
+    schematic.blocks = blocks;
+    if let Some(metadata) = metadata {
+        let text = |name| metadata.get::<_, &str>(name).unwrap_or_default().to_string();
+        schematic.metadata = Metadata { name: text("Name"), author: text("Author"), description: String::new() };
+    }
+
+    for entity in entities.iter().flat_map(|l| l.iter_map::<&NbtCompound>()) {
+        let entity = entity.map_err(|e| e.to_string())?;
+        let pos = match entity.get::<_, &[i32]>("Pos").map_err(err)? {
+            [x, y, z] => BlockPos::new(*x, *y, *z),
+            _ => return Err("a block entity has no position".to_string()),
+        };
+        if !schematic.contains(pos) {
+            continue;
+        }
+
+        let data = if version == 3 { entity.get("Data").unwrap_or(entity) } else { entity };
+        let block = schematic.blocks[schematic.index(pos.x, pos.y, pos.z)];
+        if let Some(entity) = storage::block_entity_from_nbt(block, data) {
+            schematic.block_entities.push((pos, entity));
+        }
+    }
+
+    Ok(schematic)
+}
+
+/// The palette and block data of a schematic.
+fn encode_blocks(schematic: &Schematic) -> (NbtCompound, Vec<i8>) {
+    let mut palette = NbtCompound::new();
+    let mut data = Vec::new();
+    let mut unknown = 0;
+
+    for block in &schematic.blocks {
+        let name = state::name(block.get_id()).unwrap_or_else(|| {
+            unknown += 1;
+            "minecraft:air".to_string()
+        });
+
+        let next = palette.len() as i32;
+        let index = match palette.get::<_, i32>(&name) {
+            Ok(index) => index,
+            Err(_) => {
+                palette.insert(name, next);
+                next
+            },
+        };
+        write_varint(&mut data, index as u32);
+    }
+
+    if unknown > 0 {
+        warn!("{} blocks of unknown kinds were saved as air", unknown);
+    }
+
+    (palette, data)
+}
+
+/// The block entities of a schematic, with their data where `data` says.
+fn encode_block_entities(schematic: &Schematic, nested: bool) -> NbtList {
+    let mut list = NbtList::new();
+    for (pos, entity) in &schematic.block_entities {
+        let block = schematic.blocks[schematic.index(pos.x, pos.y, pos.z)];
+        let Some((id, data)) = storage::block_entity_to_nbt(block, entity) else { continue };
+
+        let mut nbt = if nested { NbtCompound::new() } else { data.clone() };
+        if nested {
+            nbt.insert("Data", data);
+        }
+        nbt.insert("Id", id);
+        nbt.insert("Pos", vec![pos.x, pos.y, pos.z]);
+        list.push(nbt);
+    }
+
+    list
+}
+
+/// The fields both versions share.
+fn write_common(schematic: &Schematic, version: i32) -> NbtCompound {
+    let mut nbt = NbtCompound::new();
+    nbt.insert("Version", version);
+    nbt.insert("DataVersion", DATA_VERSION);
+    nbt.insert("Width", schematic.size.0 as i16);
+    nbt.insert("Height", schematic.size.1 as i16);
+    nbt.insert("Length", schematic.size.2 as i16);
+
+    let offset = schematic.offset;
+    nbt.insert("Offset", vec![offset.x, offset.y, offset.z]);
+    let mut metadata = NbtCompound::new();
+    metadata.insert("WEOffsetX", offset.x);
+    metadata.insert("WEOffsetY", offset.y);
+    metadata.insert("WEOffsetZ", offset.z);
+    metadata.insert("Name", schematic.metadata.name.as_str());
+    metadata.insert("Author", schematic.metadata.author.as_str());
+    nbt.insert("Metadata", metadata);
+    nbt
+}
+
+pub fn write_v2(schematic: &Schematic) -> NbtCompound {
+    let mut nbt = write_common(schematic, 2);
+    let (palette, data) = encode_blocks(schematic);
+    nbt.insert("PaletteMax", palette.len() as i32);
+    nbt.insert("Palette", palette);
+    nbt.insert("BlockData", data);
+    nbt.insert("BlockEntities", encode_block_entities(schematic, false));
+    nbt
+}
+
+pub fn write_v3(schematic: &Schematic) -> NbtCompound {
+    let mut nbt = write_common(schematic, 3);
+    let (palette, data) = encode_blocks(schematic);
+    let mut blocks = NbtCompound::new();
+    blocks.insert("Palette", palette);
+    blocks.insert("Data", data);
+    blocks.insert("BlockEntities", encode_block_entities(schematic, true));
+    nbt.insert("Blocks", blocks);
+
+    let mut root = NbtCompound::new();
+    root.insert("Schematic", nbt);
+    root
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> Schematic {
+        let mut schematic = Schematic::new((3, 2, 2), BlockPos::new(-1, 0, 2));
+        schematic.blocks[1] = Block::RedstoneLamp { lit: true };
+        schematic.blocks[5] = Block::from_id(state::parse("minecraft:stone").unwrap());
+        schematic.blocks[11] = Block::from_id(state::parse("minecraft:repeater[delay=3,facing=east]").unwrap());
+        schematic.metadata.name = "lamp".to_string();
+        schematic.metadata.author = "Alice".to_string();
+        schematic
+    }
+
+    fn assert_same(read: &Schematic, written: &Schematic) {
+        assert_eq!(read.size, written.size);
+        assert_eq!(read.offset, written.offset);
+        assert_eq!(read.blocks, written.blocks);
+        assert_eq!(read.metadata.name, written.metadata.name);
+        assert_eq!(read.metadata.author, written.metadata.author);
+    }
+
+    #[test]
+    fn v2_round_trips() {
+        let schematic = sample();
+        assert_same(&read(&write_v2(&schematic)).unwrap(), &schematic);
+    }
+
+    #[test]
+    fn v3_round_trips() {
+        let schematic = sample();
+        assert_same(&read(&write_v3(&schematic)).unwrap(), &schematic);
+    }
+
+    #[test]
+    fn varints_round_trip() {
+        let mut data = Vec::new();
+        for value in [0, 127, 128, 300, u32::MAX >> 4] {
+            write_varint(&mut data, value);
+        }
+        assert_eq!(read_varints(&data).unwrap(), [0, 127, 128, 300, u32::MAX >> 4]);
+    }
+
+    #[test]
+    fn negative_palette_indices_are_refused() {
+        let mut nbt = write_v2(&sample());
+        nbt.get_mut::<_, &mut NbtCompound>("Palette").unwrap().insert("minecraft:glass", -1);
+        assert!(read(&nbt).is_err());
+    }
+
+    #[test]
+    fn palette_indices_past_the_palette_are_refused() {
+        let mut nbt = write_v2(&sample());
+        nbt.get_mut::<_, &mut NbtCompound>("Palette").unwrap().insert("minecraft:glass", i32::MAX);
+        assert!(read(&nbt).is_err());
+    }
+
+    #[test]
+    fn oversized_schematics_are_refused() {
+        let mut nbt = write_v2(&sample());
+        for name in ["Width", "Height", "Length"] {
+            nbt.insert(name, -1_i16);
+        }
+        assert!(read(&nbt).unwrap_err().contains("65535x65535x65535"));
+    }
+}
@@ -0,0 +1,1044 @@
+use std::ops::RangeInclusive;
+
+/// Full, opaque blocks that conduct redstone power (1.18.2 block state ids).
+const SOLID_BLOCKS: &[RangeInclusive<u32>] = &[
+    1..=7,          // stone, granite, diorite, andesite
+    10..=11,        // dirt, coarse dirt
+    14..=20,        // cobblestone, planks
+    33..=33,        // bedrock
+    278..=280,      // sandstone
+    1440..=1455,    // wool
+    1483..=1485,    // gold block, iron block, bricks
+    1488..=1490,    // bookshelf, mossy cobblestone, obsidian
+    3412..=3412,    // diamond block
+    4564..=4567,    // stone bricks
+    5609..=5609,    // emerald block
+    6944..=6945,    // quartz block
+    7065..=7080,    // terracotta
+    8132..=8132,    // terracotta
+    9688..=9703,    // concrete
+];
+
+/// Full blocks that support redstone components but don't conduct power.
+const TRANSPARENT_BLOCKS: &[RangeInclusive<u32>] = &[
+    262..=262,      // glass
+    4164..=4179,    // stained glass
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Direction {
+    Down,
+    Up,
+    North,
+    South,
+    West,
+    East,
+}
+
+impl Direction {
+    /// All directions in the order vanilla iterates them.
+    pub const ALL: [Self; 6] = [Self::Down, Self::Up, Self::North, Self::South, Self::West, Self::East];
+    pub const HORIZONTAL: [Self; 4] = [Self::North, Self::East, Self::South, Self::West];
+
+    /// Decodes the face id used by the protocol.
+    pub fn from_id(id: i32) -> Option<Self> {
+        Self::ALL.get(id as usize).copied()
+    }
+
+    /// Decodes a look angle into the direction closest to it.
+    pub fn from_look(yaw: f32, pitch: f32) -> Self {
+        let (y, horizontal) = pitch.to_radians().sin_cos();
+        let (x, z) = yaw.to_radians().sin_cos();
+
+        if y.abs() <= x.abs().max(z.abs()) * horizontal {
+            Self::from_yaw(yaw)
+        } else if pitch < 0.0 {
+            Self::Up
+        } else {
+            Self::Down
+        }
+    }
+
+    /// Decodes a yaw angle into the horizontal direction it faces.
+    pub fn from_yaw(yaw: f32) -> Self {
+        match (yaw / 90.0 + 0.5).floor() as i32 & 3 {
+            0 => Self::South,
+            1 => Self::West,
+            2 => Self::North,
+            _ => Self::East,
+        }
+    }
+
+    pub fn offset(self) -> (i32, i32, i32) {
+        match self {
+            Self::Down => (0, -1, 0),
+            Self::Up => (0, 1, 0),
+            Self::North => (0, 0, -1),
+            Self::South => (0, 0, 1),
+            Self::West => (-1, 0, 0),
+            Self::East => (1, 0, 0),
+        }
+    }
+
+    pub fn opposite(self) -> Self {
+        match self {
+            Self::Down => Self::Up,
+            Self::Up => Self::Down,
+            Self::North => Self::South,
+            Self::South => Self::North,
+            Self::West => Self::East,
+            Self::East => Self::West,
+        }
+    }
+
+    pub fn clockwise(self) -> Self {
+        match self {
+            Self::North => Self::East,
+            Self::East => Self::South,
+            Self::South => Self::West,
+            Self::West => Self::North,
+            d => d,
+        }
+    }
+
+    pub fn counter_clockwise(self) -> Self {
+        self.clockwise().opposite()
+    }
+
+    /// Turns the direction clockwise, seen from above, by quarter turns.
+    pub fn rotate(self, turns: u8) -> Self {
+        (0..turns % 4).fold(self, |d, _| d.clockwise())
+    }
+
+    /// Mirrors the direction along `axis`.
+    pub fn flip(self, axis: Axis) -> Self {
+        match (axis, self) {
+            (Axis::X, Self::West | Self::East) => self.opposite(),
+            (Axis::Y, Self::Down | Self::Up) => self.opposite(),
+            (Axis::Z, Self::North | Self::South) => self.opposite(),
+            _ => self,
+        }
+    }
+
+    pub fn axis(self) -> Axis {
+        match self {
+            Self::West | Self::East => Axis::X,
+            Self::Down | Self::Up => Axis::Y,
+            Self::North | Self::South => Axis::Z,
+        }
+    }
+
+    /// Index in the 4-valued horizontal `facing` property.
+    fn horizontal_id(self) -> u32 {
+        match self {
+            Self::North => 0,
+            Self::South => 1,
+            Self::West => 2,
+            _ => 3,
+        }
+    }
+
+    fn from_horizontal_id(id: u32) -> Self {
+        [Self::North, Self::South, Self::West, Self::East][id as usize]
+    }
+
+    /// Index in the 6-valued `facing` property.
+    fn facing_id(self) -> u32 {
+        match self {
+            Self::North => 0,
+            Self::East => 1,
+            Self::South => 2,
+            Self::West => 3,
+            Self::Up => 4,
+            Self::Down => 5,
+        }
+    }
+
+    fn from_facing_id(id: u32) -> Self {
+        [Self::North, Self::East, Self::South, Self::West, Self::Up, Self::Down][id as usize]
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Axis {
+    X,
+    Y,
+    Z,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WireSide {
+    Up,
+    Side,
+    None,
+}
+
+impl WireSide {
+    pub fn is_connected(self) -> bool {
+        self != Self::None
+    }
+
+    fn id(self) -> u32 {
+        self as u32
+    }
+
+    fn from_id(id: u32) -> Self {
+        [Self::Up, Self::Side, Self::None][id as usize]
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RedstoneWire {
+    pub north: WireSide,
+    pub south: WireSide,
+    pub east: WireSide,
+    pub west: WireSide,
+    pub power: u8,
+}
+
+impl RedstoneWire {
+    pub fn side(&self, dir: Direction) -> WireSide {
+        match dir {
+            Direction::North => self.north,
+            Direction::South => self.south,
+            Direction::East => self.east,
+            Direction::West => self.west,
+            _ => WireSide::None,
+        }
+    }
+
+    pub fn set_side(&mut self, dir: Direction, side: WireSide) {
+        match dir {
+            Direction::North => self.north = side,
+            Direction::South => self.south = side,
+            Direction::East => self.east = side,
+            Direction::West => self.west = side,
+            _ => {},
+        }
+    }
+
+    pub fn is_dot(&self) -> bool {
+        Direction::HORIZONTAL.iter().all(|d| !self.side(*d).is_connected())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AttachFace {
+    Floor,
+    Wall,
+    Ceiling,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ButtonKind {
+    Stone,
+    Oak,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ComparatorMode {
+    Compare,
+    Subtract,
+}
+
+/// Wooden doors and trapdoors can be opened by hand, iron ones only by power.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DoorKind {
+    Oak,
+    Iron,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Half {
+    Upper,
+    Lower,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Hinge {
+    Left,
+    Right,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Instrument {
+    Harp,
+    BaseDrum,
+    Snare,
+    Hat,
+    Bass,
+    Flute,
+    Bell,
+    Guitar,
+    Chime,
+    Xylophone,
+    IronXylophone,
+    CowBell,
+    Didgeridoo,
+    Bit,
+    Banjo,
+    Pling,
+}
+
+impl Instrument {
+    const ALL: [Self; 16] = [
+        Self::Harp, Self::BaseDrum, Self::Snare, Self::Hat, Self::Bass, Self::Flute, Self::Bell, Self::Guitar,
+        Self::Chime, Self::Xylophone, Self::IronXylophone, Self::CowBell, Self::Didgeridoo, Self::Bit,
+        Self::Banjo, Self::Pling,
+    ];
+
+    /// The instrument a note block plays on top of `block`.
+    pub fn below(block: Block) -> Self {
+        match block.get_id() {
+            1..=7 | 14 | 33 | 278..=280 | 1485 | 1489..=1490 | 4564..=4567 | 6944..=6945
+                | 7065..=7080 | 8132 | 9688..=9703 | 266..=277 | 7053..=7064 | 9510..=9521 => Self::BaseDrum,
+            15..=20 | 1488 | 2090..=2113 | 15041..=15052 => Self::Bass,
+            262 | 4164..=4179 => Self::Hat,
+            1440..=1455 => Self::Guitar,
+            1483 => Self::Bell,
+            1484 => Self::IronXylophone,
+            5609 => Self::Bit,
+            _ => Self::Harp,
+        }
+    }
+}
+
+/// Plates that press for any mob, or for any entity at all.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlateKind {
+    Stone,
+    Oak,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChestType {
+    Single,
+    Left,
+    Right,
+}
+
+impl ChestType {
+    pub fn opposite(self) -> Self {
+        match self {
+            Self::Single => Self::Single,
+            Self::Left => Self::Right,
+            Self::Right => Self::Left,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tripwire {
+    pub attached: bool,
+    pub disarmed: bool,
+    pub powered: bool,
+    pub north: bool,
+    pub south: bool,
+    pub east: bool,
+    pub west: bool,
+}
+
+impl Tripwire {
+    pub fn side(&self, dir: Direction) -> bool {
+        match dir {
+            Direction::North => self.north,
+            Direction::South => self.south,
+            Direction::East => self.east,
+            Direction::West => self.west,
+            _ => false,
+        }
+    }
+
+    pub fn set_side(&mut self, dir: Direction, connected: bool) {
+        match dir {
+            Direction::North => self.north = connected,
+            Direction::South => self.south = connected,
+            Direction::East => self.east = connected,
+            Direction::West => self.west = connected,
+            _ => {},
+        }
+    }
+}
+
+/// What happens to a block when a piston pushes it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PushReaction {
+    Normal,
+    Destroy,
+    Block,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[allow(clippy::enum_variant_names)]
+pub enum Block {
+    Air,
+    Solid(u32),
+    Transparent(u32),
+    RedstoneWire(RedstoneWire),
+    RedstoneTorch { lit: bool },
+    RedstoneWallTorch { facing: Direction, lit: bool },
+    Lever { face: AttachFace, facing: Direction, powered: bool },
+    Button { kind: ButtonKind, face: AttachFace, facing: Direction, powered: bool },
+    Repeater { delay: u8, facing: Direction, locked: bool, powered: bool },
+    Comparator { facing: Direction, mode: ComparatorMode, powered: bool },
+    RedstoneBlock,
+    NoteBlock { instrument: Instrument, note: u8, powered: bool },
+    DaylightDetector { inverted: bool, power: u8 },
+    Door { kind: DoorKind, facing: Direction, half: Half, hinge: Hinge, open: bool, powered: bool },
+    Trapdoor { kind: DoorKind, facing: Direction, half: Half, open: bool, powered: bool },
+    Piston { sticky: bool, facing: Direction, extended: bool },
+    PistonHead { sticky: bool, facing: Direction, short: bool },
+    /// Placeholder for a block being moved by a piston, see
+    /// [`super::MovingBlock`].
+    MovingPiston { sticky: bool, facing: Direction },
+    SlimeBlock,
+    HoneyBlock,
+    Observer { facing: Direction, powered: bool },
+    RedstoneLamp { lit: bool },
+    Tnt,
+    Target { power: u8 },
+    PressurePlate { kind: PlateKind, powered: bool },
+    WeightedPressurePlate { heavy: bool, power: u8 },
+    TripwireHook { facing: Direction, attached: bool, powered: bool },
+    Tripwire(Tripwire),
+    Hopper { facing: Direction, enabled: bool },
+    Dispenser { facing: Direction, triggered: bool },
+    Dropper { facing: Direction, triggered: bool },
+    Chest { facing: Direction, kind: ChestType },
+    Barrel { facing: Direction, open: bool },
+    Other(u32),
+}
+
+/// Boolean properties enumerate `true` before `false`.
+fn b(v: bool) -> u32 {
+    !v as u32
+}
+
+fn from_b(id: u32) -> bool {
+    id == 0
+}
+
+fn face_id(face: AttachFace) -> u32 {
+    face as u32
+}
+
+fn face_from_id(id: u32) -> AttachFace {
+    [AttachFace::Floor, AttachFace::Wall, AttachFace::Ceiling][id as usize]
+}
+
+fn flip_face(face: AttachFace) -> AttachFace {
+    match face {
+        AttachFace::Floor => AttachFace::Ceiling,
+        AttachFace::Ceiling => AttachFace::Floor,
+        AttachFace::Wall => AttachFace::Wall,
+    }
+}
+
+/// Values of the `facing` property of hoppers, which can't face up.
+const HOPPER_FACING: [Direction; 5] = [Direction::Down, Direction::North, Direction::South, Direction::West, Direction::East];
+
+impl Block {
+    /// The block with `f` applied to every direction it has, including the
+    /// sides dust and tripwire connect to.
+    fn map_directions(self, f: impl Fn(Direction) -> Direction) -> Self {
+        match self {
+            Self::RedstoneWire(w) => {
+                let mut wire = w;
+                for d in Direction::HORIZONTAL {
+                    wire.set_side(f(d), w.side(d));
+                }
+                Self::RedstoneWire(wire)
+            },
+            Self::Tripwire(t) => {
+                let mut wire = t;
+                for d in Direction::HORIZONTAL {
+                    wire.set_side(f(d), t.side(d));
+                }
+                Self::Tripwire(wire)
+            },
+            Self::RedstoneWallTorch { facing, lit } => Self::RedstoneWallTorch { facing: f(facing), lit },
+            Self::Lever { face, facing, powered } => Self::Lever { face, facing: f(facing), powered },
+            Self::Button { kind, face, facing, powered } => Self::Button { kind, face, facing: f(facing), powered },
+            Self::Repeater { delay, facing, locked, powered } => Self::Repeater { delay, facing: f(facing), locked, powered },
+            Self::Comparator { facing, mode, powered } => Self::Comparator { facing: f(facing), mode, powered },
+            Self::Door { kind, facing, half, hinge, open, powered } => {
+                Self::Door { kind, facing: f(facing), half, hinge, open, powered }
+            },
+            Self::Trapdoor { kind, facing, half, open, powered } => Self::Trapdoor { kind, facing: f(facing), half, open, powered },
+            Self::Piston { sticky, facing, extended } => Self::Piston { sticky, facing: f(facing), extended },
+            Self::PistonHead { sticky, facing, short } => Self::PistonHead { sticky, facing: f(facing), short },
+            Self::MovingPiston { sticky, facing } => Self::MovingPiston { sticky, facing: f(facing) },
+            Self::Observer { facing, powered } => Self::Observer { facing: f(facing), powered },
+            Self::TripwireHook { facing, attached, powered } => Self::TripwireHook { facing: f(facing), attached, powered },
+            Self::Hopper { facing, enabled } => Self::Hopper { facing: f(facing), enabled },
+            Self::Dispenser { facing, triggered } => Self::Dispenser { facing: f(facing), triggered },
+            Self::Dropper { facing, triggered } => Self::Dropper { facing: f(facing), triggered },
+            Self::Chest { facing, kind } => Self::Chest { facing: f(facing), kind },
+            Self::Barrel { facing, open } => Self::Barrel { facing: f(facing), open },
+            block => block,
+        }
+    }
+
+    /// The block turned clockwise, seen from above, by quarter turns.
+    pub fn rotate(self, turns: u8) -> Self {
+        self.map_directions(|d| d.rotate(turns))
+    }
+
+    /// The block mirrored along `axis`.
+    pub fn flip(self, axis: Axis) -> Self {
+        let block = self.map_directions(|d| d.flip(axis));
+        let flip_half = |half| match half {
+            Half::Upper => Half::Lower,
+            Half::Lower => Half::Upper,
+        };
+
+        match block {
+            Self::Door { kind, facing, half, hinge, open, powered } if axis != Axis::Y => {
+                let hinge = match hinge {
+                    Hinge::Left => Hinge::Right,
+                    Hinge::Right => Hinge::Left,
+                };
+                Self::Door { kind, facing, half, hinge, open, powered }
+            },
+            Self::Door { kind, facing, half, hinge, open, powered } => {
+                Self::Door { kind, facing, half: flip_half(half), hinge, open, powered }
+            },
+            Self::Trapdoor { kind, facing, half, open, powered } if axis == Axis::Y => {
+                Self::Trapdoor { kind, facing, half: flip_half(half), open, powered }
+            },
+            Self::Chest { facing, kind } if axis != Axis::Y => Self::Chest { facing, kind: kind.opposite() },
+            Self::Lever { face, facing, powered } if axis == Axis::Y => Self::Lever { face: flip_face(face), facing, powered },
+            Self::Button { kind, face, facing, powered } if axis == Axis::Y => {
+                Self::Button { kind, face: flip_face(face), facing, powered }
+            },
+            block => block,
+        }
+    }
+
+    pub fn from_id(id: u32) -> Self {
+        match id {
+            0 => Self::Air,
+            2114..=3409 => {
+                let i = id - 2114;
+                Self::RedstoneWire(RedstoneWire {
+                    east: WireSide::from_id(i / 432),
+                    north: WireSide::from_id(i / 144 % 3),
+                    power: (i / 9 % 16) as u8,
+                    south: WireSide::from_id(i / 3 % 3),
+                    west: WireSide::from_id(i % 3),
+                })
+            },
+            3850..=3873 => {
+                let i = id - 3850;
+                Self::Lever {
+                    face: face_from_id(i / 8),
+                    facing: Direction::from_horizontal_id(i / 2 % 4),
+                    powered: from_b(i % 2),
+                }
+            },
+            3956..=3957 => Self::RedstoneTorch { lit: id == 3956 },
+            3958..=3965 => {
+                let i = id - 3958;
+                Self::RedstoneWallTorch {
+                    facing: Direction::from_horizontal_id(i / 2),
+                    lit: from_b(i % 2),
+                }
+            },
+            3966..=3989 | 6552..=6575 => {
+                let (kind, i) = if id < 6552 {
+                    (ButtonKind::Stone, id - 3966)
+                } else {
+                    (ButtonKind::Oak, id - 6552)
+                };
+                Self::Button {
+                    kind,
+                    face: face_from_id(i / 8),
+                    facing: Direction::from_horizontal_id(i / 2 % 4),
+                    powered: from_b(i % 2),
+                }
+            },
+            4100..=4163 => {
+                let i = id - 4100;
+                Self::Repeater {
+                    delay: (i / 16) as u8 + 1,
+                    facing: Direction::from_horizontal_id(i / 4 % 4),
+                    locked: from_b(i / 2 % 2),
+                    powered: from_b(i % 2),
+                }
+            },
+            6884..=6899 => {
+                let i = id - 6884;
+                Self::Comparator {
+                    facing: Direction::from_horizontal_id(i / 4),
+                    mode: if from_b(i / 2 % 2) { ComparatorMode::Compare } else { ComparatorMode::Subtract },
+                    powered: from_b(i % 2),
+                }
+            },
+            6932 => Self::RedstoneBlock,
+            281..=1080 => {
+                let i = id - 281;
+                Self::NoteBlock {
+                    instrument: Instrument::ALL[(i / 50) as usize],
+                    note: (i / 2 % 25) as u8,
+                    powered: from_b(i % 2),
+                }
+            },
+            6900..=6931 => {
+                let i = id - 6900;
+                Self::DaylightDetector {
+                    inverted: from_b(i / 16),
+                    power: (i % 16) as u8,
+                }
+            },
+            3630..=3693 | 3876..=3939 => {
+                let (kind, i) = if id < 3876 {
+                    (DoorKind::Oak, id - 3630)
+                } else {
+                    (DoorKind::Iron, id - 3876)
+                };
+                Self::Door {
+                    kind,
+                    facing: Direction::from_horizontal_id(i / 16),
+                    half: if from_b(i / 8 % 2) { Half::Upper } else { Half::Lower },
+                    hinge: if i / 4 % 2 == 0 { Hinge::Left } else { Hinge::Right },
+                    open: from_b(i / 2 % 2),
+                    powered: from_b(i % 2),
+                }
+            },
+            // waterlogged trapdoors are taken as dry ones
+            4180..=4243 | 7787..=7850 => {
+                let (kind, i) = if id < 7787 {
+                    (DoorKind::Oak, id - 4180)
+                } else {
+                    (DoorKind::Iron, id - 7787)
+                };
+                Self::Trapdoor {
+                    kind,
+                    facing: Direction::from_horizontal_id(i / 16),
+                    half: if from_b(i / 8 % 2) { Half::Upper } else { Half::Lower },
+                    open: from_b(i / 4 % 2),
+                    powered: from_b(i / 2 % 2),
+                }
+            },
+            1385..=1396 | 1404..=1415 => {
+                let (sticky, i) = if id < 1404 { (true, id - 1385) } else { (false, id - 1404) };
+                Self::Piston {
+                    sticky,
+                    facing: Direction::from_facing_id(i % 6),
+                    extended: from_b(i / 6),
+                }
+            },
+            1416..=1439 => {
+                let i = id - 1416;
+                Self::PistonHead {
+                    sticky: i % 2 == 1,
+                    facing: Direction::from_facing_id(i / 4),
+                    short: from_b(i / 2 % 2),
+                }
+            },
+            1456..=1467 => {
+                let i = id - 1456;
+                Self::MovingPiston {
+                    sticky: i % 2 == 1,
+                    facing: Direction::from_facing_id(i / 2),
+                }
+            },
+            7753 => Self::SlimeBlock,
+            16078 => Self::HoneyBlock,
+            9510..=9521 => {
+                let i = id - 9510;
+                Self::Observer {
+                    facing: Direction::from_facing_id(i / 2),
+                    powered: from_b(i % 2),
+                }
+            },
+            5361..=5362 => Self::RedstoneLamp { lit: id == 5361 },
+            1486..=1487 => Self::Tnt,
+            16014..=16029 => Self::Target { power: (id - 16014) as u8 },
+            3874..=3875 => Self::PressurePlate { kind: PlateKind::Stone, powered: id == 3874 },
+            3940..=3941 => Self::PressurePlate { kind: PlateKind::Oak, powered: id == 3940 },
+            6852..=6883 => Self::WeightedPressurePlate { heavy: id >= 6868, power: ((id - 6852) % 16) as u8 },
+            5465..=5480 => {
+                let i = id - 5465;
+                Self::TripwireHook {
+                    facing: Direction::from_horizontal_id(i / 2 % 4),
+                    attached: from_b(i / 8),
+                    powered: from_b(i % 2),
+                }
+            },
+            5481..=5608 => {
+                let i = id - 5481;
+                Self::Tripwire(Tripwire {
+                    attached: from_b(i / 64),
+                    disarmed: from_b(i / 32 % 2),
+                    east: from_b(i / 16 % 2),
+                    north: from_b(i / 8 % 2),
+                    powered: from_b(i / 4 % 2),
+                    south: from_b(i / 2 % 2),
+                    west: from_b(i % 2),
+                })
+            },
+            6934..=6943 => {
+                let i = id - 6934;
+                Self::Hopper {
+                    facing: HOPPER_FACING[(i % 5) as usize],
+                    enabled: from_b(i / 5),
+                }
+            },
+            266..=277 => {
+                let i = id - 266;
+                Self::Dispenser {
+                    facing: Direction::from_facing_id(i / 2),
+                    triggered: from_b(i % 2),
+                }
+            },
+            7053..=7064 => {
+                let i = id - 7053;
+                Self::Dropper {
+                    facing: Direction::from_facing_id(i / 2),
+                    triggered: from_b(i % 2),
+                }
+            },
+            // waterlogged chests are taken as dry ones
+            2090..=2113 => {
+                let i = id - 2090;
+                Self::Chest {
+                    facing: Direction::from_horizontal_id(i / 6),
+                    kind: [ChestType::Single, ChestType::Left, ChestType::Right][(i / 2 % 3) as usize],
+                }
+            },
+            15041..=15052 => {
+                let i = id - 15041;
+                Self::Barrel {
+                    facing: Direction::from_facing_id(i / 2),
+                    open: from_b(i % 2),
+                }
+            },
+            _ if SOLID_BLOCKS.iter().any(|r| r.contains(&id)) => Self::Solid(id),
+            _ if TRANSPARENT_BLOCKS.iter().any(|r| r.contains(&id)) => Self::Transparent(id),
+            _ => Self::Other(id),
+        }
+    }
+
+    pub fn get_id(self) -> u32 {
+        match self {
+            Self::Air => 0,
+            Self::Solid(id) | Self::Transparent(id) | Self::Other(id) => id,
+            Self::RedstoneWire(w) => {
+                2114 + w.east.id() * 432 + w.north.id() * 144 + w.power as u32 * 9
+                    + w.south.id() * 3 + w.west.id()
+            },
+            Self::Lever { face, facing, powered } => {
+                3850 + face_id(face) * 8 + facing.horizontal_id() * 2 + b(powered)
+            },
+            Self::RedstoneTorch { lit } => 3956 + b(lit),
+            Self::RedstoneWallTorch { facing, lit } => 3958 + facing.horizontal_id() * 2 + b(lit),
+            Self::Button { kind, face, facing, powered } => {
+                let base = match kind {
+                    ButtonKind::Stone => 3966,
+                    ButtonKind::Oak => 6552,
+                };
+                base + face_id(face) * 8 + facing.horizontal_id() * 2 + b(powered)
+            },
+            Self::Repeater { delay, facing, locked, powered } => {
+                4100 + (delay as u32 - 1) * 16 + facing.horizontal_id() * 4 + b(locked) * 2 + b(powered)
+            },
+            Self::Comparator { facing, mode, powered } => {
+                6884 + facing.horizontal_id() * 4 + mode as u32 * 2 + b(powered)
+            },
+            Self::RedstoneBlock => 6932,
+            Self::NoteBlock { instrument, note, powered } => {
+                281 + instrument as u32 * 50 + note as u32 * 2 + b(powered)
+            },
+            Self::DaylightDetector { inverted, power } => 6900 + b(inverted) * 16 + power as u32,
+            Self::Door { kind, facing, half, hinge, open, powered } => {
+                let base = match kind {
+                    DoorKind::Oak => 3630,
+                    DoorKind::Iron => 3876,
+                };
+                base + facing.horizontal_id() * 16 + b(half == Half::Upper) * 8 + hinge as u32 * 4
+                    + b(open) * 2 + b(powered)
+            },
+            Self::Trapdoor { kind, facing, half, open, powered } => {
+                let base = match kind {
+                    DoorKind::Oak => 4180,
+                    DoorKind::Iron => 7787,
+                };
+                base + facing.horizontal_id() * 16 + b(half == Half::Upper) * 8 + b(open) * 4 + b(powered) * 2 + 1
+            },
+            Self::Piston { sticky, facing, extended } => {
+                let base = if sticky { 1385 } else { 1404 };
+                base + b(extended) * 6 + facing.facing_id()
+            },
+            Self::PistonHead { sticky, facing, short } => 1416 + facing.facing_id() * 4 + b(short) * 2 + sticky as u32,
+            Self::MovingPiston { sticky, facing } => 1456 + facing.facing_id() * 2 + sticky as u32,
+            Self::SlimeBlock => 7753,
+            Self::HoneyBlock => 16078,
+            Self::Observer { facing, powered } => 9510 + facing.facing_id() * 2 + b(powered),
+            Self::RedstoneLamp { lit } => 5361 + b(lit),
+            Self::Tnt => 1487,
+            Self::Target { power } => 16014 + power as u32,
+            Self::PressurePlate { kind, powered } => {
+                let base = match kind {
+                    PlateKind::Stone => 3874,
+                    PlateKind::Oak => 3940,
+                };
+                base + b(powered)
+            },
+            Self::WeightedPressurePlate { heavy, power } => (if heavy { 6868 } else { 6852 }) + power as u32,
+            Self::TripwireHook { facing, attached, powered } => {
+                5465 + b(attached) * 8 + facing.horizontal_id() * 2 + b(powered)
+            },
+            Self::Tripwire(t) => {
+                5481 + b(t.attached) * 64 + b(t.disarmed) * 32 + b(t.east) * 16 + b(t.north) * 8
+                    + b(t.powered) * 4 + b(t.south) * 2 + b(t.west)
+            },
+            Self::Hopper { facing, enabled } => {
+                6934 + b(enabled) * 5 + HOPPER_FACING.iter().position(|d| *d == facing).unwrap_or(0) as u32
+            },
+            Self::Dispenser { facing, triggered } => 266 + facing.facing_id() * 2 + b(triggered),
+            Self::Dropper { facing, triggered } => 7053 + facing.facing_id() * 2 + b(triggered),
+            Self::Chest { facing, kind } => 2090 + facing.horizontal_id() * 6 + kind as u32 * 2 + 1,
+            Self::Barrel { facing, open } => 15041 + facing.facing_id() * 2 + b(open),
+        }
+    }
+
+    /// Id of the block in the block registry, which block actions refer to.
+    pub fn block_type(self) -> Option<i32> {
+        match self {
+            Self::Piston { sticky: true, .. } => Some(99),
+            Self::Piston { sticky: false, .. } => Some(106),
+            Self::NoteBlock { .. } => Some(80),
+            _ => None,
+        }
+    }
+
+    /// Whether the block conducts redstone power.
+    pub fn is_solid(self) -> bool {
+        matches!(
+            self,
+            Self::Solid(_)
+                | Self::NoteBlock { .. }
+                | Self::SlimeBlock
+                | Self::RedstoneLamp { .. }
+                | Self::Tnt
+                | Self::Target { .. }
+                | Self::Dispenser { .. }
+                | Self::Dropper { .. }
+                | Self::Barrel { .. }
+        )
+    }
+
+    /// Whether redstone components can be attached to the block.
+    pub fn is_sturdy(self) -> bool {
+        matches!(
+            self,
+            Self::Solid(_)
+                | Self::Transparent(_)
+                | Self::RedstoneBlock
+                | Self::NoteBlock { .. }
+                | Self::Piston { extended: false, .. }
+                | Self::SlimeBlock
+                | Self::HoneyBlock
+                | Self::Observer { .. }
+                | Self::RedstoneLamp { .. }
+                | Self::Tnt
+                | Self::Target { .. }
+                | Self::Dispenser { .. }
+                | Self::Dropper { .. }
+                | Self::Barrel { .. }
+        )
+    }
+
+    pub fn push_reaction(self) -> PushReaction {
+        match self {
+            Self::RedstoneWire(_)
+                | Self::RedstoneTorch { .. }
+                | Self::RedstoneWallTorch { .. }
+                | Self::Lever { .. }
+                | Self::Button { .. }
+                | Self::Repeater { .. }
+                | Self::Comparator { .. }
+                | Self::Door { .. }
+                | Self::PressurePlate { .. }
+                | Self::WeightedPressurePlate { .. }
+                | Self::TripwireHook { .. }
+                | Self::Tripwire(_) => PushReaction::Destroy,
+            // bedrock and obsidian
+            Self::Solid(33 | 1490) => PushReaction::Block,
+            // these have block entities
+            Self::DaylightDetector { .. }
+                | Self::Hopper { .. }
+                | Self::Dispenser { .. }
+                | Self::Dropper { .. }
+                | Self::Chest { .. }
+                | Self::Barrel { .. }
+                | Self::Piston { extended: true, .. }
+                | Self::PistonHead { .. }
+                | Self::MovingPiston { .. }
+                | Self::Other(_) => PushReaction::Block,
+            _ => PushReaction::Normal,
+        }
+    }
+
+    /// Whether the block drags along the blocks touching it when it's moved.
+    pub fn is_sticky(self) -> bool {
+        matches!(self, Self::SlimeBlock | Self::HoneyBlock)
+    }
+
+    pub fn is_replaceable(self) -> bool {
+        matches!(self, Self::Air)
+    }
+
+    pub fn is_signal_source(self) -> bool {
+        matches!(
+            self,
+            Self::RedstoneWire(_)
+                | Self::RedstoneTorch { .. }
+                | Self::RedstoneWallTorch { .. }
+                | Self::Lever { .. }
+                | Self::Button { .. }
+                | Self::Repeater { .. }
+                | Self::Comparator { .. }
+                | Self::RedstoneBlock
+                | Self::DaylightDetector { .. }
+                | Self::Observer { .. }
+                | Self::Target { .. }
+                | Self::PressurePlate { .. }
+                | Self::WeightedPressurePlate { .. }
+                | Self::TripwireHook { .. }
+        )
+    }
+
+    pub fn is_diode(self) -> bool {
+        matches!(self, Self::Repeater { .. } | Self::Comparator { .. })
+    }
+
+    pub fn has_block_entity(self) -> bool {
+        matches!(
+            self,
+            Self::Comparator { .. }
+                | Self::MovingPiston { .. }
+                | Self::DaylightDetector { .. }
+                | Self::Hopper { .. }
+                | Self::Dispenser { .. }
+                | Self::Dropper { .. }
+                | Self::Chest { .. }
+                | Self::Barrel { .. }
+        )
+    }
+
+    /// Whether comparators read the block instead of its power.
+    pub fn has_analog_output(self) -> bool {
+        matches!(
+            self,
+            Self::Hopper { .. } | Self::Dispenser { .. } | Self::Dropper { .. } | Self::Chest { .. } | Self::Barrel { .. }
+        )
+    }
+
+    /// Orients the block for placement against the clicked face. Properties
+    /// that depend on the surrounding blocks are left to the redstone engine.
+    pub fn for_placement(self, ctx: &PlaceContext) -> Option<Self> {
+        let (face, horizontal) = (ctx.face, ctx.facing);
+        let attach = |facing| match face {
+            Direction::Up => (AttachFace::Floor, horizontal),
+            Direction::Down => (AttachFace::Ceiling, horizontal),
+            _ => (AttachFace::Wall, facing),
+        };
+
+        Some(match self {
+            Self::RedstoneWire(_) => Self::RedstoneWire(RedstoneWire {
+                north: WireSide::Side,
+                south: WireSide::Side,
+                east: WireSide::Side,
+                west: WireSide::Side,
+                power: 0,
+            }),
+            Self::RedstoneTorch { .. } | Self::RedstoneWallTorch { .. } => match face {
+                Direction::Up => Self::RedstoneTorch { lit: true },
+                Direction::Down => return None,
+                facing => Self::RedstoneWallTorch { facing, lit: true },
+            },
+            Self::Lever { .. } => {
+                let (face, facing) = attach(face);
+                Self::Lever { face, facing, powered: false }
+            },
+            Self::Button { kind, .. } => {
+                let (face, facing) = attach(face);
+                Self::Button { kind, face, facing, powered: false }
+            },
+            Self::Repeater { .. } => Self::Repeater {
+                delay: 1,
+                facing: horizontal.opposite(),
+                locked: false,
+                powered: false,
+            },
+            Self::Comparator { .. } => Self::Comparator {
+                facing: horizontal.opposite(),
+                mode: ComparatorMode::Compare,
+                powered: false,
+            },
+            Self::NoteBlock { .. } => Self::NoteBlock { instrument: Instrument::Harp, note: 0, powered: false },
+            Self::DaylightDetector { .. } => Self::DaylightDetector { inverted: false, power: 0 },
+            Self::Door { kind, .. } => Self::Door {
+                kind,
+                facing: horizontal,
+                half: Half::Lower,
+                hinge: Hinge::Left,
+                open: false,
+                powered: false,
+            },
+            Self::Trapdoor { kind, .. } => {
+                let (facing, half) = match face {
+                    Direction::Up => (horizontal.opposite(), Half::Lower),
+                    Direction::Down => (horizontal.opposite(), Half::Upper),
+                    f if ctx.cursor.1 > 0.5 => (f, Half::Upper),
+                    f => (f, Half::Lower),
+                };
+                Self::Trapdoor { kind, facing, half, open: false, powered: false }
+            },
+            Self::Piston { sticky, .. } => Self::Piston { sticky, facing: ctx.look.opposite(), extended: false },
+            Self::Observer { .. } => Self::Observer { facing: ctx.look, powered: false },
+            Self::RedstoneLamp { .. } => Self::RedstoneLamp { lit: false },
+            Self::Target { .. } => Self::Target { power: 0 },
+            Self::PressurePlate { kind, .. } => Self::PressurePlate { kind, powered: false },
+            Self::WeightedPressurePlate { heavy, .. } => Self::WeightedPressurePlate { heavy, power: 0 },
+            Self::TripwireHook { .. } => match face {
+                Direction::Up | Direction::Down => return None,
+                facing => Self::TripwireHook { facing, attached: false, powered: false },
+            },
+            Self::Tripwire(_) => Self::Tripwire(Tripwire {
+                attached: false,
+                disarmed: false,
+                powered: false,
+                north: false,
+                south: false,
+                east: false,
+                west: false,
+            }),
+            Self::Hopper { .. } => Self::Hopper {
+                facing: if face == Direction::Up { Direction::Down } else { face.opposite() },
+                enabled: true,
+            },
+            Self::Dispenser { .. } => Self::Dispenser { facing: ctx.look.opposite(), triggered: false },
+            Self::Dropper { .. } => Self::Dropper { facing: ctx.look.opposite(), triggered: false },
+            Self::Chest { .. } => Self::Chest { facing: horizontal.opposite(), kind: ChestType::Single },
+            Self::Barrel { .. } => Self::Barrel { facing: ctx.look.opposite(), open: false },
+            Self::Air | Self::PistonHead { .. } | Self::MovingPiston { .. } => return None,
+            b => b,
+        })
+    }
+}
+
+/// How a player placed a block.
+pub struct PlaceContext {
+    /// Face of the block that was clicked.
+    pub face: Direction,
+    /// Horizontal direction the player is facing.
+    pub facing: Direction,
+    /// Direction closest to where the player is looking.
+    pub look: Direction,
+    /// Position of the click on the clicked block.
+    pub cursor: (f32, f32, f32),
+    pub sneaking: bool,
+}
@@ -0,0 +1,136 @@
+use crate::server::packet::PacketWriter;
+
+pub const SECTIONS: usize = 16;
+
+/// Bits per entry used for the global block state palette in 1.18.2.
+const DIRECT_BITS: u8 = 15;
+
+#[derive(Clone)]
+pub struct ChunkSection {
+    pub blocks: Vec<u32>,
+    pub block_count: u16,
+}
+
+impl ChunkSection {
+    pub fn new() -> Self {
+        Self {
+            blocks: vec![0; 4096],
+            block_count: 0,
+        }
+    }
+
+    fn index(x: usize, y: usize, z: usize) -> usize {
+        (y << 8) | (z << 4) | x
+    }
+
+    pub fn get_block(&self, x: usize, y: usize, z: usize) -> u32 {
+        self.blocks[Self::index(x, y, z)]
+    }
+
+    /// Returns whether the block has changed.
+    pub fn set_block(&mut self, x: usize, y: usize, z: usize, block: u32) -> bool {
+        let old = std::mem::replace(&mut self.blocks[Self::index(x, y, z)], block);
+        match (old, block) {
+            (0, 0) => false,
+            (0, _) => {
+                self.block_count += 1;
+                true
+            },
+            (_, 0) => {
+                self.block_count -= 1;
+                true
+            },
+            (o, n) => o != n,
+        }
+    }
+
+    fn write(&self, p: &mut PacketWriter) {
+        p.write_be(self.block_count as i16);
+
+        let mut palette = Vec::new();
+        for b in self.blocks.iter() {
+            if !palette.contains(b) {
+                palette.push(*b);
+                if palette.len() > 256 {
+                    break;
+                }
+            }
+        }
+
+        if palette.len() == 1 {
+            p.write_be(0_u8);
+            p.write_varint(palette[0] as i32);
+            p.write_varint(0);
+        } else if palette.len() <= 256 {
+            let bits = (usize::BITS - (palette.len() - 1).leading_zeros()).max(4) as u8;
+            p.write_be(bits);
+            p.write_varint(palette.len() as i32);
+            for b in palette.iter() {
+                p.write_varint(*b as i32);
+            }
+
+            let indices = self.blocks.iter().map(|b| palette.iter().position(|e| e == b).unwrap() as u64);
+            write_packed(p, bits, indices);
+        } else {
+            p.write_be(DIRECT_BITS);
+            write_packed(p, DIRECT_BITS, self.blocks.iter().map(|b| *b as u64));
+        }
+
+        // biomes: a single entry of id 0
+        p.write_be(0_u8);
+        p.write_varint(0);
+        p.write_varint(0);
+    }
+}
+
+/// Packs entries into longs without letting an entry span two longs.
+fn write_packed(p: &mut PacketWriter, bits: u8, entries: impl Iterator<Item = u64>) {
+    let per_long = 64 / bits as usize;
+    let mut longs = Vec::with_capacity(4096 / per_long + 1);
+    for (i, e) in entries.enumerate() {
+        if i % per_long == 0 {
+            longs.push(0_u64);
+        }
+
+        *longs.last_mut().unwrap() |= e << (i % per_long * bits as usize);
+    }
+
+    p.write_varint(longs.len() as i32);
+    for l in longs {
+        p.write_be(l);
+    }
+}
+
+#[derive(Clone)]
+pub struct Chunk {
+    pub sections: Vec<ChunkSection>,
+}
+
+impl Chunk {
+    /// Creates a flat chunk with a single layer of `ground` at y = 0.
+    pub fn flat(ground: u32) -> Self {
+        let mut sections = vec![ChunkSection::new(); SECTIONS];
+        for x in 0..16 {
+            for z in 0..16 {
+                sections[0].set_block(x, 0, z, ground);
+            }
+        }
+
+        Self { sections }
+    }
+
+    pub fn get_block(&self, x: usize, y: usize, z: usize) -> u32 {
+        self.sections[y >> 4].get_block(x, y & 15, z)
+    }
+
+    pub fn set_block(&mut self, x: usize, y: usize, z: usize, block: u32) -> bool {
+        self.sections[y >> 4].set_block(x, y & 15, z, block)
+    }
+
+    /// Encodes the sections in the format of the Chunk Data packet.
+    pub fn write(&self, p: &mut PacketWriter) {
+        for s in self.sections.iter() {
+            s.write(p);
+        }
+    }
+}
@@ -0,0 +1,501 @@
+pub mod block;
+pub mod chunk;
+pub mod state;
+pub mod storage;
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+use std::hash::Hash;
+use block::{Block, Direction};
+use chunk::Chunk;
+use super::item::ItemStack;
+use super::plot::{Layout, PlotId};
+use super::redstone::compiled::Circuit;
+use super::redstone::debug::{Profile, Trace};
+
+pub const HEIGHT: i32 = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct BlockPos {
+    pub x: i32,
+    pub y: i32,
+    pub z: i32,
+}
+
+impl BlockPos {
+    pub fn new(x: i32, y: i32, z: i32) -> Self {
+        Self { x, y, z }
+    }
+
+    pub fn offset(self, dir: Direction) -> Self {
+        let (x, y, z) = dir.offset();
+        Self::new(self.x + x, self.y + y, self.z + z)
+    }
+
+    pub fn offset_by(self, dir: Direction, n: i32) -> Self {
+        let (x, y, z) = dir.offset();
+        Self::new(self.x + x * n, self.y + y * n, self.z + z * n)
+    }
+
+    /// Like [`BlockPos::offset_by`], or none past the coordinates there are.
+    pub fn checked_offset_by(self, dir: Direction, n: i32) -> Option<Self> {
+        let (x, y, z) = dir.offset();
+        Some(Self::new(
+            self.x.checked_add(x.checked_mul(n)?)?,
+            self.y.checked_add(y.checked_mul(n)?)?,
+            self.z.checked_add(z.checked_mul(n)?)?,
+        ))
+    }
+
+    pub fn chunk(self) -> (i32, i32) {
+        (self.x >> 4, self.z >> 4)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum TickPriority {
+    ExtremelyHigh,
+    VeryHigh,
+    High,
+    Normal,
+}
+
+/// Scheduled block ticks, run in order of time, priority and scheduling.
+pub struct TickScheduler<T = BlockPos> {
+    pending: BTreeMap<(u64, TickPriority, u64), T>,
+    scheduled: HashSet<T>,
+    running: VecDeque<T>,
+    count: u64,
+}
+
+impl<T> Default for TickScheduler<T> {
+    fn default() -> Self {
+        Self {
+            pending: BTreeMap::new(),
+            scheduled: HashSet::new(),
+            running: VecDeque::new(),
+            count: 0,
+        }
+    }
+}
+
+impl<T: Copy + Eq + Hash> TickScheduler<T> {
+    /// Does nothing if the block already has a pending tick.
+    pub fn schedule(&mut self, pos: T, time: u64, priority: TickPriority) {
+        if self.scheduled.insert(pos) {
+            self.pending.insert((time, priority, self.count), pos);
+            self.count += 1;
+        }
+    }
+
+    pub fn is_scheduled(&self, pos: T) -> bool {
+        self.scheduled.contains(&pos)
+    }
+
+    pub fn will_tick_this_tick(&self, pos: T) -> bool {
+        self.running.contains(&pos)
+    }
+
+    /// Time and priority of the pending tick of `pos`.
+    pub fn pending_at(&self, pos: T) -> Option<(u64, TickPriority)> {
+        if !self.is_scheduled(pos) {
+            return None;
+        }
+
+        self.pending.iter().find(|(_, p)| **p == pos).map(|((time, priority, _), _)| (*time, *priority))
+    }
+
+    /// Moves every tick due at `time` into the running queue.
+    pub fn prepare(&mut self, time: u64) {
+        while let Some(entry) = self.pending.first_entry() {
+            if entry.key().0 > time {
+                break;
+            }
+
+            let pos = entry.remove();
+            self.scheduled.remove(&pos);
+            self.running.push_back(pos);
+        }
+    }
+
+    /// Blocks with a pending tick, in no particular order.
+    pub fn pending(&self) -> impl Iterator<Item = T> + '_ {
+        self.pending.values().copied()
+    }
+
+    pub fn next(&mut self) -> Option<T> {
+        self.running.pop_front()
+    }
+
+    /// Takes out the pending ticks `f` picks, with their time and priority,
+    /// in the order they would run.
+    pub fn take_pending(&mut self, f: impl Fn(T) -> bool) -> Vec<(u64, TickPriority, T)> {
+        let mut taken = Vec::new();
+        self.pending.retain(|(time, priority, _), pos| {
+            if !f(*pos) {
+                return true;
+            }
+
+            taken.push((*time, *priority, *pos));
+            false
+        });
+
+        for (_, _, pos) in &taken {
+            self.scheduled.remove(pos);
+        }
+
+        taken
+    }
+}
+
+/// A block on its way to or from a piston.
+#[derive(Debug, Clone)]
+pub struct MovingBlock {
+    /// Block that will be placed once the move is done.
+    pub block: Block,
+    pub facing: Direction,
+    pub extending: bool,
+    /// Whether this is the head or base of the piston itself.
+    pub source: bool,
+    /// Progress of the move in half blocks, and what it was before the last tick.
+    pub progress: u8,
+    pub progress_old: u8,
+    pub last_ticked: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct Hopper {
+    pub items: Vec<Option<ItemStack>>,
+    /// Ticks left until the hopper moves items again.
+    pub cooldown: i32,
+    pub last_ticked: u64,
+}
+
+#[derive(Debug, Clone)]
+pub enum BlockEntity {
+    Comparator { output: u8 },
+    MovingPiston(MovingBlock),
+    DaylightDetector,
+    /// Chests, barrels, dispensers and droppers.
+    Container(Vec<Option<ItemStack>>),
+    Hopper(Hopper),
+}
+
+impl BlockEntity {
+    /// The block entity a newly set `block` starts out with.
+    pub fn new(block: Block) -> Option<Self> {
+        Some(match block {
+            Block::Comparator { .. } => Self::Comparator { output: 0 },
+            Block::DaylightDetector { .. } => Self::DaylightDetector,
+            Block::Chest { .. } | Block::Barrel { .. } => Self::Container(vec![None; 27]),
+            Block::Dispenser { .. } | Block::Dropper { .. } => Self::Container(vec![None; 9]),
+            Block::Hopper { .. } => Self::Hopper(Hopper { items: vec![None; 5], cooldown: -1, last_ticked: 0 }),
+            _ => return None,
+        })
+    }
+
+    fn is_ticking(&self) -> bool {
+        matches!(self, Self::MovingPiston(_) | Self::DaylightDetector | Self::Hopper(_))
+    }
+
+    pub fn items(&self) -> Option<&Vec<Option<ItemStack>>> {
+        match self {
+            Self::Container(items) | Self::Hopper(Hopper { items, .. }) => Some(items),
+            _ => None,
+        }
+    }
+
+    pub fn items_mut(&mut self) -> Option<&mut Vec<Option<ItemStack>>> {
+        match self {
+            Self::Container(items) | Self::Hopper(Hopper { items, .. }) => Some(items),
+            _ => None,
+        }
+    }
+}
+
+/// An event a block sends to itself at the end of the tick, which players
+/// are told about if it goes through.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockEvent {
+    pub pos: BlockPos,
+    /// Block registry id, see [`Block::block_type`].
+    pub block: i32,
+    pub id: u8,
+    pub param: u8,
+}
+
+pub struct World {
+    pub chunks: HashMap<(i32, i32), Chunk>,
+    pub block_entities: HashMap<BlockPos, BlockEntity>,
+    pub ticks: TickScheduler,
+    pub time: u64,
+    /// Whether scheduled ticks are being run right now.
+    pub handling_ticks: bool,
+
+    pub block_events: VecDeque<BlockEvent>,
+    /// Ticking block entities in the order they were created, which is the
+    /// order they tick in.
+    pub tickers: Vec<(BlockPos, u64)>,
+    /// Tells apart block entities that replaced each other at the same position.
+    serials: HashMap<BlockPos, u64>,
+    next_serial: u64,
+
+    /// Feet positions of the entities blocks can detect, by entity id. Only
+    /// players are simulated.
+    pub entities: HashMap<i32, (f64, f64, f64)>,
+    seed: u64,
+    /// Random number generators by plot, and the tick they were made for.
+    /// Each plot draws from its own, so what happens on one doesn't depend
+    /// on the others or on whether the plots tick in parallel.
+    randoms: HashMap<Option<PlotId>, (u64, u64)>,
+
+    /// Regions whose redstone runs compiled instead of block by block.
+    pub circuits: Vec<Circuit>,
+    /// Where the plots are, in a plot world.
+    pub plots: Option<Layout>,
+    pub trace: Option<Trace>,
+    pub profile: Option<Profile>,
+
+    /// Blocks changed since they were last sent to players.
+    pub changes: BTreeSet<BlockPos>,
+    /// Block events that went through since they were last sent to players.
+    pub block_actions: Vec<BlockEvent>,
+}
+
+/// Mixes the seed of a plot's random number generator for a tick, scrambled
+/// like `java.util.Random` does its seed.
+fn random_seed(seed: u64, time: u64, plot: Option<PlotId>) -> u64 {
+    let plot = plot.map_or(u64::MAX, |p| ((p.x as u32 as u64) << 32) | p.z as u32 as u64);
+    let mut mixed = seed ^ time.wrapping_mul(0x9e3779b97f4a7c15) ^ plot.wrapping_mul(0xd6e8feb86659fd93);
+    // the finalizer of SplitMix64
+    mixed = (mixed ^ (mixed >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    mixed = (mixed ^ (mixed >> 27)).wrapping_mul(0x94d049bb133111eb);
+    mixed ^= mixed >> 31;
+    (mixed ^ 0x5deece66d) & ((1 << 48) - 1)
+}
+
+impl World {
+    pub fn new() -> Self {
+        Self {
+            chunks: HashMap::new(),
+            block_entities: HashMap::new(),
+            ticks: TickScheduler::default(),
+            time: 0,
+            handling_ticks: false,
+
+            block_events: VecDeque::new(),
+            tickers: Vec::new(),
+            serials: HashMap::new(),
+            next_serial: 0,
+
+            entities: HashMap::new(),
+            seed: 0,
+            randoms: HashMap::new(),
+
+            circuits: Vec::new(),
+            plots: None,
+            trace: None,
+            profile: None,
+
+            changes: BTreeSet::new(),
+            block_actions: Vec::new(),
+        }
+    }
+
+    pub fn load_chunk(&mut self, x: i32, z: i32) -> &Chunk {
+        let plots = self.plots;
+        self.chunks.entry((x, z)).or_insert_with(|| match plots {
+            Some(layout) => layout.generate(x, z),
+            None => Chunk::flat(Block::Solid(1).get_id()),
+        })
+    }
+
+    /// Moves the simulation state of the blocks `pick` chooses into a world
+    /// of their own, which gets `chunks` to run in. Nothing outside the
+    /// picked blocks may depend on them until it's merged back.
+    pub fn split_off(&mut self, chunks: HashMap<(i32, i32), Chunk>, pick: impl Fn(BlockPos) -> bool) -> World {
+        let mut island = World::new();
+        island.chunks = chunks;
+        island.time = self.time;
+        island.plots = self.plots;
+        island.entities = self.entities.clone();
+        island.next_serial = self.next_serial;
+        island.seed = self.seed;
+
+        for (time, priority, pos) in self.ticks.take_pending(&pick) {
+            island.ticks.schedule(pos, time, priority);
+        }
+
+        let picked: Vec<_> = self.block_entities.keys().copied().filter(|p| pick(*p)).collect();
+        for pos in picked {
+            island.block_entities.insert(pos, self.block_entities.remove(&pos).unwrap());
+            if let Some(serial) = self.serials.remove(&pos) {
+                island.serials.insert(pos, serial);
+            }
+        }
+
+        (island.tickers, self.tickers) = self.tickers.iter().partition(|(p, _)| pick(*p));
+        (island.block_events, self.block_events) = self.block_events.iter().partition(|e| pick(e.pos));
+        (island.circuits, self.circuits) = std::mem::take(&mut self.circuits).into_iter().partition(|c| pick(c.region.min));
+        island
+    }
+
+    /// Takes back a world made by [`World::split_off`]. Chunks this world
+    /// still has were copies, so only the blocks that changed in them are
+    /// copied over.
+    pub fn merge(&mut self, mut island: World) {
+        for ((cx, cz), chunk) in island.chunks {
+            let Some(own) = self.chunks.get_mut(&(cx, cz)) else {
+                self.chunks.insert((cx, cz), chunk);
+                continue;
+            };
+
+            for pos in island.changes.iter().filter(|p| p.chunk() == (cx, cz)) {
+                let (x, y, z) = (pos.x as usize & 15, pos.y as usize, pos.z as usize & 15);
+                own.set_block(x, y, z, chunk.get_block(x, y, z));
+            }
+        }
+
+        for (time, priority, pos) in island.ticks.take_pending(|_| true) {
+            self.ticks.schedule(pos, time, priority);
+        }
+
+        self.block_entities.extend(island.block_entities);
+        self.serials.extend(island.serials);
+        self.next_serial = self.next_serial.max(island.next_serial);
+        self.tickers.extend(island.tickers);
+        self.block_events.extend(island.block_events);
+        self.circuits.extend(island.circuits);
+        self.changes.extend(island.changes);
+        self.block_actions.extend(island.block_actions);
+    }
+
+    /// Whether `pos` is on the roads of a plot world, where redstone can't
+    /// change or notice anything.
+    pub fn on_road(&self, pos: BlockPos) -> bool {
+        self.plots.is_some_and(|l| l.plot_at(pos).is_none())
+    }
+
+    pub fn get_block_id(&self, pos: BlockPos) -> u32 {
+        if !(0..HEIGHT).contains(&pos.y) {
+            return 0;
+        }
+
+        self.chunks.get(&pos.chunk())
+            .map_or(0, |c| c.get_block(pos.x as usize & 15, pos.y as usize, pos.z as usize & 15))
+    }
+
+    pub fn get_block(&self, pos: BlockPos) -> Block {
+        Block::from_id(self.get_block_id(pos))
+    }
+
+    /// Sets a block without any updates. Returns whether the block has changed.
+    pub fn set_block(&mut self, pos: BlockPos, block: Block) -> bool {
+        if !(0..HEIGHT).contains(&pos.y) {
+            return false;
+        }
+
+        let old = self.get_block(pos);
+        let (cx, cz) = pos.chunk();
+        self.load_chunk(cx, cz);
+        let chunk = self.chunks.get_mut(&(cx, cz)).unwrap();
+        let changed = chunk.set_block(pos.x as usize & 15, pos.y as usize, pos.z as usize & 15, block.get_id());
+
+        if changed {
+            if !block.has_block_entity() || std::mem::discriminant(&old) != std::mem::discriminant(&block) {
+                self.remove_block_entity(pos);
+            }
+
+            if !self.block_entities.contains_key(&pos) {
+                if let Some(entity) = BlockEntity::new(block) {
+                    self.add_block_entity(pos, entity);
+                }
+            }
+
+            self.changes.insert(pos);
+        }
+
+        changed
+    }
+
+    pub fn schedule_tick(&mut self, pos: BlockPos, delay: u64, priority: TickPriority) {
+        self.ticks.schedule(pos, self.time + delay, priority);
+    }
+
+    /// Queues a block event, unless the same one is already queued.
+    pub fn block_event(&mut self, event: BlockEvent) {
+        if !self.block_events.contains(&event) {
+            self.block_events.push_back(event);
+        }
+    }
+
+    /// Draws a number below `bound` for the block at `pos` the way
+    /// `java.util.Random` does, from a generator seeded by the world's seed,
+    /// the tick and the plot. Runs of the same circuit play out the same.
+    pub fn random(&mut self, pos: BlockPos, bound: u32) -> u32 {
+        let plot = self.plots.and_then(|l| l.plot_at(pos));
+        let (seed, time) = (self.seed, self.time);
+        let (tick, state) = self.randoms.entry(plot).or_insert((time, random_seed(seed, time, plot)));
+        if *tick != time {
+            (*tick, *state) = (time, random_seed(seed, time, plot));
+        }
+
+        *state = state.wrapping_mul(0x5DEECE66D).wrapping_add(0xB) & ((1 << 48) - 1);
+        (*state >> 17) as u32 % bound
+    }
+
+    pub fn add_block_entity(&mut self, pos: BlockPos, entity: BlockEntity) {
+        let serial = self.next_serial;
+        self.next_serial += 1;
+
+        if entity.is_ticking() {
+            self.tickers.push((pos, serial));
+        }
+
+        self.serials.insert(pos, serial);
+        self.block_entities.insert(pos, entity);
+    }
+
+    pub fn remove_block_entity(&mut self, pos: BlockPos) -> Option<BlockEntity> {
+        self.serials.remove(&pos);
+        self.block_entities.remove(&pos)
+    }
+
+    /// Whether the block entity `serial` is still at `pos`.
+    pub fn is_ticker(&self, pos: BlockPos, serial: u64) -> bool {
+        self.serials.get(&pos) == Some(&serial)
+    }
+
+    /// Starts moving `block`. The block at `pos` should be a moving piston.
+    pub fn add_moving_block(&mut self, pos: BlockPos, block: Block, facing: Direction, extending: bool, source: bool) {
+        self.add_block_entity(pos, BlockEntity::MovingPiston(MovingBlock {
+            block,
+            facing,
+            extending,
+            source,
+            progress: 0,
+            progress_old: 0,
+            last_ticked: 0,
+        }));
+    }
+
+    pub fn moving_block(&self, pos: BlockPos) -> Option<&MovingBlock> {
+        match self.block_entities.get(&pos) {
+            Some(BlockEntity::MovingPiston(m)) => Some(m),
+            _ => None,
+        }
+    }
+
+    /// Block entities in the chunk that players need to draw its blocks,
+    /// with their block entity type ids.
+    pub fn client_block_entities(&self, x: i32, z: i32) -> Vec<(BlockPos, i32)> {
+        let mut entities: Vec<_> = self.block_entities.keys()
+            .filter(|p| p.chunk() == (x, z))
+            .filter_map(|p| match self.get_block(*p) {
+                Block::Chest { .. } => Some((*p, 1)),
+                _ => None,
+            })
+            .collect();
+        entities.sort();
+        entities
+    }
+}
@@ -0,0 +1,203 @@
+//! Block states as the `minecraft:name[property=value,...]` strings structure
+//! files use, for the blocks whose 1.18.2 state ids are known.
+
+const BOOL: &[&str] = &["true", "false"];
+const POWER: &[&str] = &["0", "1", "2", "3", "4", "5", "6", "7", "8", "9", "10", "11", "12", "13", "14", "15"];
+const FACING: &[&str] = &["north", "east", "south", "west", "up", "down"];
+const HORIZONTAL: &[&str] = &["north", "south", "west", "east"];
+const FACE: &[&str] = &["floor", "wall", "ceiling"];
+const WIRE_SIDE: &[&str] = &["up", "side", "none"];
+const PISTON_TYPE: &[&str] = &["normal", "sticky"];
+const INSTRUMENT: &[&str] = &[
+    "harp", "basedrum", "snare", "hat", "bass", "flute", "bell", "guitar", "chime", "xylophone",
+    "iron_xylophone", "cow_bell", "didgeridoo", "bit", "banjo", "pling",
+];
+const NOTE: &[&str] = &[
+    "0", "1", "2", "3", "4", "5", "6", "7", "8", "9", "10", "11", "12", "13", "14", "15", "16", "17", "18",
+    "19", "20", "21", "22", "23", "24",
+];
+const COLORS: [&str; 16] = [
+    "white", "orange", "magenta", "light_blue", "yellow", "lime", "pink", "gray", "light_gray", "cyan",
+    "purple", "blue", "brown", "green", "red", "black",
+];
+
+/// Values properties take when left out, where they aren't the first value.
+const DEFAULTS: &[&str] = &["false", "none", "lower", "bottom", "wall"];
+
+type Properties = &'static [(&'static str, &'static [&'static str])];
+
+/// A block and its states, numbered from `base` with the properties in
+/// alphabetical order and the last one counting fastest, like vanilla does.
+struct State {
+    name: &'static str,
+    base: u32,
+    properties: Properties,
+}
+
+const fn state(name: &'static str, base: u32, properties: Properties) -> State {
+    State { name, base, properties }
+}
+
+const STATES: &[State] = &[
+    state("air", 0, &[]),
+    state("stone", 1, &[]),
+    state("granite", 2, &[]),
+    state("polished_granite", 3, &[]),
+    state("diorite", 4, &[]),
+    state("polished_diorite", 5, &[]),
+    state("andesite", 6, &[]),
+    state("polished_andesite", 7, &[]),
+    state("grass_block", 8, &[("snowy", BOOL)]),
+    state("dirt", 10, &[]),
+    state("coarse_dirt", 11, &[]),
+    state("cobblestone", 14, &[]),
+    state("oak_planks", 15, &[]),
+    state("spruce_planks", 16, &[]),
+    state("birch_planks", 17, &[]),
+    state("jungle_planks", 18, &[]),
+    state("acacia_planks", 19, &[]),
+    state("dark_oak_planks", 20, &[]),
+    state("bedrock", 33, &[]),
+    state("glass", 262, &[]),
+    state("dispenser", 266, &[("facing", FACING), ("triggered", BOOL)]),
+    state("sandstone", 278, &[]),
+    state("chiseled_sandstone", 279, &[]),
+    state("cut_sandstone", 280, &[]),
+    state("note_block", 281, &[("instrument", INSTRUMENT), ("note", NOTE), ("powered", BOOL)]),
+    state("sticky_piston", 1385, &[("extended", BOOL), ("facing", FACING)]),
+    state("piston", 1404, &[("extended", BOOL), ("facing", FACING)]),
+    state("piston_head", 1416, &[("facing", FACING), ("short", BOOL), ("type", PISTON_TYPE)]),
+    state("moving_piston", 1456, &[("facing", FACING), ("type", PISTON_TYPE)]),
+    state("gold_block", 1483, &[]),
+    state("iron_block", 1484, &[]),
+    state("bricks", 1485, &[]),
+    state("tnt", 1486, &[("unstable", BOOL)]),
+    state("bookshelf", 1488, &[]),
+    state("mossy_cobblestone", 1489, &[]),
+    state("obsidian", 1490, &[]),
+    state("chest", 2090, &[("facing", HORIZONTAL), ("type", &["single", "left", "right"]), ("waterlogged", BOOL)]),
+    state("redstone_wire", 2114, &[
+        ("east", WIRE_SIDE), ("north", WIRE_SIDE), ("power", POWER), ("south", WIRE_SIDE), ("west", WIRE_SIDE),
+    ]),
+    state("diamond_block", 3412, &[]),
+    state("oak_door", 3630, &[
+        ("facing", HORIZONTAL), ("half", &["upper", "lower"]), ("hinge", &["left", "right"]), ("open", BOOL),
+        ("powered", BOOL),
+    ]),
+    state("lever", 3850, &[("face", FACE), ("facing", HORIZONTAL), ("powered", BOOL)]),
+    state("stone_pressure_plate", 3874, &[("powered", BOOL)]),
+    state("iron_door", 3876, &[
+        ("facing", HORIZONTAL), ("half", &["upper", "lower"]), ("hinge", &["left", "right"]), ("open", BOOL),
+        ("powered", BOOL),
+    ]),
+    state("oak_pressure_plate", 3940, &[("powered", BOOL)]),
+    state("redstone_torch", 3956, &[("lit", BOOL)]),
+    state("redstone_wall_torch", 3958, &[("facing", HORIZONTAL), ("lit", BOOL)]),
+    state("stone_button", 3966, &[("face", FACE), ("facing", HORIZONTAL), ("powered", BOOL)]),
+    state("repeater", 4100, &[("delay", &["1", "2", "3", "4"]), ("facing", HORIZONTAL), ("locked", BOOL), ("powered", BOOL)]),
+    state("oak_trapdoor", 4180, &[
+        ("facing", HORIZONTAL), ("half", &["top", "bottom"]), ("open", BOOL), ("powered", BOOL), ("waterlogged", BOOL),
+    ]),
+    state("stone_bricks", 4564, &[]),
+    state("mossy_stone_bricks", 4565, &[]),
+    state("cracked_stone_bricks", 4566, &[]),
+    state("chiseled_stone_bricks", 4567, &[]),
+    state("redstone_lamp", 5361, &[("lit", BOOL)]),
+    state("tripwire_hook", 5465, &[("attached", BOOL), ("facing", HORIZONTAL), ("powered", BOOL)]),
+    state("tripwire", 5481, &[
+        ("attached", BOOL), ("disarmed", BOOL), ("east", BOOL), ("north", BOOL), ("powered", BOOL), ("south", BOOL),
+        ("west", BOOL),
+    ]),
+    state("emerald_block", 5609, &[]),
+    state("oak_button", 6552, &[("face", FACE), ("facing", HORIZONTAL), ("powered", BOOL)]),
+    state("light_weighted_pressure_plate", 6852, &[("power", POWER)]),
+    state("heavy_weighted_pressure_plate", 6868, &[("power", POWER)]),
+    state("comparator", 6884, &[("facing", HORIZONTAL), ("mode", &["compare", "subtract"]), ("powered", BOOL)]),
+    state("daylight_detector", 6900, &[("inverted", BOOL), ("power", POWER)]),
+    state("redstone_block", 6932, &[]),
+    state("hopper", 6934, &[("enabled", BOOL), ("facing", &["down", "north", "south", "west", "east"])]),
+    state("quartz_block", 6944, &[]),
+    state("chiseled_quartz_block", 6945, &[]),
+    state("dropper", 7053, &[("facing", FACING), ("triggered", BOOL)]),
+    state("slime_block", 7753, &[]),
+    state("iron_trapdoor", 7787, &[
+        ("facing", HORIZONTAL), ("half", &["top", "bottom"]), ("open", BOOL), ("powered", BOOL), ("waterlogged", BOOL),
+    ]),
+    state("terracotta", 8132, &[]),
+    state("observer", 9510, &[("facing", FACING), ("powered", BOOL)]),
+    state("target", 16014, &[("power", POWER)]),
+    state("barrel", 15041, &[("facing", FACING), ("open", BOOL)]),
+    state("honey_block", 16078, &[]),
+];
+
+/// Blocks that come in all 16 colours, and the state id of the white one.
+const COLORED: &[(&str, u32)] = &[
+    ("wool", 1440),
+    ("stained_glass", 4164),
+    ("terracotta", 7065),
+    ("concrete", 9688),
+];
+
+fn count(properties: Properties) -> u32 {
+    properties.iter().map(|(_, values)| values.len() as u32).product()
+}
+
+/// The state string of a block state id.
+pub fn name(id: u32) -> Option<String> {
+    for (kind, white) in COLORED {
+        if (*white..*white + 16).contains(&id) {
+            return Some(format!("minecraft:{}_{}", COLORS[(id - white) as usize], kind));
+        }
+    }
+
+    let state = STATES.iter().find(|s| (s.base..s.base + count(s.properties)).contains(&id))?;
+    let mut name = format!("minecraft:{}", state.name);
+    let mut i = id - state.base;
+    let mut values = Vec::new();
+    for (property, options) in state.properties.iter().rev() {
+        let n = options.len() as u32;
+        values.push(format!("{}={}", property, options[(i % n) as usize]));
+        i /= n;
+    }
+
+    if !values.is_empty() {
+        values.reverse();
+        name += &format!("[{}]", values.join(","));
+    }
+
+    Some(name)
+}
+
+/// The state id of a state string. Properties left out take their vanilla
+/// default, and unknown ones are ignored.
+pub fn parse(state: &str) -> Option<u32> {
+    let (name, properties) = match state.split_once('[') {
+        Some((name, rest)) => (name, rest.strip_suffix(']')?),
+        None => (state, ""),
+    };
+    let name = name.strip_prefix("minecraft:").unwrap_or(name);
+
+    for (kind, white) in COLORED {
+        if let Some(color) = name.strip_suffix(kind).and_then(|n| n.strip_suffix('_')) {
+            if let Some(i) = COLORS.iter().position(|c| *c == color) {
+                return Some(white + i as u32);
+            }
+        }
+    }
+
+    let state = STATES.iter().find(|s| s.name == name)?;
+    let mut id = 0;
+    for (property, options) in state.properties {
+        let value = properties.split(',')
+            .filter_map(|p| p.split_once('='))
+            .find(|(k, _)| k == property)
+            .map(|(_, v)| v);
+        let index = match value {
+            Some(v) => options.iter().position(|o| *o == v)?,
+            None => options.iter().position(|o| DEFAULTS.contains(o)).unwrap_or(0),
+        };
+        id = id * options.len() as u32 + index as u32;
+    }
+
+    Some(state.base + id)
+}
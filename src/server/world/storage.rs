@@ -0,0 +1,199 @@
+//! The world file, and the NBT form of block entities it shares with
+//! structure files.
+
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use quartz_nbt::io::{self, Flavor};
+use quartz_nbt::{NbtCompound, NbtList, NbtReprError};
+use super::*;
+use super::chunk::{ChunkSection, SECTIONS};
+use super::super::item::{Item, ItemStack};
+
+/// Data version of 1.18.2, which structure files are tagged with.
+pub const DATA_VERSION: i32 = 2975;
+
+fn items_to_nbt(items: &[Option<ItemStack>]) -> NbtList {
+    let mut list = NbtList::new();
+    for (slot, stack) in items.iter().enumerate() {
+        let Some(stack) = stack else { continue };
+        let Some(item) = stack.item() else {
+            warn!("item {} has no known name, leaving it out", stack.id);
+            continue;
+        };
+
+        let mut nbt = NbtCompound::new();
+        nbt.insert("Slot", slot as i8);
+        nbt.insert("id", item.name);
+        nbt.insert("Count", stack.count as i8);
+        if let Some(tag) = &stack.nbt {
+            nbt.insert("tag", tag.clone());
+        }
+        list.push(nbt);
+    }
+
+    list
+}
+
+fn items_from_nbt(list: &NbtList, items: &mut [Option<ItemStack>]) {
+    for nbt in list.iter_map::<&NbtCompound>().flatten() {
+        let (Ok(slot), Ok(name), Ok(count)) = (nbt.get::<_, i8>("Slot"), nbt.get::<_, &str>("id"), nbt.get::<_, i8>("Count")) else {
+            continue;
+        };
+        let Some(item) = Item::from_name(name) else {
+            warn!("{} isn't a known item, leaving it out", name);
+            continue;
+        };
+
+        if let Some(slot) = items.get_mut(slot as usize) {
+            let tag = nbt.get::<_, &NbtCompound>("tag").ok().cloned();
+            *slot = Some(ItemStack { id: item.id, count: count as u8, nbt: tag });
+        }
+    }
+}
+
+/// The id and data of a block entity. Moving pistons aren't kept.
+pub fn block_entity_to_nbt(block: Block, entity: &BlockEntity) -> Option<(&'static str, NbtCompound)> {
+    let id = match block {
+        Block::Comparator { .. } => "minecraft:comparator",
+        Block::DaylightDetector { .. } => "minecraft:daylight_detector",
+        Block::Chest { .. } => "minecraft:chest",
+        Block::Barrel { .. } => "minecraft:barrel",
+        Block::Dispenser { .. } => "minecraft:dispenser",
+        Block::Dropper { .. } => "minecraft:dropper",
+        Block::Hopper { .. } => "minecraft:hopper",
+        _ => return None,
+    };
+
+    let mut nbt = NbtCompound::new();
+    match entity {
+        BlockEntity::Comparator { output } => nbt.insert("OutputSignal", *output as i32),
+        BlockEntity::Container(items) => nbt.insert("Items", items_to_nbt(items)),
+        BlockEntity::Hopper(hopper) => {
+            nbt.insert("Items", items_to_nbt(&hopper.items));
+            nbt.insert("TransferCooldown", hopper.cooldown);
+        },
+        BlockEntity::DaylightDetector | BlockEntity::MovingPiston(_) => {},
+    }
+
+    Some((id, nbt))
+}
+
+/// The block entity of `block` with the data from `nbt`, where there is any.
+pub fn block_entity_from_nbt(block: Block, nbt: &NbtCompound) -> Option<BlockEntity> {
+    let mut entity = BlockEntity::new(block)?;
+    match &mut entity {
+        BlockEntity::Comparator { output } => *output = nbt.get::<_, i32>("OutputSignal").unwrap_or(0).clamp(0, 15) as u8,
+        BlockEntity::Hopper(hopper) => hopper.cooldown = nbt.get("TransferCooldown").unwrap_or(-1),
+        _ => {},
+    }
+
+    if let (Some(items), Ok(list)) = (entity.items_mut(), nbt.get::<_, &NbtList>("Items")) {
+        items_from_nbt(list, items);
+    }
+
+    Some(entity)
+}
+
+impl World {
+    /// Writes the blocks and block entities of the loaded chunks. Scheduled
+    /// ticks and moving blocks aren't kept.
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        let mut chunks = NbtList::new();
+        for ((x, z), chunk) in &self.chunks {
+            let mut sections = NbtList::new();
+            for (y, section) in chunk.sections.iter().enumerate() {
+                if section.block_count == 0 {
+                    continue;
+                }
+
+                let mut nbt = NbtCompound::new();
+                nbt.insert("Y", y as i8);
+                nbt.insert("Blocks", section.blocks.iter().map(|b| *b as i32).collect::<Vec<_>>());
+                sections.push(nbt);
+            }
+
+            let mut nbt = NbtCompound::new();
+            nbt.insert("X", *x);
+            nbt.insert("Z", *z);
+            nbt.insert("Sections", sections);
+            chunks.push(nbt);
+        }
+
+        let mut entities = NbtList::new();
+        for (pos, entity) in &self.block_entities {
+            let Some((id, mut nbt)) = block_entity_to_nbt(self.get_block(*pos), entity) else { continue };
+            nbt.insert("id", id);
+            nbt.insert("Pos", vec![pos.x, pos.y, pos.z]);
+            entities.push(nbt);
+        }
+
+        let mut root = NbtCompound::new();
+        root.insert("DataVersion", DATA_VERSION);
+        root.insert("Time", self.time as i64);
+        root.insert("Chunks", chunks);
+        root.insert("BlockEntities", entities);
+
+        let file = File::create(path).map_err(|e| format!("can't create {}: {}", path, e))?;
+        io::write_nbt(&mut BufWriter::new(file), Some(""), &root, Flavor::GzCompressed)
+            .map_err(|e| format!("can't write {}: {}", path, e))
+    }
+
+    /// Reads a world written by [`World::save`].
+    pub fn load(path: &str) -> Result<Self, String> {
+        let file = File::open(path).map_err(|e| format!("can't open {}: {}", path, e))?;
+        let (root, _) = io::read_nbt(&mut BufReader::new(file), Flavor::GzCompressed)
+            .map_err(|e| format!("can't read {}: {}", path, e))?;
+        let invalid = |e: NbtReprError| format!("{} isn't a valid world: {}", path, e);
+
+        let mut world = World::new();
+        let mut with_entities = Vec::new();
+        world.time = root.get::<_, i64>("Time").map_err(invalid)? as u64;
+
+        for chunk in root.get::<_, &NbtList>("Chunks").map_err(invalid)?.iter_map::<&NbtCompound>() {
+            let chunk = chunk.map_err(|e| invalid(e.into()))?;
+            let (x, z): (i32, i32) = (chunk.get("X").map_err(invalid)?, chunk.get("Z").map_err(invalid)?);
+            let mut sections = vec![ChunkSection::new(); SECTIONS];
+            for section in chunk.get::<_, &NbtList>("Sections").map_err(invalid)?.iter_map::<&NbtCompound>() {
+                let section = section.map_err(|e| invalid(e.into()))?;
+                let y = section.get::<_, i8>("Y").map_err(invalid)? as usize;
+                let blocks = section.get::<_, &[i32]>("Blocks").map_err(invalid)?;
+                let (Some(target), 4096) = (sections.get_mut(y), blocks.len()) else {
+                    return Err(format!("{} has a broken chunk section", path));
+                };
+
+                for (i, block) in blocks.iter().enumerate() {
+                    let (bx, by, bz) = (i & 15, i >> 8, (i >> 4) & 15);
+                    target.set_block(bx, by, bz, *block as u32);
+                    if Block::from_id(*block as u32).has_block_entity() {
+                        with_entities.push(BlockPos::new(x * 16 + bx as i32, (y * 16 + by) as i32, z * 16 + bz as i32));
+                    }
+                }
+            }
+
+            world.chunks.insert((x, z), Chunk { sections });
+        }
+
+        for nbt in root.get::<_, &NbtList>("BlockEntities").map_err(invalid)?.iter_map::<&NbtCompound>() {
+            let nbt = nbt.map_err(|e| invalid(e.into()))?;
+            let pos = match nbt.get::<_, &[i32]>("Pos").map_err(invalid)? {
+                [x, y, z] => BlockPos::new(*x, *y, *z),
+                _ => return Err(format!("{} has a block entity without a position", path)),
+            };
+
+            if let Some(entity) = block_entity_from_nbt(world.get_block(pos), nbt) {
+                world.add_block_entity(pos, entity);
+            }
+        }
+
+        // blocks whose block entity wasn't saved get a fresh one
+        for pos in with_entities {
+            if !world.block_entities.contains_key(&pos) {
+                if let Some(entity) = BlockEntity::new(world.get_block(pos)) {
+                    world.add_block_entity(pos, entity);
+                }
+            }
+        }
+
+        Ok(world)
+    }
+}